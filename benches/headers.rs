@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ngx::ffi::{ngx_list_t, ngx_str_t, ngx_table_elt_t};
+use ngx::http::list_iterator;
+use std::mem;
+
+const HEADERS: &[(&str, &str)] = &[
+    ("Host", "example.com"),
+    (
+        "User-Agent",
+        "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0",
+    ),
+    (
+        "Accept",
+        "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+    ),
+    ("Accept-Language", "en-US,en;q=0.5"),
+    ("Accept-Encoding", "gzip, deflate, br, zstd"),
+    ("Connection", "keep-alive"),
+    ("Cookie", "session=0123456789abcdef; theme=dark"),
+    ("Upgrade-Insecure-Requests", "1"),
+    ("Sec-Fetch-Dest", "document"),
+    ("Sec-Fetch-Mode", "navigate"),
+];
+
+fn ngx_str(s: &'static str) -> ngx_str_t {
+    ngx_str_t {
+        len: s.len(),
+        data: s.as_ptr() as *mut u8,
+    }
+}
+
+fn headers(c: &mut Criterion) {
+    // A single-part `ngx_list_t`, laid out the way `ngx_list_push` builds `headers_in`.
+    let mut elts: Vec<ngx_table_elt_t> = HEADERS
+        .iter()
+        .map(|(key, value)| {
+            let mut h: ngx_table_elt_t = unsafe { mem::zeroed() };
+            h.hash = 1;
+            h.key = ngx_str(key);
+            h.value = ngx_str(value);
+            h
+        })
+        .collect();
+
+    let mut list: ngx_list_t = unsafe { mem::zeroed() };
+    list.part.elts = elts.as_mut_ptr().cast();
+    list.part.nelts = elts.len();
+    list.size = mem::size_of::<ngx_table_elt_t>();
+    list.nalloc = elts.len();
+
+    c.bench_function("headers/iterate", |b| {
+        b.iter(|| unsafe { list_iterator(black_box(&list)) }.count())
+    });
+
+    c.bench_function("headers/find", |b| {
+        b.iter(|| {
+            unsafe { list_iterator(black_box(&list)) }
+                .find(|(key, _)| key.as_bytes().eq_ignore_ascii_case(b"sec-fetch-mode"))
+                .map(|(_, value)| value.as_bytes().len())
+        })
+    });
+}
+
+criterion_group!(benches, headers);
+criterion_main!(benches);
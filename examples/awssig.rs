@@ -1,12 +1,12 @@
-use http::HeaderMap;
+use http::{HeaderMap, HeaderValue};
 use ngx::ffi::{
     nginx_version, ngx_array_push, ngx_command_t, ngx_conf_t, ngx_http_core_module, ngx_http_handler_pt,
     ngx_http_module_t, ngx_http_phases_NGX_HTTP_PRECONTENT_PHASE, ngx_http_request_t, ngx_int_t, ngx_module_t,
-    ngx_str_t, ngx_uint_t, NGX_CONF_TAKE1, NGX_HTTP_LOC_CONF, NGX_HTTP_MODULE, NGX_HTTP_SRV_CONF,
-    NGX_RS_HTTP_LOC_CONF_OFFSET, NGX_RS_MODULE_SIGNATURE,
+    ngx_uint_t, NGX_HTTP_LOC_CONF, NGX_HTTP_MODULE, NGX_HTTP_SRV_CONF, NGX_RS_HTTP_LOC_CONF_OFFSET,
+    NGX_RS_MODULE_SIGNATURE,
 };
 use ngx::{core, core::Status, http::*};
-use ngx::{http_request_handler, ngx_log_debug_http, ngx_null_command, ngx_string};
+use ngx::{http_request_handler, ngx_conf_log_error, ngx_log_debug_http};
 use std::os::raw::{c_char, c_void};
 use std::ptr::addr_of;
 
@@ -31,59 +31,25 @@ impl HTTPModule for Module {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, core::Commands, core::DescribeConf)]
+#[commands(context = NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF, conf = NGX_RS_HTTP_LOC_CONF_OFFSET)]
+#[describe(redacted)]
 struct ModuleConfig {
+    #[directive(name = "awssigv4", slot = flag)]
     enable: bool,
+    #[directive(name = "awssigv4_access_key", take = 1)]
     access_key: String,
-    secret_key: String,
+    #[directive(name = "awssigv4_secret_key", take = 1)]
+    #[describe(redact)]
+    secret_key: core::Secret,
+    #[directive(name = "awssigv4_s3_bucket", handler = ngx_http_awssigv4_commands_set_s3_bucket)]
     s3_bucket: String,
+    #[directive(name = "awssigv4_s3_endpoint", take = 1)]
     s3_endpoint: String,
 }
 
 #[no_mangle]
-static mut ngx_http_awssigv4_commands: [ngx_command_t; 6] = [
-    ngx_command_t {
-        name: ngx_string!("awssigv4"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_enable),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_command_t {
-        name: ngx_string!("awssigv4_access_key"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_access_key),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_command_t {
-        name: ngx_string!("awssigv4_secret_key"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_secret_key),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_command_t {
-        name: ngx_string!("awssigv4_s3_bucket"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_s3_bucket),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_command_t {
-        name: ngx_string!("awssigv4_s3_endpoint"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_s3_endpoint),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_null_command!(),
-];
+static mut ngx_http_awssigv4_commands: [ngx_command_t; 6] = ModuleConfig::COMMANDS;
 
 #[no_mangle]
 static ngx_http_awssigv4_module_ctx: ngx_http_module_t = ngx_http_module_t {
@@ -153,11 +119,7 @@ impl Merge for ModuleConfig {
         }
 
         if self.secret_key.is_empty() {
-            self.secret_key = String::from(if !prev.secret_key.is_empty() {
-                &prev.secret_key
-            } else {
-                ""
-            });
+            self.secret_key = prev.secret_key.clone();
         }
         if self.enable && self.secret_key.is_empty() {
             return Err(MergeConfigError::NoValue);
@@ -185,60 +147,6 @@ impl Merge for ModuleConfig {
     }
 }
 
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_enable(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        let val = (*args.add(1)).to_str();
-
-        // set default value optionally
-        conf.enable = false;
-
-        if val.len() == 2 && val.eq_ignore_ascii_case("on") {
-            conf.enable = true;
-        } else if val.len() == 3 && val.eq_ignore_ascii_case("off") {
-            conf.enable = false;
-        }
-    };
-
-    std::ptr::null_mut()
-}
-
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_access_key(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        conf.access_key = (*args.add(1)).to_string();
-    };
-
-    std::ptr::null_mut()
-}
-
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_secret_key(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        conf.secret_key = (*args.add(1)).to_string();
-    };
-
-    std::ptr::null_mut()
-}
-
 #[no_mangle]
 extern "C" fn ngx_http_awssigv4_commands_set_s3_bucket(
     cf: *mut ngx_conf_t,
@@ -247,104 +155,81 @@ extern "C" fn ngx_http_awssigv4_commands_set_s3_bucket(
 ) -> *mut c_char {
     unsafe {
         let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        conf.s3_bucket = (*args.add(1)).to_string();
+        if let Some(val) = core::Args::from_conf(cf).first() {
+            conf.s3_bucket = val.to_string_lossy().into_owned();
+        }
         if conf.s3_bucket.len() == 1 {
-            println!("Validation failed");
+            ngx_conf_log_error!(cf, "invalid \"s3_bucket\" value \"{}\"", conf.s3_bucket);
             return ngx::core::NGX_CONF_ERROR as _;
         }
     };
     std::ptr::null_mut()
 }
 
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_s3_endpoint(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        conf.s3_endpoint = (*args.add(1)).to_string();
-    };
-
-    std::ptr::null_mut()
-}
-
-http_request_handler!(awssigv4_header_handler, |request: &mut Request| {
-    // get Module Config from request
-    let conf = unsafe { request.get_module_loc_conf::<ModuleConfig>(&*addr_of!(ngx_http_awssigv4_module)) };
-    let conf = conf.unwrap();
-    ngx_log_debug_http!(request, "AWS signature V4 module {}", {
-        if conf.enable {
-            "enabled"
-        } else {
-            "disabled"
-        }
-    });
-    if !conf.enable {
-        return core::Status::NGX_DECLINED;
-    }
+impl UpstreamRequestSigner for ModuleConfig {
+    fn sign(&self, request: &OutboundRequest<'_>, auth: &mut AuthHeaders) -> Result<(), Status> {
+        // TODO: build url properly from the original URL from client
+        let method = request.method();
 
-    // TODO: build url properly from the original URL from client
-    let method = request.method();
-    if !matches!(method, ngx::http::Method::HEAD | ngx::http::Method::GET) {
-        return HTTPStatus::FORBIDDEN.into();
-    }
+        let Some(secret_key) = self.secret_key.expose_str() else {
+            return Err(HTTPStatus::INTERNAL_SERVER_ERROR.into());
+        };
 
-    let datetime = chrono::Utc::now();
-    let uri = match request.unparsed_uri().to_str() {
-        Ok(v) => format!("https://{}.{}{}", conf.s3_bucket, conf.s3_endpoint, v),
-        Err(_) => return core::Status::NGX_DECLINED,
-    };
+        let datetime = chrono::Utc::now();
+        let uri = match request.uri().to_str() {
+            Ok(v) => format!("https://{}.{}{}", self.s3_bucket, self.s3_endpoint, v),
+            Err(_) => return Err(HTTPStatus::BAD_REQUEST.into()),
+        };
 
-    let datetime_now = datetime.format("%Y%m%dT%H%M%SZ");
-    let datetime_now = datetime_now.to_string();
+        let datetime_now = datetime.format("%Y%m%dT%H%M%SZ");
+        let datetime_now = datetime_now.to_string();
 
-    let signature = {
         // NOTE: aws_sign_v4::AwsSign::new() implementation requires a HeaderMap.
-        // Iterate over requests headers_in and copy into HeaderMap
         // Copy only headers that will be used to sign the request
         let mut headers = HeaderMap::new();
-        for (name, value) in request.headers_in_iterator() {
-            match name.to_lowercase().as_str() {
-                "host" => {
-                    headers.insert(http::header::HOST, value.parse().unwrap());
-                }
-                &_ => {}
-            };
+        if let Some(host) = request.header("host") {
+            headers.insert(http::header::HOST, HeaderValue::from_bytes(host.as_bytes()).unwrap());
         }
         headers.insert("X-Amz-Date", datetime_now.parse().unwrap());
-        ngx_log_debug_http!(request, "headers {:?}", headers);
-        ngx_log_debug_http!(request, "method {:?}", method);
-        ngx_log_debug_http!(request, "uri {:?}", uri);
-        ngx_log_debug_http!(request, "datetime_now {:?}", datetime_now);
+        ngx_log_debug_http!(request.request(), "headers {:?}", headers);
+        ngx_log_debug_http!(request.request(), "method {:?}", method);
+        ngx_log_debug_http!(request.request(), "uri {:?}", uri);
+        ngx_log_debug_http!(request.request(), "datetime_now {:?}", datetime_now);
 
-        let s = aws_sign_v4::AwsSign::new(
+        let signature = aws_sign_v4::AwsSign::new(
             method.as_str(),
             &uri,
             &datetime,
             &headers,
             "us-east-1",
-            conf.access_key.as_str(),
-            conf.secret_key.as_str(),
+            self.access_key.as_str(),
+            secret_key,
             "s3",
             "",
-        );
-        s.sign()
-    };
+        )
+        .sign();
 
-    request.add_header_in("authorization", signature.as_str());
-    request.add_header_in("X-Amz-Date", datetime_now.as_str());
+        auth.insert("authorization", signature);
+        auth.insert("X-Amz-Date", datetime_now);
+        Ok(())
+    }
+}
 
-    // done signing, let's print values we have in request.headers_out, request.headers_in
-    for (name, value) in request.headers_out_iterator() {
-        ngx_log_debug_http!(request, "headers_out {}: {}", name, value);
+http_request_handler!(awssigv4_header_handler, |request: &mut Request| {
+    // get Module Config from request
+    let module = unsafe { &*addr_of!(ngx_http_awssigv4_module) };
+    let conf = request.get_module_loc_conf::<ModuleConfig>(module).unwrap();
+    if !conf.enable {
+        return core::Status::NGX_DECLINED;
+    }
+
+    if !matches!(request.method(), ngx::http::Method::HEAD | ngx::http::Method::GET) {
+        return HTTPStatus::FORBIDDEN.into();
     }
-    for (name, value) in request.headers_in_iterator() {
-        ngx_log_debug_http!(request, "headers_in  {}: {}", name, value);
+    if let Err(rc) = request.sign_upstream_request::<ModuleConfig>(module) {
+        return rc;
     }
 
+    // the request is signed right before the content handler runs
     core::Status::NGX_OK
 });
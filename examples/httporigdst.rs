@@ -1,55 +1,47 @@
+use ngx::core::{NgxString, Status};
 use ngx::ffi::{
     in_port_t, nginx_version, ngx_conf_t, ngx_connection_local_sockaddr, ngx_http_add_variable, ngx_http_module_t,
-    ngx_http_request_t, ngx_http_variable_t, ngx_inet_get_port, ngx_int_t, ngx_module_t, ngx_sock_ntop, ngx_str_t,
-    ngx_uint_t, ngx_variable_value_t, sockaddr, sockaddr_storage, INET_ADDRSTRLEN, NGX_HTTP_MODULE,
-    NGX_RS_MODULE_SIGNATURE,
+    ngx_http_request_t, ngx_http_variable_t, ngx_inet_get_port, ngx_int_t, ngx_module_t, ngx_sock_ntop, ngx_uint_t,
+    ngx_variable_value_t, sockaddr, sockaddr_storage, INET_ADDRSTRLEN, NGX_HTTP_MODULE, NGX_RS_MODULE_SIGNATURE,
 };
-use ngx::{core, core::Status, http, http::HTTPModule};
-use ngx::{http_variable_get, ngx_http_null_variable, ngx_log_debug_http, ngx_null_string, ngx_string};
-use std::os::raw::{c_char, c_int, c_void};
+use ngx::{core, http, http::HTTPModule};
+use ngx::{http_variable_get, ngx_http_null_variable, ngx_log_debug_http, ngx_string};
+use std::os::raw::{c_char, c_int};
 use std::ptr::addr_of;
 
 const IPV4_STRLEN: usize = INET_ADDRSTRLEN as usize;
 
 #[derive(Debug)]
 struct NgxHttpOrigDstCtx {
-    orig_dst_addr: ngx_str_t,
-    orig_dst_port: ngx_str_t,
+    orig_dst_addr: NgxString,
+    orig_dst_port: NgxString,
 }
 
 impl Default for NgxHttpOrigDstCtx {
     fn default() -> NgxHttpOrigDstCtx {
         NgxHttpOrigDstCtx {
-            orig_dst_addr: ngx_null_string!(),
-            orig_dst_port: ngx_null_string!(),
+            orig_dst_addr: NgxString::default(),
+            orig_dst_port: NgxString::default(),
         }
     }
 }
 
 impl NgxHttpOrigDstCtx {
     pub fn save(&mut self, addr: &str, port: in_port_t, pool: &mut core::Pool) -> core::Status {
-        let addr_data = pool.alloc(addr.len());
-        if addr_data.is_null() {
+        let (Some(addr), Some(port)) = (
+            NgxString::from_str_in(pool, addr),
+            NgxString::from_str_in(pool, &port.to_string()),
+        ) else {
             return core::Status::NGX_ERROR;
-        }
-        unsafe { libc::memcpy(addr_data, addr.as_ptr() as *const c_void, addr.len()) };
-        self.orig_dst_addr.len = addr.len();
-        self.orig_dst_addr.data = addr_data as *mut u8;
-
-        let port_str = port.to_string();
-        let port_data = pool.alloc(port_str.len());
-        if port_data.is_null() {
-            return core::Status::NGX_ERROR;
-        }
-        unsafe { libc::memcpy(port_data, port_str.as_bytes().as_ptr() as *const c_void, port_str.len()) };
-        self.orig_dst_port.len = port_str.len();
-        self.orig_dst_port.data = port_data as *mut u8;
+        };
+        self.orig_dst_addr = addr;
+        self.orig_dst_port = port;
 
         core::Status::NGX_OK
     }
 
     pub unsafe fn bind_addr(&self, v: *mut ngx_variable_value_t) {
-        if self.orig_dst_addr.len == 0 {
+        if self.orig_dst_addr.is_empty() {
             (*v).set_not_found(1);
             return;
         }
@@ -57,12 +49,12 @@ impl NgxHttpOrigDstCtx {
         (*v).set_valid(1);
         (*v).set_no_cacheable(0);
         (*v).set_not_found(0);
-        (*v).set_len(self.orig_dst_addr.len as u32);
-        (*v).data = self.orig_dst_addr.data;
+        (*v).set_len(self.orig_dst_addr.len() as u32);
+        (*v).data = self.orig_dst_addr.as_ngx_str().data;
     }
 
     pub unsafe fn bind_port(&self, v: *mut ngx_variable_value_t) {
-        if self.orig_dst_port.len == 0 {
+        if self.orig_dst_port.is_empty() {
             (*v).set_not_found(1);
             return;
         }
@@ -70,8 +62,8 @@ impl NgxHttpOrigDstCtx {
         (*v).set_valid(1);
         (*v).set_no_cacheable(0);
         (*v).set_not_found(0);
-        (*v).set_len(self.orig_dst_port.len as u32);
-        (*v).data = self.orig_dst_port.data;
+        (*v).set_len(self.orig_dst_port.len() as u32);
+        (*v).data = self.orig_dst_port.as_ngx_str().data;
     }
 }
 
@@ -234,16 +226,14 @@ http_variable_get!(
             Ok((ip, port)) => {
                 // create context,
                 // set context
-                let new_ctx = request.pool().allocate::<NgxHttpOrigDstCtx>(Default::default());
+                let mut new_ctx = NgxHttpOrigDstCtx::default();
+                ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
+                new_ctx.save(&ip, port, &mut request.pool());
 
-                if new_ctx.is_null() {
+                let Some(new_ctx) = request.insert_module_ctx(&*addr_of!(ngx_http_orig_dst_module), new_ctx) else {
                     return core::Status::NGX_ERROR;
-                }
-
-                ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
-                (*new_ctx).save(&ip, port, &mut request.pool());
-                (*new_ctx).bind_addr(v);
-                request.set_module_ctx(new_ctx as *mut c_void, &*addr_of!(ngx_http_orig_dst_module));
+                };
+                new_ctx.bind_addr(v);
             }
         }
         core::Status::NGX_OK
@@ -273,16 +263,14 @@ http_variable_get!(
             Ok((ip, port)) => {
                 // create context,
                 // set context
-                let new_ctx = request.pool().allocate::<NgxHttpOrigDstCtx>(Default::default());
+                let mut new_ctx = NgxHttpOrigDstCtx::default();
+                ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
+                new_ctx.save(&ip, port, &mut request.pool());
 
-                if new_ctx.is_null() {
+                let Some(new_ctx) = request.insert_module_ctx(&*addr_of!(ngx_http_orig_dst_module), new_ctx) else {
                     return core::Status::NGX_ERROR;
-                }
-
-                ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
-                (*new_ctx).save(&ip, port, &mut request.pool());
-                (*new_ctx).bind_port(v);
-                request.set_module_ctx(new_ctx as *mut c_void, &*addr_of!(ngx_http_orig_dst_module));
+                };
+                new_ctx.bind_port(v);
             }
         }
         core::Status::NGX_OK
@@ -7,14 +7,14 @@
  * to the community at large.
  */
 use ngx::{
-    core::{Pool, Status},
+    core::{ngx_conf_result, Args, ConfError, Pool, Status},
     ffi::{
         nginx_version, ngx_atoi, ngx_command_t, ngx_conf_log_error, ngx_conf_t, ngx_connection_t,
         ngx_event_free_peer_pt, ngx_event_get_peer_pt, ngx_http_module_t, ngx_http_request_t,
         ngx_http_upstream_init_peer_pt, ngx_http_upstream_init_pt, ngx_http_upstream_init_round_robin,
         ngx_http_upstream_module, ngx_http_upstream_srv_conf_t, ngx_http_upstream_t, ngx_int_t, ngx_module_t,
-        ngx_peer_connection_t, ngx_str_t, ngx_uint_t, NGX_CONF_NOARGS, NGX_CONF_TAKE1, NGX_CONF_UNSET, NGX_ERROR,
-        NGX_HTTP_MODULE, NGX_HTTP_UPS_CONF, NGX_LOG_EMERG, NGX_RS_HTTP_SRV_CONF_OFFSET, NGX_RS_MODULE_SIGNATURE,
+        ngx_peer_connection_t, ngx_uint_t, NGX_CONF_NOARGS, NGX_CONF_TAKE1, NGX_CONF_UNSET, NGX_ERROR, NGX_HTTP_MODULE,
+        NGX_HTTP_UPS_CONF, NGX_LOG_EMERG, NGX_RS_HTTP_SRV_CONF_OFFSET, NGX_RS_MODULE_SIGNATURE,
     },
     http::{
         ngx_http_conf_get_module_srv_conf, ngx_http_conf_upstream_srv_conf_immutable,
@@ -28,7 +28,6 @@ use std::{
     mem,
     os::raw::{c_char, c_void},
     ptr::addr_of,
-    slice,
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -296,19 +295,11 @@ unsafe extern "C" fn ngx_http_upstream_commands_set_custom(
 
     let ccf = &mut (*(conf as *mut SrvConfig));
 
-    if (*(*cf).args).nelts == 2 {
-        let value: &[ngx_str_t] = slice::from_raw_parts((*(*cf).args).elts as *const ngx_str_t, (*(*cf).args).nelts);
-        let n = ngx_atoi(value[1].data, value[1].len);
+    if let Some(value) = Args::from_conf(cf).first() {
+        let n = ngx_atoi(value.as_bytes().as_ptr() as *mut u8, value.as_bytes().len());
         if n == (NGX_ERROR as isize) || n == 0 {
-            ngx_conf_log_error(
-                NGX_LOG_EMERG as usize,
-                cf,
-                0,
-                "invalid value \"%V\" in \"%V\" directive".as_bytes().as_ptr() as *const c_char,
-                value[1],
-                &(*cmd).name,
-            );
-            return usize::MAX as *mut c_char;
+            let err = ConfError::new(format!("invalid value \"{}\"", value.to_string_lossy())).with_arg(0);
+            return ngx_conf_result(cf, cmd, Err(err));
         }
         ccf.max = n as u32;
     }
@@ -37,10 +37,14 @@ fn main() -> Result<(), Box<dyn StdError>> {
 /// Generates Rust bindings for NGINX
 fn generate_binding(nginx_build_dir: PathBuf) {
     let autoconf_makefile_path = nginx_build_dir.join("Makefile");
-    let clang_args: Vec<String> = parse_includes_from_makefile(&autoconf_makefile_path)
+    let mut clang_args: Vec<String> = parse_includes_from_makefile(&autoconf_makefile_path)
         .into_iter()
         .map(|path| format!("-I{}", path.to_string_lossy()))
         .collect();
+    // the stream headers are only usable if NGINX is configured with the stream module
+    if env::var_os("CARGO_FEATURE_STREAM").is_some() {
+        clang_args.push("-DNGX_RS_STREAM".to_string());
+    }
 
     let bindings = bindgen::Builder::default()
         // Bindings will not compile on Linux without block listing this item
@@ -10,6 +10,13 @@ const size_t NGX_RS_HTTP_LOC_CONF_OFFSET = NGX_HTTP_LOC_CONF_OFFSET;
 
 const char *NGX_RS_MODULE_SIGNATURE = NGX_MODULE_SIGNATURE;
 
+#ifdef NGX_RS_STREAM
+#include <ngx_stream.h>
+
+const size_t NGX_RS_STREAM_MAIN_CONF_OFFSET = NGX_STREAM_MAIN_CONF_OFFSET;
+const size_t NGX_RS_STREAM_SRV_CONF_OFFSET = NGX_STREAM_SRV_CONF_OFFSET;
+#endif
+
 // `--prefix=` results in not emitting the declaration
 #ifndef NGX_PREFIX
 #define NGX_PREFIX ""
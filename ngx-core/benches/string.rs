@@ -0,0 +1,36 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ngx_core::{HTTPStatus, NgxStr};
+use std::fmt::Write;
+
+const HEADER: &[u8] = b"Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+
+fn ngx_str(c: &mut Criterion) {
+    let mut group = c.benchmark_group("NgxStr");
+
+    group.bench_function("from_raw_parts", |b| {
+        b.iter(|| unsafe { NgxStr::from_raw_parts(black_box(HEADER.as_ptr()), black_box(HEADER.len())) })
+    });
+
+    let s: &NgxStr = HEADER.into();
+    group.bench_function("to_str", |b| b.iter(|| black_box(s).to_str()));
+    group.bench_function("to_string_lossy", |b| b.iter(|| black_box(s).to_string_lossy()));
+
+    let mut out = String::with_capacity(HEADER.len());
+    group.bench_function("display", |b| {
+        b.iter(|| {
+            out.clear();
+            write!(out, "{}", black_box(s))
+        })
+    });
+
+    group.finish();
+}
+
+fn parse(c: &mut Criterion) {
+    c.bench_function("HTTPStatus::from_bytes", |b| {
+        b.iter(|| HTTPStatus::from_bytes(black_box(b"404")))
+    });
+}
+
+criterion_group!(benches, ngx_str, parse);
+criterion_main!(benches);
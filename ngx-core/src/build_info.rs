@@ -0,0 +1,82 @@
+use core::fmt;
+
+/// Identification of a module build, for operators to verify which build is loaded.
+///
+/// The `ngx` crate provides the `ngx_build_info!` macro filling it in at compile time. The
+/// [`Display`](fmt::Display) implementation gives the usual `name/version` form, followed by the
+/// commit if known:
+///
+/// ```
+/// use ngx_core::BuildInfo;
+///
+/// let info = BuildInfo {
+///     name: "ngx_http_auth_module",
+///     version: "0.3.1",
+///     commit: Some("9f2c1e4"),
+///     profile: "release",
+/// };
+/// assert_eq!(info.to_string(), "ngx_http_auth_module/0.3.1 (9f2c1e4)");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// The module name, usually the crate name.
+    pub name: &'static str,
+    /// The module version.
+    pub version: &'static str,
+    /// The commit the module is built from, if known.
+    pub commit: Option<&'static str>,
+    /// The build profile, `debug` or `release`.
+    pub profile: &'static str,
+}
+
+impl BuildInfo {
+    /// Writes the build information as a Prometheus gauge named `metric`, with the value 1 and
+    /// the build details as labels.
+    pub fn write_prometheus<W: fmt::Write>(&self, w: &mut W, metric: &str) -> fmt::Result {
+        writeln!(w, "# TYPE {} gauge", metric)?;
+        writeln!(
+            w,
+            "{}{{name=\"{}\",version=\"{}\",commit=\"{}\",profile=\"{}\"}} 1",
+            metric,
+            self.name,
+            self.version,
+            self.commit.unwrap_or(""),
+            self.profile
+        )
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.name, self.version)?;
+        if let Some(commit) = self.commit {
+            write!(f, " ({})", commit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+
+    #[test]
+    fn test_build_info() {
+        let info = BuildInfo {
+            name: "ngx_http_test_module",
+            version: "1.2.0",
+            commit: None,
+            profile: "debug",
+        };
+        assert_eq!(info.to_string(), "ngx_http_test_module/1.2.0");
+
+        let mut out = String::new();
+        info.write_prometheus(&mut out, "ngx_test_build_info").unwrap();
+        assert_eq!(
+            out,
+            "# TYPE ngx_test_build_info gauge\n\
+             ngx_test_build_info{name=\"ngx_http_test_module\",version=\"1.2.0\",commit=\"\",profile=\"debug\"} 1\n"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::json::write_json_str;
+use crate::NgxStr;
+
+/// A module configuration struct able to describe its effective values.
+///
+/// Implementations list the fields of the configuration, typically after merging, so the values
+/// seen by a location can be dumped for debugging, e.g. from an admin endpoint:
+///
+/// ```
+/// use ngx_core::{ConfDump, DescribeConf};
+///
+/// struct ModuleConfig {
+///     enable: bool,
+///     upstream: Option<String>,
+///     timeouts: Vec<u64>,
+/// }
+///
+/// impl DescribeConf for ModuleConfig {
+///     fn describe(&self, dump: &mut ConfDump) {
+///         dump.field("enable", self.enable)
+///             .field("upstream", self.upstream.as_deref())
+///             .field("timeouts", &self.timeouts[..]);
+///     }
+/// }
+///
+/// let conf = ModuleConfig { enable: true, upstream: None, timeouts: vec![5, 60] };
+/// assert_eq!(conf.dump().to_string(), "enable on;\ntimeouts 5 60;\n");
+/// assert_eq!(conf.dump().to_json(), r#"{"enable":true,"upstream":null,"timeouts":[5,60]}"#);
+/// ```
+///
+/// The implementation can be derived with `#[derive(DescribeConf)]` from `ngx`, which names the
+/// fields after their directives and redacts the ones marked with `#[describe(redact)]`, see
+/// [`RedactedConf`].
+pub trait DescribeConf {
+    /// Adds the configuration fields to `dump`.
+    fn describe(&self, dump: &mut ConfDump);
+
+    /// Returns the description of the configuration.
+    fn dump(&self) -> ConfDump {
+        let mut dump = ConfDump::new();
+        self.describe(&mut dump);
+        dump
+    }
+}
+
+/// A configuration whose description redacts its secrets, so it can be exposed outside of the
+/// error log, e.g. by an admin endpoint with `Request::send_conf_dump` from `ngx`.
+///
+/// This is implemented by `#[derive(DescribeConf)]` for a structure marked
+/// `#[describe(redacted)]`, which asserts that the fields holding secrets are marked with
+/// `#[describe(redact)]` or have the type [`Secret`](crate::Secret). A manual implementation
+/// asserts that [`DescribeConf::describe`] does the same.
+pub trait RedactedConf: DescribeConf {}
+
+/// A value in a [`ConfDump`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfValue {
+    /// The value was not set in the configuration and has no default.
+    Unset,
+    /// A flag.
+    Bool(bool),
+    /// A signed number.
+    Int(i64),
+    /// An unsigned number, such as a size or a duration.
+    UInt(u64),
+    /// A string.
+    Str(String),
+    /// A list of values, e.g. from a multi-value directive.
+    List(Vec<ConfValue>),
+    /// A nested configuration block.
+    Block(ConfDump),
+}
+
+/// Structured description of a configuration, as an ordered list of named values.
+///
+/// The [`Display`](fmt::Display) implementation renders the description in the NGINX
+/// configuration syntax, similarly to `nginx -T`, omitting unset values. [`ConfDump::to_json`]
+/// renders it as a JSON object.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfDump {
+    fields: Vec<(String, ConfValue)>,
+}
+
+impl ConfDump {
+    /// Creates an empty description.
+    pub const fn new() -> Self {
+        ConfDump { fields: Vec::new() }
+    }
+
+    /// Adds a named value.
+    pub fn field<V: Into<ConfValue>>(&mut self, name: &str, value: V) -> &mut Self {
+        self.fields.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Adds a nested block holding the description of `conf`.
+    pub fn block<C: DescribeConf + ?Sized>(&mut self, name: &str, conf: &C) -> &mut Self {
+        self.field(name, ConfValue::Block(conf.dump()))
+    }
+
+    /// Returns an iterator over the named values, in insertion order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &ConfValue)> {
+        self.fields.iter().map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// Returns the value named `name`.
+    pub fn get(&self, name: &str) -> Option<&ConfValue> {
+        self.fields().find(|(n, _)| *n == name).map(|(_, value)| value)
+    }
+
+    /// Returns `true` if the description has no values.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Renders the description as a compact JSON object.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        // writing to a String never fails
+        let _ = self.write_json(&mut out);
+        out
+    }
+
+    fn write_json<W: Write>(&self, out: &mut W) -> fmt::Result {
+        out.write_char('{')?;
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            write_json_str(out, name)?;
+            out.write_char(':')?;
+            value.write_json(out)?;
+        }
+        out.write_char('}')
+    }
+
+    fn write_text(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        for (name, value) in &self.fields {
+            match value {
+                ConfValue::Unset => continue,
+                ConfValue::Block(block) => {
+                    writeln!(f, "{:indent$}{} {{", "", name, indent = depth * 4)?;
+                    block.write_text(f, depth + 1)?;
+                    writeln!(f, "{:indent$}}}", "", indent = depth * 4)?;
+                }
+                value => writeln!(f, "{:indent$}{} {};", "", name, value, indent = depth * 4)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ConfDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_text(f, 0)
+    }
+}
+
+impl ConfValue {
+    fn write_json<W: Write>(&self, out: &mut W) -> fmt::Result {
+        match self {
+            ConfValue::Unset => out.write_str("null"),
+            ConfValue::Bool(value) => write!(out, "{}", value),
+            ConfValue::Int(value) => write!(out, "{}", value),
+            ConfValue::UInt(value) => write!(out, "{}", value),
+            ConfValue::Str(value) => write_json_str(out, value),
+            ConfValue::List(values) => {
+                out.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.write_char(',')?;
+                    }
+                    value.write_json(out)?;
+                }
+                out.write_char(']')
+            }
+            ConfValue::Block(block) => block.write_json(out),
+        }
+    }
+}
+
+/// Formats the value as directive arguments.
+impl fmt::Display for ConfValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfValue::Unset => Ok(()),
+            ConfValue::Bool(value) => f.write_str(if *value { "on" } else { "off" }),
+            ConfValue::Int(value) => write!(f, "{}", value),
+            ConfValue::UInt(value) => write!(f, "{}", value),
+            ConfValue::Str(value) => write_conf_str(f, value),
+            ConfValue::List(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+            ConfValue::Block(_) => f.write_str("{...}"),
+        }
+    }
+}
+
+/// Writes a configuration argument, quoting it if it would not parse as a single token.
+fn write_conf_str<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    let needs_quotes = s.is_empty()
+        || s.chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';' | '{' | '}' | '#' | '$'));
+    if !needs_quotes {
+        return out.write_str(s);
+    }
+
+    out.write_char('"')?;
+    for c in s.chars() {
+        if matches!(c, '"' | '\\') {
+            out.write_char('\\')?;
+        }
+        out.write_char(c)?;
+    }
+    out.write_char('"')
+}
+
+impl From<bool> for ConfValue {
+    fn from(value: bool) -> Self {
+        ConfValue::Bool(value)
+    }
+}
+
+macro_rules! impl_from_int {
+    ($variant: ident, $as: ty, $($ty: ty),+) => {
+        $(
+            impl From<$ty> for ConfValue {
+                fn from(value: $ty) -> Self {
+                    ConfValue::$variant(value as $as)
+                }
+            }
+        )+
+    };
+}
+
+impl_from_int!(Int, i64, i8, i16, i32, i64, isize);
+impl_from_int!(UInt, u64, u8, u16, u32, u64, usize);
+
+macro_rules! impl_from_ref {
+    ($($ty: ty),+) => {
+        $(
+            impl From<&$ty> for ConfValue {
+                fn from(value: &$ty) -> Self {
+                    (*value).into()
+                }
+            }
+        )+
+    };
+}
+
+// the fields described by `#[derive(DescribeConf)]` are passed by reference
+impl_from_ref!(bool, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl From<&str> for ConfValue {
+    fn from(value: &str) -> Self {
+        ConfValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for ConfValue {
+    fn from(value: String) -> Self {
+        ConfValue::Str(value)
+    }
+}
+
+impl From<&String> for ConfValue {
+    fn from(value: &String) -> Self {
+        ConfValue::Str(value.clone())
+    }
+}
+
+impl From<&NgxStr> for ConfValue {
+    fn from(value: &NgxStr) -> Self {
+        ConfValue::Str(value.to_string_lossy().into_owned())
+    }
+}
+
+impl<T: Into<ConfValue>> From<Option<T>> for ConfValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(ConfValue::Unset, Into::into)
+    }
+}
+
+impl<'a, T> From<&'a Option<T>> for ConfValue
+where
+    &'a T: Into<ConfValue>,
+{
+    fn from(value: &'a Option<T>) -> Self {
+        value.as_ref().map_or(ConfValue::Unset, Into::into)
+    }
+}
+
+impl<'a, T> From<&'a Vec<T>> for ConfValue
+where
+    &'a T: Into<ConfValue>,
+{
+    fn from(values: &'a Vec<T>) -> Self {
+        ConfValue::List(values.iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Clone + Into<ConfValue>> From<&[T]> for ConfValue {
+    fn from(values: &[T]) -> Self {
+        ConfValue::List(values.iter().cloned().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<ConfValue>> From<Vec<T>> for ConfValue {
+    fn from(values: Vec<T>) -> Self {
+        ConfValue::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<ConfDump> for ConfValue {
+    fn from(value: ConfDump) -> Self {
+        ConfValue::Block(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_conf_dump() {
+        let mut inner = ConfDump::new();
+        inner.field("pass", "http://backend").field("timeout", 30u64);
+
+        let mut dump = ConfDump::new();
+        dump.field("enable", false)
+            .field("name", "a \"quoted\" value")
+            .field("limit", None::<u64>)
+            .field("levels", vec![-1i64, 2])
+            .field("upstream", inner);
+
+        assert_eq!(
+            dump.to_string(),
+            "enable off;\nname \"a \\\"quoted\\\" value\";\nlevels -1 2;\nupstream {\n    pass http://backend;\n    timeout 30;\n}\n"
+        );
+        assert_eq!(
+            dump.to_json(),
+            r#"{"enable":false,"name":"a \"quoted\" value","limit":null,"levels":[-1,2],"upstream":{"pass":"http://backend","timeout":30}}"#
+        );
+        assert_eq!(dump.get("limit"), Some(&ConfValue::Unset));
+        assert_eq!(ConfDump::new().to_json(), "{}");
+    }
+}
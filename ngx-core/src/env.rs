@@ -0,0 +1,112 @@
+use alloc::borrow::Cow;
+use alloc::string::String;
+use core::fmt;
+
+/// Error returned by [`expand_env`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EnvExpandError {
+    /// The variable is not set.
+    Missing(String),
+    /// A `${` placeholder without a closing `}`.
+    Unterminated,
+    /// The name of the variable is empty or contains characters other than ASCII letters,
+    /// digits and underscores, or starts with a digit.
+    InvalidName(String),
+}
+
+impl fmt::Display for EnvExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvExpandError::Missing(name) => write!(f, "environment variable \"{name}\" is not set"),
+            EnvExpandError::Unterminated => f.write_str("unterminated \"${\" placeholder"),
+            EnvExpandError::InvalidName(name) => write!(f, "invalid environment variable name \"{name}\""),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EnvExpandError {}
+
+/// Replaces the `${NAME}` placeholders in `input` with the values returned by `lookup`, e.g.
+/// `std::env::var(name).ok()`.
+///
+/// A `$` not followed by `{` is kept as is, and `$${` is a literal `${`. The input is borrowed
+/// when it has no placeholders.
+///
+/// ```
+/// # use ngx_core::{expand_env, EnvExpandError};
+/// let lookup = |name: &str| (name == "REGION").then(|| "eu-west-1".into());
+/// assert_eq!(expand_env("s3.${REGION}.amazonaws.com", lookup).unwrap(), "s3.eu-west-1.amazonaws.com");
+/// assert_eq!(expand_env("$host", lookup).unwrap(), "$host");
+/// assert_eq!(expand_env("${BUCKET}", lookup), Err(EnvExpandError::Missing("BUCKET".into())));
+/// ```
+pub fn expand_env<F>(input: &str, mut lookup: F) -> Result<Cow<'_, str>, EnvExpandError>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    if !input.contains("${") {
+        return Ok(Cow::Borrowed(input));
+    }
+
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if let Some(tail) = rest.strip_prefix("$${") {
+            out.push_str("${");
+            rest = tail;
+            continue;
+        }
+        let Some(tail) = rest.strip_prefix("${") else {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let end = tail.find('}').ok_or(EnvExpandError::Unterminated)?;
+        let name = &tail[..end];
+        if !is_valid_name(name) {
+            return Err(EnvExpandError::InvalidName(name.into()));
+        }
+        let value = lookup(name).ok_or_else(|| EnvExpandError::Missing(name.into()))?;
+        out.push_str(&value);
+        rest = &tail[end + 1..];
+    }
+    out.push_str(rest);
+
+    Ok(Cow::Owned(out))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    matches!(bytes.next(), Some(b'A'..=b'Z' | b'a'..=b'z' | b'_'))
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_env() {
+        let lookup = |name: &str| match name {
+            "HOST" => Some("example.com".into()),
+            "PORT" => Some("8080".into()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        let expand = |input| expand_env(input, lookup);
+
+        assert!(matches!(expand("plain $arg"), Ok(Cow::Borrowed("plain $arg"))));
+        assert_eq!(expand("${HOST}:${PORT}").unwrap(), "example.com:8080");
+        assert_eq!(expand("a${EMPTY}b$").unwrap(), "ab$");
+        assert_eq!(expand("$${HOST} ${HOST}").unwrap(), "${HOST} example.com");
+        assert_eq!(expand("${MISSING}"), Err(EnvExpandError::Missing("MISSING".into())));
+        assert_eq!(expand("${HOST"), Err(EnvExpandError::Unterminated));
+        assert_eq!(expand("${}"), Err(EnvExpandError::InvalidName("".into())));
+        assert_eq!(expand("${1A}"), Err(EnvExpandError::InvalidName("1A".into())));
+        assert_eq!(expand("${A-B}"), Err(EnvExpandError::InvalidName("A-B".into())));
+    }
+}
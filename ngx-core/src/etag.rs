@@ -0,0 +1,332 @@
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+use sha2::{Digest, Sha256};
+
+/// How the `ETag` of a response is derived, usually set with a directive.
+///
+/// The cheap policies do not read the response body, or only checksum it, while the strong
+/// policy hashes the body with SHA-256. The policy also decides whether the tag is [weak or
+/// strong](Etag::is_weak): a CRC-32 checksum may collide for different bodies, so it can only
+/// vouch for semantically equivalent responses, and its tags are weak.
+///
+/// ```
+/// use ngx_core::EtagPolicy;
+///
+/// let policy: EtagPolicy = "sha256".parse().unwrap();
+/// assert!(policy.reads_body() && policy.is_strong());
+/// assert_eq!(policy.to_string(), "sha256");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EtagPolicy {
+    /// No `ETag` is set.
+    Off,
+    /// A strong tag built from the length and the modification time of the response, in the
+    /// same format as the `etag` directive of NGINX. Written as `mtime`.
+    #[default]
+    LengthMtime,
+    /// A weak tag built from the length and the CRC-32 checksum of the response body. Written
+    /// as `crc32`.
+    Crc32,
+    /// A strong tag built from the SHA-256 hash of the response body. Written as `sha256`.
+    Sha256,
+}
+
+/// An error returned when parsing an unknown [`EtagPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidEtagPolicy;
+
+impl fmt::Display for InvalidEtagPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid ETag policy, it must be \"off\", \"mtime\", \"crc32\" or \"sha256\"")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidEtagPolicy {}
+
+impl EtagPolicy {
+    /// Returns `true` if the tag is computed from the response body.
+    pub fn reads_body(&self) -> bool {
+        matches!(self, EtagPolicy::Crc32 | EtagPolicy::Sha256)
+    }
+
+    /// Returns `true` if the policy produces strong tags.
+    pub fn is_strong(&self) -> bool {
+        matches!(self, EtagPolicy::LengthMtime | EtagPolicy::Sha256)
+    }
+
+    /// Returns the tag of a response with the body `body`, modified at `mtime` in seconds since
+    /// the Unix epoch.
+    ///
+    /// Returns `None` with [`EtagPolicy::Off`], or with [`EtagPolicy::LengthMtime`] if the
+    /// modification time is unknown.
+    pub fn etag(&self, body: &[u8], mtime: Option<i64>) -> Option<Etag> {
+        match self {
+            EtagPolicy::Off => None,
+            EtagPolicy::LengthMtime => mtime.map(|mtime| Etag::from_length_mtime(body.len() as u64, mtime)),
+            EtagPolicy::Crc32 | EtagPolicy::Sha256 => EtagHasher::new(*self).map(|mut hasher| {
+                hasher.update(body);
+                hasher.finish()
+            }),
+        }
+    }
+}
+
+impl FromStr for EtagPolicy {
+    type Err = InvalidEtagPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(EtagPolicy::Off),
+            "mtime" => Ok(EtagPolicy::LengthMtime),
+            "crc32" => Ok(EtagPolicy::Crc32),
+            "sha256" => Ok(EtagPolicy::Sha256),
+            _ => Err(InvalidEtagPolicy),
+        }
+    }
+}
+
+impl fmt::Display for EtagPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EtagPolicy::Off => "off",
+            EtagPolicy::LengthMtime => "mtime",
+            EtagPolicy::Crc32 => "crc32",
+            EtagPolicy::Sha256 => "sha256",
+        })
+    }
+}
+
+/// An entity tag, as sent in the `ETag` header and compared with `If-None-Match` and `If-Match`.
+///
+/// The tag is formatted with its quotes, prefixed with `W/` if it is weak:
+///
+/// ```
+/// use ngx_core::Etag;
+///
+/// let etag = Etag::from_length_mtime(1234, 1_700_000_000);
+/// assert_eq!(etag.to_string(), "\"6553f100-4d2\"");
+/// assert_eq!(etag.into_weak().to_string(), "W/\"6553f100-4d2\"");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Etag {
+    opaque: String,
+    weak: bool,
+}
+
+impl Etag {
+    /// Creates a strong tag with the value `opaque`, without quotes.
+    ///
+    /// Returns `None` if the value contains quotes, spaces or control characters.
+    pub fn strong(opaque: &str) -> Option<Self> {
+        Self::new(opaque, false)
+    }
+
+    /// Creates a weak tag with the value `opaque`, without quotes and `W/` prefix.
+    ///
+    /// Returns `None` if the value contains quotes, spaces or control characters.
+    pub fn weak(opaque: &str) -> Option<Self> {
+        Self::new(opaque, true)
+    }
+
+    fn new(opaque: &str, weak: bool) -> Option<Self> {
+        // etagc = %x21 / %x23-7E / obs-text
+        if opaque.bytes().any(|b| b <= b' ' || b == b'"' || b == 0x7f) {
+            return None;
+        }
+        Some(Etag {
+            opaque: opaque.into(),
+            weak,
+        })
+    }
+
+    /// Creates the strong tag of a response of `length` bytes modified at `mtime`, in seconds
+    /// since the Unix epoch, as NGINX does for static files.
+    pub fn from_length_mtime(length: u64, mtime: i64) -> Self {
+        Etag {
+            opaque: format!("{:x}-{:x}", mtime, length),
+            weak: false,
+        }
+    }
+
+    /// Parses a tag with its quotes, e.g. the `ETag` header of an upstream response.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (weak, s) = match s.strip_prefix("W/") {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let opaque = s.strip_prefix('"')?.strip_suffix('"')?;
+        Self::new(opaque, weak)
+    }
+
+    /// Returns the value of the tag, without quotes and `W/` prefix.
+    pub fn opaque(&self) -> &str {
+        &self.opaque
+    }
+
+    /// Returns `true` if the tag is weak: it only identifies semantically equivalent responses,
+    /// e.g. after compression, rather than byte-for-byte identical ones.
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Returns the weak version of the tag, e.g. when a filter transforms the response body.
+    pub fn into_weak(mut self) -> Self {
+        self.weak = true;
+        self
+    }
+
+    /// Compares the tags with the strong comparison of [RFC 9110], used for `If-Match`: both
+    /// tags must be strong and have the same value.
+    ///
+    /// [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#section-8.8.3.2
+    pub fn strong_eq(&self, other: &Etag) -> bool {
+        !self.weak && !other.weak && self.opaque == other.opaque
+    }
+
+    /// Compares the tags with the weak comparison of [RFC 9110], used for `If-None-Match`: the
+    /// tags must have the same value.
+    ///
+    /// [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#section-8.8.3.2
+    pub fn weak_eq(&self, other: &Etag) -> bool {
+        self.opaque == other.opaque
+    }
+}
+
+impl fmt::Display for Etag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.opaque)
+    }
+}
+
+/// Incremental computation of the [`Etag`] of a response body with a body-reading
+/// [`EtagPolicy`].
+///
+/// ```
+/// use ngx_core::{EtagHasher, EtagPolicy};
+///
+/// let mut hasher = EtagHasher::new(EtagPolicy::Crc32).unwrap();
+/// hasher.update(b"hello, ");
+/// hasher.update(b"world");
+/// assert_eq!(hasher.finish().to_string(), "W/\"c-ffab723a\"");
+/// ```
+#[derive(Clone, Debug)]
+pub struct EtagHasher {
+    length: u64,
+    state: HasherState,
+}
+
+#[derive(Clone, Debug)]
+enum HasherState {
+    Crc32(crc32fast::Hasher),
+    Sha256(Sha256),
+}
+
+impl EtagHasher {
+    /// Creates a hasher for `policy`.
+    ///
+    /// Returns `None` if the policy does not [read the body](EtagPolicy::reads_body).
+    pub fn new(policy: EtagPolicy) -> Option<Self> {
+        let state = match policy {
+            EtagPolicy::Crc32 => HasherState::Crc32(crc32fast::Hasher::new()),
+            EtagPolicy::Sha256 => HasherState::Sha256(Sha256::new()),
+            EtagPolicy::Off | EtagPolicy::LengthMtime => return None,
+        };
+        Some(EtagHasher { length: 0, state })
+    }
+
+    /// Adds the next part of the body.
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.length += bytes.len() as u64;
+        match &mut self.state {
+            HasherState::Crc32(crc) => crc.update(bytes),
+            HasherState::Sha256(sha) => sha.update(bytes),
+        }
+    }
+
+    /// Returns the number of body bytes added so far.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the tag of the body.
+    pub fn finish(self) -> Etag {
+        match self.state {
+            HasherState::Crc32(crc) => Etag {
+                opaque: format!("{:x}-{:08x}", self.length, crc.finalize()),
+                weak: true,
+            },
+            HasherState::Sha256(sha) => {
+                let mut opaque = String::with_capacity(64);
+                for b in sha.finalize() {
+                    opaque.push_str(&format!("{:02x}", b));
+                }
+                Etag { opaque, weak: false }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_etag() {
+        let mut hasher = EtagHasher::new(EtagPolicy::Sha256).unwrap();
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        let etag = hasher.finish();
+        assert_eq!(
+            etag.opaque(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(!etag.is_weak());
+
+        let mut hasher = EtagHasher::new(EtagPolicy::Sha256).unwrap();
+        hasher.update(&[b'a'; 56]);
+        assert_eq!(
+            hasher.finish().opaque(),
+            "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a"
+        );
+
+        let weak = Etag::parse("W/\"abc\"").unwrap();
+        let strong = Etag::strong("abc").unwrap();
+        assert!(weak.weak_eq(&strong) && !weak.strong_eq(&strong));
+        assert!(strong.strong_eq(&Etag::parse("\"abc\"").unwrap()));
+        assert_eq!(Etag::strong("a\"b"), None);
+        assert_eq!(Etag::parse("abc"), None);
+        assert!(EtagHasher::new(EtagPolicy::LengthMtime).is_none());
+    }
+
+    #[test]
+    fn test_etag_crc32() {
+        let mut hasher = EtagHasher::new(EtagPolicy::Crc32).unwrap();
+        hasher.update(b"1234");
+        hasher.update(b"56789");
+        assert_eq!(hasher.length(), 9);
+        let etag = hasher.finish();
+        assert_eq!(etag.to_string(), "W/\"9-cbf43926\"");
+        assert!(etag.is_weak());
+
+        let etag = EtagPolicy::Crc32.etag(b"", None).unwrap();
+        assert_eq!(etag.opaque(), "0-00000000");
+    }
+
+    #[test]
+    fn test_policy_etag() {
+        assert_eq!(EtagPolicy::Off.etag(b"abc", Some(0)), None);
+        let etag = EtagPolicy::LengthMtime.etag(b"abc", Some(0x5f5e100)).unwrap();
+        assert_eq!(etag.to_string(), "\"5f5e100-3\"");
+        assert!(!etag.is_weak());
+        // no tag without a known modification time
+        assert_eq!(EtagPolicy::LengthMtime.etag(b"abc", None), None);
+    }
+}
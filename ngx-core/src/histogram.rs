@@ -0,0 +1,288 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// Maximum number of bucket boundaries of a histogram.
+pub const MAX_HISTOGRAM_BUCKETS: usize = 64;
+
+/// The upper bounds of the buckets of a latency histogram, in milliseconds.
+///
+/// Bounds are strictly increasing; an implicit last bucket counts the values above the largest
+/// bound, exported as `+Inf`. Bounds are usually parsed from a directive argument, written as a
+/// comma-separated list of NGINX time intervals:
+///
+/// ```
+/// use ngx_core::HistogramBuckets;
+///
+/// let buckets: HistogramBuckets = "5ms,50ms,250ms,1s,1m30s".parse().unwrap();
+/// assert_eq!(buckets.bounds(), &[5, 50, 250, 1000, 90_000]);
+/// assert_eq!(buckets.index(50), 1);
+/// assert_eq!(buckets.index(100_000), 5);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistogramBuckets {
+    bounds: Vec<u64>,
+}
+
+/// An error returned when bucket boundaries are not valid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistogramBucketsError {
+    /// No boundary is given.
+    Empty,
+    /// More than [`MAX_HISTOGRAM_BUCKETS`] boundaries are given.
+    TooMany,
+    /// A boundary is not a valid time interval.
+    InvalidValue,
+    /// The boundaries are not strictly increasing.
+    NotIncreasing,
+}
+
+impl fmt::Display for HistogramBucketsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistogramBucketsError::Empty => f.write_str("no histogram buckets"),
+            HistogramBucketsError::TooMany => write!(f, "more than {} histogram buckets", MAX_HISTOGRAM_BUCKETS),
+            HistogramBucketsError::InvalidValue => f.write_str("invalid histogram bucket value"),
+            HistogramBucketsError::NotIncreasing => f.write_str("histogram buckets are not increasing"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HistogramBucketsError {}
+
+impl HistogramBuckets {
+    /// Creates buckets with the upper bounds `bounds`, in milliseconds.
+    pub fn new(bounds: Vec<u64>) -> Result<Self, HistogramBucketsError> {
+        if bounds.is_empty() {
+            return Err(HistogramBucketsError::Empty);
+        }
+        if bounds.len() > MAX_HISTOGRAM_BUCKETS {
+            return Err(HistogramBucketsError::TooMany);
+        }
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(HistogramBucketsError::NotIncreasing);
+        }
+        Ok(HistogramBuckets { bounds })
+    }
+
+    /// Returns the upper bounds of the buckets, in milliseconds.
+    pub fn bounds(&self) -> &[u64] {
+        &self.bounds
+    }
+
+    /// Returns the number of buckets, including the `+Inf` bucket.
+    pub fn len(&self) -> usize {
+        self.bounds.len() + 1
+    }
+
+    /// Always returns `false`: there is at least one bound and the `+Inf` bucket.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns the index of the bucket counting `value`, in milliseconds.
+    pub fn index(&self, value: u64) -> usize {
+        self.bounds.partition_point(|bound| *bound < value)
+    }
+}
+
+impl FromStr for HistogramBuckets {
+    type Err = HistogramBucketsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bounds = s
+            .split(',')
+            .map(parse_msec)
+            .collect::<Option<Vec<u64>>>()
+            .ok_or(HistogramBucketsError::InvalidValue)?;
+        HistogramBuckets::new(bounds)
+    }
+}
+
+/// Parses an NGINX time interval, e.g. `250ms` or `1m30s`, into milliseconds.
+///
+/// A number without a unit is a number of seconds.
+fn parse_msec(s: &str) -> Option<u64> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse::<u64>().ok()?.checked_mul(1000);
+    }
+
+    let mut rest = s.as_bytes();
+    let mut total: u64 = 0;
+
+    while !rest.is_empty() {
+        let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let value: u64 = core::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+        rest = &rest[digits..];
+
+        let (scale, unit_len) = match rest {
+            [b'm', b's', ..] => (1, 2),
+            [b'y', ..] => (365 * 24 * 60 * 60 * 1000, 1),
+            [b'M', ..] => (30 * 24 * 60 * 60 * 1000, 1),
+            [b'w', ..] => (7 * 24 * 60 * 60 * 1000, 1),
+            [b'd', ..] => (24 * 60 * 60 * 1000, 1),
+            [b'h', ..] => (60 * 60 * 1000, 1),
+            [b'm', ..] => (60 * 1000, 1),
+            [b's', ..] => (1000, 1),
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total = total.checked_add(value.checked_mul(scale)?)?;
+    }
+
+    Some(total)
+}
+
+/// The counters of a histogram at a point in time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    counts: Vec<u64>,
+    sum: u64,
+}
+
+impl HistogramSnapshot {
+    /// Creates a snapshot from the count of each bucket, not cumulative, and the sum of the
+    /// observed values in milliseconds.
+    pub fn new(counts: Vec<u64>, sum: u64) -> Self {
+        HistogramSnapshot { counts, sum }
+    }
+
+    /// Returns the count of each bucket, not cumulative.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the number of observed values.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the sum of the observed values, in milliseconds.
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// Writes the histogram in the Prometheus text exposition format, with values in seconds.
+    ///
+    /// `labels` are added to every sample, e.g. `server="example.com"`, and may be empty.
+    pub fn write_prometheus<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        buckets: &HistogramBuckets,
+        name: &str,
+        labels: &str,
+    ) -> fmt::Result {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+
+        writeln!(w, "# TYPE {} histogram", name)?;
+        for (i, bound) in buckets.bounds().iter().enumerate() {
+            cumulative += self.counts.get(i).copied().unwrap_or(0);
+            writeln!(
+                w,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name,
+                labels,
+                sep,
+                Seconds(*bound),
+                cumulative
+            )?;
+        }
+        writeln!(w, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, sep, self.count())?;
+
+        if labels.is_empty() {
+            writeln!(w, "{}_sum {}", name, Seconds(self.sum))?;
+            writeln!(w, "{}_count {}", name, self.count())
+        } else {
+            writeln!(w, "{}_sum{{{}}} {}", name, labels, Seconds(self.sum))?;
+            writeln!(w, "{}_count{{{}}} {}", name, labels, self.count())
+        }
+    }
+}
+
+/// Formats milliseconds as seconds, without trailing zeros.
+struct Seconds(u64);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (secs, msecs) = (self.0 / 1000, self.0 % 1000);
+        match msecs {
+            0 => write!(f, "{}", secs),
+            _ if msecs % 100 == 0 => write!(f, "{}.{}", secs, msecs / 100),
+            _ if msecs % 10 == 0 => write!(f, "{}.{:02}", secs, msecs / 10),
+            _ => write!(f, "{}.{:03}", secs, msecs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use alloc::vec;
+
+    #[test]
+    fn test_parse_buckets() {
+        let buckets: HistogramBuckets = "10ms, 100ms,1s,2,1h".parse().unwrap();
+        assert_eq!(buckets.bounds(), &[10, 100, 1000, 2000, 3_600_000]);
+        assert_eq!(buckets.len(), 6);
+
+        assert_eq!("".parse::<HistogramBuckets>(), Err(HistogramBucketsError::InvalidValue));
+        assert_eq!(
+            "5ms,x".parse::<HistogramBuckets>(),
+            Err(HistogramBucketsError::InvalidValue)
+        );
+        assert_eq!(
+            "5ms,5ms".parse::<HistogramBuckets>(),
+            Err(HistogramBucketsError::NotIncreasing)
+        );
+        assert_eq!(
+            "1s,500ms".parse::<HistogramBuckets>(),
+            Err(HistogramBucketsError::NotIncreasing)
+        );
+        assert_eq!(HistogramBuckets::new(vec![]), Err(HistogramBucketsError::Empty));
+        assert_eq!(
+            HistogramBuckets::new((1..=65).collect()),
+            Err(HistogramBucketsError::TooMany)
+        );
+    }
+
+    #[test]
+    fn test_bucket_index() {
+        let buckets = HistogramBuckets::new(vec![10, 100]).unwrap();
+        assert_eq!(buckets.index(0), 0);
+        assert_eq!(buckets.index(10), 0);
+        assert_eq!(buckets.index(11), 1);
+        assert_eq!(buckets.index(100), 1);
+        assert_eq!(buckets.index(101), 2);
+    }
+
+    #[test]
+    fn test_write_prometheus() {
+        let buckets = HistogramBuckets::new(vec![5, 250, 1000]).unwrap();
+        let snapshot = HistogramSnapshot::new(vec![2, 1, 0, 1], 4_123);
+
+        let mut out = String::new();
+        snapshot
+            .write_prometheus(&mut out, &buckets, "request_seconds", "server=\"a\"")
+            .unwrap();
+        assert_eq!(
+            out,
+            "# TYPE request_seconds histogram\n\
+             request_seconds_bucket{server=\"a\",le=\"0.005\"} 2\n\
+             request_seconds_bucket{server=\"a\",le=\"0.25\"} 3\n\
+             request_seconds_bucket{server=\"a\",le=\"1\"} 3\n\
+             request_seconds_bucket{server=\"a\",le=\"+Inf\"} 4\n\
+             request_seconds_sum{server=\"a\"} 4.123\n\
+             request_seconds_count{server=\"a\"} 4\n"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The stored outcome of a request made with an `Idempotency-Key`, replayed for retries of the
+/// request.
+///
+/// The record holds the response status and content type, a hash of the response body, and the
+/// body itself if it was small enough to be kept. The request fingerprint detects a key reused
+/// for a different request.
+///
+/// ```
+/// use ngx_core::{request_fingerprint, BodyHash, IdempotencyRecord};
+///
+/// let mut body = BodyHash::new();
+/// body.update(b"{\"amount\":100}");
+/// let record = IdempotencyRecord {
+///     fingerprint: request_fingerprint(b"POST", b"/payments", body.finish()),
+///     status: 201,
+///     content_type: "application/json".into(),
+///     body_hash: 0x1234,
+///     body: Some(b"{\"id\":42}".to_vec()),
+/// };
+/// assert_eq!(IdempotencyRecord::decode(&record.encode()), Some(record));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdempotencyRecord {
+    /// The fingerprint of the original request, see [`request_fingerprint`].
+    pub fingerprint: u64,
+    /// The response status.
+    pub status: u16,
+    /// The response `Content-Type`, empty if none.
+    pub content_type: String,
+    /// The FNV-1a hash of the response body, see [`BodyHash`].
+    pub body_hash: u64,
+    /// The response body, or `None` if it was too large to be stored.
+    pub body: Option<Vec<u8>>,
+}
+
+impl IdempotencyRecord {
+    /// Serializes the record.
+    pub fn encode(&self) -> Vec<u8> {
+        let body = self.body.as_deref().unwrap_or_default();
+        let mut out = Vec::with_capacity(27 + self.content_type.len() + body.len());
+        out.extend_from_slice(&self.fingerprint.to_le_bytes());
+        out.extend_from_slice(&self.status.to_le_bytes());
+        out.extend_from_slice(&self.body_hash.to_le_bytes());
+        out.extend_from_slice(&(self.content_type.len() as u32).to_le_bytes());
+        out.extend_from_slice(self.content_type.as_bytes());
+        out.push(self.body.is_some() as u8);
+        out.extend_from_slice(body);
+        out
+    }
+
+    /// Restores a record serialized with [`IdempotencyRecord::encode`], or returns `None` if
+    /// `bytes` is not a valid record.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (fingerprint, rest) = split_array::<8>(bytes)?;
+        let (status, rest) = split_array::<2>(rest)?;
+        let (body_hash, rest) = split_array::<8>(rest)?;
+        let (len, rest) = split_array::<4>(rest)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if rest.len() < len + 1 {
+            return None;
+        }
+        let content_type = core::str::from_utf8(&rest[..len]).ok()?.into();
+        let body = match rest[len] {
+            0 if rest.len() == len + 1 => None,
+            0 => return None,
+            1 => Some(rest[len + 1..].to_vec()),
+            _ => return None,
+        };
+
+        Some(IdempotencyRecord {
+            fingerprint: u64::from_le_bytes(fingerprint),
+            status: u16::from_le_bytes(status),
+            content_type,
+            body_hash: u64::from_le_bytes(body_hash),
+            body,
+        })
+    }
+}
+
+fn split_array<const N: usize>(bytes: &[u8]) -> Option<([u8; N], &[u8])> {
+    if bytes.len() < N {
+        return None;
+    }
+    let (head, rest) = bytes.split_at(N);
+    let mut array = [0; N];
+    array.copy_from_slice(head);
+    Some((array, rest))
+}
+
+/// Returns the fingerprint of a request from its method, URI and the [`BodyHash`] of its body,
+/// to detect an idempotency key reused for a different request.
+pub fn request_fingerprint(method: &[u8], uri: &[u8], body_hash: u64) -> u64 {
+    let mut hash = BodyHash::new();
+    hash.update(method);
+    hash.update(b" ");
+    hash.update(uri);
+    hash.update(b" ");
+    hash.update(&body_hash.to_le_bytes());
+    hash.finish()
+}
+
+/// An incremental FNV-1a 64-bit hash of a request or response body.
+///
+/// The hash detects changes of a body; it is not a cryptographic digest.
+#[derive(Clone, Copy, Debug)]
+pub struct BodyHash(u64);
+
+impl Default for BodyHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BodyHash {
+    /// Creates the hash of an empty body.
+    pub const fn new() -> Self {
+        BodyHash(0xcbf2_9ce4_8422_2325)
+    }
+
+    /// Adds the next part of the body.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// Returns the hash of the body so far.
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_idempotency_record() {
+        let mut record = IdempotencyRecord {
+            fingerprint: request_fingerprint(b"POST", b"/a", BodyHash::new().finish()),
+            status: 500,
+            content_type: String::new(),
+            body_hash: BodyHash::new().finish(),
+            body: None,
+        };
+        assert_ne!(
+            record.fingerprint,
+            request_fingerprint(b"POST", b"/b", BodyHash::new().finish())
+        );
+
+        // a retry with another body is a different request
+        let mut body = BodyHash::new();
+        body.update(b"{}");
+        assert_ne!(record.fingerprint, request_fingerprint(b"POST", b"/a", body.finish()));
+        assert_eq!(IdempotencyRecord::decode(&record.encode()), Some(record.clone()));
+
+        record.body = Some(Vec::new());
+        let encoded = record.encode();
+        assert_eq!(IdempotencyRecord::decode(&encoded), Some(record));
+        assert_eq!(IdempotencyRecord::decode(&encoded[..encoded.len() - 1]), None);
+
+        let mut hash = BodyHash::new();
+        hash.update(b"hello ");
+        hash.update(b"world");
+        let mut whole = BodyHash::new();
+        whole.update(b"hello world");
+        assert_eq!(hash.finish(), whole.finish());
+    }
+}
@@ -0,0 +1,377 @@
+use flate2::write::MultiGzDecoder;
+use flate2::{Decompress, FlushDecompress, Status};
+
+use std::io::{self, Write};
+use std::vec::Vec;
+use std::{fmt, mem};
+
+/// Limits applied when decompressing untrusted data, protecting against decompression bombs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InflateLimits {
+    /// Maximum size of the decompressed data.
+    pub max_output: usize,
+    /// Maximum ratio of the decompressed size to the compressed size.
+    pub max_ratio: usize,
+}
+
+impl Default for InflateLimits {
+    fn default() -> Self {
+        InflateLimits {
+            max_output: 10 * 1024 * 1024,
+            max_ratio: 100,
+        }
+    }
+}
+
+impl InflateLimits {
+    /// Returns the maximum decompressed size of `input_len` compressed bytes.
+    pub fn output_limit(&self, input_len: usize) -> usize {
+        self.max_output.min(input_len.saturating_mul(self.max_ratio))
+    }
+}
+
+/// An error returned when decompressing data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InflateError {
+    /// The compressed data or its header is corrupted, or its checksum does not match. Truncated
+    /// `gzip` data is reported as invalid too.
+    InvalidData,
+    /// The `zlib` or raw DEFLATE data is truncated.
+    UnexpectedEof,
+    /// The decompressed data exceeds the [`InflateLimits`].
+    TooLarge,
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InflateError::InvalidData => f.write_str("invalid compressed data"),
+            InflateError::UnexpectedEof => f.write_str("truncated compressed data"),
+            InflateError::TooLarge => f.write_str("decompressed data too large"),
+        }
+    }
+}
+
+impl std::error::Error for InflateError {}
+
+impl From<io::Error> for InflateError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => InflateError::UnexpectedEof,
+            _ => InflateError::InvalidData,
+        }
+    }
+}
+
+/// A compressed data format supported by [`Inflater`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InflateFormat {
+    /// `gzip` data ([RFC 1952]), including concatenated members.
+    ///
+    /// [RFC 1952]: https://www.rfc-editor.org/rfc/rfc1952
+    Gzip,
+    /// `zlib` data ([RFC 1950]).
+    ///
+    /// [RFC 1950]: https://www.rfc-editor.org/rfc/rfc1950
+    Zlib,
+    /// Raw DEFLATE data ([RFC 1951]), without a header or a checksum.
+    ///
+    /// [RFC 1951]: https://www.rfc-editor.org/rfc/rfc1951
+    Raw,
+    /// The HTTP `deflate` content coding: `zlib` data, or raw DEFLATE data as some clients send
+    /// instead, told apart by the header.
+    Deflate,
+}
+
+/// Size of the input pieces decompressed at once, bounding the memory used before the limits
+/// are checked.
+const INFLATE_PIECE: usize = 4096;
+
+enum Decoder {
+    /// The header bytes of a `deflate` stream, until the format is known.
+    Detect(Vec<u8>),
+    Gzip(MultiGzDecoder<Vec<u8>>),
+    /// A `zlib` or raw DEFLATE stream, and whether its end was reached.
+    Deflate(Decompress, bool),
+}
+
+/// A streaming decompressor, fed with the compressed data as it arrives.
+///
+/// ```rust,ignore
+/// let mut inflater = Inflater::new(InflateFormat::Gzip, InflateLimits::default());
+/// for chunk in chunks {
+///     sink.extend(inflater.feed(chunk)?);
+/// }
+/// sink.extend(inflater.finish()?);
+/// ```
+///
+/// The [`InflateLimits`] are checked against the total input and output so far, as the data is
+/// decompressed.
+pub struct Inflater {
+    decoder: Decoder,
+    limits: InflateLimits,
+    input: usize,
+    output: usize,
+}
+
+impl Inflater {
+    /// Creates a decompressor for `format`.
+    pub fn new(format: InflateFormat, limits: InflateLimits) -> Self {
+        let decoder = match format {
+            InflateFormat::Gzip => Decoder::Gzip(MultiGzDecoder::new(Vec::new())),
+            InflateFormat::Zlib => Decoder::Deflate(Decompress::new(true), false),
+            InflateFormat::Raw => Decoder::Deflate(Decompress::new(false), false),
+            InflateFormat::Deflate => Decoder::Detect(Vec::new()),
+        };
+        Inflater {
+            decoder,
+            limits,
+            input: 0,
+            output: 0,
+        }
+    }
+
+    /// Decompresses the next part of the input, returning the data decompressed so far.
+    pub fn feed(&mut self, input: &[u8]) -> Result<Vec<u8>, InflateError> {
+        if let Decoder::Detect(header) = &mut self.decoder {
+            let used = input.len().min(2 - header.len());
+            header.extend_from_slice(&input[..used]);
+            let [cmf, flg] = header[..] else {
+                return Ok(Vec::new());
+            };
+            self.decoder = Decoder::Deflate(Decompress::new(is_zlib_header(cmf, flg)), false);
+            let mut out = self.write(&[cmf, flg])?;
+            out.extend(self.feed(&input[used..])?);
+            return Ok(out);
+        }
+
+        let mut out = Vec::new();
+        for piece in input.chunks(INFLATE_PIECE) {
+            out.extend(self.write(piece)?);
+        }
+        Ok(out)
+    }
+
+    /// Finishes the decompression, returning the remaining decompressed data.
+    ///
+    /// Returns an error if the input is truncated.
+    pub fn finish(mut self) -> Result<Vec<u8>, InflateError> {
+        let limit = self.remaining_output();
+        let out = match &mut self.decoder {
+            Decoder::Detect(_) => return Err(InflateError::UnexpectedEof),
+            Decoder::Gzip(decoder) => {
+                decoder.try_finish()?;
+                mem::take(decoder.get_mut())
+            }
+            Decoder::Deflate(decompress, ended) => {
+                let out = inflate_stream(decompress, ended, &[], limit)?;
+                if !*ended {
+                    return Err(InflateError::UnexpectedEof);
+                }
+                out
+            }
+        };
+        self.check(out)
+    }
+
+    fn write(&mut self, input: &[u8]) -> Result<Vec<u8>, InflateError> {
+        self.input += input.len();
+        let limit = self.remaining_output();
+        let out = match &mut self.decoder {
+            Decoder::Detect(_) => unreachable!("the format is detected before writing"),
+            Decoder::Gzip(decoder) => {
+                decoder.write_all(input)?;
+                mem::take(decoder.get_mut())
+            }
+            Decoder::Deflate(decompress, ended) => inflate_stream(decompress, ended, input, limit)?,
+        };
+        self.check(out)
+    }
+
+    fn remaining_output(&self) -> usize {
+        self.limits.output_limit(self.input).saturating_sub(self.output)
+    }
+
+    fn check(&mut self, out: Vec<u8>) -> Result<Vec<u8>, InflateError> {
+        self.output += out.len();
+        if self.output > self.limits.output_limit(self.input) {
+            return Err(InflateError::TooLarge);
+        }
+        Ok(out)
+    }
+}
+
+/// Decompresses `input`, stopping early once more than `limit` bytes are decompressed.
+fn inflate_stream(
+    decompress: &mut Decompress,
+    ended: &mut bool,
+    mut input: &[u8],
+    limit: usize,
+) -> Result<Vec<u8>, InflateError> {
+    let mut out = Vec::new();
+    if *ended {
+        // data after the end of the stream
+        return if input.is_empty() {
+            Ok(out)
+        } else {
+            Err(InflateError::InvalidData)
+        };
+    }
+
+    loop {
+        out.reserve(INFLATE_PIECE);
+        let (total_in, len) = (decompress.total_in(), out.len());
+        let status = decompress
+            .decompress_vec(input, &mut out, FlushDecompress::None)
+            .map_err(|_| InflateError::InvalidData)?;
+        let used = (decompress.total_in() - total_in) as usize;
+        input = &input[used..];
+
+        if status == Status::StreamEnd {
+            *ended = true;
+            if !input.is_empty() {
+                return Err(InflateError::InvalidData);
+            }
+            return Ok(out);
+        }
+        // stop once the output is too large, or without input left and pending output
+        let progress = used > 0 || out.len() > len;
+        if out.len() > limit || !progress || (input.is_empty() && out.len() < out.capacity()) {
+            return Ok(out);
+        }
+    }
+}
+
+/// Returns `true` if `cmf` and `flg` are a valid `zlib` header.
+fn is_zlib_header(cmf: u8, flg: u8) -> bool {
+    cmf & 0x0f == 8 && cmf >> 4 <= 7 && u16::from_be_bytes([cmf, flg]).is_multiple_of(31)
+}
+
+fn inflate(format: InflateFormat, input: &[u8], limits: &InflateLimits) -> Result<Vec<u8>, InflateError> {
+    let mut inflater = Inflater::new(format, *limits);
+    let mut out = inflater.feed(input)?;
+    out.extend(inflater.finish()?);
+    Ok(out)
+}
+
+/// Decompresses `gzip` data ([RFC 1952]), including concatenated members.
+///
+/// ```
+/// use ngx_core::{gunzip, InflateLimits};
+///
+/// // "hello" compressed with `gzip -n`
+/// let gz = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\xcb\x48\xcd\xc9\xc9\x07\x00\x86\xa6\x10\x36\x05\x00\x00\x00";
+/// assert_eq!(gunzip(gz, &InflateLimits::default()).unwrap(), b"hello");
+/// ```
+///
+/// [RFC 1952]: https://www.rfc-editor.org/rfc/rfc1952
+pub fn gunzip(input: &[u8], limits: &InflateLimits) -> Result<Vec<u8>, InflateError> {
+    inflate(InflateFormat::Gzip, input, limits)
+}
+
+/// Decompresses `zlib` data ([RFC 1950]), used by the HTTP `deflate` content coding.
+///
+/// [RFC 1950]: https://www.rfc-editor.org/rfc/rfc1950
+pub fn inflate_zlib(input: &[u8], limits: &InflateLimits) -> Result<Vec<u8>, InflateError> {
+    inflate(InflateFormat::Zlib, input, limits)
+}
+
+/// Decompresses raw DEFLATE data ([RFC 1951]), without a header or a checksum.
+///
+/// [RFC 1951]: https://www.rfc-editor.org/rfc/rfc1951
+pub fn inflate_raw(input: &[u8], limits: &InflateLimits) -> Result<Vec<u8>, InflateError> {
+    inflate(InflateFormat::Raw, input, limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    // "hello hello hello hello\n" compressed with `gzip -n -9`, using a back reference
+    const HELLO_GZ: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x27\xb9\x00\x00\x88\x59\x0b\x18\x00\x00\x00";
+
+    #[test]
+    fn test_inflate() {
+        let limits = InflateLimits::default();
+        let hello = b"hello hello hello hello\n";
+        assert_eq!(gunzip(HELLO_GZ, &limits).unwrap(), hello);
+
+        let mut twice = HELLO_GZ.to_vec();
+        twice.extend_from_slice(HELLO_GZ);
+        assert_eq!(gunzip(&twice, &limits).unwrap().len(), 2 * hello.len());
+
+        let tight = InflateLimits {
+            max_output: 10,
+            max_ratio: 100,
+        };
+        assert_eq!(gunzip(HELLO_GZ, &tight), Err(InflateError::TooLarge));
+        assert_eq!(
+            gunzip(&HELLO_GZ[..HELLO_GZ.len() - 1], &limits),
+            Err(InflateError::InvalidData)
+        );
+
+        let mut corrupted = HELLO_GZ.to_vec();
+        corrupted[HELLO_GZ.len() - 6] ^= 1;
+        assert_eq!(gunzip(&corrupted, &limits), Err(InflateError::InvalidData));
+        assert_eq!(gunzip(b"hello", &limits), Err(InflateError::InvalidData));
+
+        // a stored block in a zlib stream
+        let zlib = b"\x78\x01\x01\x05\x00\xfa\xffhello\x06\x2c\x02\x15";
+        assert_eq!(inflate_zlib(zlib, &limits).unwrap(), b"hello");
+        assert_eq!(inflate_raw(&zlib[2..12], &limits).unwrap(), b"hello");
+        assert_eq!(inflate_zlib(&zlib[..8], &limits), Err(InflateError::UnexpectedEof));
+        assert_eq!(inflate_zlib(b"\x78\x01\xff", &limits), Err(InflateError::InvalidData));
+
+        let mut trailing = zlib.to_vec();
+        trailing.push(0);
+        assert_eq!(inflate_zlib(&trailing, &limits), Err(InflateError::InvalidData));
+    }
+
+    #[test]
+    fn test_inflater() {
+        let limits = InflateLimits::default();
+
+        let mut inflater = Inflater::new(InflateFormat::Gzip, limits);
+        let mut out = Vec::new();
+        for byte in HELLO_GZ {
+            out.extend(inflater.feed(&[*byte]).unwrap());
+        }
+        out.extend(inflater.finish().unwrap());
+        assert_eq!(out, b"hello hello hello hello\n");
+
+        let zlib = b"\x78\x01\x01\x05\x00\xfa\xffhello\x06\x2c\x02\x15";
+        for input in [&zlib[..], &zlib[2..12]] {
+            let mut inflater = Inflater::new(InflateFormat::Deflate, limits);
+            let (first, rest) = input.split_at(1);
+            let mut out = inflater.feed(first).unwrap();
+            out.extend(inflater.feed(rest).unwrap());
+            out.extend(inflater.finish().unwrap());
+            assert_eq!(out, b"hello");
+        }
+
+        let mut inflater = Inflater::new(InflateFormat::Zlib, limits);
+        inflater.feed(&zlib[..8]).unwrap();
+        assert_eq!(inflater.finish(), Err(InflateError::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_inflate_bomb() {
+        use flate2::write::ZlibEncoder;
+        use flate2::Compression;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0; 16 * 1024 * 1024]).unwrap();
+        let bomb = encoder.finish().unwrap();
+
+        let limits = InflateLimits {
+            max_output: 64 * 1024,
+            max_ratio: 1000,
+        };
+        assert_eq!(inflate_zlib(&bomb, &limits), Err(InflateError::TooLarge));
+        assert_eq!(
+            inflate_zlib(&bomb, &InflateLimits::default()),
+            Err(InflateError::TooLarge)
+        );
+    }
+}
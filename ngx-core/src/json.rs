@@ -0,0 +1,565 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+/// Streaming validator for JSON documents, such as request bodies received over several buffers.
+///
+/// The validator checks the JSON grammar and the configured limits without building the
+/// document, so its memory use only depends on the nesting depth:
+///
+/// ```
+/// use ngx_core::{JsonErrorKind, JsonValidator};
+///
+/// let mut validator = JsonValidator::new().max_depth(4).require("id");
+/// validator.feed(br#"{"id": 42, "tags": ["a","#).unwrap();
+/// validator.feed(br#" "b"]}"#).unwrap();
+/// assert!(validator.finish().is_ok());
+///
+/// let mut validator = JsonValidator::new().require("id");
+/// validator.feed(br#"{"name": "x"}"#).unwrap();
+/// let err = validator.finish().unwrap_err();
+/// assert_eq!(err.kind(), &JsonErrorKind::MissingField("id".to_string()));
+/// ```
+///
+/// String contents are not checked for UTF-8 validity, and required fields are compared with the
+/// raw, still escaped, top-level object keys.
+#[derive(Clone, Debug)]
+pub struct JsonValidator {
+    max_depth: usize,
+    max_size: Option<u64>,
+    required: Vec<String>,
+    found: Vec<bool>,
+
+    state: State,
+    stack: Vec<Container>,
+    key: Vec<u8>,
+    offset: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Container {
+    Object,
+    Array,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Expecting a value.
+    Value,
+    /// Expecting a value or the end of an array.
+    ValueOrEnd,
+    /// Expecting a key or the end of an object.
+    KeyOrEnd,
+    /// Expecting a key after a comma.
+    Key,
+    /// Expecting the colon following a key.
+    Colon,
+    /// Expecting a comma or the end of the enclosing container.
+    CommaOrEnd,
+    Str {
+        key: bool,
+        escape: Escape,
+    },
+    Literal {
+        literal: &'static [u8],
+        pos: usize,
+    },
+    Number(Number),
+    /// The root value is complete; only whitespace may follow.
+    Done,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Escape {
+    None,
+    Backslash,
+    Unicode(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Number {
+    Minus,
+    Zero,
+    Int,
+    Point,
+    Frac,
+    Exp,
+    ExpSign,
+    ExpInt,
+}
+
+impl Number {
+    fn is_complete(self) -> bool {
+        matches!(self, Number::Zero | Number::Int | Number::Frac | Number::ExpInt)
+    }
+
+    fn next(self, b: u8) -> Option<Number> {
+        match (self, b) {
+            (Number::Minus, b'0') => Some(Number::Zero),
+            (Number::Minus, b'1'..=b'9') => Some(Number::Int),
+            (Number::Int, b'0'..=b'9') => Some(Number::Int),
+            (Number::Zero | Number::Int, b'.') => Some(Number::Point),
+            (Number::Point | Number::Frac, b'0'..=b'9') => Some(Number::Frac),
+            (Number::Zero | Number::Int | Number::Frac, b'e' | b'E') => Some(Number::Exp),
+            (Number::Exp, b'+' | b'-') => Some(Number::ExpSign),
+            (Number::Exp | Number::ExpSign | Number::ExpInt, b'0'..=b'9') => Some(Number::ExpInt),
+            _ => None,
+        }
+    }
+}
+
+/// Default maximum nesting depth of a [`JsonValidator`].
+pub const JSON_DEFAULT_MAX_DEPTH: usize = 32;
+
+impl Default for JsonValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JsonValidator {
+    /// Creates a validator accepting any JSON document nested at most
+    /// [`JSON_DEFAULT_MAX_DEPTH`] levels deep.
+    pub fn new() -> Self {
+        JsonValidator {
+            max_depth: JSON_DEFAULT_MAX_DEPTH,
+            max_size: None,
+            required: Vec::new(),
+            found: Vec::new(),
+            state: State::Value,
+            stack: Vec::new(),
+            key: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Sets the maximum nesting depth of objects and arrays.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Sets the maximum size of the document in bytes.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Requires the document to be an object with the top-level field `name`.
+    pub fn require(mut self, name: &str) -> Self {
+        self.required.push(name.to_string());
+        self.found.push(false);
+        self
+    }
+
+    /// Number of bytes validated so far.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    /// Validates the next chunk of the document.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), JsonError> {
+        if let Some(max_size) = self.max_size {
+            if self.offset + chunk.len() as u64 > max_size {
+                return Err(JsonError::new(JsonErrorKind::TooLarge, max_size));
+            }
+        }
+
+        let mut i = 0;
+        while i < chunk.len() {
+            if self.step(chunk[i])? {
+                i += 1;
+                self.offset += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the document is complete and contains the required fields.
+    pub fn finish(&mut self) -> Result<(), JsonError> {
+        if let State::Number(number) = self.state {
+            if number.is_complete() && self.stack.is_empty() {
+                self.state = State::Done;
+            }
+        }
+        if self.state != State::Done {
+            return Err(JsonError::new(JsonErrorKind::UnexpectedEnd, self.offset));
+        }
+
+        if let Some(index) = self.found.iter().position(|found| !found) {
+            let name = self.required[index].clone();
+            return Err(JsonError::new(JsonErrorKind::MissingField(name), self.offset));
+        }
+        Ok(())
+    }
+
+    /// Resets the validator to validate a new document with the same limits.
+    pub fn reset(&mut self) {
+        self.found.iter_mut().for_each(|found| *found = false);
+        self.state = State::Value;
+        self.stack.clear();
+        self.key.clear();
+        self.offset = 0;
+    }
+
+    /// Processes a byte, returning `false` if the byte must be processed again in the new state.
+    fn step(&mut self, b: u8) -> Result<bool, JsonError> {
+        let is_ws = matches!(b, b' ' | b'\t' | b'\n' | b'\r');
+
+        match self.state {
+            State::Value
+            | State::ValueOrEnd
+            | State::KeyOrEnd
+            | State::Key
+            | State::Colon
+            | State::CommaOrEnd
+            | State::Done
+                if is_ws => {}
+
+            State::ValueOrEnd if b == b']' => self.end_container()?,
+            State::Value | State::ValueOrEnd => self.start_value(b)?,
+
+            State::KeyOrEnd if b == b'}' => self.end_container()?,
+            State::KeyOrEnd | State::Key if b == b'"' => {
+                self.key.clear();
+                self.state = State::Str {
+                    key: true,
+                    escape: Escape::None,
+                };
+            }
+            State::Colon if b == b':' => self.state = State::Value,
+
+            State::CommaOrEnd => match (b, self.stack.last()) {
+                (b',', Some(Container::Object)) => self.state = State::Key,
+                (b',', Some(Container::Array)) => self.state = State::Value,
+                (b'}', Some(Container::Object)) | (b']', Some(Container::Array)) => self.end_container()?,
+                _ => return Err(self.syntax_error()),
+            },
+
+            State::Str { key, escape } => self.string_byte(key, escape, b)?,
+
+            State::Literal { literal, pos } => {
+                if literal[pos] != b {
+                    return Err(self.syntax_error());
+                }
+                if pos + 1 == literal.len() {
+                    self.value_done();
+                } else {
+                    self.state = State::Literal { literal, pos: pos + 1 };
+                }
+            }
+
+            State::Number(number) => match number.next(b) {
+                Some(next) => self.state = State::Number(next),
+                None if number.is_complete() => {
+                    // the byte terminating the number belongs to the enclosing value
+                    self.value_done();
+                    return Ok(false);
+                }
+                None => return Err(self.syntax_error()),
+            },
+
+            State::KeyOrEnd | State::Key | State::Colon | State::Done => return Err(self.syntax_error()),
+        }
+
+        Ok(true)
+    }
+
+    fn start_value(&mut self, b: u8) -> Result<(), JsonError> {
+        self.state = match b {
+            b'{' | b'[' => {
+                if self.stack.len() >= self.max_depth {
+                    return Err(JsonError::new(JsonErrorKind::TooDeep, self.offset));
+                }
+                if b == b'{' {
+                    self.stack.push(Container::Object);
+                    State::KeyOrEnd
+                } else {
+                    self.stack.push(Container::Array);
+                    State::ValueOrEnd
+                }
+            }
+            b'"' => State::Str {
+                key: false,
+                escape: Escape::None,
+            },
+            b't' => State::Literal {
+                literal: b"true",
+                pos: 1,
+            },
+            b'f' => State::Literal {
+                literal: b"false",
+                pos: 1,
+            },
+            b'n' => State::Literal {
+                literal: b"null",
+                pos: 1,
+            },
+            b'-' => State::Number(Number::Minus),
+            b'0' => State::Number(Number::Zero),
+            b'1'..=b'9' => State::Number(Number::Int),
+            _ => return Err(self.syntax_error()),
+        };
+        Ok(())
+    }
+
+    fn string_byte(&mut self, key: bool, escape: Escape, b: u8) -> Result<(), JsonError> {
+        let escape = match escape {
+            Escape::None if b == b'"' => {
+                if key {
+                    self.key_done();
+                    self.state = State::Colon;
+                } else {
+                    self.value_done();
+                }
+                return Ok(());
+            }
+            Escape::None if b < 0x20 => return Err(self.syntax_error()),
+            Escape::None if b == b'\\' => Escape::Backslash,
+            Escape::None => Escape::None,
+            Escape::Backslash => match b {
+                b'u' => Escape::Unicode(0),
+                b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => Escape::None,
+                _ => return Err(self.syntax_error()),
+            },
+            Escape::Unicode(n) if b.is_ascii_hexdigit() => {
+                if n == 3 {
+                    Escape::None
+                } else {
+                    Escape::Unicode(n + 1)
+                }
+            }
+            Escape::Unicode(_) => return Err(self.syntax_error()),
+        };
+
+        if key && self.stack.len() == 1 && self.key.len() <= self.max_key_len() {
+            self.key.push(b);
+        }
+        self.state = State::Str { key, escape };
+        Ok(())
+    }
+
+    fn max_key_len(&self) -> usize {
+        self.required.iter().map(String::len).max().unwrap_or(0)
+    }
+
+    fn key_done(&mut self) {
+        if self.stack.len() != 1 {
+            return;
+        }
+        for (name, found) in self.required.iter().zip(self.found.iter_mut()) {
+            if name.as_bytes() == self.key.as_slice() {
+                *found = true;
+            }
+        }
+    }
+
+    fn end_container(&mut self) -> Result<(), JsonError> {
+        self.stack.pop();
+        self.value_done();
+        Ok(())
+    }
+
+    fn value_done(&mut self) {
+        self.state = if self.stack.is_empty() {
+            State::Done
+        } else {
+            State::CommaOrEnd
+        };
+    }
+
+    fn syntax_error(&self) -> JsonError {
+        JsonError::new(JsonErrorKind::Syntax, self.offset)
+    }
+}
+
+/// The reason a document was rejected by a [`JsonValidator`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JsonErrorKind {
+    /// The document is not valid JSON.
+    Syntax,
+    /// The document ended before the root value was complete.
+    UnexpectedEnd,
+    /// Objects and arrays are nested deeper than allowed.
+    TooDeep,
+    /// The document is larger than allowed.
+    TooLarge,
+    /// A required top-level field is missing.
+    MissingField(String),
+}
+
+/// An error returned by a [`JsonValidator`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    kind: JsonErrorKind,
+    offset: u64,
+}
+
+impl JsonError {
+    /// Creates an error detected at `offset` in the document.
+    pub fn new(kind: JsonErrorKind, offset: u64) -> Self {
+        JsonError { kind, offset }
+    }
+
+    /// Returns the reason of the error.
+    pub fn kind(&self) -> &JsonErrorKind {
+        &self.kind
+    }
+
+    /// Returns the offset in the document at which the error was detected.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns a short machine-readable code for the error.
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            JsonErrorKind::Syntax => "invalid_json",
+            JsonErrorKind::UnexpectedEnd => "truncated_json",
+            JsonErrorKind::TooDeep => "too_deep",
+            JsonErrorKind::TooLarge => "too_large",
+            JsonErrorKind::MissingField(_) => "missing_field",
+        }
+    }
+
+    /// Renders the error as a JSON object, suitable for a `400 Bad Request` response body.
+    ///
+    /// ```
+    /// use ngx_core::JsonValidator;
+    ///
+    /// let err = JsonValidator::new().feed(b"{]").unwrap_err();
+    /// assert_eq!(
+    ///     err.to_json(),
+    ///     r#"{"error":"invalid_json","message":"invalid JSON at offset 1","offset":1}"#
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str("{\"error\":\"");
+        out.push_str(self.code());
+        out.push_str("\",\"message\":");
+        // writing to a String never fails
+        let _ = write_json_str(&mut out, &self.to_string());
+        if let JsonErrorKind::MissingField(ref name) = self.kind {
+            out.push_str(",\"field\":");
+            let _ = write_json_str(&mut out, name);
+        }
+        out.push_str(",\"offset\":");
+        out.push_str(&self.offset.to_string());
+        out.push('}');
+        out
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            JsonErrorKind::Syntax => write!(f, "invalid JSON at offset {}", self.offset),
+            JsonErrorKind::UnexpectedEnd => f.write_str("unexpected end of JSON"),
+            JsonErrorKind::TooDeep => write!(f, "JSON nested too deeply at offset {}", self.offset),
+            JsonErrorKind::TooLarge => write!(f, "JSON larger than {} bytes", self.offset),
+            JsonErrorKind::MissingField(ref name) => write!(f, "missing required field \"{}\"", name),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for JsonError {}
+
+/// Writes `s` as a JSON string, with its quotes.
+pub(crate) fn write_json_str<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(validator: JsonValidator, chunks: &[&[u8]]) -> Result<(), JsonErrorKind> {
+        let mut validator = validator;
+        for chunk in chunks {
+            validator.feed(chunk).map_err(|err| err.kind)?;
+        }
+        validator.finish().map_err(|err| err.kind)
+    }
+
+    fn is_valid(doc: &str) -> bool {
+        // also split at every position to exercise the chunk boundaries
+        (0..=doc.len()).all(|i| {
+            let (a, b) = doc.as_bytes().split_at(i);
+            validate(JsonValidator::new(), &[a, b]).is_ok()
+        })
+    }
+
+    #[test]
+    fn test_json_grammar() {
+        for doc in [
+            "0",
+            "-12.5e+3",
+            " true ",
+            "null",
+            r#""a\"b\u00e9""#,
+            "[]",
+            "{}",
+            r#"{"a": [1, 2, {"b": null}], "c": false}"#,
+            "[0,-0.0,1E5]",
+        ] {
+            assert!(is_valid(doc), "{doc}");
+        }
+
+        for doc in [
+            "",
+            "01",
+            "-",
+            "1.",
+            "1e",
+            "tru",
+            "[1,]",
+            "{\"a\"}",
+            "{\"a\":1,}",
+            "{1:2}",
+            "[1 2]",
+            "\"\x01\"",
+            "\"\\x\"",
+            "{} {}",
+            "[}",
+            "\"\\u12g4\"",
+        ] {
+            assert!(!is_valid(doc), "{doc:?}");
+        }
+    }
+
+    #[test]
+    fn test_json_limits() {
+        let deep = JsonValidator::new().max_depth(2);
+        assert_eq!(validate(deep.clone(), &[b"[[1]]"]), Ok(()));
+        assert_eq!(validate(deep, &[b"[[[1]]]"]), Err(JsonErrorKind::TooDeep));
+
+        let small = JsonValidator::new().max_size(8);
+        assert_eq!(validate(small.clone(), &[b"[1,", b"2,3]"]), Ok(()));
+        assert_eq!(validate(small, &[b"[1,2,", b"3,4]"]), Err(JsonErrorKind::TooLarge));
+
+        let required = JsonValidator::new().require("id").require("name");
+        assert_eq!(validate(required.clone(), &[br#"{"id":1,"name":"x"}"#]), Ok(()));
+        assert_eq!(
+            validate(required.clone(), &[br#"{"i"#, br#"d":1,"nested":{"name":"x"}}"#]),
+            Err(JsonErrorKind::MissingField("name".to_string()))
+        );
+        assert_eq!(
+            validate(required, &[b"[]"]),
+            Err(JsonErrorKind::MissingField("id".to_string()))
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A set of keys for signatures, such as HMAC secrets or public keys, supporting key rotation.
+///
+/// Each key has an identifier, matched against the `kid` of a token or signature, and a validity
+/// period in seconds since the Unix epoch. After [`KeySet::rotate`], signatures are made with the
+/// new key while the previous keys still verify for an overlap window, so that tokens issued
+/// before the rotation are not rejected:
+///
+/// ```
+/// use ngx_core::KeySet;
+///
+/// let mut keys = KeySet::new();
+/// keys.insert("2024-01", b"old secret".to_vec(), 0, None).unwrap();
+/// keys.rotate("2024-02", b"new secret".to_vec(), 1_000, 600).unwrap();
+///
+/// // new signatures use the newest key
+/// assert_eq!(keys.current(1_000).unwrap().id(), "2024-02");
+///
+/// // the previous key verifies until the end of the overlap window
+/// let verify = |key: &Vec<u8>| key == b"old secret";
+/// assert!(keys.verify(Some("2024-01"), 1_599, verify).is_ok());
+/// assert!(keys.verify(Some("2024-01"), 1_600, verify).is_err());
+/// ```
+///
+/// The set does not implement any signature algorithm: verification is delegated to a closure
+/// called with the candidate keys.
+#[derive(Clone, Debug)]
+pub struct KeySet<K> {
+    keys: Vec<KeyEntry<K>>,
+}
+
+/// A key of a [`KeySet`] and its validity period.
+#[derive(Clone, Debug)]
+pub struct KeyEntry<K> {
+    id: String,
+    key: K,
+    not_before: u64,
+    not_after: Option<u64>,
+}
+
+impl<K> KeyEntry<K> {
+    /// Returns the key identifier.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns the time the key becomes valid.
+    pub fn not_before(&self) -> u64 {
+        self.not_before
+    }
+
+    /// Returns the time the key stops being valid, if any.
+    pub fn not_after(&self) -> Option<u64> {
+        self.not_after
+    }
+
+    /// Returns `true` if the key is valid at `now`.
+    pub fn is_active(&self, now: u64) -> bool {
+        self.not_before <= now && self.not_after.is_none_or(|not_after| now < not_after)
+    }
+}
+
+/// An error returned by [`KeySet`] operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeySetError {
+    /// A key with the same identifier is already in the set.
+    DuplicateKey,
+    /// The validity period of the key is empty.
+    InvalidValidity,
+    /// No key has the requested identifier.
+    UnknownKey,
+    /// The key with the requested identifier is not valid at this time.
+    InactiveKey,
+    /// No key is valid at this time.
+    NoActiveKey,
+    /// No candidate key verifies the signature.
+    InvalidSignature,
+}
+
+impl fmt::Display for KeySetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySetError::DuplicateKey => f.write_str("duplicate key identifier"),
+            KeySetError::InvalidValidity => f.write_str("invalid key validity period"),
+            KeySetError::UnknownKey => f.write_str("unknown key identifier"),
+            KeySetError::InactiveKey => f.write_str("key is not valid at this time"),
+            KeySetError::NoActiveKey => f.write_str("no valid key"),
+            KeySetError::InvalidSignature => f.write_str("invalid signature"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KeySetError {}
+
+impl<K> Default for KeySet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> KeySet<K> {
+    /// Creates an empty key set.
+    pub const fn new() -> Self {
+        KeySet { keys: Vec::new() }
+    }
+
+    /// Returns the number of keys, including inactive ones.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if the set has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns an iterator over all keys, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = &KeyEntry<K>> {
+        self.keys.iter()
+    }
+
+    /// Adds `key` as `id`, valid from `not_before` until `not_after`, exclusive.
+    pub fn insert(&mut self, id: &str, key: K, not_before: u64, not_after: Option<u64>) -> Result<(), KeySetError> {
+        if self.keys.iter().any(|entry| entry.id == id) {
+            return Err(KeySetError::DuplicateKey);
+        }
+        if not_after.is_some_and(|not_after| not_after <= not_before) {
+            return Err(KeySetError::InvalidValidity);
+        }
+        self.keys.push(KeyEntry {
+            id: id.into(),
+            key,
+            not_before,
+            not_after,
+        });
+        Ok(())
+    }
+
+    /// Adds `key` as `id`, valid from `now`, and limits the validity of the keys active at `now`
+    /// to `overlap` seconds from `now`.
+    pub fn rotate(&mut self, id: &str, key: K, now: u64, overlap: u64) -> Result<(), KeySetError> {
+        self.insert(id, key, now, None)?;
+
+        let expires = now.saturating_add(overlap);
+        for entry in self
+            .keys
+            .iter_mut()
+            .filter(|entry| entry.id != id && entry.is_active(now))
+        {
+            if entry.not_after.is_none_or(|not_after| not_after > expires) {
+                entry.not_after = Some(expires);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the key `id`.
+    pub fn remove(&mut self, id: &str) -> Option<K> {
+        let index = self.keys.iter().position(|entry| entry.id == id)?;
+        Some(self.keys.remove(index).key)
+    }
+
+    /// Removes the keys expired at `now`, returning the number of removed keys.
+    pub fn prune(&mut self, now: u64) -> usize {
+        let len = self.keys.len();
+        self.keys
+            .retain(|entry| entry.not_after.is_none_or(|not_after| now < not_after));
+        len - self.keys.len()
+    }
+
+    /// Returns the key `id` if it is valid at `now`.
+    pub fn get(&self, id: &str, now: u64) -> Option<&K> {
+        self.keys
+            .iter()
+            .find(|entry| entry.id == id && entry.is_active(now))
+            .map(|entry| &entry.key)
+    }
+
+    /// Returns the keys valid at `now`, the most recent first.
+    pub fn active(&self, now: u64) -> impl Iterator<Item = &KeyEntry<K>> {
+        let mut active: Vec<&KeyEntry<K>> = self.keys.iter().filter(|entry| entry.is_active(now)).collect();
+        active.sort_by_key(|entry| core::cmp::Reverse(entry.not_before));
+        active.into_iter()
+    }
+
+    /// Returns the most recent key valid at `now`, to be used for new signatures.
+    pub fn current(&self, now: u64) -> Option<&KeyEntry<K>> {
+        self.active(now).next()
+    }
+
+    /// Verifies a signature with the key `kid`, or with each key valid at `now` if the signature
+    /// has no key identifier, returning the key for which `verify` returns `true`.
+    pub fn verify<F>(&self, kid: Option<&str>, now: u64, mut verify: F) -> Result<&KeyEntry<K>, KeySetError>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if let Some(kid) = kid {
+            let entry = self
+                .keys
+                .iter()
+                .find(|entry| entry.id == kid)
+                .ok_or(KeySetError::UnknownKey)?;
+            if !entry.is_active(now) {
+                return Err(KeySetError::InactiveKey);
+            }
+            return if verify(&entry.key) {
+                Ok(entry)
+            } else {
+                Err(KeySetError::InvalidSignature)
+            };
+        }
+
+        let mut active = self.active(now).peekable();
+        if active.peek().is_none() {
+            return Err(KeySetError::NoActiveKey);
+        }
+        active
+            .find(|entry| verify(&entry.key))
+            .ok_or(KeySetError::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_set() {
+        let mut keys = KeySet::new();
+        keys.insert("a", 1, 100, None).unwrap();
+        assert_eq!(keys.insert("a", 2, 100, None), Err(KeySetError::DuplicateKey));
+        assert_eq!(keys.insert("b", 2, 100, Some(100)), Err(KeySetError::InvalidValidity));
+
+        assert_eq!(keys.verify(None, 99, |_| true).err(), Some(KeySetError::NoActiveKey));
+        assert_eq!(
+            keys.verify(Some("a"), 99, |_| true).err(),
+            Some(KeySetError::InactiveKey)
+        );
+        assert_eq!(
+            keys.verify(Some("x"), 100, |_| true).err(),
+            Some(KeySetError::UnknownKey)
+        );
+        assert_eq!(
+            keys.verify(Some("a"), 100, |k| *k == 2).err(),
+            Some(KeySetError::InvalidSignature)
+        );
+
+        keys.rotate("b", 2, 200, 50).unwrap();
+        assert_eq!(keys.current(200).map(KeyEntry::id), Some("b"));
+        assert_eq!(keys.get("a", 249), Some(&1));
+        assert_eq!(keys.get("a", 250), None);
+        assert_eq!(keys.verify(None, 220, |k| *k == 1).map(KeyEntry::id), Ok("a"));
+        assert_eq!(
+            keys.verify(None, 250, |k| *k == 1).err(),
+            Some(KeySetError::InvalidSignature)
+        );
+
+        // a second rotation within the window does not extend the first overlap
+        keys.rotate("c", 3, 210, 100).unwrap();
+        assert_eq!(
+            keys.iter().map(KeyEntry::not_after).collect::<Vec<_>>(),
+            [Some(250), Some(310), None]
+        );
+
+        assert_eq!(keys.prune(250), 1);
+        assert_eq!(keys.remove("b"), Some(2));
+        assert_eq!(keys.len(), 1);
+    }
+}
@@ -0,0 +1,63 @@
+//! # ngx-core
+//!
+//! Pure data types shared by the [ngx](https://crates.io/crates/ngx) NGINX module SDK.
+//!
+//! The types in this crate do not depend on the NGINX bindings or the NGINX runtime, which makes
+//! them usable in unit tests and in business logic crates that should build and test without
+//! compiling NGINX. All of them are re-exported by `ngx`, so module code does not need to depend
+//! on this crate directly.
+//!
+//! ## Features
+//!
+//! - `std`: implements `std::error::Error` for the error types, and provides the `gzip` and
+//!   DEFLATE decompression of `Inflater`. This feature is enabled by default.
+#![no_std]
+#![warn(missing_docs)]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod build_info;
+mod dump;
+mod env;
+mod etag;
+mod histogram;
+mod http_status;
+mod idempotency;
+#[cfg(feature = "std")]
+mod inflate;
+mod json;
+mod key_set;
+mod lru;
+mod method;
+mod query;
+mod random;
+mod scan;
+mod secret;
+mod status;
+mod string;
+mod uuid;
+mod wheel;
+
+pub use build_info::*;
+pub use dump::*;
+pub use env::*;
+pub use etag::*;
+pub use histogram::*;
+pub use http_status::*;
+pub use idempotency::*;
+#[cfg(feature = "std")]
+pub use inflate::*;
+pub use json::*;
+pub use key_set::*;
+pub use lru::*;
+pub use method::*;
+pub use query::*;
+pub use random::*;
+pub use scan::*;
+pub use secret::*;
+pub use status::*;
+pub use string::*;
+pub use uuid::*;
+pub use wheel::*;
@@ -0,0 +1,318 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::hash::BuildHasher;
+use core::mem;
+
+use hashbrown::{DefaultHashBuilder, HashTable};
+
+const NIL: usize = usize::MAX;
+
+/// A least recently used cache keyed by byte strings, with entry count and size limits.
+///
+/// The cache is meant for state private to a worker process, such as parsed tokens or compiled
+/// patterns, where sharing between workers is not needed. Entries are stored on the heap; keys
+/// can be anything viewable as bytes, e.g. an [`NgxStr`](crate::NgxStr) borrowed from a request.
+///
+/// Each entry is charged a size, by default the key length plus the size of `V`. When an
+/// insertion exceeds either limit, the least recently used entries are evicted.
+///
+/// ```
+/// use ngx_core::{LruCache, NgxStr};
+///
+/// let mut cache = LruCache::new(2, usize::MAX);
+/// cache.insert("a", 1).unwrap();
+/// cache.insert("b", 2).unwrap();
+/// let key: &NgxStr = "a".into();
+/// assert_eq!(cache.get(key), Some(&1));
+///
+/// // "b" is now the least recently used entry
+/// cache.insert("c", 3).unwrap();
+/// assert_eq!(cache.get("b"), None);
+/// assert_eq!(cache.len(), 2);
+/// ```
+pub struct LruCache<V> {
+    table: HashTable<usize>,
+    hasher: DefaultHashBuilder,
+    nodes: Vec<Node<V>>,
+    /// Most recently used node.
+    head: usize,
+    /// Least recently used node.
+    tail: usize,
+    max_entries: usize,
+    max_size: usize,
+    size: usize,
+}
+
+struct Node<V> {
+    key: Box<[u8]>,
+    value: V,
+    size: usize,
+    prev: usize,
+    next: usize,
+}
+
+impl<V> LruCache<V> {
+    /// Creates an empty cache holding at most `max_entries` entries of a total size of at most
+    /// `max_size`.
+    pub fn new(max_entries: usize, max_size: usize) -> Self {
+        LruCache {
+            table: HashTable::new(),
+            hasher: DefaultHashBuilder::default(),
+            nodes: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            max_entries,
+            max_size,
+            size: 0,
+        }
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the total size charged for the entries.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns `true` if the cache holds an entry for `key`, without marking it as used.
+    pub fn contains_key<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        self.find(key.as_ref()).is_some()
+    }
+
+    /// Returns the value of `key`, marking the entry as the most recently used.
+    pub fn get<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) -> Option<&V> {
+        let index = self.find(key.as_ref())?;
+        self.touch(index);
+        Some(&self.nodes[index].value)
+    }
+
+    /// Returns a mutable reference to the value of `key`, marking the entry as the most recently
+    /// used.
+    pub fn get_mut<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.find(key.as_ref())?;
+        self.touch(index);
+        Some(&mut self.nodes[index].value)
+    }
+
+    /// Returns the value of `key`, without marking the entry as used.
+    pub fn peek<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> Option<&V> {
+        self.find(key.as_ref()).map(|index| &self.nodes[index].value)
+    }
+
+    /// Inserts a value for `key`, charged the key length plus the size of `V`.
+    ///
+    /// See [`LruCache::insert_with_size`].
+    pub fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K, value: V) -> Result<Option<V>, V> {
+        let size = key.as_ref().len() + mem::size_of::<V>();
+        self.insert_with_size(key, value, size)
+    }
+
+    /// Inserts a value for `key`, charged `size`, and marks it as the most recently used.
+    ///
+    /// Returns the previous value of the key, or gives `value` back if the entry does not fit
+    /// in the cache even after evicting all other entries.
+    pub fn insert_with_size<K: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        key: &K,
+        value: V,
+        size: usize,
+    ) -> Result<Option<V>, V> {
+        let key = key.as_ref();
+        if size > self.max_size || self.max_entries == 0 {
+            return Err(value);
+        }
+
+        let previous = self.remove(key);
+
+        while self.nodes.len() >= self.max_entries || self.size + size > self.max_size {
+            if self.pop_lru().is_none() {
+                break;
+            }
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            key: key.into(),
+            value,
+            size,
+            prev: NIL,
+            next: NIL,
+        });
+        self.size += size;
+        self.push_front(index);
+
+        let hash = self.hasher.hash_one(key);
+        let (hasher, nodes) = (&self.hasher, &self.nodes);
+        self.table
+            .insert_unique(hash, index, |&i| hasher.hash_one(&*nodes[i].key));
+
+        Ok(previous)
+    }
+
+    /// Removes the entry of `key`, returning its value.
+    pub fn remove<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) -> Option<V> {
+        let key = key.as_ref();
+        let hash = self.hasher.hash_one(key);
+        let nodes = &self.nodes;
+        let index = self.table.find_entry(hash, |&i| *nodes[i].key == *key).ok()?.remove().0;
+        Some(self.remove_node(index).1)
+    }
+
+    /// Removes the least recently used entry, returning its key and value.
+    pub fn pop_lru(&mut self) -> Option<(Box<[u8]>, V)> {
+        if self.tail == NIL {
+            return None;
+        }
+        let index = self.tail;
+        let hash = self.hasher.hash_one(&*self.nodes[index].key);
+        if let Ok(entry) = self.table.find_entry(hash, |&i| i == index) {
+            entry.remove();
+        }
+        Some(self.remove_node(index))
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.table.clear();
+        self.nodes.clear();
+        self.head = NIL;
+        self.tail = NIL;
+        self.size = 0;
+    }
+
+    /// Returns an iterator over the entries, from the most to the least recently used.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &V)> {
+        let mut index = self.head;
+        core::iter::from_fn(move || {
+            let node = self.nodes.get(index)?;
+            index = node.next;
+            Some((&*node.key, &node.value))
+        })
+    }
+
+    fn find(&self, key: &[u8]) -> Option<usize> {
+        let hash = self.hasher.hash_one(key);
+        self.table.find(hash, |&i| *self.nodes[i].key == *key).copied()
+    }
+
+    /// Unlinks and removes the node at `index`, which is no longer in the table.
+    fn remove_node(&mut self, index: usize) -> (Box<[u8]>, V) {
+        self.unlink(index);
+
+        // the last node moves into the freed slot
+        let last = self.nodes.len() - 1;
+        if index != last {
+            let hash = self.hasher.hash_one(&*self.nodes[last].key);
+            if let Some(slot) = self.table.find_mut(hash, |&i| i == last) {
+                *slot = index;
+            }
+
+            let (prev, next) = (self.nodes[last].prev, self.nodes[last].next);
+            match prev {
+                NIL => self.head = index,
+                prev => self.nodes[prev].next = index,
+            }
+            match next {
+                NIL => self.tail = index,
+                next => self.nodes[next].prev = index,
+            }
+        }
+
+        let node = self.nodes.swap_remove(index);
+        self.size -= node.size;
+        (node.key, node.value)
+    }
+
+    fn touch(&mut self, index: usize) {
+        if self.head != index {
+            self.unlink(index);
+            self.push_front(index);
+        }
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = (self.nodes[index].prev, self.nodes[index].next);
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, index: usize) {
+        self.nodes[index].prev = NIL;
+        self.nodes[index].next = self.head;
+        match self.head {
+            NIL => self.tail = index,
+            head => self.nodes[head].prev = index,
+        }
+        self.head = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn keys<V>(cache: &LruCache<V>) -> Vec<&[u8]> {
+        cache.iter().map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn test_lru_order() {
+        let mut cache = LruCache::new(3, usize::MAX);
+        for (i, key) in ["a", "b", "c"].iter().enumerate() {
+            assert_eq!(cache.insert(key, i), Ok(None));
+        }
+        assert_eq!(keys(&cache), [b"c", b"b", b"a"]);
+
+        assert_eq!(cache.get("a"), Some(&0));
+        assert_eq!(cache.peek("b"), Some(&1));
+        assert_eq!(keys(&cache), [b"a", b"c", b"b"]);
+
+        assert_eq!(cache.insert("d", 3), Ok(None));
+        assert!(!cache.contains_key("b"));
+        assert_eq!(keys(&cache), [b"d", b"a", b"c"]);
+
+        assert_eq!(cache.insert("a", 10), Ok(Some(0)));
+        assert_eq!(cache.remove("c"), Some(2));
+        assert_eq!(keys(&cache), [b"a", b"d"]);
+        assert_eq!(
+            cache.pop_lru().map(|(key, value)| (key.to_vec(), value)),
+            Some((vec![b'd'], 3))
+        );
+        assert_eq!(cache.get("a"), Some(&10));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_lru_size() {
+        let mut cache = LruCache::new(usize::MAX, 10);
+        assert_eq!(cache.insert_with_size("a", (), 4), Ok(None));
+        assert_eq!(cache.insert_with_size("b", (), 4), Ok(None));
+        assert_eq!(cache.size(), 8);
+
+        assert_eq!(cache.insert_with_size("c", (), 4), Ok(None));
+        assert_eq!(keys(&cache), [b"c", b"b"]);
+        assert_eq!(cache.insert_with_size("big", (), 11), Err(()));
+        assert_eq!(cache.insert_with_size("b", (), 6), Ok(Some(())));
+        assert_eq!(keys(&cache), [b"b", b"c"]);
+        assert_eq!(cache.size(), 10);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.size(), 0);
+    }
+}
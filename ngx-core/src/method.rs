@@ -0,0 +1,274 @@
+use core::fmt;
+use core::str::FromStr;
+
+/// A possible error value when converting `Method`
+pub struct InvalidMethod {
+    _priv: (),
+}
+
+/// Request method verb
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Method(MethodInner);
+
+impl Method {
+    /// UNKNOWN
+    pub const UNKNOWN: Method = Method(MethodInner::Unknown);
+
+    /// GET
+    pub const GET: Method = Method(MethodInner::Get);
+
+    /// HEAD
+    pub const HEAD: Method = Method(MethodInner::Head);
+
+    /// POST
+    pub const POST: Method = Method(MethodInner::Post);
+
+    /// PUT
+    pub const PUT: Method = Method(MethodInner::Put);
+
+    /// DELETE
+    pub const DELETE: Method = Method(MethodInner::Delete);
+
+    /// MKCOL
+    pub const MKCOL: Method = Method(MethodInner::Mkcol);
+
+    /// COPY
+    pub const COPY: Method = Method(MethodInner::Copy);
+
+    /// MOVE
+    pub const MOVE: Method = Method(MethodInner::Move);
+
+    /// OPTIONS
+    pub const OPTIONS: Method = Method(MethodInner::Options);
+
+    /// PROPFIND
+    pub const PROPFIND: Method = Method(MethodInner::Propfind);
+
+    /// PROPPATCH
+    pub const PROPPATCH: Method = Method(MethodInner::Proppatch);
+
+    /// LOCK
+    pub const LOCK: Method = Method(MethodInner::Lock);
+
+    /// UNLOCK
+    pub const UNLOCK: Method = Method(MethodInner::Unlock);
+
+    /// PATCH
+    pub const PATCH: Method = Method(MethodInner::Patch);
+
+    /// TRACE
+    pub const TRACE: Method = Method(MethodInner::Trace);
+
+    /// CONNECT
+    pub const CONNECT: Method = Method(MethodInner::Connect);
+
+    /// Convert a Method to a &str.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        match self.0 {
+            MethodInner::Unknown => "UNKNOWN",
+            MethodInner::Get => "GET",
+            MethodInner::Head => "HEAD",
+            MethodInner::Post => "POST",
+            MethodInner::Put => "PUT",
+            MethodInner::Delete => "DELETE",
+            MethodInner::Mkcol => "MKCOL",
+            MethodInner::Copy => "COPY",
+            MethodInner::Move => "MOVE",
+            MethodInner::Options => "OPTIONS",
+            MethodInner::Propfind => "PROPFIND",
+            MethodInner::Proppatch => "PROPPATCH",
+            MethodInner::Lock => "LOCK",
+            MethodInner::Unlock => "UNLOCK",
+            MethodInner::Patch => "PATCH",
+            MethodInner::Trace => "TRACE",
+            MethodInner::Connect => "CONNECT",
+        }
+    }
+
+    /// Returns `true` for the safe methods of RFC 9110, which are not expected to change the state
+    /// of the server: `GET`, `HEAD`, `OPTIONS` and `TRACE`.
+    #[inline]
+    pub fn is_safe(&self) -> bool {
+        matches!(
+            self.0,
+            MethodInner::Get | MethodInner::Head | MethodInner::Options | MethodInner::Trace
+        )
+    }
+
+    fn from_bytes(t: &[u8]) -> Result<Method, InvalidMethod> {
+        let inner = match t {
+            b"GET" => MethodInner::Get,
+            b"HEAD" => MethodInner::Head,
+            b"POST" => MethodInner::Post,
+            b"PUT" => MethodInner::Put,
+            b"DELETE" => MethodInner::Delete,
+            b"MKCOL" => MethodInner::Mkcol,
+            b"COPY" => MethodInner::Copy,
+            b"MOVE" => MethodInner::Move,
+            b"OPTIONS" => MethodInner::Options,
+            b"PROPFIND" => MethodInner::Propfind,
+            b"PROPPATCH" => MethodInner::Proppatch,
+            b"LOCK" => MethodInner::Lock,
+            b"UNLOCK" => MethodInner::Unlock,
+            b"PATCH" => MethodInner::Patch,
+            b"TRACE" => MethodInner::Trace,
+            b"CONNECT" => MethodInner::Connect,
+            _ => return Err(InvalidMethod::new()),
+        };
+        Ok(Method(inner))
+    }
+}
+
+impl AsRef<str> for Method {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> PartialEq<&'a Method> for Method {
+    #[inline]
+    fn eq(&self, other: &&'a Method) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<Method> for &Method {
+    #[inline]
+    fn eq(&self, other: &Method) -> bool {
+        *self == other
+    }
+}
+
+impl PartialEq<str> for Method {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.as_ref() == other
+    }
+}
+
+impl PartialEq<Method> for str {
+    #[inline]
+    fn eq(&self, other: &Method) -> bool {
+        self == other.as_ref()
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Method {
+    #[inline]
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_ref() == *other
+    }
+}
+
+impl PartialEq<Method> for &str {
+    #[inline]
+    fn eq(&self, other: &Method) -> bool {
+        *self == other.as_ref()
+    }
+}
+
+impl fmt::Debug for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(self.as_ref())
+    }
+}
+
+impl<'a> From<&'a Method> for Method {
+    #[inline]
+    fn from(t: &'a Method) -> Self {
+        t.clone()
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Method {
+    type Error = InvalidMethod;
+
+    #[inline]
+    fn try_from(t: &'a [u8]) -> Result<Self, Self::Error> {
+        Method::from_bytes(t)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for Method {
+    type Error = InvalidMethod;
+
+    #[inline]
+    fn try_from(t: &'a str) -> Result<Self, Self::Error> {
+        TryFrom::try_from(t.as_bytes())
+    }
+}
+
+impl FromStr for Method {
+    type Err = InvalidMethod;
+
+    #[inline]
+    fn from_str(t: &str) -> Result<Self, Self::Err> {
+        TryFrom::try_from(t)
+    }
+}
+
+impl InvalidMethod {
+    fn new() -> InvalidMethod {
+        InvalidMethod { _priv: () }
+    }
+}
+
+impl fmt::Debug for InvalidMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InvalidMethod")
+            // skip _priv noise
+            .finish()
+    }
+}
+
+impl fmt::Display for InvalidMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid HTTP method")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidMethod {}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum MethodInner {
+    Unknown,
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Mkcol,
+    Copy,
+    Move,
+    Options,
+    Propfind,
+    Proppatch,
+    Lock,
+    Unlock,
+    Patch,
+    Trace,
+    Connect,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes() {
+        assert_eq!(Method::try_from(&b"GET"[..]).unwrap(), Method::GET);
+        assert_eq!("PROPPATCH".parse::<Method>().unwrap(), Method::PROPPATCH);
+        assert!("get".parse::<Method>().is_err());
+        assert!("PURGE".parse::<Method>().is_err());
+        assert!("UNKNOWN".parse::<Method>().is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::NgxStr;
+
+/// Iterator over the key/value pairs of a query string, e.g. `a=1&b=&c`.
+///
+/// As for the `$arg_name` variables, the pairs are separated by `&`, the keys and values are not
+/// unescaped, and a key without `=` has an empty value. Empty pairs are skipped.
+///
+/// ```
+/// # use ngx_core::{NgxStr, QueryArgs};
+/// let args: Vec<_> = QueryArgs::new("a=1&&b=&c".into())
+///     .map(|(k, v)| (k.as_bytes(), v.as_bytes()))
+///     .collect();
+/// assert_eq!(args, [(&b"a"[..], &b"1"[..]), (b"b", b""), (b"c", b"")]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct QueryArgs<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> QueryArgs<'a> {
+    /// Creates an iterator over the pairs of `args`, the query string without the `?`.
+    pub fn new(args: &'a NgxStr) -> Self {
+        QueryArgs { rest: args.as_bytes() }
+    }
+
+    /// Returns the value of the first pair with the key `name`, compared case-insensitively as
+    /// for the `$arg_name` variables.
+    pub fn get(self, name: &str) -> Option<&'a NgxStr> {
+        let name = name.as_bytes();
+        self.into_iter()
+            .find(|(key, _)| key.as_bytes().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+}
+
+impl<'a> Iterator for QueryArgs<'a> {
+    type Item = (&'a NgxStr, &'a NgxStr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.rest.is_empty() {
+            let (pair, rest) = match self.rest.iter().position(|&b| b == b'&') {
+                Some(pos) => (&self.rest[..pos], &self.rest[pos + 1..]),
+                None => (self.rest, &[][..]),
+            };
+            self.rest = rest;
+
+            if pair.is_empty() {
+                continue;
+            }
+            return Some(match pair.iter().position(|&b| b == b'=') {
+                Some(pos) => (pair[..pos].into(), pair[pos + 1..].into()),
+                None => (pair.into(), Default::default()),
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_query_args() {
+        let pairs = |args: &'static str| -> Vec<(&[u8], &[u8])> {
+            QueryArgs::new(args.into())
+                .map(|(k, v)| (k.as_bytes(), v.as_bytes()))
+                .collect()
+        };
+
+        assert!(pairs("").is_empty());
+        assert!(pairs("&&").is_empty());
+        assert_eq!(pairs("a=1&b=2=3"), [(&b"a"[..], &b"1"[..]), (b"b", b"2=3")]);
+        assert_eq!(pairs("=1&x&y="), [(&b""[..], &b"1"[..]), (b"x", b""), (b"y", b"")]);
+        assert_eq!(pairs("q=a%20b+c"), [(&b"q"[..], &b"a%20b+c"[..])]);
+
+        let args = QueryArgs::new("Page=2&page=3".into());
+        assert_eq!(args.clone().get("PAGE").map(NgxStr::as_bytes), Some(&b"2"[..]));
+        assert_eq!(args.get("size"), None);
+    }
+}
@@ -0,0 +1,130 @@
+/// A fast pseudo-random number generator, xoshiro256++.
+///
+/// The generator is not cryptographically secure: it is meant for load balancing, sampling,
+/// jitter and identifiers, not for keys or tokens. It is deterministic for a given seed:
+///
+/// ```
+/// use ngx_core::Rng;
+///
+/// let mut a = Rng::from_seed(42);
+/// let mut b = Rng::from_seed(42);
+/// assert_eq!(a.next_u64(), b.next_u64());
+/// assert!(a.below(10) < 10);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Rng {
+    s: [u64; 4],
+}
+
+impl Rng {
+    /// Creates a generator from a 64-bit seed, expanded with SplitMix64.
+    pub fn from_seed(seed: u64) -> Self {
+        let mut x = seed;
+        let mut s = [0; 4];
+        for word in s.iter_mut() {
+            x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            *word = splitmix64(x);
+        }
+        Rng { s }
+    }
+
+    /// Creates a generator from 32 bytes of seed material, e.g. read from the operating system.
+    pub fn from_seed_bytes(seed: [u8; 32]) -> Self {
+        let mut s = [0; 4];
+        for (word, bytes) in s.iter_mut().zip(seed.chunks_exact(8)) {
+            let mut b = [0; 8];
+            b.copy_from_slice(bytes);
+            *word = u64::from_le_bytes(b);
+        }
+        // the all-zero state is a fixed point of the generator
+        if s == [0; 4] {
+            return Rng::from_seed(0);
+        }
+        Rng { s }
+    }
+
+    /// Returns the next random `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.s;
+        let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// Returns the next random `u32`.
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Returns a uniformly distributed number in `0..n`, or 0 if `n` is 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        // Lemire's multiply-and-reject method, without modulo bias
+        let threshold = n.wrapping_neg() % n;
+        loop {
+            let m = (self.next_u64() as u128) * (n as u128);
+            if (m as u64) >= threshold {
+                return (m >> 64) as u64;
+            }
+        }
+    }
+
+    /// Fills `dest` with random bytes.
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut chunks = dest.chunks_exact_mut(8);
+        for chunk in chunks.by_ref() {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+        let rest = chunks.into_remainder();
+        if !rest.is_empty() {
+            let bytes = self.next_u64().to_le_bytes();
+            rest.copy_from_slice(&bytes[..rest.len()]);
+        }
+    }
+}
+
+/// The SplitMix64 finalizer, used to expand and mix seeds.
+pub fn splitmix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut rng = Rng::from_seed(1);
+        let first = rng.next_u64();
+        assert_ne!(first, rng.next_u64());
+        assert_eq!(Rng::from_seed(1).next_u64(), first);
+        assert_ne!(Rng::from_seed(2).next_u64(), first);
+
+        assert_eq!(rng.below(0), 0);
+        assert_eq!(rng.below(1), 0);
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            seen[rng.below(6) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+
+        let mut buf = [0u8; 13];
+        rng.fill_bytes(&mut buf);
+        assert!(buf.iter().any(|&b| b != 0));
+
+        let mut zero = Rng::from_seed_bytes([0; 32]);
+        assert_ne!(zero.next_u64(), zero.next_u64());
+    }
+}
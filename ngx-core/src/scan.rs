@@ -0,0 +1,202 @@
+use alloc::vec::Vec;
+
+use memchr::memmem;
+
+/// Returns the index of the first occurrence of `byte` in `haystack`.
+///
+/// Uses SIMD instructions where the target supports them, and a word-at-a-time search otherwise.
+pub fn find_byte(byte: u8, haystack: &[u8]) -> Option<usize> {
+    memchr::memchr(byte, haystack)
+}
+
+/// Returns the index of the first occurrence of either `b1` or `b2` in `haystack`.
+pub fn find_byte2(b1: u8, b2: u8, haystack: &[u8]) -> Option<usize> {
+    memchr::memchr2(b1, b2, haystack)
+}
+
+/// Returns the index of the first occurrence of any of `b1`, `b2` or `b3` in `haystack`.
+pub fn find_byte3(b1: u8, b2: u8, b3: u8, haystack: &[u8]) -> Option<usize> {
+    memchr::memchr3(b1, b2, b3, haystack)
+}
+
+/// Returns the index of the last occurrence of `byte` in `haystack`.
+pub fn rfind_byte(byte: u8, haystack: &[u8]) -> Option<usize> {
+    memchr::memrchr(byte, haystack)
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+///
+/// When searching for the same needle repeatedly, build a [`Finder`] once instead.
+pub fn find(needle: &[u8], haystack: &[u8]) -> Option<usize> {
+    memmem::find(haystack, needle)
+}
+
+/// A substring searcher for a fixed needle.
+#[derive(Clone, Debug)]
+pub struct Finder<'n>(memmem::Finder<'n>);
+
+impl<'n> Finder<'n> {
+    /// Builds a searcher for `needle`.
+    pub fn new(needle: &'n [u8]) -> Self {
+        Finder(memmem::Finder::new(needle))
+    }
+
+    /// Returns the needle being searched for.
+    pub fn needle(&self) -> &[u8] {
+        self.0.needle()
+    }
+
+    /// Returns the index of the first occurrence of the needle in `haystack`.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        self.0.find(haystack)
+    }
+
+    /// Returns an iterator over the indices of the non-overlapping occurrences of the needle in
+    /// `haystack`.
+    pub fn find_iter<'h>(&'h self, haystack: &'h [u8]) -> impl Iterator<Item = usize> + 'h {
+        self.0.find_iter(haystack)
+    }
+}
+
+/// Incremental substring search over a byte stream split into chunks, such as the buffers of a
+/// body chain.
+///
+/// Occurrences spanning chunk boundaries are found as well. Only the last `needle.len() - 1`
+/// bytes of the stream are retained between chunks.
+///
+/// ```
+/// use ngx_core::StreamFinder;
+///
+/// let mut finder = StreamFinder::new(b"--boundary");
+/// let mut found = Vec::new();
+/// for chunk in [&b"preamble--bou"[..], b"ndary data --boundary"] {
+///     finder.feed(chunk, |offset| found.push(offset));
+/// }
+/// assert_eq!(found, [8, 24]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct StreamFinder<'n> {
+    finder: Finder<'n>,
+    /// Tail of the stream that may hold the beginning of an occurrence.
+    tail: Vec<u8>,
+    /// Stream offset of the first byte of the next chunk.
+    offset: u64,
+    /// Stream offset right past the last reported occurrence.
+    next: u64,
+}
+
+impl<'n> StreamFinder<'n> {
+    /// Builds a stream searcher for `needle`.
+    pub fn new(needle: &'n [u8]) -> Self {
+        StreamFinder {
+            finder: Finder::new(needle),
+            tail: Vec::new(),
+            offset: 0,
+            next: 0,
+        }
+    }
+
+    /// Number of bytes fed so far.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    /// Searches the next chunk of the stream, calling `on_match` with the stream offset of each
+    /// non-overlapping occurrence of the needle completed in this chunk.
+    pub fn feed<F: FnMut(u64)>(&mut self, chunk: &[u8], mut on_match: F) {
+        let needle_len = self.finder.needle().len();
+        if needle_len == 0 {
+            self.offset += chunk.len() as u64;
+            return;
+        }
+
+        if !self.tail.is_empty() {
+            // occurrences starting in the tail and ending in this chunk
+            let head = &chunk[..chunk.len().min(needle_len - 1)];
+            let tail_start = self.offset - self.tail.len() as u64;
+            let tail_len = self.tail.len();
+
+            self.tail.extend_from_slice(head);
+            let mut from = 0;
+            while let Some(pos) = self.finder.find(&self.tail[from..]) {
+                let pos = from + pos;
+                if pos >= tail_len {
+                    break;
+                }
+                let start = tail_start + pos as u64;
+                if start >= self.next {
+                    on_match(start);
+                    self.next = start + needle_len as u64;
+                }
+                from = pos + 1;
+            }
+            self.tail.truncate(tail_len);
+        }
+
+        // occurrences within this chunk, after the last reported one
+        let mut from = self.next.saturating_sub(self.offset) as usize;
+        while from <= chunk.len() {
+            let Some(pos) = self.finder.find(&chunk[from..]) else {
+                break;
+            };
+            let start = from + pos;
+            on_match(self.offset + start as u64);
+            self.next = self.offset + (start + needle_len) as u64;
+            from = start + needle_len;
+        }
+
+        // keep the bytes that may start an occurrence completed by the next chunk
+        let keep = needle_len - 1;
+        if chunk.len() >= keep {
+            self.tail.clear();
+            self.tail.extend_from_slice(&chunk[chunk.len() - keep..]);
+        } else {
+            self.tail.extend_from_slice(chunk);
+            let excess = self.tail.len().saturating_sub(keep);
+            self.tail.drain(..excess);
+        }
+        self.offset += chunk.len() as u64;
+    }
+
+    /// Resets the searcher to the beginning of a new stream.
+    pub fn reset(&mut self) {
+        self.tail.clear();
+        self.offset = 0;
+        self.next = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn feed_all(needle: &[u8], chunks: &[&[u8]]) -> Vec<u64> {
+        let mut finder = StreamFinder::new(needle);
+        let mut found = vec![];
+        for chunk in chunks {
+            finder.feed(chunk, |offset| found.push(offset));
+        }
+        found
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find_byte(b':', b"Host: example.com"), Some(4));
+        assert_eq!(find_byte3(b'\r', b'\n', b';', b"a=b; c"), Some(3));
+        assert_eq!(rfind_byte(b'/', b"/a/b/c"), Some(4));
+        assert_eq!(find(b"\r\n\r\n", b"GET / HTTP/1.1\r\nHost: x\r\n\r\nbody"), Some(23));
+        assert_eq!(Finder::new(b"ab").find_iter(b"abxabab").collect::<Vec<_>>(), [0, 3, 5]);
+    }
+
+    #[test]
+    fn test_stream_finder() {
+        assert_eq!(feed_all(b"abc", &[b"xxabcxxabc"]), [2, 7]);
+        assert_eq!(feed_all(b"abc", &[b"xa", b"b", b"cx"]), [1]);
+        assert_eq!(feed_all(b"abcd", &[b"ab", b"c", b"d", b"abcd"]), [0, 4]);
+        assert_eq!(feed_all(b"aa", &[b"a", b"aa", b"a"]), [0, 2]);
+        assert_eq!(feed_all(b"aa", &[b"a", b"aaa"]), [0, 2]);
+        assert_eq!(feed_all(b"abc", &[b"", b"ab", b"", b"c"]), [0]);
+        assert_eq!(feed_all(b"abc", &[b"ab", b"xc"]), []);
+    }
+}
@@ -0,0 +1,203 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::{fmt, ptr, str};
+
+use crate::ConfValue;
+
+/// The text shown in place of a secret value.
+pub const REDACTED: &str = "[redacted]";
+
+/// A sensitive configuration value, such as a key or a password.
+///
+/// The value is overwritten with zeroes when the secret is dropped, and is never shown by the
+/// [`Debug`](fmt::Debug) and [`Display`](fmt::Display) implementations or in a
+/// [`ConfDump`](crate::ConfDump). Access to the value is explicit, with [`Secret::expose`]:
+///
+/// ```
+/// use ngx_core::Secret;
+///
+/// let key = Secret::new(b"wJalrXUtnFEMI/K7MDENG".to_vec());
+/// assert_eq!(format!("{:?}", key), "Secret([redacted])");
+/// assert_eq!(key.expose(), b"wJalrXUtnFEMI/K7MDENG");
+/// ```
+///
+/// Only the buffer owned by the secret is zeroed: copies made before the value is wrapped, or
+/// from [`Secret::expose`], are not.
+#[derive(Clone, Default)]
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    /// Wraps `value`, taking ownership of its buffer.
+    pub fn new(value: Vec<u8>) -> Self {
+        Secret(value)
+    }
+
+    /// Returns the secret value.
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the secret value if it is valid UTF-8.
+    pub fn expose_str(&self) -> Option<&str> {
+        str::from_utf8(&self.0).ok()
+    }
+
+    /// Returns the length of the secret value.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the secret value is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        for b in self.0.iter_mut() {
+            // SAFETY: `b` is a valid, aligned reference; volatile writes are not elided
+            unsafe { ptr::write_volatile(b, 0) };
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Compares the values in constant time for equal lengths.
+impl PartialEq for Secret {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(other.0.iter()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+impl Eq for Secret {}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl From<&Secret> for ConfValue {
+    fn from(_: &Secret) -> Self {
+        ConfValue::Str(REDACTED.into())
+    }
+}
+
+/// Where the value of a [`Secret`] directive argument comes from.
+///
+/// Arguments prefixed with `env:` name an environment variable, and arguments prefixed with
+/// `file:` name a file holding the value; any other argument is the value itself:
+///
+/// ```
+/// use ngx_core::SecretSource;
+///
+/// assert_eq!(SecretSource::parse(b"env:AWS_SECRET"), Ok(SecretSource::Env("AWS_SECRET")));
+/// assert_eq!(SecretSource::parse(b"file:/etc/nginx/key"), Ok(SecretSource::File("/etc/nginx/key")));
+/// assert_eq!(SecretSource::parse(b"hunter2"), Ok(SecretSource::Literal(b"hunter2")));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretSource<'a> {
+    /// The value itself.
+    Literal(&'a [u8]),
+    /// The name of an environment variable.
+    Env(&'a str),
+    /// The path of a file.
+    File(&'a str),
+}
+
+/// An error returned when a secret source is not valid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretSourceError {
+    /// The environment variable name or the file path is empty.
+    Empty,
+    /// The environment variable name or the file path is not valid UTF-8, or the variable name
+    /// contains `=`.
+    InvalidName,
+}
+
+impl fmt::Display for SecretSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSourceError::Empty => f.write_str("empty secret source"),
+            SecretSourceError::InvalidName => f.write_str("invalid secret source"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SecretSourceError {}
+
+impl<'a> SecretSource<'a> {
+    /// Parses a directive argument.
+    pub fn parse(arg: &'a [u8]) -> Result<Self, SecretSourceError> {
+        if let Some(name) = arg.strip_prefix(b"env:") {
+            let name = Self::name(name)?;
+            if name.contains('=') {
+                return Err(SecretSourceError::InvalidName);
+            }
+            return Ok(SecretSource::Env(name));
+        }
+        if let Some(path) = arg.strip_prefix(b"file:") {
+            return Self::name(path).map(SecretSource::File);
+        }
+        Ok(SecretSource::Literal(arg))
+    }
+
+    fn name(name: &[u8]) -> Result<&str, SecretSourceError> {
+        if name.is_empty() {
+            return Err(SecretSourceError::Empty);
+        }
+        str::from_utf8(name).map_err(|_| SecretSourceError::InvalidName)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConfDump, DescribeConf};
+    use alloc::format;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_secret_redaction() {
+        struct Conf {
+            key: Secret,
+        }
+
+        impl DescribeConf for Conf {
+            fn describe(&self, dump: &mut ConfDump) {
+                dump.field("key", &self.key);
+            }
+        }
+
+        let conf = Conf {
+            key: Secret::new(b"s3cr3t".to_vec()),
+        };
+        assert_eq!(conf.key.to_string(), "[redacted]");
+        assert_eq!(format!("{:?}", conf.key), "Secret([redacted])");
+        assert_eq!(conf.dump().to_json(), r#"{"key":"[redacted]"}"#);
+        assert_eq!(conf.key.expose_str(), Some("s3cr3t"));
+
+        assert_eq!(conf.key, Secret::new(b"s3cr3t".to_vec()));
+        assert_ne!(conf.key, Secret::new(b"s3cr3u".to_vec()));
+        assert_ne!(conf.key, Secret::new(b"s3cr3".to_vec()));
+    }
+
+    #[test]
+    fn test_secret_source() {
+        assert_eq!(SecretSource::parse(b""), Ok(SecretSource::Literal(b"")));
+        assert_eq!(SecretSource::parse(b"env"), Ok(SecretSource::Literal(b"env")));
+        assert_eq!(SecretSource::parse(b"env:"), Err(SecretSourceError::Empty));
+        assert_eq!(SecretSource::parse(b"env:A=B"), Err(SecretSourceError::InvalidName));
+        assert_eq!(SecretSource::parse(b"file:"), Err(SecretSourceError::Empty));
+        assert_eq!(SecretSource::parse(b"file:\xff"), Err(SecretSourceError::InvalidName));
+        assert_eq!(SecretSource::parse(b"file:key.pem"), Ok(SecretSource::File("key.pem")));
+    }
+}
@@ -0,0 +1,62 @@
+use core::fmt;
+
+/// Status
+///
+/// Rust native wrapper for NGINX status codes.
+#[derive(Ord, PartialOrd, Eq, PartialEq)]
+pub struct Status(pub isize);
+
+impl Status {
+    /// Is this Status equivalent to NGX_OK?
+    pub fn is_ok(&self) -> bool {
+        self == &Status::NGX_OK
+    }
+}
+
+impl fmt::Debug for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl From<Status> for isize {
+    fn from(val: Status) -> Self {
+        val.0
+    }
+}
+
+macro_rules! ngx_codes {
+    (
+        $(
+            $(#[$docs:meta])*
+            ($konst:ident, $value:expr);
+        )+
+    ) => {
+        impl Status {
+        $(
+            $(#[$docs])*
+            pub const $konst: Status = Status($value);
+        )+
+
+        }
+    }
+}
+
+// Values of the `NGX_*` return codes defined in `src/core/ngx_core.h`.
+ngx_codes! {
+    /// NGX_OK - Operation succeeded.
+    (NGX_OK, 0);
+    /// NGX_ERROR - Operation failed.
+    (NGX_ERROR, -1);
+    /// NGX_AGAIN - Operation incomplete; call the function again.
+    (NGX_AGAIN, -2);
+    /// NGX_BUSY - Resource is not available.
+    (NGX_BUSY, -3);
+    /// NGX_DONE - Operation complete or continued elsewhere. Also used as an alternative success code.
+    (NGX_DONE, -4);
+    /// NGX_DECLINED - Operation rejected, for example, because it is disabled in the configuration.
+    /// This is never an error.
+    (NGX_DECLINED, -5);
+    /// NGX_ABORT - Function was aborted. Also used as an alternative error code.
+    (NGX_ABORT, -6);
+}
@@ -0,0 +1,173 @@
+use alloc::borrow::Cow;
+use alloc::string::String;
+use core::fmt::{self, Write};
+use core::slice;
+use core::str::{self, Utf8Error};
+
+/// Representation of a borrowed [Nginx string].
+///
+/// [Nginx string]: https://nginx.org/en/docs/dev/development_guide.html#string_overview
+#[repr(transparent)]
+pub struct NgxStr([u8]);
+
+impl NgxStr {
+    /// Create an [`NgxStr`] from a pointer and a length, as stored in an `ngx_str_t`.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a `data` pointer that points to range of bytes of at least `len`
+    /// bytes, whose content remains valid and doesn't change for the lifetime of the returned
+    /// `NgxStr`. `data` may be null if `len` is zero.
+    pub unsafe fn from_raw_parts<'a>(data: *const u8, len: usize) -> &'a NgxStr {
+        if len == 0 {
+            return Default::default();
+        }
+        slice::from_raw_parts(data, len).into()
+    }
+
+    /// Create an [`NgxStr`] from an [`ngx_str_t`], or any other string that converts to a byte
+    /// slice.
+    ///
+    /// [`ngx_str_t`]: https://nginx.org/en/docs/dev/development_guide.html#string_overview
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid `ngx_str_t` with a `data` pointer that points
+    /// to range of bytes of at least `len` bytes, whose content remains valid and doesn't
+    /// change for the lifetime of the returned `NgxStr`.
+    pub unsafe fn from_ngx_str<'a, S: Into<&'a [u8]>>(str: S) -> &'a NgxStr {
+        str.into().into()
+    }
+
+    /// Access the [`NgxStr`] as a byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Yields a `&str` slice if the [`NgxStr`] contains valid UTF-8.
+    pub fn to_str(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(self.as_bytes())
+    }
+
+    /// Converts an [`NgxStr`] into a [`Cow<str>`], replacing invalid UTF-8 sequences.
+    ///
+    /// See [`String::from_utf8_lossy`].
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.as_bytes())
+    }
+
+    /// Returns the length of the [`NgxStr`] in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the [`NgxStr`] is empty, otherwise `false`.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl PartialEq for NgxStr {
+    fn eq(&self, other: &NgxStr) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for NgxStr {}
+
+impl PartialEq<str> for NgxStr {
+    fn eq(&self, other: &str) -> bool {
+        &self.0 == other.as_bytes()
+    }
+}
+
+impl PartialEq<[u8]> for NgxStr {
+    fn eq(&self, other: &[u8]) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<NgxStr> for str {
+    fn eq(&self, other: &NgxStr) -> bool {
+        other == self
+    }
+}
+
+impl From<&[u8]> for &NgxStr {
+    fn from(bytes: &[u8]) -> Self {
+        // SAFETY: An `NgxStr` is identical to a `[u8]` slice.
+        unsafe { &*(bytes as *const [u8] as *const NgxStr) }
+    }
+}
+
+impl From<&str> for &NgxStr {
+    fn from(s: &str) -> Self {
+        s.as_bytes().into()
+    }
+}
+
+impl AsRef<[u8]> for NgxStr {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Default for &NgxStr {
+    fn default() -> Self {
+        let empty: &[u8] = &[];
+        empty.into()
+    }
+}
+
+impl fmt::Display for NgxStr {
+    /// Formats the string, replacing invalid UTF-8 sequences without allocating.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            f.write_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() {
+                f.write_char(char::REPLACEMENT_CHARACTER)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for NgxStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        for chunk in self.0.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                for c in c.escape_debug() {
+                    f.write_char(c)?;
+                }
+            }
+            for b in chunk.invalid() {
+                write!(f, "\\x{:02x}", b)?;
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_fmt() {
+        let s: &NgxStr = b"caf\xc3\xa9 \xff\"x\"".as_slice().into();
+        assert_eq!(format!("{}", s), "caf\u{e9} \u{fffd}\"x\"");
+        assert_eq!(format!("{:?}", s), "\"caf\u{e9} \\xff\\\"x\\\"\"");
+    }
+
+    #[test]
+    fn test_eq() {
+        let s: &NgxStr = "text/html".into();
+        assert!(s == "text/html");
+        assert!(*"text/html" == *s);
+        assert!(s == b"text/html".as_slice());
+        assert!(s != <&NgxStr>::from("text/plain"));
+        assert_eq!(s.len(), 9);
+    }
+}
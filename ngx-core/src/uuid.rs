@@ -0,0 +1,165 @@
+use core::fmt;
+use core::str::FromStr;
+
+/// A universally unique identifier, as defined by [RFC 9562].
+///
+/// Version 4 identifiers are random, and version 7 identifiers start with a millisecond Unix
+/// timestamp followed by random bits, so they sort by creation time and index well in databases.
+/// The random bits are provided by the caller, e.g. from an [`Rng`](crate::Rng):
+///
+/// ```
+/// use ngx_core::{Rng, Uuid};
+///
+/// let mut rng = Rng::from_seed(7);
+/// let mut random = [0; 16];
+/// rng.fill_bytes(&mut random);
+///
+/// let id = Uuid::new_v7(1_700_000_000_000, random);
+/// assert_eq!(id.version(), 7);
+/// assert_eq!(id.timestamp_ms(), Some(1_700_000_000_000));
+/// assert_eq!(id.to_string().parse::<Uuid>(), Ok(id));
+/// ```
+///
+/// [RFC 9562]: https://www.rfc-editor.org/rfc/rfc9562
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid([u8; 16]);
+
+/// An error returned when parsing a [`Uuid`] from its text representation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidUuid;
+
+impl fmt::Display for InvalidUuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid UUID")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidUuid {}
+
+impl Uuid {
+    /// The nil UUID, with all bits set to zero.
+    pub const NIL: Uuid = Uuid([0; 16]);
+
+    /// Creates a UUID from its bytes, in network order.
+    pub const fn from_bytes(bytes: [u8; 16]) -> Self {
+        Uuid(bytes)
+    }
+
+    /// Returns the bytes of the UUID, in network order.
+    pub const fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    /// Creates a version 4 UUID from 16 random bytes.
+    pub fn new_v4(random: [u8; 16]) -> Self {
+        Uuid(random).with_version(4)
+    }
+
+    /// Creates a version 7 UUID from a Unix timestamp in milliseconds and 16 random bytes, of
+    /// which the first 10 are used.
+    pub fn new_v7(unix_ms: u64, random: [u8; 16]) -> Self {
+        let mut bytes = [0; 16];
+        bytes[..6].copy_from_slice(&unix_ms.to_be_bytes()[2..]);
+        bytes[6..].copy_from_slice(&random[..10]);
+        Uuid(bytes).with_version(7)
+    }
+
+    /// Returns the version of the UUID.
+    pub fn version(&self) -> u8 {
+        self.0[6] >> 4
+    }
+
+    /// Returns the Unix timestamp in milliseconds of a version 7 UUID.
+    pub fn timestamp_ms(&self) -> Option<u64> {
+        if self.version() != 7 {
+            return None;
+        }
+        let mut bytes = [0; 8];
+        bytes[2..].copy_from_slice(&self.0[..6]);
+        Some(u64::from_be_bytes(bytes))
+    }
+
+    fn with_version(mut self, version: u8) -> Self {
+        self.0[6] = (self.0[6] & 0x0f) | (version << 4);
+        // the RFC 9562 variant
+        self.0[8] = (self.0[8] & 0x3f) | 0x80;
+        self
+    }
+}
+
+impl fmt::Display for Uuid {
+    /// Formats the UUID as lowercase hexadecimal digits, grouped with hyphens.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Uuid({})", self)
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = InvalidUuid;
+
+    /// Parses the hyphenated form of a UUID, in either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.as_bytes();
+        if s.len() != 36 {
+            return Err(InvalidUuid);
+        }
+
+        let mut bytes = [0; 16];
+        let mut digits = s.iter().enumerate().filter(|(i, _)| !matches!(i, 8 | 13 | 18 | 23));
+        for byte in bytes.iter_mut() {
+            let (_, hi) = digits.next().ok_or(InvalidUuid)?;
+            let (_, lo) = digits.next().ok_or(InvalidUuid)?;
+            *byte = (hex_digit(*hi)? << 4) | hex_digit(*lo)?;
+        }
+        if [8, 13, 18, 23].iter().any(|&i| s[i] != b'-') {
+            return Err(InvalidUuid);
+        }
+        Ok(Uuid(bytes))
+    }
+}
+
+fn hex_digit(c: u8) -> Result<u8, InvalidUuid> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(InvalidUuid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_uuid() {
+        let v4 = Uuid::new_v4([0xff; 16]);
+        assert_eq!(v4.to_string(), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+        assert_eq!(v4.version(), 4);
+        assert_eq!(v4.timestamp_ms(), None);
+
+        let v7 = Uuid::new_v7(0x0123_4567_89ab, [0; 16]);
+        assert_eq!(v7.to_string(), "01234567-89ab-7000-8000-000000000000");
+        assert!(Uuid::new_v7(0x0123_4567_89ac, [0; 16]) > Uuid::new_v7(0x0123_4567_89ab, [0xff; 16]));
+
+        assert_eq!("01234567-89AB-7000-8000-000000000000".parse(), Ok(v7));
+        assert_eq!("01234567-89ab-7000-8000-00000000000".parse::<Uuid>(), Err(InvalidUuid));
+        assert_eq!("01234567x89ab-7000-8000-000000000000".parse::<Uuid>(), Err(InvalidUuid));
+        assert_eq!("01234567-89ab-7000-8000-00000000000g".parse::<Uuid>(), Err(InvalidUuid));
+        assert_eq!(Uuid::NIL.to_string(), "00000000-0000-0000-0000-000000000000");
+    }
+}
@@ -0,0 +1,357 @@
+use alloc::vec::Vec;
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+/// Deadlines further than this many ticks away are clamped to the last level.
+const MAX_TICKS: u64 = 1 << (SLOT_BITS as usize * LEVELS);
+const NIL: usize = usize::MAX;
+
+/// A hierarchical timer wheel, scheduling many cheap timeouts with O(1) insertion and removal.
+///
+/// Time is measured in ticks of an arbitrary resolution. Entries are stored in six levels of
+/// 64 slots, each level covering a 64 times longer period than the previous one; entries move to
+/// lower levels as their deadline approaches. The wheel is driven by [`TimerWheel::advance`],
+/// typically from a single event timer armed for [`TimerWheel::next_deadline`].
+///
+/// ```
+/// use ngx_core::TimerWheel;
+///
+/// let mut wheel = TimerWheel::new(0);
+/// let session = wheel.insert(1000, "session");
+/// wheel.insert(30, "window");
+/// assert_eq!(wheel.next_deadline(), Some(30));
+///
+/// let mut expired = Vec::new();
+/// wheel.advance(500, |value| expired.push(value));
+/// assert_eq!(expired, ["window"]);
+///
+/// assert_eq!(wheel.remove(session), Some("session"));
+/// assert!(wheel.is_empty());
+/// ```
+pub struct TimerWheel<T> {
+    levels: [Level; LEVELS],
+    entries: Vec<Entry<T>>,
+    free: usize,
+    /// Entries with a deadline that already passed when they were inserted.
+    expired: usize,
+    elapsed: u64,
+    len: usize,
+}
+
+/// A handle to an entry of a [`TimerWheel`], used to remove it before it expires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimerKey {
+    index: usize,
+    generation: u64,
+}
+
+struct Level {
+    occupied: u64,
+    slots: [usize; SLOTS],
+}
+
+struct Entry<T> {
+    value: Option<T>,
+    deadline: u64,
+    generation: u64,
+    prev: usize,
+    next: usize,
+    /// `(level, slot)` of the list holding the entry, or `None` for the expired list.
+    list: Option<(usize, usize)>,
+}
+
+impl<T> TimerWheel<T> {
+    /// Creates an empty wheel with the current time `now`.
+    pub fn new(now: u64) -> Self {
+        TimerWheel {
+            levels: core::array::from_fn(|_| Level {
+                occupied: 0,
+                slots: [NIL; SLOTS],
+            }),
+            entries: Vec::new(),
+            free: NIL,
+            expired: NIL,
+            elapsed: now,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of scheduled entries.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no entries are scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the time the wheel was last advanced to.
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    /// Schedules `value` to expire at `deadline`.
+    ///
+    /// An entry with a deadline that already passed expires on the next call to
+    /// [`TimerWheel::advance`].
+    pub fn insert(&mut self, deadline: u64, value: T) -> TimerKey {
+        let index = if self.free != NIL {
+            let index = self.free;
+            self.free = self.entries[index].next;
+            index
+        } else {
+            self.entries.push(Entry {
+                value: None,
+                deadline: 0,
+                generation: 0,
+                prev: NIL,
+                next: NIL,
+                list: None,
+            });
+            self.entries.len() - 1
+        };
+
+        let entry = &mut self.entries[index];
+        entry.value = Some(value);
+        entry.deadline = deadline;
+        entry.generation += 1;
+        let generation = entry.generation;
+
+        self.len += 1;
+        self.schedule(index);
+        TimerKey { index, generation }
+    }
+
+    /// Removes a scheduled entry, returning its value, or `None` if it already expired or was
+    /// removed.
+    pub fn remove(&mut self, key: TimerKey) -> Option<T> {
+        let entry = self.entries.get(key.index)?;
+        if entry.generation != key.generation || entry.value.is_none() {
+            return None;
+        }
+
+        self.unlink(key.index);
+        self.len -= 1;
+        Some(self.release(key.index))
+    }
+
+    /// Returns the deadline of a scheduled entry.
+    pub fn deadline(&self, key: TimerKey) -> Option<u64> {
+        let entry = self.entries.get(key.index)?;
+        (entry.generation == key.generation && entry.value.is_some()).then_some(entry.deadline)
+    }
+
+    /// Returns the time at which [`TimerWheel::advance`] should be called next, or `None` if no
+    /// entries are scheduled.
+    ///
+    /// The time may be earlier than the earliest deadline, when entries of a higher level need to
+    /// move to a lower one, but never later.
+    pub fn next_deadline(&self) -> Option<u64> {
+        if self.expired != NIL {
+            return Some(self.elapsed);
+        }
+        (0..LEVELS).find_map(|level| self.next_slot(level).map(|(_, deadline)| deadline))
+    }
+
+    /// Advances the wheel to `now`, calling `on_expire` for each entry with a deadline not later
+    /// than `now`.
+    pub fn advance<F: FnMut(T)>(&mut self, now: u64, mut on_expire: F) {
+        while self.expired != NIL {
+            let index = self.expired;
+            self.unlink(index);
+            self.len -= 1;
+            on_expire(self.release(index));
+        }
+
+        while let Some((level, slot, deadline)) =
+            (0..LEVELS).find_map(|level| self.next_slot(level).map(|(slot, deadline)| (level, slot, deadline)))
+        {
+            if deadline > now {
+                break;
+            }
+
+            self.elapsed = self.elapsed.max(deadline);
+            self.levels[level].occupied &= !(1 << slot);
+            let mut index = core::mem::replace(&mut self.levels[level].slots[slot], NIL);
+
+            while index != NIL {
+                let next = self.entries[index].next;
+                if self.entries[index].deadline <= self.elapsed {
+                    self.len -= 1;
+                    on_expire(self.release(index));
+                } else {
+                    self.schedule(index);
+                }
+                index = next;
+            }
+        }
+
+        self.elapsed = self.elapsed.max(now);
+    }
+
+    /// Returns the next occupied slot of `level` and the time it must be processed at.
+    fn next_slot(&self, level: usize) -> Option<(usize, u64)> {
+        let occupied = self.levels[level].occupied;
+        if occupied == 0 {
+            return None;
+        }
+
+        let slot_range = 1u64 << (SLOT_BITS as usize * level);
+        let level_range = slot_range << SLOT_BITS;
+
+        let now_slot = (self.elapsed / slot_range) as u32 % SLOTS as u32;
+        let slot = (occupied.rotate_right(now_slot).trailing_zeros() + now_slot) as usize % SLOTS;
+
+        let level_start = self.elapsed & !(level_range - 1);
+        let mut deadline = level_start + slot as u64 * slot_range;
+        if (slot as u32) < now_slot {
+            // the slot belongs to the next rotation of the level
+            deadline += level_range;
+        }
+        Some((slot, deadline.max(self.elapsed)))
+    }
+
+    /// Links the entry at `index` into the list matching its deadline.
+    fn schedule(&mut self, index: usize) {
+        let deadline = self.entries[index].deadline;
+        if deadline <= self.elapsed {
+            let head = self.expired;
+            self.link(index, None, head);
+            self.expired = index;
+            return;
+        }
+
+        let mut masked = (self.elapsed ^ deadline) | (SLOTS as u64 - 1);
+        if masked >= MAX_TICKS {
+            masked = MAX_TICKS - 1;
+        }
+        let level = ((63 - masked.leading_zeros()) / SLOT_BITS) as usize;
+        let deadline = if deadline - self.elapsed >= MAX_TICKS {
+            self.elapsed + MAX_TICKS - 1
+        } else {
+            deadline
+        };
+        let slot = (deadline >> (SLOT_BITS as usize * level)) as usize % SLOTS;
+
+        let head = self.levels[level].slots[slot];
+        self.link(index, Some((level, slot)), head);
+        self.levels[level].slots[slot] = index;
+        self.levels[level].occupied |= 1 << slot;
+    }
+
+    fn link(&mut self, index: usize, list: Option<(usize, usize)>, head: usize) {
+        let entry = &mut self.entries[index];
+        entry.list = list;
+        entry.prev = NIL;
+        entry.next = head;
+        if head != NIL {
+            self.entries[head].prev = index;
+        }
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (prev, next, list) = {
+            let entry = &self.entries[index];
+            (entry.prev, entry.next, entry.list)
+        };
+
+        if next != NIL {
+            self.entries[next].prev = prev;
+        }
+        if prev != NIL {
+            self.entries[prev].next = next;
+            return;
+        }
+
+        match list {
+            Some((level, slot)) => {
+                self.levels[level].slots[slot] = next;
+                if next == NIL {
+                    self.levels[level].occupied &= !(1 << slot);
+                }
+            }
+            None => self.expired = next,
+        }
+    }
+
+    /// Takes the value of the unlinked entry at `index` and adds the entry to the free list.
+    fn release(&mut self, index: usize) -> T {
+        let entry = &mut self.entries[index];
+        entry.next = self.free;
+        entry.prev = NIL;
+        self.free = index;
+        entry.value.take().expect("scheduled entry has a value")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn advance(wheel: &mut TimerWheel<u64>, now: u64) -> Vec<u64> {
+        let mut expired = vec![];
+        wheel.advance(now, |value| expired.push(value));
+        expired.sort();
+        expired
+    }
+
+    #[test]
+    fn test_wheel_expiry() {
+        let mut wheel = TimerWheel::new(100);
+        for deadline in [101, 163, 164, 5000, 100_000, 7_000_000] {
+            wheel.insert(deadline, deadline);
+        }
+        assert_eq!(wheel.len(), 6);
+
+        assert_eq!(advance(&mut wheel, 100), []);
+        assert_eq!(advance(&mut wheel, 163), [101, 163]);
+        assert_eq!(advance(&mut wheel, 4999), [164]);
+        assert_eq!(advance(&mut wheel, 5000), [5000]);
+        assert_eq!(advance(&mut wheel, 1_000_000), [100_000]);
+        assert_eq!(advance(&mut wheel, 7_000_000), [7_000_000]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_wheel_step_by_step() {
+        // every deadline expires exactly at its time, whatever the advance granularity
+        for step in [1, 7, 64, 1000] {
+            let mut wheel = TimerWheel::new(0);
+            let deadlines: Vec<u64> = (0..200).map(|i| i * 37 % 5000 + 1).collect();
+            for &deadline in &deadlines {
+                wheel.insert(deadline, deadline);
+            }
+
+            let mut now = 0;
+            while !wheel.is_empty() {
+                let next = wheel.next_deadline().unwrap();
+                assert!(next <= *deadlines.iter().filter(|d| **d > now).min().unwrap_or(&u64::MAX));
+                now += step;
+                wheel.advance(now, |deadline| assert!(deadline <= now && deadline > now - step));
+            }
+        }
+    }
+
+    #[test]
+    fn test_wheel_remove() {
+        let mut wheel = TimerWheel::new(0);
+        let a = wheel.insert(10, 10);
+        let b = wheel.insert(10, 20);
+        let past = wheel.insert(0, 0);
+
+        assert_eq!(wheel.next_deadline(), Some(0));
+        assert_eq!(wheel.remove(past), Some(0));
+        assert_eq!(wheel.remove(a), Some(10));
+        assert_eq!(wheel.remove(a), None);
+        assert_eq!(wheel.deadline(b), Some(10));
+
+        // the slot of a removed entry is reused with a new generation
+        let c = wheel.insert(20, 30);
+        assert_eq!(wheel.remove(a), None);
+        assert_eq!(advance(&mut wheel, 20), [20, 30]);
+        assert_eq!(wheel.remove(c), None);
+    }
+}
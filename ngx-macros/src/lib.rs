@@ -0,0 +1,661 @@
+//! # ngx-macros
+//!
+//! Procedural macros of the [ngx](https://crates.io/crates/ngx) NGINX module SDK.
+//!
+//! The macros are re-exported by `ngx`, and the code they generate refers to it, so module code
+//! does not need to depend on this crate directly.
+#![warn(missing_docs)]
+
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Derives the command array of the directives stored in a configuration structure.
+///
+/// The structure gets a `COMMANDS` constant, an array of `ngx_command_t` terminated by a null
+/// command, with a command for each field annotated with `#[directive(...)]`:
+///
+/// ```rust,ignore
+/// #[derive(Debug, Default, Commands)]
+/// #[commands(context = NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF, conf = NGX_RS_HTTP_LOC_CONF_OFFSET)]
+/// struct ModuleConfig {
+///     #[directive(name = "awssigv4", slot = flag)]
+///     enable: bool,
+///     #[directive(name = "awssigv4_access_key", take = 1)]
+///     access_key: String,
+///     #[directive(name = "awssigv4_s3_bucket", handler = set_s3_bucket)]
+///     s3_bucket: String,
+/// }
+///
+/// #[no_mangle]
+/// static mut ngx_http_awssigv4_commands: [ngx_command_t; 4] = ModuleConfig::COMMANDS;
+/// ```
+///
+/// The structure attribute `#[commands(...)]` takes the following options:
+///
+/// - `context`: the configuration contexts the directives are allowed in, e.g.
+///   `NGX_HTTP_LOC_CONF`.
+/// - `conf`: the offset of the structure in the module context, e.g.
+///   `NGX_RS_HTTP_LOC_CONF_OFFSET`. Defaults to `0`.
+///
+/// The field attribute `#[directive(...)]` takes the following options:
+///
+/// - `name`: the name of the directive, required.
+/// - `take`: the number of arguments, from 1 to 7, defaulting to 1. The field is parsed with
+///   `FromArg` when the directive takes one argument, and is a tuple of `FromArg` values
+///   otherwise, see `Command::value` and `Command::tuple`.
+/// - `slot`: one of the prebuilt setters `flag`, `num`, `size`, `msec` and `str`, e.g.
+///   `Command::size`, instead of `FromArg`.
+/// - `list`: a parser of the elements of a `PoolVec` field, for a directive taking one or more
+///   arguments, see `Command::list`.
+/// - `handler`: a handler written against the raw NGINX API, taking `take` arguments, see
+///   `Command::handler`. The field is not accessed by the generated code.
+/// - `context`: the configuration contexts of the directive, overriding the ones of the
+///   structure.
+///
+/// The field may be an `Option` of the value, see `SlotField`, spelled `Option<T>` for the
+/// derive to see through it. Generic structures are not supported.
+#[proc_macro_derive(Commands, attributes(commands, directive))]
+pub fn derive_commands(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(output) => output,
+        Err(err) => err.into_compile_error(),
+    }
+}
+
+/// Derives `DescribeConf` for a configuration structure, describing each of its fields.
+///
+/// With the structure attribute `#[describe(redacted)]`, the structure also gets an
+/// implementation of `RedactedConf`, the marker required to expose the description, e.g. with
+/// `Request::send_conf_dump`. The attribute asserts that every field holding a secret is marked
+/// with `#[describe(redact)]`, skipped, or has the type `Secret`:
+///
+/// ```rust,ignore
+/// #[derive(Debug, Default, Commands, DescribeConf)]
+/// #[commands(context = NGX_HTTP_LOC_CONF, conf = NGX_RS_HTTP_LOC_CONF_OFFSET)]
+/// #[describe(redacted)]
+/// struct ModuleConfig {
+///     #[directive(name = "awssigv4", slot = flag)]
+///     enable: bool,
+///     #[directive(name = "awssigv4_secret_key", take = 1)]
+///     #[describe(redact)]
+///     secret_key: Secret,
+///     #[describe(skip)]
+///     cache: Option<Cache>,
+/// }
+/// ```
+///
+/// A field is described by the name of its directive, or by its own name, with the value
+/// `ConfValue::from(&self.field)`. The field attribute `#[describe(...)]` takes the following
+/// options:
+///
+/// - `name`: the name of the field in the description, as a string literal.
+/// - `redact`: the value is replaced with `REDACTED`, as for a `Secret`.
+/// - `skip`: the field is not described.
+///
+/// Generic structures are not supported.
+#[proc_macro_derive(DescribeConf, attributes(describe))]
+pub fn derive_describe_conf(input: TokenStream) -> TokenStream {
+    match expand_describe(input) {
+        Ok(output) => output,
+        Err(err) => err.into_compile_error(),
+    }
+}
+
+struct Error {
+    span: Span,
+    message: String,
+}
+
+impl Error {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        Error {
+            span,
+            message: message.into(),
+        }
+    }
+
+    fn into_compile_error(self) -> TokenStream {
+        let mut message = Group::new(
+            Delimiter::Parenthesis,
+            TokenStream::from(TokenTree::Literal(Literal::string(&self.message))),
+        );
+        message.set_span(self.span);
+
+        [
+            TokenTree::Ident(Ident::new("compile_error", self.span)),
+            TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+            TokenTree::Group(message),
+            TokenTree::Punct(Punct::new(';', Spacing::Alone)),
+        ]
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(self.span);
+            token
+        })
+        .collect()
+    }
+}
+
+/// A `key = value` option of an attribute.
+struct Param {
+    key: Ident,
+    value: Vec<TokenTree>,
+}
+
+impl Param {
+    fn value_string(&self) -> String {
+        self.value.iter().cloned().collect::<TokenStream>().to_string()
+    }
+}
+
+/// How the value of a directive is set.
+enum Setter {
+    Value,
+    Slot(Ident),
+    List(String),
+    Handler(String),
+}
+
+struct Directive {
+    field: Ident,
+    /// The type of the field, or of its value if it is an `Option`.
+    ty: String,
+    name: String,
+    take: usize,
+    setter: Setter,
+    context: Option<String>,
+}
+
+/// Parses a struct with named fields, passing its attributes to `attr`, and returns its name and
+/// fields.
+fn parse_struct(
+    input: TokenStream,
+    derive: &str,
+    mut attr: impl FnMut(&Group) -> Result<(), Error>,
+) -> Result<(Ident, Group), Error> {
+    let mut tokens = input.into_iter();
+
+    let name = loop {
+        match tokens.next() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '#' => {
+                let Some(TokenTree::Group(group)) = tokens.next() else {
+                    return Err(Error::new(punct.span(), "expected an attribute"));
+                };
+                attr(&group)?;
+            }
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "struct" => match tokens.next() {
+                Some(TokenTree::Ident(name)) => break name,
+                _ => return Err(Error::new(ident.span(), "expected the name of the struct")),
+            },
+            Some(TokenTree::Ident(ident)) if matches!(ident.to_string().as_str(), "enum" | "union") => {
+                return Err(Error::new(
+                    ident.span(),
+                    format!("`{derive}` can only be derived for structs"),
+                ));
+            }
+            // visibility
+            Some(_) => {}
+            None => return Err(Error::new(Span::call_site(), "expected a struct")),
+        }
+    };
+
+    match tokens.next() {
+        Some(TokenTree::Group(fields)) if fields.delimiter() == Delimiter::Brace => Ok((name, fields)),
+        Some(TokenTree::Punct(punct)) if punct.as_char() == '<' => Err(Error::new(
+            punct.span(),
+            format!("`{derive}` cannot be derived for generic structs"),
+        )),
+        _ => Err(Error::new(
+            name.span(),
+            format!("`{derive}` can only be derived for structs with named fields"),
+        )),
+    }
+}
+
+fn expand(input: TokenStream) -> Result<TokenStream, Error> {
+    let mut context = None;
+    let mut conf = None;
+
+    let (name, fields) = parse_struct(input, "Commands", |attr| {
+        for param in parse_attr(attr, "commands", &[])?.unwrap_or_default() {
+            match param.key.to_string().as_str() {
+                "context" => context = Some(param.value_string()),
+                "conf" => conf = Some(param.value_string()),
+                key => {
+                    return Err(Error::new(
+                        param.key.span(),
+                        format!("unknown `commands` option `{key}`"),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    let Some(context) = context else {
+        return Err(Error::new(
+            name.span(),
+            "missing `#[commands(context = ...)]` attribute",
+        ));
+    };
+    let conf = conf.unwrap_or_else(|| String::from("0"));
+
+    let mut commands = Vec::new();
+    for directive in parse_fields(fields.stream())? {
+        let cname = format!("c{}", directive.name);
+        let field = &directive.field;
+        let accessor = format!("|conf: &mut {name}| &mut conf.{field}");
+        let command = match &directive.setter {
+            // the value type is ambiguous for an `Option` field
+            Setter::Value if directive.take == 1 => format!(
+                "::ngx::core::Command::value::<{name}, {}, _>({cname}, {accessor})",
+                directive.ty
+            ),
+            Setter::Value => {
+                // the arity of the tuple is checked against the type of the field
+                let tuple = vec!["_"; directive.take].join(", ");
+                format!("::ngx::core::Command::tuple::<_, ({tuple}), _>({cname}, {accessor})")
+            }
+            Setter::Slot(slot) => format!("::ngx::core::Command::{slot}({cname}, {accessor})"),
+            Setter::List(parser) => {
+                format!("::ngx::core::Command::list({cname}, &::ngx::core::ListField::new({parser}, {accessor}))")
+            }
+            Setter::Handler(handler) => format!(
+                "::ngx::core::Command::handler({cname}, ::ngx::ffi::NGX_CONF_TAKE{}, {handler})",
+                directive.take
+            ),
+        };
+        let context = directive.context.as_ref().unwrap_or(&context);
+        commands.push(format!("{command}.context({context}).conf({conf}).build(),"));
+    }
+
+    let output = format!(
+        "impl {name} {{
+            /// The commands of the directives of the structure, terminated by a null command.
+            pub const COMMANDS: [::ngx::ffi::ngx_command_t; {len}] = [
+                {commands}
+                ::ngx::core::Command::null(),
+            ];
+        }}",
+        len = commands.len() + 1,
+        commands = commands.concat(),
+    );
+    output
+        .parse()
+        .map_err(|_| Error::new(name.span(), "invalid `Commands` attribute options"))
+}
+
+/// A field described by `DescribeConf`.
+struct Described {
+    field: String,
+    name: String,
+    redact: bool,
+}
+
+fn expand_describe(input: TokenStream) -> Result<TokenStream, Error> {
+    let mut redacted = false;
+    let (name, fields) = parse_struct(input, "DescribeConf", |attr| {
+        for param in parse_attr(attr, "describe", &["redacted"])?.unwrap_or_default() {
+            match param.key.to_string().as_str() {
+                "redacted" if !param.value.is_empty() => {
+                    return Err(Error::new(param.key.span(), "`redacted` takes no value"))
+                }
+                "redacted" => redacted = true,
+                key => {
+                    return Err(Error::new(
+                        param.key.span(),
+                        format!("unknown `describe` structure option `{key}`"),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    let described = parse_described_fields(fields.stream())?;
+    describe_impls(&name.to_string(), &described, redacted)
+        .parse()
+        .map_err(|_| Error::new(name.span(), "invalid `describe` attribute options"))
+}
+
+/// Returns the implementations derived by `DescribeConf` for the structure `name`.
+///
+/// `RedactedConf` is only implemented if the structure is marked `#[describe(redacted)]`, as the
+/// derive cannot tell which fields hold secrets.
+fn describe_impls(name: &str, fields: &[Described], redacted: bool) -> String {
+    let mut described = Vec::new();
+    for Described { field, name, redact } in fields {
+        if *redact {
+            described.push(format!("dump.field({name}, ::ngx::core::REDACTED);"));
+        } else {
+            described.push(format!("dump.field({name}, &self.{field});"));
+        }
+    }
+
+    let mut output = format!(
+        "impl ::ngx::core::DescribeConf for {name} {{
+            fn describe(&self, dump: &mut ::ngx::core::ConfDump) {{
+                {described}
+            }}
+        }}",
+        described = described.concat(),
+    );
+    if redacted {
+        output.push_str(&format!("impl ::ngx::core::RedactedConf for {name} {{}}"));
+    }
+    output
+}
+
+fn parse_described_fields(stream: TokenStream) -> Result<Vec<Described>, Error> {
+    let mut described = Vec::new();
+
+    for field in split_commas(stream) {
+        let mut tokens = field.into_iter().peekable();
+        let mut name = None;
+        let mut directive = None;
+        let mut redact = false;
+        let mut skip = false;
+
+        while let Some(TokenTree::Punct(punct)) = tokens.peek() {
+            if punct.as_char() != '#' {
+                break;
+            }
+            let span = punct.span();
+            tokens.next();
+            let Some(TokenTree::Group(attr)) = tokens.next() else {
+                return Err(Error::new(span, "expected an attribute"));
+            };
+            for param in parse_attr(&attr, "directive", &[])?.unwrap_or_default() {
+                if param.key.to_string() == "name" {
+                    directive = Some(param.value_string());
+                }
+            }
+            for param in parse_attr(&attr, "describe", &["redact", "skip"])?.unwrap_or_default() {
+                let key = param.key.to_string();
+                match key.as_str() {
+                    "name" => match param.value.as_slice() {
+                        [TokenTree::Literal(lit)] if lit.to_string().starts_with('"') => name = Some(lit.to_string()),
+                        _ => return Err(Error::new(param.key.span(), "`name` must be a string literal")),
+                    },
+                    "redact" | "skip" if !param.value.is_empty() => {
+                        return Err(Error::new(param.key.span(), format!("`{key}` takes no value")))
+                    }
+                    "redact" => redact = true,
+                    "skip" => skip = true,
+                    _ => {
+                        return Err(Error::new(
+                            param.key.span(),
+                            format!("unknown `describe` option `{key}`"),
+                        ))
+                    }
+                }
+            }
+        }
+
+        // visibility
+        if matches!(tokens.peek(), Some(TokenTree::Ident(ident)) if ident.to_string() == "pub") {
+            tokens.next();
+            if matches!(tokens.peek(), Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis) {
+                tokens.next();
+            }
+        }
+
+        let field = match tokens.next() {
+            Some(TokenTree::Ident(field)) => field,
+            Some(token) => return Err(Error::new(token.span(), "expected a field name")),
+            None => continue,
+        };
+        if skip {
+            continue;
+        }
+        let name = name
+            .or(directive)
+            .unwrap_or_else(|| format!("\"{}\"", field.to_string().trim_start_matches("r#")));
+        described.push(Described {
+            field: field.to_string(),
+            name,
+            redact,
+        });
+    }
+
+    Ok(described)
+}
+
+fn parse_fields(stream: TokenStream) -> Result<Vec<Directive>, Error> {
+    let mut directives = Vec::new();
+
+    for field in split_commas(stream) {
+        let mut tokens = field.into_iter().peekable();
+        let mut params = None;
+
+        while let Some(TokenTree::Punct(punct)) = tokens.peek() {
+            if punct.as_char() != '#' {
+                break;
+            }
+            let span = punct.span();
+            tokens.next();
+            let Some(TokenTree::Group(attr)) = tokens.next() else {
+                return Err(Error::new(span, "expected an attribute"));
+            };
+            if let Some(attr_params) = parse_attr(&attr, "directive", &[])? {
+                if params.is_some() {
+                    return Err(Error::new(attr.span(), "duplicate `directive` attribute"));
+                }
+                params = Some(attr_params);
+            }
+        }
+
+        // visibility
+        if matches!(tokens.peek(), Some(TokenTree::Ident(ident)) if ident.to_string() == "pub") {
+            tokens.next();
+            if matches!(tokens.peek(), Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis) {
+                tokens.next();
+            }
+        }
+
+        let field = match tokens.next() {
+            Some(TokenTree::Ident(field)) => field,
+            Some(token) => return Err(Error::new(token.span(), "expected a field name")),
+            None => continue,
+        };
+        // the colon
+        tokens.next();
+        if let Some(params) = params {
+            directives.push(parse_directive(field, value_type(tokens.collect()), params)?);
+        }
+    }
+
+    Ok(directives)
+}
+
+fn parse_directive(field: Ident, ty: String, params: Vec<Param>) -> Result<Directive, Error> {
+    let mut name = None;
+    let mut take = None;
+    let mut setter = None;
+    let mut context = None;
+
+    for param in params {
+        let key = param.key.to_string();
+        match key.as_str() {
+            "name" => match param.value.as_slice() {
+                [TokenTree::Literal(lit)] if lit.to_string().starts_with('"') => name = Some(lit.to_string()),
+                _ => return Err(Error::new(param.key.span(), "`name` must be a string literal")),
+            },
+            "take" => match param.value.as_slice() {
+                [TokenTree::Literal(lit)] if matches!(lit.to_string().parse(), Ok(1..=7)) => {
+                    take = lit.to_string().parse().ok();
+                }
+                _ => return Err(Error::new(param.key.span(), "`take` must be a number from 1 to 7")),
+            },
+            "slot" | "list" | "handler" => {
+                if setter.is_some() {
+                    return Err(Error::new(
+                        param.key.span(),
+                        "only one of `slot`, `list` and `handler` can be given",
+                    ));
+                }
+                setter = Some(match key.as_str() {
+                    "slot" => match param.value.as_slice() {
+                        [TokenTree::Ident(slot)]
+                            if matches!(slot.to_string().as_str(), "flag" | "num" | "size" | "msec" | "str") =>
+                        {
+                            Setter::Slot(slot.clone())
+                        }
+                        _ => {
+                            return Err(Error::new(
+                                param.key.span(),
+                                "`slot` must be one of `flag`, `num`, `size`, `msec` and `str`",
+                            ))
+                        }
+                    },
+                    "list" => Setter::List(param.value_string()),
+                    _ => Setter::Handler(param.value_string()),
+                });
+            }
+            "context" => context = Some(param.value_string()),
+            _ => {
+                return Err(Error::new(
+                    param.key.span(),
+                    format!("unknown `directive` option `{key}`"),
+                ))
+            }
+        }
+    }
+
+    let Some(name) = name else {
+        return Err(Error::new(field.span(), "missing `name` of the directive"));
+    };
+    let setter = setter.unwrap_or(Setter::Value);
+    if take.is_some() && matches!(setter, Setter::Slot(_) | Setter::List(_)) {
+        return Err(Error::new(
+            field.span(),
+            "`take` cannot be combined with `slot` or `list`",
+        ));
+    }
+
+    Ok(Directive {
+        field,
+        ty,
+        name,
+        take: take.unwrap_or(1),
+        setter,
+        context,
+    })
+}
+
+/// Returns the value type of a field of type `ty`: `T` for an `Option<T>`, and `ty` otherwise.
+fn value_type(ty: Vec<TokenTree>) -> String {
+    let ty: Vec<TokenTree> = match ty.as_slice() {
+        [TokenTree::Ident(option), TokenTree::Punct(open), inner @ .., TokenTree::Punct(close)]
+            if option.to_string() == "Option" && open.as_char() == '<' && close.as_char() == '>' =>
+        {
+            inner.to_vec()
+        }
+        _ => ty,
+    };
+    ty.into_iter().collect::<TokenStream>().to_string()
+}
+
+/// Returns the options of the attribute `name` in `attr`, the brackets of `#[...]`, or `None`
+/// for another attribute.
+///
+/// The options in `flags` are given without a value, e.g. `#[describe(skip)]`.
+fn parse_attr(attr: &Group, name: &str, flags: &[&str]) -> Result<Option<Vec<Param>>, Error> {
+    let mut tokens = attr.stream().into_iter();
+    match tokens.next() {
+        Some(TokenTree::Ident(ident)) if ident.to_string() == name => {}
+        _ => return Ok(None),
+    }
+    let Some(TokenTree::Group(group)) = tokens.next() else {
+        return Err(Error::new(attr.span(), format!("expected `#[{name}(...)]`")));
+    };
+
+    let mut params = Vec::new();
+    for param in split_commas(group.stream()) {
+        let mut tokens = param.into_iter();
+        let key = match tokens.next() {
+            Some(TokenTree::Ident(key)) => key,
+            Some(token) => return Err(Error::new(token.span(), "expected an option name")),
+            None => continue,
+        };
+        match tokens.next() {
+            None if flags.contains(&key.to_string().as_str()) => {
+                params.push(Param { key, value: Vec::new() });
+                continue;
+            }
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '=' => {}
+            _ => return Err(Error::new(key.span(), format!("expected `{key} = ...`"))),
+        }
+        let value: Vec<TokenTree> = tokens.collect();
+        if value.is_empty() {
+            return Err(Error::new(key.span(), format!("missing value of `{key}`")));
+        }
+        params.push(Param { key, value });
+    }
+
+    Ok(Some(params))
+}
+
+/// Splits `stream` at the commas outside of groups and angle brackets, e.g. the fields of a
+/// struct whose types have generic arguments.
+fn split_commas(stream: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut items = vec![Vec::new()];
+    let mut depth = 0usize;
+    let mut arrow = false;
+
+    for token in stream {
+        if let TokenTree::Punct(punct) = &token {
+            match punct.as_char() {
+                ',' if depth == 0 => {
+                    items.push(Vec::new());
+                    arrow = false;
+                    continue;
+                }
+                '<' => depth += 1,
+                // the `>` of `->` in a function type
+                '>' if !arrow => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            arrow = punct.as_char() == '-' && punct.spacing() == Spacing::Joint;
+        } else {
+            arrow = false;
+        }
+        items.last_mut().expect("at least one item").push(token);
+    }
+
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_impls() {
+        let fields = [
+            Described {
+                field: "enable".into(),
+                name: "\"awssigv4\"".into(),
+                redact: false,
+            },
+            Described {
+                field: "secret_key".into(),
+                name: "\"awssigv4_secret_key\"".into(),
+                redact: true,
+            },
+        ];
+
+        let output = describe_impls("ModuleConfig", &fields, false);
+        assert!(output.contains("impl ::ngx::core::DescribeConf for ModuleConfig"));
+        assert!(output.contains("dump.field(\"awssigv4\", &self.enable);"));
+        assert!(output.contains("dump.field(\"awssigv4_secret_key\", ::ngx::core::REDACTED);"));
+        assert!(!output.contains("self.secret_key"));
+        // redacting a field does not certify the others
+        assert!(!output.contains("RedactedConf"));
+
+        let output = describe_impls("ModuleConfig", &fields, true);
+        assert!(output.contains("impl ::ngx::core::RedactedConf for ModuleConfig {}"));
+    }
+}
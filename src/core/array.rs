@@ -0,0 +1,64 @@
+use crate::ffi::*;
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::slice;
+
+/// A borrowed view of the elements of an [`ngx_array_t`] of `T`.
+///
+/// [`ngx_array_t`]: https://nginx.org/en/docs/dev/development_guide.html#array
+#[derive(Clone, Copy)]
+pub struct Array<'a, T> {
+    elts: &'a [T],
+    _array: PhantomData<&'a ngx_array_t>,
+}
+
+impl<'a, T> Array<'a, T> {
+    /// Creates an [`Array`] from an [`ngx_array_t`] pointer.
+    ///
+    /// [`ngx_array_t`]: https://nginx.org/en/docs/dev/development_guide.html#array
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided either a null pointer or a valid `ngx_array_t` whose elements are
+    /// of type `T`, and which is not modified for the lifetime `'a`.
+    pub unsafe fn from_ngx_array(array: *const ngx_array_t) -> Self {
+        let elts = match array.as_ref() {
+            Some(array) if array.nelts > 0 => slice::from_raw_parts(array.elts as *const T, array.nelts),
+            _ => &[],
+        };
+        Array {
+            elts,
+            _array: PhantomData,
+        }
+    }
+
+    /// Returns the elements as a slice.
+    pub fn as_slice(&self) -> &'a [T] {
+        self.elts
+    }
+}
+
+impl<T> Deref for Array<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.elts
+    }
+}
+
+impl<'a, T> IntoIterator for Array<'a, T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elts.iter()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Array<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.elts).finish()
+    }
+}
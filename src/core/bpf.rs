@@ -0,0 +1,138 @@
+use crate::ffi::*;
+
+use std::io;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::os::unix::io::RawFd;
+use std::slice;
+
+/// A single classic BPF instruction.
+///
+/// Mirrors `struct sock_filter` from `<linux/filter.h>`, which is not part of the NGINX bindings.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SockFilter {
+    /// Instruction opcode.
+    pub code: u16,
+    /// Jump offset if the condition is true.
+    pub jt: u8,
+    /// Jump offset if the condition is false.
+    pub jf: u8,
+    /// Generic multiuse field.
+    pub k: u32,
+}
+
+/// Mirrors `struct sock_fprog` from `<linux/filter.h>`.
+#[repr(C)]
+struct SockFprog {
+    len: u16,
+    filter: *const SockFilter,
+}
+
+/// A socket steering program for a `SO_REUSEPORT` group.
+///
+/// See [socket(7)](https://man7.org/linux/man-pages/man7/socket.7.html) for the semantics of
+/// `SO_ATTACH_REUSEPORT_CBPF` and `SO_ATTACH_REUSEPORT_EBPF`.
+#[derive(Clone, Copy, Debug)]
+pub enum ReuseportProgram<'a> {
+    /// Classic BPF program, attached with `SO_ATTACH_REUSEPORT_CBPF`.
+    Classic(&'a [SockFilter]),
+    /// File descriptor of a loaded `BPF_PROG_TYPE_SOCKET_FILTER` program, attached with
+    /// `SO_ATTACH_REUSEPORT_EBPF`.
+    Extended(RawFd),
+}
+
+impl ReuseportProgram<'_> {
+    /// Attaches the program to the socket `fd`.
+    ///
+    /// The program is shared by the whole reuseport group the socket belongs to.
+    pub fn attach(&self, fd: RawFd) -> io::Result<()> {
+        let rc = match *self {
+            ReuseportProgram::Classic(filter) => {
+                if filter.is_empty() || filter.len() > u16::MAX as usize {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "invalid classic BPF program length",
+                    ));
+                }
+                let prog = SockFprog {
+                    len: filter.len() as u16,
+                    filter: filter.as_ptr(),
+                };
+                unsafe {
+                    setsockopt(
+                        fd,
+                        SOL_SOCKET as c_int,
+                        SO_ATTACH_REUSEPORT_CBPF as c_int,
+                        &prog as *const SockFprog as *const c_void,
+                        mem::size_of::<SockFprog>() as socklen_t,
+                    )
+                }
+            }
+            ReuseportProgram::Extended(prog_fd) => unsafe {
+                setsockopt(
+                    fd,
+                    SOL_SOCKET as c_int,
+                    SO_ATTACH_REUSEPORT_EBPF as c_int,
+                    &prog_fd as *const RawFd as *const c_void,
+                    mem::size_of::<RawFd>() as socklen_t,
+                )
+            },
+        };
+
+        if rc == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Attaches a steering program to every open listening socket of `cycle` configured with `reuseport`.
+///
+/// Listening sockets are opened before the `init_module` callbacks run, so this is intended to be
+/// called from the module's `init_module` hook. NGINX clones `reuseport` listening sockets per
+/// worker; all clones belong to the same group and receive the same program.
+///
+/// Returns the number of sockets the program was attached to.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null pointer to an `ngx_cycle_t` whose listening sockets
+/// have already been opened.
+pub unsafe fn attach_reuseport_program(cycle: *mut ngx_cycle_t, program: ReuseportProgram) -> io::Result<usize> {
+    attach_reuseport_program_filtered(cycle, program, |_| true)
+}
+
+/// Same as [`attach_reuseport_program`], but only for the listening sockets accepted by `filter`.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null pointer to an `ngx_cycle_t` whose listening sockets
+/// have already been opened.
+pub unsafe fn attach_reuseport_program_filtered<F>(
+    cycle: *mut ngx_cycle_t,
+    program: ReuseportProgram,
+    filter: F,
+) -> io::Result<usize>
+where
+    F: Fn(&ngx_listening_t) -> bool,
+{
+    let listening = &(*cycle).listening;
+    if listening.nelts == 0 {
+        return Ok(0);
+    }
+
+    let sockets = slice::from_raw_parts(listening.elts as *const ngx_listening_t, listening.nelts);
+    let mut attached = 0;
+
+    for ls in sockets {
+        if ls.fd == -1 || ls.reuseport() == 0 || !filter(ls) {
+            continue;
+        }
+
+        program.attach(ls.fd)?;
+        attached += 1;
+    }
+
+    Ok(attached)
+}
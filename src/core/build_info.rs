@@ -0,0 +1,25 @@
+pub use ngx_core::BuildInfo;
+
+/// Returns the [`BuildInfo`] of the crate invoking the macro.
+///
+/// The name and version are taken from the Cargo package. The commit is taken from the
+/// `VERGEN_GIT_SHA` environment variable if set at compile time, e.g. by
+/// [vergen](https://crates.io/crates/vergen) in a build script.
+///
+/// ```rust,ignore
+/// static BUILD_INFO: BuildInfo = ngx_build_info!();
+///
+/// // in preconfiguration, exposes `$my_module_build`
+/// (*cf).add_build_info_variable("my_module_build", &BUILD_INFO)?;
+/// ```
+#[macro_export]
+macro_rules! ngx_build_info {
+    () => {
+        $crate::core::BuildInfo {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            commit: option_env!("VERGEN_GIT_SHA"),
+            profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+        }
+    };
+}
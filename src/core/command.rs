@@ -0,0 +1,777 @@
+use crate::core::{ngx_conf_result, Array, ConfError, ConfOk, NgxStr, Pool, PoolVec};
+use crate::ffi::*;
+
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_void};
+use std::time::Duration;
+use std::{mem, ptr, slice};
+
+pub use ngx_macros::Commands;
+
+/// A value parsed from a single directive argument.
+pub trait FromArg<'a>: Sized {
+    /// Parses the argument.
+    fn from_arg(arg: &'a NgxStr) -> Result<Self, ConfError>;
+}
+
+impl<'a> FromArg<'a> for &'a NgxStr {
+    fn from_arg(arg: &'a NgxStr) -> Result<Self, ConfError> {
+        Ok(arg)
+    }
+}
+
+impl<'a> FromArg<'a> for &'a str {
+    fn from_arg(arg: &'a NgxStr) -> Result<Self, ConfError> {
+        arg.to_str().map_err(|err| ConfError::from_error(&err))
+    }
+}
+
+impl FromArg<'_> for String {
+    fn from_arg(arg: &NgxStr) -> Result<Self, ConfError> {
+        <&str>::from_arg(arg).map(String::from)
+    }
+}
+
+impl FromArg<'_> for bool {
+    fn from_arg(arg: &NgxStr) -> Result<Self, ConfError> {
+        parse_flag(arg)
+    }
+}
+
+/// Parses a flag directive argument, equivalent to `ngx_conf_set_flag_slot`.
+///
+/// Accepts `on` and `off` in any letter case, and rejects anything else.
+pub fn parse_flag(value: &NgxStr) -> Result<bool, ConfError> {
+    let value = value.as_bytes();
+    if value.eq_ignore_ascii_case(b"on") {
+        Ok(true)
+    } else if value.eq_ignore_ascii_case(b"off") {
+        Ok(false)
+    } else {
+        Err(ConfError::new(format!(
+            "invalid value \"{}\", it must be \"on\" or \"off\"",
+            String::from_utf8_lossy(value)
+        )))
+    }
+}
+
+/// Parses a non-negative number directive argument, equivalent to `ngx_conf_set_num_slot`.
+pub fn parse_number(value: &NgxStr) -> Result<usize, ConfError> {
+    let number = unsafe { ngx_atoi(value.as_bytes().as_ptr() as *mut u8, value.len()) };
+    usize::try_from(number).map_err(|_| ConfError::new("invalid number"))
+}
+
+/// Parses a size directive argument, e.g. `64k` or `1m`, equivalent to
+/// `ngx_conf_set_size_slot`.
+pub fn parse_size(value: &NgxStr) -> Result<usize, ConfError> {
+    let mut value = ngx_str_t {
+        len: value.len(),
+        data: value.as_bytes().as_ptr() as *mut u8,
+    };
+    let size = unsafe { ngx_parse_size(&mut value) };
+    usize::try_from(size).map_err(|_| ConfError::new("invalid value"))
+}
+
+/// Parses a time interval directive argument, e.g. `500ms` or `1m30s`, equivalent to
+/// `ngx_conf_set_msec_slot`.
+pub fn parse_msec(value: &NgxStr) -> Result<Duration, ConfError> {
+    let mut value = ngx_str_t {
+        len: value.len(),
+        data: value.as_bytes().as_ptr() as *mut u8,
+    };
+    let msec = unsafe { ngx_parse_time(&mut value, 0) };
+    u64::try_from(msec)
+        .map(Duration::from_millis)
+        .map_err(|_| ConfError::new("invalid value"))
+}
+
+/// The arguments of the directive being parsed, excluding the directive name.
+///
+/// Arguments are numbered from 0, starting with the first argument after the directive name, the
+/// same way as in [`ConfError::with_arg`].
+#[derive(Clone, Copy)]
+pub struct Args<'a>(&'a [ngx_str_t]);
+
+impl<'a> Args<'a> {
+    /// Returns the arguments of the directive `cf` is currently parsing.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer with the arguments of the
+    /// directive being parsed, as passed to a directive handler.
+    pub unsafe fn from_conf(cf: *const ngx_conf_t) -> Self {
+        let args = &*(*cf).args;
+        if args.nelts == 0 {
+            return Args(&[]);
+        }
+        // the first element is the directive name
+        Args(&slice::from_raw_parts(args.elts as *const ngx_str_t, args.nelts)[1..])
+    }
+
+    /// Returns the number of arguments.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the directive has no arguments.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the argument at `index`, if present.
+    pub fn arg(&self, index: usize) -> Option<&'a NgxStr> {
+        // SAFETY: the arguments are allocated from the configuration pool and outlive the handler.
+        self.0.get(index).map(|arg| unsafe { NgxStr::from_ngx_str(*arg) })
+    }
+
+    /// Returns the first argument, if present.
+    pub fn first(&self) -> Option<&'a NgxStr> {
+        self.arg(0)
+    }
+
+    /// Returns the argument at `index`, or an error naming the missing argument.
+    pub fn require(&self, index: usize) -> Result<&'a NgxStr, ConfError> {
+        self.arg(index)
+            .ok_or_else(|| ConfError::new("missing argument").with_arg(index))
+    }
+
+    /// Returns an iterator over the arguments.
+    pub fn iter(&self) -> ArgsIter<'a> {
+        ArgsIter(self.0.iter())
+    }
+}
+
+/// All the tokens of a line, for a [`BlockParser`], where the first token is not a directive
+/// name.
+impl<'a> From<Array<'a, ngx_str_t>> for Args<'a> {
+    fn from(tokens: Array<'a, ngx_str_t>) -> Self {
+        Args(tokens.as_slice())
+    }
+}
+
+impl<'a> IntoIterator for Args<'a> {
+    type Item = &'a NgxStr;
+    type IntoIter = ArgsIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the [`Args`] of a directive.
+pub struct ArgsIter<'a>(slice::Iter<'a, ngx_str_t>);
+
+impl<'a> Iterator for ArgsIter<'a> {
+    type Item = &'a NgxStr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // SAFETY: the arguments are allocated from the configuration pool and outlive the handler.
+        self.0.next().map(|arg| unsafe { NgxStr::from_ngx_str(*arg) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// The typed arguments of a directive.
+///
+/// Implemented for `()`, for tuples of up to seven [`FromArg`] values, and for [`Args`]. The
+/// number of arguments NGINX accepts for the directive is derived from the tuple type, so a
+/// directive cannot declare an argument count that differs from what its handler consumes.
+pub trait Arguments<'a>: Sized {
+    /// The `NGX_CONF_NOARGS`/`NGX_CONF_TAKE*` flag matching the number of arguments.
+    const ARGS: ngx_uint_t;
+
+    /// Parses the directive arguments.
+    fn from_args(args: Args<'a>) -> Result<Self, ConfError>;
+}
+
+impl Arguments<'_> for () {
+    const ARGS: ngx_uint_t = NGX_CONF_NOARGS as ngx_uint_t;
+
+    fn from_args(_args: Args) -> Result<Self, ConfError> {
+        Ok(())
+    }
+}
+
+/// Accepts any number of arguments, for directives validating them in [`Directive::set`].
+impl<'a> Arguments<'a> for Args<'a> {
+    const ARGS: ngx_uint_t = NGX_CONF_ANY as ngx_uint_t;
+
+    fn from_args(args: Args<'a>) -> Result<Self, ConfError> {
+        Ok(args)
+    }
+}
+
+macro_rules! impl_arguments {
+    ($args:ident; $($index:tt: $ty:ident),+) => {
+        impl<'a, $($ty: FromArg<'a>),+> Arguments<'a> for ($($ty,)+) {
+            const ARGS: ngx_uint_t = $args as ngx_uint_t;
+
+            fn from_args(args: Args<'a>) -> Result<Self, ConfError> {
+                Ok(($(
+                    $ty::from_arg(args.require($index)?).map_err(|err| err.with_arg($index))?,
+                )+))
+            }
+        }
+    };
+}
+
+impl_arguments!(NGX_CONF_TAKE1; 0: A);
+impl_arguments!(NGX_CONF_TAKE2; 0: A, 1: B);
+impl_arguments!(NGX_CONF_TAKE3; 0: A, 1: B, 2: C);
+impl_arguments!(NGX_CONF_TAKE4; 0: A, 1: B, 2: C, 3: D);
+impl_arguments!(NGX_CONF_TAKE5; 0: A, 1: B, 2: C, 3: D, 4: E);
+impl_arguments!(NGX_CONF_TAKE6; 0: A, 1: B, 2: C, 3: D, 4: E, 5: F);
+impl_arguments!(NGX_CONF_TAKE7; 0: A, 1: B, 2: C, 3: D, 4: E, 5: F, 6: G);
+
+/// A configuration directive with typed arguments.
+///
+/// ```rust,ignore
+/// struct Upstream;
+///
+/// impl Directive for Upstream {
+///     type Conf = ModuleConfig;
+///     type Args<'a> = (&'a str, &'a str);
+///
+///     fn set(_cf: &mut ngx_conf_t, conf: &mut ModuleConfig, (host, port): Self::Args<'_>) -> Result<ConfOk, ConfError> {
+///         conf.host = host.to_string();
+///         conf.port = port.parse().map_err(|err| ConfError::from_error(&err).with_arg(1))?;
+///         Ok(ConfOk::Applied)
+///     }
+/// }
+/// ```
+pub trait Directive {
+    /// The configuration structure the directive is stored in.
+    type Conf;
+    /// The arguments of the directive, see [`Arguments`].
+    type Args<'a>: Arguments<'a>;
+
+    /// Applies the parsed arguments to the configuration.
+    ///
+    /// Returns [`ConfOk::Warning`] to log a non-fatal warning, e.g. for a deprecated value.
+    fn set(cf: &mut ngx_conf_t, conf: &mut Self::Conf, args: Self::Args<'_>) -> Result<ConfOk, ConfError>;
+}
+
+/// Builder for an [`ngx_command_t`] backed by a [`Directive`].
+///
+/// ```rust,ignore
+/// static mut ngx_http_upstream_commands: [ngx_command_t; 2] = [
+///     Command::new::<Upstream>(c"my_upstream")
+///         .context(NGX_HTTP_LOC_CONF)
+///         .conf(NGX_RS_HTTP_LOC_CONF_OFFSET)
+///         .build(),
+///     ngx_null_command!(),
+/// ];
+/// ```
+///
+/// [`ngx_command_t`]: https://nginx.org/en/docs/dev/development_guide.html#config_directives
+#[derive(Clone, Copy)]
+pub struct Command(ngx_command_t);
+
+impl Command {
+    /// Creates a command for the directive `D` named `name`.
+    ///
+    /// The argument count flag is taken from [`Directive::Args`].
+    pub const fn new<D: Directive>(name: &'static CStr) -> Self {
+        Command(ngx_command_t {
+            name: ngx_str_t {
+                len: name.to_bytes().len(),
+                data: name.as_ptr() as *mut u8,
+            },
+            type_: <D::Args<'static> as Arguments<'static>>::ARGS,
+            set: Some(ngx_command_handler::<D>),
+            conf: 0,
+            offset: 0,
+            post: ptr::null_mut(),
+        })
+    }
+
+    /// Adds the configuration contexts the directive is allowed in, e.g. `NGX_HTTP_LOC_CONF`.
+    pub const fn context(mut self, context: u32) -> Self {
+        self.0.type_ |= context as ngx_uint_t;
+        self
+    }
+
+    /// Sets the offset of the configuration structure in the module context, e.g.
+    /// `NGX_RS_HTTP_LOC_CONF_OFFSET`.
+    pub const fn conf(mut self, conf: ngx_uint_t) -> Self {
+        self.0.conf = conf;
+        self
+    }
+
+    /// Returns the command.
+    pub const fn build(self) -> ngx_command_t {
+        self.0
+    }
+
+    /// Creates a command named `name` setting a flag, equivalent to `ngx_conf_set_flag_slot`.
+    ///
+    /// The directive takes `on` or `off`, stored in the field returned by `field`:
+    ///
+    /// ```rust,ignore
+    /// static mut ngx_http_curl_commands: [ngx_command_t; 2] = [
+    ///     Command::flag(c"curl", |conf: &mut ModuleConfig| &mut conf.enable)
+    ///         .context(NGX_HTTP_LOC_CONF)
+    ///         .conf(NGX_RS_HTTP_LOC_CONF_OFFSET)
+    ///         .build(),
+    ///     ngx_null_command!(),
+    /// ];
+    /// ```
+    ///
+    /// The accessor is a function, or a closure not capturing variables. The field is either a
+    /// `bool` or an `Option<bool>`, see [`SlotField`].
+    pub const fn flag<C, S>(name: &'static CStr, field: fn(&mut C) -> &mut S) -> Self
+    where
+        S: SlotField<bool>,
+    {
+        Self::slot::<FlagSlot, C, S>(name, field)
+    }
+
+    /// Creates a command named `name` setting a non-negative number, equivalent to
+    /// `ngx_conf_set_num_slot`.
+    pub const fn num<C, S>(name: &'static CStr, field: fn(&mut C) -> &mut S) -> Self
+    where
+        S: SlotField<usize>,
+    {
+        Self::slot::<NumSlot, C, S>(name, field)
+    }
+
+    /// Creates a command named `name` setting a size, e.g. `64k` or `1m`, equivalent to
+    /// `ngx_conf_set_size_slot`.
+    pub const fn size<C, S>(name: &'static CStr, field: fn(&mut C) -> &mut S) -> Self
+    where
+        S: SlotField<usize>,
+    {
+        Self::slot::<SizeSlot, C, S>(name, field)
+    }
+
+    /// Creates a command named `name` setting a time interval, e.g. `500ms` or `1m30s`,
+    /// equivalent to `ngx_conf_set_msec_slot`.
+    pub const fn msec<C, S>(name: &'static CStr, field: fn(&mut C) -> &mut S) -> Self
+    where
+        S: SlotField<Duration>,
+    {
+        Self::slot::<MsecSlot, C, S>(name, field)
+    }
+
+    /// Creates a command named `name` setting a string, equivalent to `ngx_conf_set_str_slot`.
+    ///
+    /// The argument must be valid UTF-8.
+    pub const fn str<C, S>(name: &'static CStr, field: fn(&mut C) -> &mut S) -> Self
+    where
+        S: SlotField<String>,
+    {
+        Self::slot::<StrSlot, C, S>(name, field)
+    }
+
+    /// Creates a command named `name` setting a value parsed with [`FromArg`], e.g. a `String`.
+    pub const fn value<C, T, S>(name: &'static CStr, field: fn(&mut C) -> &mut S) -> Self
+    where
+        T: for<'a> FromArg<'a>,
+        S: SlotField<T>,
+    {
+        Self::slot::<ValueSlot<T>, C, S>(name, field)
+    }
+
+    /// Creates a command named `name` setting a tuple of values parsed with [`FromArg`], e.g.
+    /// `(String, String)` for a directive with two arguments.
+    pub const fn tuple<C, A, S>(name: &'static CStr, field: fn(&mut C) -> &mut S) -> Self
+    where
+        A: for<'a> Arguments<'a>,
+        S: SlotField<A>,
+    {
+        Self::slot::<TupleSlot<A>, C, S>(name, field)
+    }
+
+    /// Creates a command named `name` with a handler written against the raw NGINX API.
+    ///
+    /// `args` is the argument count flag, e.g. `NGX_CONF_TAKE1`.
+    pub const fn handler(
+        name: &'static CStr,
+        args: u32,
+        handler: unsafe extern "C" fn(*mut ngx_conf_t, *mut ngx_command_t, *mut c_void) -> *mut c_char,
+    ) -> Self {
+        Command(ngx_command_t {
+            name: ngx_str_t {
+                len: name.to_bytes().len(),
+                data: name.as_ptr() as *mut u8,
+            },
+            type_: args as ngx_uint_t,
+            set: Some(handler),
+            conf: 0,
+            offset: 0,
+            post: ptr::null_mut(),
+        })
+    }
+
+    /// Returns the null command terminating a command array, like [`ngx_null_command!`].
+    ///
+    /// [`ngx_null_command!`]: crate::ngx_null_command
+    pub const fn null() -> ngx_command_t {
+        ngx_command_t {
+            name: ngx_str_t {
+                len: 0,
+                data: ptr::null_mut(),
+            },
+            type_: 0,
+            set: None,
+            conf: 0,
+            offset: 0,
+            post: ptr::null_mut(),
+        }
+    }
+
+    /// Creates a command named `name` appending its arguments to a list, see [`ListField`].
+    ///
+    /// The directive takes one or more arguments, and may be repeated to add more values:
+    ///
+    /// ```rust,ignore
+    /// // my_buffer_sizes 4k 16k;
+    /// // my_buffer_sizes 64k;
+    /// Command::list(c"my_buffer_sizes", &ListField::new(parse_size, |conf: &mut LocConf| &mut conf.buffer_sizes))
+    ///     .context(NGX_HTTP_LOC_CONF)
+    ///     .conf(NGX_RS_HTTP_LOC_CONF_OFFSET)
+    ///     .build(),
+    /// ```
+    ///
+    /// The merge of the list is up to the module, see [`PoolVec::merge`].
+    pub const fn list<C, T>(name: &'static CStr, list: &'static ListField<C, T>) -> Self {
+        Command(ngx_command_t {
+            name: ngx_str_t {
+                len: name.to_bytes().len(),
+                data: name.as_ptr() as *mut u8,
+            },
+            type_: NGX_CONF_1MORE as ngx_uint_t,
+            set: Some(ngx_list_handler::<C, T>),
+            conf: 0,
+            offset: 0,
+            post: list as *const ListField<C, T> as *mut c_void,
+        })
+    }
+
+    const fn slot<K, C, S>(name: &'static CStr, field: fn(&mut C) -> &mut S) -> Self
+    where
+        K: Slot,
+        S: SlotField<K::Value>,
+    {
+        Command(ngx_command_t {
+            name: ngx_str_t {
+                len: name.to_bytes().len(),
+                data: name.as_ptr() as *mut u8,
+            },
+            type_: K::ARGS,
+            set: Some(ngx_slot_handler::<K, C, S>),
+            conf: 0,
+            offset: 0,
+            post: field as *mut c_void,
+        })
+    }
+
+    /// Creates a block command named `name` whose contents are parsed by `P`, like the `types`
+    /// block of NGINX.
+    ///
+    /// The directive takes no arguments; see [`ngx_conf_parse_block`] for block directives with
+    /// arguments.
+    pub const fn block<P: BlockParser>(name: &'static CStr) -> Self {
+        Command(ngx_command_t {
+            name: ngx_str_t {
+                len: name.to_bytes().len(),
+                data: name.as_ptr() as *mut u8,
+            },
+            type_: (NGX_CONF_BLOCK | NGX_CONF_NOARGS) as ngx_uint_t,
+            set: Some(ngx_block_handler::<P>),
+            conf: 0,
+            offset: 0,
+            post: ptr::null_mut(),
+        })
+    }
+}
+
+/// The list set by [`Command::list`]: the parser of its elements, and the accessor of the field.
+///
+/// The parser is a function such as [`parse_size`] or [`parse_msec`], or a closure not capturing
+/// variables, like `|arg: &NgxStr| u16::from_arg(arg)`. A `ListField` built in the initializer of
+/// the command array lives as long as the array, so it can be passed by reference.
+pub struct ListField<C, T> {
+    parser: fn(&NgxStr) -> Result<T, ConfError>,
+    field: fn(&mut C) -> &mut PoolVec<T>,
+}
+
+impl<C, T> ListField<C, T> {
+    /// Creates a list parsing its elements with `parser` and stored in the field returned by
+    /// `field`.
+    pub const fn new(parser: fn(&NgxStr) -> Result<T, ConfError>, field: fn(&mut C) -> &mut PoolVec<T>) -> Self {
+        ListField { parser, field }
+    }
+}
+
+unsafe extern "C" fn ngx_list_handler<C, T>(
+    cf: *mut ngx_conf_t,
+    cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    // SAFETY: the command was created by `Command::list`, with a `ListField` of the same types
+    let ListField { parser, field } = &*((*cmd).post as *const ListField<C, T>);
+    let list = field(&mut *(conf as *mut C));
+    let mut pool = Pool::from_ngx_pool((*cf).pool);
+
+    let result = Args::from_conf(cf).iter().enumerate().try_for_each(|(index, arg)| {
+        let value = parser(arg).map_err(|err| err.with_arg(index))?;
+        list.push(&mut pool, value)
+    });
+
+    ngx_conf_result(cf, cmd, result)
+}
+
+/// A parser of the lines of a configuration block with arbitrary contents, instead of
+/// directives, e.g. a table of names and values:
+///
+/// ```rust,ignore
+/// // my_routes {
+/// //     /users  users_backend;
+/// //     /posts  posts_backend;
+/// // }
+/// struct Routes;
+///
+/// impl BlockParser for Routes {
+///     type Conf = LocConf;
+///
+///     fn parse_line(_cf: &mut ngx_conf_t, conf: &mut LocConf, tokens: Array<'_, ngx_str_t>) -> Result<(), ConfError> {
+///         let (prefix, backend) = <(String, String)>::from_args(tokens.into())?;
+///         conf.routes.push((prefix, backend));
+///         Ok(())
+///     }
+/// }
+///
+/// static mut ngx_http_router_commands: [ngx_command_t; 2] = [
+///     Command::block::<Routes>(c"my_routes")
+///         .context(NGX_HTTP_LOC_CONF)
+///         .conf(NGX_RS_HTTP_LOC_CONF_OFFSET)
+///         .build(),
+///     ngx_null_command!(),
+/// ];
+/// ```
+pub trait BlockParser {
+    /// The configuration structure the block is stored in.
+    type Conf;
+
+    /// Parses a line of the block, given as its tokens without the terminating `;`.
+    ///
+    /// NGINX rejects nested blocks in the block without calling the parser.
+    fn parse_line(cf: &mut ngx_conf_t, conf: &mut Self::Conf, tokens: Array<'_, ngx_str_t>) -> Result<(), ConfError>;
+}
+
+/// Parses the block of the directive being handled with `P`, storing its contents in `conf`.
+///
+/// This is called from the handler of a block directive, e.g. one with arguments such as
+/// `my_map $arg $value { ... }`. The errors of the lines are logged by NGINX with their position
+/// in the configuration file; the returned value is then `NGX_CONF_ERROR`, to be returned from
+/// the handler.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer of a block directive being
+/// handled.
+pub unsafe fn ngx_conf_parse_block<P: BlockParser>(cf: *mut ngx_conf_t, conf: &mut P::Conf) -> *mut c_char {
+    let saved = ptr::read(cf);
+    (*cf).handler = Some(ngx_block_line_handler::<P>);
+    (*cf).handler_conf = conf as *mut P::Conf as *mut c_void;
+
+    let rv = ngx_conf_parse(cf, ptr::null_mut());
+
+    ptr::write(cf, saved);
+    rv
+}
+
+unsafe extern "C" fn ngx_block_handler<P: BlockParser>(
+    cf: *mut ngx_conf_t,
+    _cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    ngx_conf_parse_block::<P>(cf, &mut *(conf as *mut P::Conf))
+}
+
+unsafe extern "C" fn ngx_block_line_handler<P: BlockParser>(
+    cf: *mut ngx_conf_t,
+    _dummy: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    let tokens = Array::from_ngx_array((*cf).args);
+    let result = P::parse_line(&mut *cf, &mut *(conf as *mut P::Conf), tokens);
+
+    ngx_conf_result(cf, ptr::null(), result)
+}
+
+/// A configuration field set by the prebuilt setters of [`Command`], e.g. [`Command::flag`].
+///
+/// Implemented for the value type itself, and for an `Option` of it. An `Option` field stays
+/// `None` until the directive is set, like the `NGX_CONF_UNSET` fields of the NGINX setters:
+/// setting it twice is an error, and the merge can tell an unset value from a default one.
+pub trait SlotField<T> {
+    /// Stores the value parsed from the directive.
+    fn set_slot(&mut self, value: T) -> Result<(), ConfError>;
+}
+
+impl<T> SlotField<T> for T {
+    fn set_slot(&mut self, value: T) -> Result<(), ConfError> {
+        *self = value;
+        Ok(())
+    }
+}
+
+impl<T> SlotField<T> for Option<T> {
+    fn set_slot(&mut self, value: T) -> Result<(), ConfError> {
+        if self.is_some() {
+            return Err(ConfError::new("is duplicate"));
+        }
+        *self = Some(value);
+        Ok(())
+    }
+}
+
+/// The argument parser of a prebuilt setter.
+trait Slot {
+    type Value;
+    const ARGS: ngx_uint_t;
+
+    fn parse(arg: &NgxStr) -> Result<Self::Value, ConfError>;
+
+    fn parse_args(args: Args<'_>) -> Result<Self::Value, ConfError> {
+        let arg = args.require(0)?;
+        Self::parse(arg).map_err(|err| err.with_arg(0))
+    }
+}
+
+struct FlagSlot;
+struct NumSlot;
+struct SizeSlot;
+struct MsecSlot;
+struct StrSlot;
+struct ValueSlot<T>(PhantomData<T>);
+struct TupleSlot<A>(PhantomData<A>);
+
+impl Slot for FlagSlot {
+    type Value = bool;
+    const ARGS: ngx_uint_t = NGX_CONF_FLAG as ngx_uint_t;
+
+    fn parse(arg: &NgxStr) -> Result<bool, ConfError> {
+        parse_flag(arg)
+    }
+}
+
+impl Slot for NumSlot {
+    type Value = usize;
+    const ARGS: ngx_uint_t = NGX_CONF_TAKE1 as ngx_uint_t;
+
+    fn parse(arg: &NgxStr) -> Result<usize, ConfError> {
+        parse_number(arg)
+    }
+}
+
+impl Slot for SizeSlot {
+    type Value = usize;
+    const ARGS: ngx_uint_t = NGX_CONF_TAKE1 as ngx_uint_t;
+
+    fn parse(arg: &NgxStr) -> Result<usize, ConfError> {
+        parse_size(arg)
+    }
+}
+
+impl Slot for MsecSlot {
+    type Value = Duration;
+    const ARGS: ngx_uint_t = NGX_CONF_TAKE1 as ngx_uint_t;
+
+    fn parse(arg: &NgxStr) -> Result<Duration, ConfError> {
+        parse_msec(arg)
+    }
+}
+
+impl Slot for StrSlot {
+    type Value = String;
+    const ARGS: ngx_uint_t = NGX_CONF_TAKE1 as ngx_uint_t;
+
+    fn parse(arg: &NgxStr) -> Result<String, ConfError> {
+        String::from_arg(arg)
+    }
+}
+
+impl<T: for<'a> FromArg<'a>> Slot for ValueSlot<T> {
+    type Value = T;
+    const ARGS: ngx_uint_t = NGX_CONF_TAKE1 as ngx_uint_t;
+
+    fn parse(arg: &NgxStr) -> Result<T, ConfError> {
+        T::from_arg(arg)
+    }
+}
+
+impl<A: for<'a> Arguments<'a>> Slot for TupleSlot<A> {
+    type Value = A;
+    const ARGS: ngx_uint_t = <A as Arguments<'static>>::ARGS;
+
+    fn parse(_arg: &NgxStr) -> Result<A, ConfError> {
+        // the arguments are parsed together by `parse_args`
+        Err(ConfError::new("takes several arguments"))
+    }
+
+    fn parse_args(args: Args<'_>) -> Result<A, ConfError> {
+        A::from_args(args)
+    }
+}
+
+unsafe extern "C" fn ngx_slot_handler<K, C, S>(
+    cf: *mut ngx_conf_t,
+    cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char
+where
+    K: Slot,
+    S: SlotField<K::Value>,
+{
+    // SAFETY: the command was created by `Command::slot`, with an accessor of the same types
+    let field = mem::transmute::<*mut c_void, fn(&mut C) -> &mut S>((*cmd).post);
+    let conf = &mut *(conf as *mut C);
+
+    let result = K::parse_args(Args::from_conf(cf)).and_then(|value| field(conf).set_slot(value));
+
+    ngx_conf_result(cf, cmd, result)
+}
+
+unsafe extern "C" fn ngx_command_handler<D: Directive>(
+    cf: *mut ngx_conf_t,
+    cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    let result =
+        D::Args::from_args(Args::from_conf(cf)).and_then(|args| D::set(&mut *cf, &mut *(conf as *mut D::Conf), args));
+
+    ngx_conf_result(cf, cmd, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flag() {
+        assert_eq!(parse_flag("on".into()), Ok(true));
+        assert_eq!(parse_flag("OFF".into()), Ok(false));
+        assert!(parse_flag("o n".into()).is_err());
+        assert!(parse_flag("".into()).is_err());
+        assert!(parse_flag("onn".into()).is_err());
+    }
+
+    #[test]
+    fn test_slot_field() {
+        let mut value = false;
+        assert!(value.set_slot(true).is_ok() && value.set_slot(true).is_ok());
+        assert!(value);
+
+        let mut value: Option<Duration> = None;
+        assert!(value.set_slot(Duration::from_secs(1)).is_ok());
+        assert!(value.set_slot(Duration::from_secs(2)).is_err());
+        assert_eq!(value, Some(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,366 @@
+use crate::core::NGX_CONF_ERROR;
+use crate::ffi::*;
+
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::c_char;
+use std::ptr;
+
+/// An error raised while parsing a configuration directive.
+///
+/// Nested helpers (size, URL or file parsers) can wrap the errors of the code they call with
+/// [`ConfError::context`], building a chain of causes. The directive handler then reports the
+/// whole chain as a single `ngx_conf_log_error` message, which includes the directive name, the
+/// offending argument, and the configuration file position:
+///
+/// ```text
+/// nginx: [emerg] "my_timeout" directive, argument 1: invalid timeout "1z": unknown unit "z" in /etc/nginx/nginx.conf:12
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfError {
+    arg: Option<usize>,
+    /// Messages from the outermost context to the root cause.
+    messages: Vec<String>,
+}
+
+impl ConfError {
+    /// Creates an error with the given message.
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        ConfError {
+            arg: None,
+            messages: vec![message.into()],
+        }
+    }
+
+    /// Creates an error from `err` and all of its [`Error::source`]s.
+    pub fn from_error(err: &dyn Error) -> Self {
+        let mut messages = vec![err.to_string()];
+        let mut source = err.source();
+        while let Some(err) = source {
+            messages.push(err.to_string());
+            source = err.source();
+        }
+
+        ConfError { arg: None, messages }
+    }
+
+    /// Wraps the error with an outer message describing what was being done.
+    pub fn context<M: Into<String>>(mut self, message: M) -> Self {
+        self.messages.insert(0, message.into());
+        self
+    }
+
+    /// Records the index of the offending directive argument.
+    ///
+    /// Arguments are numbered from 0, starting with the first argument after the directive name.
+    pub fn with_arg(mut self, index: usize) -> Self {
+        self.arg = Some(index);
+        self
+    }
+
+    /// Index of the offending directive argument, if known.
+    pub fn arg(&self) -> Option<usize> {
+        self.arg
+    }
+
+    /// The outermost error message.
+    pub fn message(&self) -> &str {
+        &self.messages[0]
+    }
+
+    /// Messages of the underlying causes, from the outermost to the root cause.
+    pub fn causes(&self) -> impl Iterator<Item = &str> {
+        self.messages[1..].iter().map(String::as_str)
+    }
+
+    /// Logs the error at the `emerg` level for the directive `cmd` being parsed.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer. `cmd` is either null or
+    /// points to a valid `ngx_command_t`.
+    pub unsafe fn log(&self, cf: *mut ngx_conf_t, cmd: *const ngx_command_t) {
+        log_directive(cf, cmd, NGX_LOG_EMERG, self.arg, &self.to_string());
+    }
+}
+
+impl fmt::Display for ConfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.messages.join(": "))
+    }
+}
+
+impl Error for ConfError {}
+
+/// A non-fatal problem found while parsing a configuration directive, e.g. a deprecated
+/// directive or value, logged at the `warn` level with the directive name and the configuration
+/// file position:
+///
+/// ```text
+/// nginx: [warn] "my_timeout" directive, argument 1: the "s" unit is implied in /etc/nginx/nginx.conf:12
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfWarning {
+    arg: Option<usize>,
+    message: String,
+}
+
+impl ConfWarning {
+    /// Creates a warning with the given message.
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        ConfWarning {
+            arg: None,
+            message: message.into(),
+        }
+    }
+
+    /// Records the index of the directive argument the warning is about, as
+    /// [`ConfError::with_arg`].
+    pub fn with_arg(mut self, index: usize) -> Self {
+        self.arg = Some(index);
+        self
+    }
+
+    /// Index of the directive argument the warning is about, if known.
+    pub fn arg(&self) -> Option<usize> {
+        self.arg
+    }
+
+    /// The warning message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Logs the warning at the `warn` level for the directive `cmd` being parsed.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer. `cmd` is either null or
+    /// points to a valid `ngx_command_t`.
+    pub unsafe fn log(&self, cf: *mut ngx_conf_t, cmd: *const ngx_command_t) {
+        log_directive(cf, cmd, NGX_LOG_WARN, self.arg, &self.message);
+    }
+}
+
+impl fmt::Display for ConfWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// The outcome of a directive handler that succeeded.
+///
+/// A handler accepting a deprecated directive or value still applies it, and returns a warning
+/// for the configuration to keep loading:
+///
+/// ```rust,ignore
+/// fn set(_cf: &mut ngx_conf_t, conf: &mut LocConf, (value,): Self::Args<'_>) -> Result<ConfOk, ConfError> {
+///     conf.timeout = parse_msec(value).map_err(|err| err.with_arg(0))?;
+///     if value.as_bytes().iter().all(u8::is_ascii_digit) {
+///         return Ok(ConfOk::Warning(ConfWarning::new("the \"s\" unit is implied").with_arg(0)));
+///     }
+///     Ok(ConfOk::Applied)
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfOk {
+    /// The directive was applied.
+    Applied,
+    /// The directive was applied, with a warning.
+    Warning(ConfWarning),
+}
+
+impl From<()> for ConfOk {
+    fn from(_: ()) -> Self {
+        ConfOk::Applied
+    }
+}
+
+impl From<ConfWarning> for ConfOk {
+    fn from(warning: ConfWarning) -> Self {
+        ConfOk::Warning(warning)
+    }
+}
+
+/// Logs `message` for the directive `cmd` and its argument `arg`, both optional.
+unsafe fn log_directive(cf: *mut ngx_conf_t, cmd: *const ngx_command_t, level: u32, arg: Option<usize>, text: &str) {
+    let mut message = String::new();
+    if let Some(cmd) = cmd.as_ref() {
+        message.push_str(&format!("\"{}\" directive, ", cmd.name));
+    }
+    if let Some(arg) = arg {
+        message.push_str(&format!("argument {}: ", arg + 1));
+    }
+    message.push_str(text);
+
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    ngx_conf_log_error(level as ngx_uint_t, cf, 0, c"%s".as_ptr(), message.as_ptr());
+}
+
+/// The configuration block a directive is being parsed in, as opposed to the contexts it is
+/// allowed in.
+///
+/// A directive allowed in several contexts can behave differently depending on where it
+/// appears, e.g. registering state for a whole server rather than for a single location:
+///
+/// ```rust,ignore
+/// fn set(cf: &mut ngx_conf_t, conf: &mut SrvConf, args: Self::Args<'_>) -> Result<ConfOk, ConfError> {
+///     match ConfContext::of(cf) {
+///         ConfContext::Server => conf.default_zone = Some(args.0.to_string()),
+///         _ => conf.zones.push(args.0.to_string()),
+///     }
+///     Ok(ConfOk::Applied)
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfContext {
+    /// The main context, outside of any block.
+    Main,
+    /// The `events` block.
+    Events,
+    /// The `http` block.
+    Http,
+    /// A `server` block of the `http` block.
+    Server,
+    /// A `location` block, including nested locations.
+    Location,
+    /// An `if` block of a `server` block.
+    ServerIf,
+    /// An `if` block of a `location` block.
+    LocationIf,
+    /// A `limit_except` block.
+    LimitExcept,
+    /// An `upstream` block of the `http` block.
+    Upstream,
+    /// The `stream` block.
+    #[cfg(feature = "stream")]
+    Stream,
+    /// A `server` block of the `stream` block.
+    #[cfg(feature = "stream")]
+    StreamServer,
+    /// An `upstream` block of the `stream` block.
+    #[cfg(feature = "stream")]
+    StreamUpstream,
+    /// Any other block, e.g. of the `mail` modules.
+    Other,
+}
+
+impl ConfContext {
+    /// Returns the block `cf` is parsing, e.g. from [`Directive::set`](crate::core::Directive::set).
+    pub fn of(cf: &ngx_conf_t) -> Self {
+        let cmd_type = cf.cmd_type as u32;
+        // the flags of the http and stream contexts share the same bits
+        match cf.module_type as u32 {
+            NGX_CORE_MODULE if cmd_type & NGX_MAIN_CONF != 0 => ConfContext::Main,
+            NGX_EVENT_MODULE => ConfContext::Events,
+            NGX_HTTP_MODULE => match cmd_type {
+                NGX_HTTP_MAIN_CONF => ConfContext::Http,
+                NGX_HTTP_SRV_CONF => ConfContext::Server,
+                NGX_HTTP_LOC_CONF => ConfContext::Location,
+                NGX_HTTP_SIF_CONF => ConfContext::ServerIf,
+                NGX_HTTP_LIF_CONF => ConfContext::LocationIf,
+                NGX_HTTP_LMT_CONF => ConfContext::LimitExcept,
+                NGX_HTTP_UPS_CONF => ConfContext::Upstream,
+                _ => ConfContext::Other,
+            },
+            #[cfg(feature = "stream")]
+            NGX_STREAM_MODULE => match cmd_type {
+                NGX_STREAM_MAIN_CONF => ConfContext::Stream,
+                NGX_STREAM_SRV_CONF => ConfContext::StreamServer,
+                NGX_STREAM_UPS_CONF => ConfContext::StreamUpstream,
+                _ => ConfContext::Other,
+            },
+            _ => ConfContext::Other,
+        }
+    }
+
+    /// Returns `true` for the blocks within the `http` block, including the `http` block itself.
+    pub fn is_http(&self) -> bool {
+        matches!(
+            self,
+            ConfContext::Http
+                | ConfContext::Server
+                | ConfContext::Location
+                | ConfContext::ServerIf
+                | ConfContext::LocationIf
+                | ConfContext::LimitExcept
+                | ConfContext::Upstream
+        )
+    }
+}
+
+/// Converts the result of a directive handler into the value expected by NGINX, logging the
+/// error or the warning if there is one.
+///
+/// Returns `NGX_CONF_OK` on success, including with a warning, or `NGX_CONF_ERROR` on failure.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer. `cmd` is either null or points
+/// to a valid `ngx_command_t`.
+pub unsafe fn ngx_conf_result<T: Into<ConfOk>>(
+    cf: *mut ngx_conf_t,
+    cmd: *const ngx_command_t,
+    result: Result<T, ConfError>,
+) -> *mut c_char {
+    match result.map(Into::into) {
+        Ok(ConfOk::Applied) => ptr::null_mut(),
+        Ok(ConfOk::Warning(warning)) => {
+            warning.log(cf, cmd);
+            ptr::null_mut()
+        }
+        Err(err) => {
+            err.log(cf, cmd);
+            NGX_CONF_ERROR as _
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_chain() {
+        let err = ConfError::new("invalid digit")
+            .context("invalid size \"10q\"")
+            .with_arg(1);
+
+        assert_eq!(err.message(), "invalid size \"10q\"");
+        assert_eq!(err.causes().collect::<Vec<_>>(), ["invalid digit"]);
+        assert_eq!(err.arg(), Some(1));
+        assert_eq!(err.to_string(), "invalid size \"10q\": invalid digit");
+    }
+
+    #[test]
+    fn test_from_error() {
+        let err = "x".parse::<u32>().unwrap_err();
+        let err = ConfError::from_error(&err).context("invalid number");
+
+        assert_eq!(err.to_string(), "invalid number: invalid digit found in string");
+    }
+
+    #[test]
+    fn test_conf_ok() {
+        let warning = ConfWarning::new("the \"s\" unit is implied").with_arg(0);
+
+        assert_eq!(ConfOk::from(()), ConfOk::Applied);
+        assert_eq!(ConfOk::from(warning.clone()), ConfOk::Warning(warning.clone()));
+        assert_eq!(warning.arg(), Some(0));
+        assert_eq!(warning.to_string(), "the \"s\" unit is implied");
+    }
+
+    #[test]
+    fn test_conf_context() {
+        let mut cf: ngx_conf_t = unsafe { std::mem::zeroed() };
+        cf.module_type = NGX_CORE_MODULE as ngx_uint_t;
+        cf.cmd_type = NGX_MAIN_CONF as ngx_uint_t;
+        assert_eq!(ConfContext::of(&cf), ConfContext::Main);
+
+        cf.module_type = NGX_HTTP_MODULE as ngx_uint_t;
+        cf.cmd_type = NGX_HTTP_LIF_CONF as ngx_uint_t;
+        assert_eq!(ConfContext::of(&cf), ConfContext::LocationIf);
+        assert!(ConfContext::of(&cf).is_http());
+    }
+}
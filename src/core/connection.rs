@@ -0,0 +1,383 @@
+use crate::core::{Pool, Status};
+use crate::event::{duration_to_msec, ngx_add_timer, ngx_del_timer};
+use crate::ffi::*;
+
+use std::io;
+use std::net::TcpStream;
+use std::ops::{Deref, DerefMut};
+use std::os::fd::{FromRawFd, IntoRawFd};
+use std::os::raw::c_void;
+use std::ptr::NonNull;
+use std::time::Duration;
+
+/// Wrapper struct for an [`ngx_connection_t`] pointer.
+///
+/// [`ngx_connection_t`]: https://nginx.org/en/docs/dev/development_guide.html#connection
+#[repr(transparent)]
+pub struct Connection(ngx_connection_t);
+
+impl Connection {
+    /// Creates a [`Connection`] from an [`ngx_connection_t`].
+    ///
+    /// [`ngx_connection_t`]: https://nginx.org/en/docs/dev/development_guide.html#connection
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a valid `ngx_connection_t`
+    /// which shares the same representation as `Connection`.
+    pub unsafe fn from_ngx_connection<'a>(c: *mut ngx_connection_t) -> &'a mut Connection {
+        &mut *c.cast::<Connection>()
+    }
+
+    /// Connection pool.
+    ///
+    /// The pool lives as long as the client connection, across all requests of a keepalive or
+    /// HTTP/2 connection.
+    pub fn pool(&self) -> Pool {
+        // SAFETY: an active connection always has a pool
+        unsafe { Pool::from_ngx_pool(self.0.pool) }
+    }
+
+    /// Pointer to a [`ngx_log_t`].
+    ///
+    /// [`ngx_log_t`]: https://nginx.org/en/docs/dev/development_guide.html#logging
+    pub fn log(&self) -> *mut ngx_log_t {
+        self.0.log
+    }
+
+    /// Stores a value of type `T` of `module` for the lifetime of the connection, replacing the
+    /// previous value of the same type stored by the module.
+    ///
+    /// Unlike the module context of a request, the value survives between the requests of a
+    /// keepalive connection, e.g. to remember capabilities negotiated by the client. It is
+    /// dropped when the connection pool is destroyed. The values are keyed by module as well as
+    /// by type, so modules storing values of a common type, e.g. a `bool`, do not overwrite each
+    /// other.
+    ///
+    /// Returns `None` if the value cannot be allocated.
+    pub fn set_ctx<T: 'static>(&mut self, module: &ngx_module_t, value: T) -> Option<()> {
+        // the index of the module among all the modules is unique across module types, unlike
+        // the context index
+        self.pool().set_data(module.index, value).map(|_| ())
+    }
+
+    /// Returns the value of type `T` of `module` stored with [`Connection::set_ctx`].
+    pub fn get_ctx<T: 'static>(&self, module: &ngx_module_t) -> Option<&T> {
+        let value = self.pool().data::<T>(module.index)?;
+        unsafe { value.as_ref() }
+    }
+
+    /// Returns a mutable reference to the value of type `T` of `module` stored with
+    /// [`Connection::set_ctx`].
+    pub fn get_ctx_mut<T: 'static>(&mut self, module: &ngx_module_t) -> Option<&mut T> {
+        let value = self.pool().data::<T>(module.index)?;
+        unsafe { value.as_mut() }
+    }
+
+    /// Creates a [`Connection`] from the `data` of one of its events, as passed to the event
+    /// handlers set with [`Connection::set_read_handler`] and [`Connection::set_write_handler`].
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a read or write event of a
+    /// connection.
+    pub unsafe fn from_event<'a>(ev: *mut ngx_event_t) -> &'a mut Connection {
+        Self::from_ngx_connection((*ev).data as *mut ngx_connection_t)
+    }
+
+    /// Returns the data of the connection, e.g. the request of an HTTP connection, or the state
+    /// of a module driving its own connection.
+    pub fn data(&self) -> *mut c_void {
+        self.0.data
+    }
+
+    /// Sets the data of the connection.
+    pub fn set_data(&mut self, data: *mut c_void) {
+        self.0.data = data;
+    }
+
+    /// Returns the read event of the connection.
+    pub fn read_event(&self) -> *mut ngx_event_t {
+        self.0.read
+    }
+
+    /// Returns the write event of the connection.
+    pub fn write_event(&self) -> *mut ngx_event_t {
+        self.0.write
+    }
+
+    /// Sets the handler called when the connection is readable or its read timeout expires.
+    pub fn set_read_handler(&mut self, handler: ngx_event_handler_pt) {
+        unsafe { (*self.0.read).handler = handler };
+    }
+
+    /// Sets the handler called when the connection is writable or its write timeout expires.
+    pub fn set_write_handler(&mut self, handler: ngx_event_handler_pt) {
+        unsafe { (*self.0.write).handler = handler };
+    }
+
+    /// Returns `true` if data can be read without blocking.
+    pub fn is_read_ready(&self) -> bool {
+        unsafe { (*self.0.read).ready() != 0 }
+    }
+
+    /// Returns `true` if data can be written without blocking.
+    pub fn is_write_ready(&self) -> bool {
+        unsafe { (*self.0.write).ready() != 0 }
+    }
+
+    /// Returns `true` if the read event was triggered by its timeout.
+    pub fn is_read_timedout(&self) -> bool {
+        unsafe { (*self.0.read).timedout() != 0 }
+    }
+
+    /// Returns `true` if the write event was triggered by its timeout.
+    pub fn is_write_timedout(&self) -> bool {
+        unsafe { (*self.0.write).timedout() != 0 }
+    }
+
+    /// Registers the read event with the event loop if needed, so the read handler is called
+    /// once the connection is readable, equivalent to `ngx_handle_read_event`.
+    ///
+    /// This is called after a read returned [`io::ErrorKind::WouldBlock`].
+    pub fn handle_read_event(&mut self) -> Result<(), Status> {
+        match unsafe { ngx_handle_read_event(self.0.read, 0) } {
+            rc if rc == NGX_OK as ngx_int_t => Ok(()),
+            rc => Err(Status(rc)),
+        }
+    }
+
+    /// Registers the write event with the event loop if needed, so the write handler is called
+    /// once the connection is writable, equivalent to `ngx_handle_write_event`.
+    ///
+    /// This is called after a write returned [`io::ErrorKind::WouldBlock`].
+    pub fn handle_write_event(&mut self) -> Result<(), Status> {
+        match unsafe { ngx_handle_write_event(self.0.write, 0) } {
+            rc if rc == NGX_OK as ngx_int_t => Ok(()),
+            rc => Err(Status(rc)),
+        }
+    }
+
+    /// Arms the timeout of the read event, replacing the current one, or disarms it with
+    /// `None`.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        unsafe { set_event_timeout(self.0.read, timeout) };
+    }
+
+    /// Arms the timeout of the write event, replacing the current one, or disarms it with
+    /// `None`.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        unsafe { set_event_timeout(self.0.write, timeout) };
+    }
+
+    /// Reads data from the connection with its `recv` method, e.g. through SSL/TLS for secure
+    /// connections.
+    ///
+    /// Returns `Ok(0)` at the end of the stream, and [`io::ErrorKind::WouldBlock`] if no data
+    /// is available; the read handler is then called once the connection is readable again,
+    /// after [`Connection::handle_read_event`]. Other errors are logged to the connection log.
+    pub fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(recv) = self.0.recv else {
+            return Err(io::ErrorKind::Unsupported.into());
+        };
+        let n = unsafe { recv(self.as_ptr(), buf.as_mut_ptr(), buf.len()) };
+        io_result(n)
+    }
+
+    /// Writes data to the connection with its `send` method, returning the number of bytes
+    /// written.
+    ///
+    /// Returns [`io::ErrorKind::WouldBlock`] if no data can be written; the write handler is
+    /// then called once the connection is writable again, after
+    /// [`Connection::handle_write_event`]. Other errors are logged to the connection log.
+    pub fn send(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(send) = self.0.send else {
+            return Err(io::ErrorKind::Unsupported.into());
+        };
+        let n = unsafe { send(self.as_ptr(), buf.as_ptr() as *mut u_char, buf.len()) };
+        io_result(n)
+    }
+
+    /// Returns a reference to the underlying [`ngx_connection_t`].
+    pub fn get_inner(&self) -> &ngx_connection_t {
+        &self.0
+    }
+
+    /// Returns a raw pointer to the underlying [`ngx_connection_t`].
+    pub fn as_ptr(&self) -> *mut ngx_connection_t {
+        &self.0 as *const ngx_connection_t as *mut ngx_connection_t
+    }
+}
+
+#[cfg(feature = "ssl")]
+impl Connection {
+    /// Returns `true` if the connection uses SSL/TLS, including QUIC connections.
+    pub fn is_ssl(&self) -> bool {
+        !self.0.ssl.is_null()
+    }
+
+    /// Returns `true` if the TLS handshake of the connection is complete.
+    pub fn is_handshake_complete(&self) -> bool {
+        unsafe { self.0.ssl.as_ref() }.is_some_and(|ssl| ssl.handshaked() != 0)
+    }
+
+    /// Returns `true` if data is currently received as TLS 1.3 early data (0-RTT), before the
+    /// handshake is complete, with the same semantics as the `$ssl_early_data` variable.
+    ///
+    /// Early data can be replayed by an attacker; requests received this way should not have
+    /// side effects.
+    pub fn is_early_data(&self) -> bool {
+        if self.0.ssl.is_null() {
+            return false;
+        }
+
+        let mut value: ngx_str_t = crate::ngx_null_string!();
+        let c = self.as_ptr();
+        unsafe { ngx_ssl_get_early_data(c, self.0.pool, &mut value) == NGX_OK as ngx_int_t && value.len != 0 }
+    }
+}
+
+unsafe fn set_event_timeout(ev: *mut ngx_event_t, timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => ngx_add_timer(ev, duration_to_msec(timeout)),
+        None if (*ev).timer_set() != 0 => ngx_del_timer(ev),
+        None => {}
+    }
+}
+
+fn io_result(n: isize) -> io::Result<usize> {
+    match n {
+        n if n >= 0 => Ok(n as usize),
+        n if n == NGX_AGAIN as isize => Err(io::ErrorKind::WouldBlock.into()),
+        _ => Err(io::Error::other("connection I/O failed")),
+    }
+}
+
+/// A connection created by a module for its own socket, e.g. to talk to an external service
+/// from the event loop, closed when dropped.
+///
+/// The connection is driven by the event handlers set on it, which find the connection with
+/// [`Connection::from_event`]:
+///
+/// ```rust,ignore
+/// let stream = TcpStream::connect(auth_addr)?;
+/// // the log of the cycle outlives the connection, unlike the log of a request
+/// let mut conn = unsafe { OwnedConnection::from_tcp_stream(stream, (*ngx_cycle).log) }?;
+/// conn.set_data(state as *mut c_void);
+/// conn.set_read_handler(Some(auth_read_handler));
+/// conn.set_read_timeout(Some(Duration::from_secs(5)));
+/// conn.send(b"PING\r\n")?;
+/// conn.handle_read_event()?;
+///
+/// unsafe extern "C" fn auth_read_handler(ev: *mut ngx_event_t) {
+///     let conn = Connection::from_event(ev);
+///     if conn.is_read_timedout() {
+///         return auth_failed(conn.data());
+///     }
+///     let mut buf = [0; 512];
+///     match conn.recv(&mut buf) {
+///         Ok(n) => auth_reply(conn.data(), &buf[..n]),
+///         Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+///             let _ = conn.handle_read_event();
+///         }
+///         Err(_) => auth_failed(conn.data()),
+///     }
+/// }
+/// ```
+///
+/// The connection takes one of the `worker_connections` of the worker. Its events must not be
+/// used after it is dropped.
+pub struct OwnedConnection(NonNull<ngx_connection_t>);
+
+impl OwnedConnection {
+    /// Takes ownership of a connection obtained with `ngx_get_connection`, e.g. by
+    /// `ngx_event_connect_peer`, to close it on drop along with its pool.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to an open `ngx_connection_t` that is
+    /// not closed elsewhere.
+    pub unsafe fn from_ngx_connection(c: NonNull<ngx_connection_t>) -> Self {
+        OwnedConnection(c)
+    }
+
+    /// Creates a connection for a connected TCP socket, switched to non-blocking mode.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_log_t` pointer which outlives the
+    /// connection, e.g. the log of the cycle.
+    pub unsafe fn from_tcp_stream(stream: TcpStream, log: *mut ngx_log_t) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+
+        let fd = stream.into_raw_fd();
+        let c = unsafe { ngx_get_connection(fd, log) };
+        let Some(c) = NonNull::new(c) else {
+            // SAFETY: the descriptor was not taken over by NGINX
+            drop(unsafe { TcpStream::from_raw_fd(fd) });
+            return Err(io::Error::other("no free connections"));
+        };
+        // closes the descriptor on errors
+        let conn = OwnedConnection(c);
+
+        unsafe {
+            let c = c.as_ptr();
+            (*c).pool = ngx_create_pool(NGX_DEFAULT_POOL_SIZE as usize, log);
+            if (*c).pool.is_null() {
+                return Err(io::ErrorKind::OutOfMemory.into());
+            }
+
+            (*c).log = log;
+            (*c).recv = ngx_io.recv;
+            (*c).send = ngx_io.send;
+            (*c).recv_chain = ngx_io.recv_chain;
+            (*c).send_chain = ngx_io.send_chain;
+            (*(*c).read).log = log;
+            (*(*c).write).log = log;
+            // the socket is connected
+            (*(*c).write).set_ready(1);
+        }
+
+        Ok(conn)
+    }
+}
+
+impl Deref for OwnedConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        unsafe { Connection::from_ngx_connection(self.0.as_ptr()) }
+    }
+}
+
+impl DerefMut for OwnedConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        unsafe { Connection::from_ngx_connection(self.0.as_ptr()) }
+    }
+}
+
+impl Drop for OwnedConnection {
+    fn drop(&mut self) {
+        let c = self.0.as_ptr();
+        unsafe {
+            // removes the timers and events, and closes the descriptor
+            let pool = (*c).pool;
+            ngx_close_connection(c);
+            if !pool.is_null() {
+                ngx_destroy_pool(pool);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for OwnedConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OwnedConnection").field(&**self).finish()
+    }
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connection").field("fd", &self.0.fd).finish()
+    }
+}
@@ -0,0 +1,177 @@
+use crate::core::ConfError;
+use crate::ffi::*;
+
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+/// Returns `true` if `cycle` is the initial cycle NGINX creates before reading any
+/// configuration, equivalent to the `ngx_is_init_cycle` macro.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null pointer to an `ngx_cycle_t`.
+pub unsafe fn ngx_is_init_cycle(cycle: *const ngx_cycle_t) -> bool {
+    (*cycle).conf_ctx.is_null()
+}
+
+/// Returns `true` if the configuration being parsed replaces the configuration of a running
+/// cycle, i.e. NGINX is reloading rather than starting.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer.
+pub unsafe fn ngx_conf_is_reload(cf: *const ngx_conf_t) -> bool {
+    let old_cycle = (*(*cf).cycle).old_cycle;
+    !old_cycle.is_null() && !ngx_is_init_cycle(old_cycle)
+}
+
+/// Returns `true` if NGINX only tests the configuration, as with `nginx -t` or `nginx -T`.
+///
+/// Modules can skip side effects, or run expensive validations, in this mode: no worker is
+/// started once the configuration is parsed. See also [`CoreModule::check_conf`] and
+/// [`HTTPModule::check_conf`].
+///
+/// [`CoreModule::check_conf`]: crate::core::CoreModule::check_conf
+/// [`HTTPModule::check_conf`]: crate::http::HTTPModule::check_conf
+pub fn ngx_is_config_test() -> bool {
+    // SAFETY: the flag is set from the command line arguments before any configuration is read.
+    unsafe { ngx_test_config != 0 }
+}
+
+/// Global state kept for each live configuration cycle, keyed by the cycle address.
+///
+/// The state of a cycle is created on first use and dropped together with the pool of that
+/// cycle, so a reload starts from a fresh state and a failed reload leaves the state of the
+/// running cycle untouched.
+pub(crate) struct CycleLocal<T>(Mutex<Vec<(usize, T)>>);
+
+/// Cleanup data registered in the pool of a cycle with state in a [`CycleLocal`].
+struct CycleLocalCleanup<T: 'static> {
+    local: &'static CycleLocal<T>,
+    cycle: usize,
+}
+
+impl<T: Default + 'static> CycleLocal<T> {
+    /// Creates an empty registry.
+    pub const fn new() -> Self {
+        CycleLocal(Mutex::new(Vec::new()))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<(usize, T)>> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Calls `f` with the state of `cycle`, creating it first if needed.
+    ///
+    /// Returns an error if the cleanup dropping the state cannot be registered.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to an `ngx_cycle_t`.
+    pub unsafe fn with_mut<R>(
+        &'static self,
+        cycle: *mut ngx_cycle_t,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<R, ConfError> {
+        let mut entries = self.lock();
+
+        let index = match entries.iter().position(|(key, _)| *key == cycle as usize) {
+            Some(index) => index,
+            None => {
+                // drop the state together with the cycle
+                let cln = ngx_pool_cleanup_add((*cycle).pool, mem::size_of::<CycleLocalCleanup<T>>());
+                if cln.is_null() {
+                    return Err(ConfError::new("failed to register cycle cleanup"));
+                }
+                ptr::write(
+                    (*cln).data as *mut CycleLocalCleanup<T>,
+                    CycleLocalCleanup {
+                        local: self,
+                        cycle: cycle as usize,
+                    },
+                );
+                (*cln).handler = Some(cycle_local_cleanup::<T>);
+
+                entries.push((cycle as usize, T::default()));
+                entries.len() - 1
+            }
+        };
+
+        Ok(f(&mut entries[index].1))
+    }
+
+    /// Calls `f` with the state of `cycle`, if any.
+    pub fn with<R>(&self, cycle: *const ngx_cycle_t, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.lock()
+            .iter()
+            .find(|(key, _)| *key == cycle as usize)
+            .map(|(_, state)| f(state))
+    }
+}
+
+unsafe extern "C" fn cycle_local_cleanup<T: Default + 'static>(data: *mut c_void) {
+    let cln = &*(data as *const CycleLocalCleanup<T>);
+    let state = {
+        let mut entries = cln.local.lock();
+        let index = entries.iter().position(|(key, _)| *key == cln.cycle);
+        index.map(|index| entries.swap_remove(index))
+    };
+    // the state is dropped outside of the lock, as its destructor may use the registry
+    drop(state);
+}
+
+/// A guard running a piece of configuration work exactly once per configuration cycle.
+///
+/// Global registrations done at postconfiguration, such as inserting an output filter, must
+/// happen once for each new cycle, even if the code is reached several times (e.g. shared by
+/// several modules or `http` blocks). The guard remembers the cycles it ran for and forgets each
+/// of them when it is destroyed, so a failed reload does not suppress the next attempt.
+///
+/// ```rust,ignore
+/// static FILTER_INIT: CycleOnce = CycleOnce::new();
+///
+/// unsafe extern "C" fn postconfiguration(cf: *mut ngx_conf_t) -> ngx_int_t {
+///     FILTER_INIT.call_once(cf, || install_filters());
+///     Status::NGX_OK.into()
+/// }
+/// ```
+pub struct CycleOnce(CycleLocal<bool>);
+
+impl CycleOnce {
+    /// Creates a guard that has not run yet.
+    pub const fn new() -> Self {
+        CycleOnce(CycleLocal::new())
+    }
+
+    /// Returns `true` if the guard already ran for the cycle being configured.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer.
+    pub unsafe fn is_done(&self, cf: *const ngx_conf_t) -> bool {
+        self.0.with((*cf).cycle, |done| *done).unwrap_or(false)
+    }
+
+    /// Calls `f` unless it already ran for the cycle being configured.
+    ///
+    /// Returns the result of `f`, or `None` if the call was skipped.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer.
+    pub unsafe fn call_once<R, F: FnOnce() -> R>(&'static self, cf: *mut ngx_conf_t, f: F) -> Option<R> {
+        // without the cleanup the guard cannot remember the cycle, and `f` runs regardless
+        if let Ok(true) = self.0.with_mut((*cf).cycle, |done| mem::replace(done, true)) {
+            return None;
+        }
+        Some(f())
+    }
+}
+
+impl Default for CycleOnce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
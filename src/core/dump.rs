@@ -0,0 +1,2 @@
+pub use ngx_core::{ConfDump, ConfValue, DescribeConf, RedactedConf};
+pub use ngx_macros::DescribeConf;
@@ -0,0 +1,53 @@
+pub use ngx_core::{expand_env, EnvExpandError};
+
+use crate::core::{ConfError, FromArg, NgxStr};
+
+use std::fmt;
+use std::ops::Deref;
+
+/// Expands the `${NAME}` environment variable placeholders of a directive argument, see
+/// [`expand_env`].
+///
+/// The configuration is parsed by the master process, before the `env` directives clear the
+/// environment of the workers, so all the variables of the master are visible. Fails if a
+/// variable is not set, is not valid UTF-8, or if the argument is not valid UTF-8.
+pub fn expand_env_arg(arg: &NgxStr) -> Result<String, ConfError> {
+    let arg = arg.to_str().map_err(|err| ConfError::from_error(&err))?;
+    expand_env(arg, |name| std::env::var(name).ok())
+        .map(String::from)
+        .map_err(|err| ConfError::from_error(&err))
+}
+
+/// A string directive argument with its environment variable placeholders expanded, for
+/// directives opting in to values like `${AWS_ACCESS_KEY_ID}`.
+///
+/// The placeholders use the `${name}` syntax of NGINX variables, so the directive should not
+/// also accept NGINX variables.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvString(pub String);
+
+impl FromArg<'_> for EnvString {
+    fn from_arg(arg: &NgxStr) -> Result<Self, ConfError> {
+        expand_env_arg(arg).map(EnvString)
+    }
+}
+
+impl Deref for EnvString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<EnvString> for String {
+    fn from(value: EnvString) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for EnvString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
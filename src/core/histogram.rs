@@ -0,0 +1,164 @@
+pub use ngx_core::{HistogramBuckets, HistogramBucketsError, HistogramSnapshot, MAX_HISTOGRAM_BUCKETS};
+
+use crate::core::{ConfError, FromArg, NgxStr, Pool, SharedZone, ShmSafe, ZoneSpec};
+use crate::ffi::*;
+
+use std::array;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+impl FromArg<'_> for HistogramBuckets {
+    fn from_arg(arg: &NgxStr) -> Result<Self, ConfError> {
+        let arg = arg.to_str().map_err(|err| ConfError::from_error(&err))?;
+        arg.parse().map_err(|err| ConfError::from_error(&err))
+    }
+}
+
+/// A latency histogram shared by all workers through a shared memory zone.
+///
+/// Observations are counted with atomic increments of the bucket counters, without locking the
+/// zone. The bucket boundaries are usually taken from a directive, as [`HistogramBuckets`]
+/// implements [`FromArg`]:
+///
+/// ```rust,ignore
+/// // my_latency_buckets 5ms,25ms,100ms,500ms,2s;
+/// impl Directive for LatencyBuckets {
+///     type Conf = MainConfig;
+///     type Args<'a> = (HistogramBuckets,);
+///
+///     fn set(cf: &mut ngx_conf_t, conf: &mut MainConfig, (buckets,): Self::Args<'_>) -> Result<ConfOk, ConfError> {
+///         conf.latency = Some(unsafe { ShmHistogram::add(cf, "latency", buckets, &*addr_of!(my_module))? });
+///         Ok(ConfOk::Applied)
+///     }
+/// }
+///
+/// // in a log phase handler
+/// latency.observe(request_time);
+///
+/// // in a content handler exporting the metrics
+/// latency.snapshot().write_prometheus(&mut body, latency.buckets(), "request_duration_seconds", "")?;
+/// ```
+///
+/// On reload, the counters are kept if the bucket boundaries did not change.
+#[derive(Clone, Copy)]
+pub struct ShmHistogram {
+    zone: SharedZone<HistogramShared>,
+    /// Buckets of the histogram, allocated from the configuration pool.
+    buckets: *const HistogramBuckets,
+}
+
+/// Counters of a histogram in the zone.
+#[repr(C)]
+struct HistogramShared {
+    /// Bucket boundaries the counters were collected for.
+    bounds: [AtomicU64; MAX_HISTOGRAM_BUCKETS],
+    nbounds: AtomicUsize,
+    sum: AtomicU64,
+    counts: [AtomicU64; MAX_HISTOGRAM_BUCKETS + 1],
+}
+
+// SAFETY: the counters are only updated with atomic operations
+unsafe impl ShmSafe for HistogramShared {}
+
+impl Default for HistogramShared {
+    fn default() -> Self {
+        HistogramShared {
+            bounds: array::from_fn(|_| AtomicU64::new(0)),
+            nbounds: AtomicUsize::new(0),
+            sum: AtomicU64::new(0),
+            counts: array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+}
+
+impl HistogramShared {
+    /// Zeroes the counters if they were collected for other buckets, i.e. on reload.
+    fn reset_for(&self, buckets: &HistogramBuckets) {
+        let bounds = buckets.bounds();
+        let same = self.nbounds.load(Ordering::Relaxed) == bounds.len()
+            && self
+                .bounds
+                .iter()
+                .zip(bounds)
+                .all(|(bound, &expected)| bound.load(Ordering::Relaxed) == expected);
+        if same {
+            return;
+        }
+
+        for (bound, &value) in self.bounds.iter().zip(bounds) {
+            bound.store(value, Ordering::Relaxed);
+        }
+        self.nbounds.store(bounds.len(), Ordering::Relaxed);
+        self.sum.store(0, Ordering::Relaxed);
+        for count in &self.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ShmHistogram {
+    /// Adds a shared memory zone named `name` holding a histogram with the buckets `buckets`.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer.
+    pub unsafe fn add(
+        cf: *mut ngx_conf_t,
+        name: &str,
+        buckets: HistogramBuckets,
+        module: &ngx_module_t,
+    ) -> Result<Self, ConfError> {
+        let spec = ZoneSpec {
+            name: name.into(),
+            size: 8 * ngx_pagesize,
+        };
+        let zone = SharedZone::<HistogramShared>::add_unique(cf, &spec, module)?;
+
+        let buckets = Pool::from_ngx_pool((*cf).pool).allocate(buckets) as *const HistogramBuckets;
+        if buckets.is_null() {
+            return Err(ConfError::new("failed to allocate zone context"));
+        }
+
+        // the zone is reused on reload, and the counters are zeroed if the buckets changed
+        zone.on_init(move |shared, _| {
+            shared.reset_for(unsafe { &*buckets });
+            Some(())
+        });
+
+        Ok(ShmHistogram { zone, buckets })
+    }
+
+    /// Returns the buckets of the histogram.
+    pub fn buckets(&self) -> &HistogramBuckets {
+        unsafe { &*self.buckets }
+    }
+
+    /// Counts an observed duration, with millisecond precision.
+    pub fn observe(&self, value: Duration) {
+        self.observe_msec(value.as_millis().try_into().unwrap_or(u64::MAX));
+    }
+
+    /// Counts an observed duration in milliseconds.
+    pub fn observe_msec(&self, msec: u64) {
+        let Some(shared) = self.zone.get() else {
+            return;
+        };
+        shared.counts[self.buckets().index(msec)].fetch_add(1, Ordering::Relaxed);
+        shared.sum.fetch_add(msec, Ordering::Relaxed);
+    }
+
+    /// Returns the current counters of the histogram.
+    ///
+    /// The counters are read one by one while other workers keep updating them, so the sum may
+    /// not exactly match the counts.
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let Some(shared) = self.zone.get() else {
+            return HistogramSnapshot::new(vec![0; self.buckets().len()], 0);
+        };
+        let counts = shared.counts[..self.buckets().len()]
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        HistogramSnapshot::new(counts, shared.sum.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1 @@
+pub use ngx_core::{KeyEntry, KeySet, KeySetError};
@@ -0,0 +1,693 @@
+use crate::core::{with_rng, ConfError, SharedZone, ShmMutex, ShmSafe, ZoneSpec};
+use crate::event::duration_to_msec;
+use crate::ffi::*;
+
+use std::borrow::Cow;
+use std::cell::UnsafeCell;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::time::Duration;
+use std::{mem, ptr, slice};
+
+/// A value that can be stored in a [`Memo`].
+pub trait MemoValue: Sized {
+    /// Serializes the value into the bytes stored in shared memory.
+    fn to_bytes(&self) -> Cow<'_, [u8]>;
+
+    /// Restores a value from its stored bytes, or returns `None` if they are not a valid value.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl MemoValue for Vec<u8> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl MemoValue for String {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+/// Limits of a [`Memo`].
+#[derive(Clone, Copy, Debug)]
+pub struct MemoConfig {
+    /// Maximum number of keys, after which the least recently used ones are evicted.
+    pub max_entries: usize,
+    /// Time a computed value stays fresh.
+    pub ttl: Duration,
+    /// Time after which a computation that was not completed is considered lost, and another
+    /// worker may start it again.
+    pub compute_timeout: Duration,
+}
+
+impl Default for MemoConfig {
+    fn default() -> Self {
+        MemoConfig {
+            max_entries: 1024,
+            ttl: Duration::from_secs(60),
+            compute_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The result of a [`Memo::lookup`].
+pub enum MemoLookup<V> {
+    /// A fresh value.
+    Hit(V),
+    /// No fresh value exists and nobody is computing it: the caller is responsible for computing
+    /// the value and passing it to [`Memo::complete`] with the ticket.
+    Compute(MemoTicket),
+    /// Another request, possibly in another worker, is computing the value.
+    Pending,
+    /// Another request is computing a new value; the expired value may be used meanwhile.
+    Stale(V),
+    /// The value cannot be shared, e.g. because the zone is not created yet or is full: the
+    /// caller may compute the value for itself, without storing it.
+    Bypass,
+}
+
+/// The right to compute the value of a key of a [`Memo`], obtained from [`Memo::lookup`].
+///
+/// Only one ticket exists for a key at a time. If it is dropped without being completed or
+/// abandoned, e.g. because the worker exited, other workers get a new ticket after
+/// [`MemoConfig::compute_timeout`]. A ticket superseded this way can no longer complete or
+/// abandon the computation of the key.
+#[must_use]
+pub struct MemoTicket {
+    key: Vec<u8>,
+    hash: u32,
+    /// The end of the reservation of the key, identifying the ticket among the successive
+    /// tickets of the key.
+    pending_until: ngx_msec_t,
+}
+
+impl MemoTicket {
+    /// Returns the key to compute the value for.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Returns `true` if the entry of the key is still reserved for this ticket.
+    fn holds(&self, e: &Entry) -> bool {
+        e.flags & ENTRY_PENDING != 0 && e.pending_until == self.pending_until
+    }
+}
+
+/// A cache of expensive computation results shared by all workers through a shared memory zone.
+///
+/// Entries expire after a TTL, and the least recently used entries are evicted when the cache is
+/// full or the zone runs out of memory. Lookups have single-flight semantics: when a key is
+/// missing or expired, only the first caller is asked to compute it, while concurrent callers
+/// in all workers are told the value is pending (or get the expired value, if any).
+///
+/// ```rust,ignore
+/// // in a directive handler or `init_main_conf`
+/// conf.jwks = Some(Memo::add(cf, "jwks", 1024 * 1024, &*addr_of!(my_module), MemoConfig::default())?);
+///
+/// // in a request handler
+/// match jwks.lookup(issuer) {
+///     MemoLookup::Hit(keys) | MemoLookup::Stale(keys) => verify(request, &keys),
+///     MemoLookup::Compute(ticket) => spawn_fetch(request, ticket), // calls jwks.complete(ticket, &keys)
+///     MemoLookup::Pending => retry_later(request),
+///     MemoLookup::Bypass => fetch_unshared(request),
+/// }
+/// ```
+///
+/// Values are compared by key bytes and copied in and out of the shared memory zone through
+/// [`MemoValue`]. Expiration uses the cached monotonic time of the worker, `ngx_current_msec`.
+pub struct Memo<K: ?Sized, V> {
+    zone: SharedZone<MemoRoot>,
+    config: MemoConfig,
+    _type: PhantomData<(fn(&K), fn() -> V)>,
+}
+
+impl<K: ?Sized, V> Clone for Memo<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K: ?Sized, V> Copy for Memo<K, V> {}
+
+/// Root of the cache in the shared memory zone.
+#[derive(Default)]
+struct MemoRoot(UnsafeCell<MemoShared>);
+
+// SAFETY: the cache points to the slab pool of the zone, and is only accessed under its lock
+unsafe impl ShmSafe for MemoRoot {}
+
+/// Cache structure in the shared memory zone.
+#[repr(C)]
+struct MemoShared {
+    buckets: *mut *mut Entry,
+    nbuckets: usize,
+    count: usize,
+    max_entries: usize,
+    /// Most recently used entry.
+    lru_head: *mut Entry,
+    /// Least recently used entry.
+    lru_tail: *mut Entry,
+    /// Random key of the hash of the keys, so that clients cannot choose keys colliding into
+    /// one bucket.
+    seed: [u64; 2],
+}
+
+impl Default for MemoShared {
+    fn default() -> Self {
+        MemoShared {
+            buckets: ptr::null_mut(),
+            nbuckets: 0,
+            count: 0,
+            max_entries: 0,
+            lru_head: ptr::null_mut(),
+            lru_tail: ptr::null_mut(),
+            seed: [0; 2],
+        }
+    }
+}
+
+/// Cache entry in the shared memory zone, followed by the key and the value bytes.
+#[repr(C)]
+struct Entry {
+    hash: u32,
+    flags: u32,
+    key_len: usize,
+    value_len: usize,
+    expires: ngx_msec_t,
+    pending_until: ngx_msec_t,
+    next: *mut Entry,
+    lru_prev: *mut Entry,
+    lru_next: *mut Entry,
+}
+
+const ENTRY_HAS_VALUE: u32 = 0x1;
+const ENTRY_PENDING: u32 = 0x2;
+
+impl<K: AsRef<[u8]> + ?Sized, V: MemoValue> Memo<K, V> {
+    /// Adds a shared memory zone named `name` of `size` bytes holding the cache.
+    ///
+    /// The zone is created when the configuration is applied; lookups done before that are
+    /// bypassed, see [`MemoLookup::Bypass`]. On reload, the cached values are kept if the zone
+    /// size did not change.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer.
+    pub unsafe fn add(
+        cf: *mut ngx_conf_t,
+        name: &str,
+        size: usize,
+        module: &ngx_module_t,
+        config: MemoConfig,
+    ) -> Result<Self, ConfError> {
+        let spec = ZoneSpec {
+            name: name.into(),
+            size,
+        };
+        let zone = SharedZone::<MemoRoot>::add_unique(cf, &spec, module)?;
+
+        zone.on_init(move |root, pool| {
+            let locked = pool.lock();
+            let shared = root.0.get();
+
+            // the zone is reused on reload, with the limit of the new configuration
+            unsafe {
+                if (*shared).buckets.is_null() {
+                    let nbuckets = config.max_entries.max(16).next_power_of_two();
+                    let buckets = locked.calloc(nbuckets * mem::size_of::<*mut Entry>()) as *mut *mut Entry;
+                    if buckets.is_null() {
+                        return None;
+                    }
+                    (*shared).buckets = buckets;
+                    (*shared).nbuckets = nbuckets;
+                    (*shared).seed = with_rng(|rng| [rng.next_u64(), rng.next_u64()]);
+                }
+                (*shared).max_entries = config.max_entries.max(1);
+            }
+
+            // running out of memory is expected, and handled by evicting entries
+            pool.set_log_nomem(false);
+            Some(())
+        });
+
+        Ok(Memo {
+            zone,
+            config,
+            _type: PhantomData,
+        })
+    }
+
+    /// Looks up the value of `key`.
+    pub fn lookup(&self, key: &K) -> MemoLookup<V> {
+        let key = key.as_ref();
+        let Some((shpool, shared, config)) = self.zone() else {
+            return MemoLookup::Bypass;
+        };
+
+        // SAFETY: the seed is not changed once the zone is created
+        let hash = hash_key(unsafe { &(*shared).seed }, key);
+
+        let now = unsafe { ngx_current_msec };
+        let pending_until = now.wrapping_add(duration_to_msec(config.compute_timeout));
+        let ticket = || {
+            MemoLookup::Compute(MemoTicket {
+                key: key.to_vec(),
+                hash,
+                pending_until,
+            })
+        };
+
+        let value = unsafe {
+            let _lock = ShmMutex::from_ngx_shmtx(ptr::addr_of_mut!((*shpool).mutex)).lock();
+
+            let e = find(shared, hash, key);
+            if e.is_null() {
+                // reserve the key, so concurrent lookups know the value is being computed
+                return if reserve(shpool, shared, hash, key, pending_until) {
+                    ticket()
+                } else {
+                    MemoLookup::Bypass
+                };
+            }
+
+            let e = &mut *e;
+            touch(shared, e);
+
+            let has_value = e.flags & ENTRY_HAS_VALUE != 0;
+            if has_value && msec_before(now, e.expires) {
+                Ok(entry_value(e).to_vec())
+            } else if e.flags & ENTRY_PENDING != 0 && msec_before(now, e.pending_until) {
+                if has_value {
+                    Err(Some(entry_value(e).to_vec()))
+                } else {
+                    Err(None)
+                }
+            } else {
+                e.flags |= ENTRY_PENDING;
+                e.pending_until = pending_until;
+                return ticket();
+            }
+        };
+
+        let bytes = match value {
+            Ok(bytes) => match V::from_bytes(&bytes) {
+                Some(value) => return MemoLookup::Hit(value),
+                None => bytes,
+            },
+            Err(Some(bytes)) => match V::from_bytes(&bytes) {
+                Some(value) => return MemoLookup::Stale(value),
+                None => bytes,
+            },
+            Err(None) => return MemoLookup::Pending,
+        };
+
+        // the value cannot be decoded, e.g. it was stored by another version of the module: it is
+        // evicted, keeping the reservation of a computation in progress
+        unsafe {
+            let _lock = ShmMutex::from_ngx_shmtx(ptr::addr_of_mut!((*shpool).mutex)).lock();
+
+            let e = find(shared, hash, key);
+            if let Some(entry) = e.as_ref() {
+                if entry.flags & ENTRY_HAS_VALUE == 0 || entry_value(entry) != bytes {
+                    // the value was replaced meanwhile
+                    return MemoLookup::Pending;
+                }
+            }
+            let computing = e
+                .as_ref()
+                .filter(|e| e.flags & ENTRY_PENDING != 0 && msec_before(now, e.pending_until))
+                .map(|e| e.pending_until);
+            if !e.is_null() {
+                unlink(shpool, shared, e);
+            }
+
+            match computing {
+                Some(until) => {
+                    // the ticket stores the value even if the reservation is lost
+                    reserve(shpool, shared, hash, key, until);
+                    MemoLookup::Pending
+                }
+                None if reserve(shpool, shared, hash, key, pending_until) => ticket(),
+                None => MemoLookup::Bypass,
+            }
+        }
+    }
+
+    /// Stores the value computed for the key of `ticket`.
+    ///
+    /// Returns `None` if the value could not be stored, e.g. because it does not fit in the
+    /// zone, and the key is then released for another computation, or because the reservation
+    /// of the ticket expired and another computation of the key started.
+    pub fn complete(&self, ticket: MemoTicket, value: &V) -> Option<()> {
+        let (shpool, shared, config) = self.zone()?;
+        let bytes = value.to_bytes();
+        let now = unsafe { ngx_current_msec };
+
+        unsafe {
+            let _lock = ShmMutex::from_ngx_shmtx(ptr::addr_of_mut!((*shpool).mutex)).lock();
+
+            let e = find(shared, ticket.hash, &ticket.key);
+            if let Some(entry) = e.as_ref() {
+                if !ticket.holds(entry) {
+                    return None;
+                }
+                unlink(shpool, shared, e);
+            }
+
+            let e = insert(shpool, shared, ticket.hash, &ticket.key, &bytes).as_mut()?;
+            e.flags = ENTRY_HAS_VALUE;
+            e.expires = now.wrapping_add(duration_to_msec(config.ttl));
+        }
+        Some(())
+    }
+
+    /// Gives up computing the value for the key of `ticket`, letting the next lookup compute it.
+    ///
+    /// An expired value of the key is kept and still served as stale. Nothing is done if the
+    /// reservation of the ticket expired and another computation of the key started.
+    pub fn abandon(&self, ticket: MemoTicket) {
+        let Some((shpool, shared, _)) = self.zone() else {
+            return;
+        };
+
+        unsafe {
+            let _lock = ShmMutex::from_ngx_shmtx(ptr::addr_of_mut!((*shpool).mutex)).lock();
+
+            let e = find(shared, ticket.hash, &ticket.key);
+            if let Some(entry) = e.as_mut().filter(|entry| ticket.holds(entry)) {
+                if entry.flags & ENTRY_HAS_VALUE != 0 {
+                    entry.flags &= !ENTRY_PENDING;
+                } else {
+                    unlink(shpool, shared, e);
+                }
+            }
+        }
+    }
+
+    /// Removes the value of `key`, if any.
+    pub fn remove(&self, key: &K) {
+        let Some((shpool, shared, _)) = self.zone() else {
+            return;
+        };
+        let key = key.as_ref();
+
+        unsafe {
+            let _lock = ShmMutex::from_ngx_shmtx(ptr::addr_of_mut!((*shpool).mutex)).lock();
+
+            let e = find(shared, hash_key(&(*shared).seed, key), key);
+            if !e.is_null() {
+                unlink(shpool, shared, e);
+            }
+        }
+    }
+
+    fn zone(&self) -> Option<(*mut ngx_slab_pool_t, *mut MemoShared, MemoConfig)> {
+        let root = self.zone.get()?;
+        let shpool = self.zone.slab_pool()?;
+        Some((shpool.as_ptr(), root.0.get(), self.config))
+    }
+}
+
+/// Inserts an entry without a value for `key`, marking its value as being computed until
+/// `pending_until`.
+///
+/// Returns `false` if the entry cannot be allocated.
+unsafe fn reserve(
+    shpool: *mut ngx_slab_pool_t,
+    shared: *mut MemoShared,
+    hash: u32,
+    key: &[u8],
+    pending_until: ngx_msec_t,
+) -> bool {
+    match insert(shpool, shared, hash, key, &[]).as_mut() {
+        Some(e) => {
+            e.flags = ENTRY_PENDING;
+            e.pending_until = pending_until;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns `true` if `a` is earlier than `b`, accounting for the wrapping of the msec clock.
+fn msec_before(a: ngx_msec_t, b: ngx_msec_t) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+/// SipHash-2-4 of a key, keyed with the seed of the zone.
+fn hash_key(seed: &[u64; 2], key: &[u8]) -> u32 {
+    // the hasher is only deprecated in favor of the unkeyed `DefaultHasher`
+    #[allow(deprecated)]
+    let mut hasher = std::hash::SipHasher::new_with_keys(seed[0], seed[1]);
+    hasher.write(key);
+    hasher.finish() as u32
+}
+
+unsafe fn entry_key<'a>(e: *const Entry) -> &'a [u8] {
+    slice::from_raw_parts((e as *const u8).add(mem::size_of::<Entry>()), (*e).key_len)
+}
+
+unsafe fn entry_value<'a>(e: *const Entry) -> &'a [u8] {
+    let data = (e as *const u8).add(mem::size_of::<Entry>() + (*e).key_len);
+    slice::from_raw_parts(data, (*e).value_len)
+}
+
+unsafe fn bucket(shared: *mut MemoShared, hash: u32) -> *mut *mut Entry {
+    (*shared).buckets.add(hash as usize & ((*shared).nbuckets - 1))
+}
+
+unsafe fn find(shared: *mut MemoShared, hash: u32, key: &[u8]) -> *mut Entry {
+    let mut e = *bucket(shared, hash);
+    while !e.is_null() {
+        if (*e).hash == hash && entry_key(e) == key {
+            return e;
+        }
+        e = (*e).next;
+    }
+    ptr::null_mut()
+}
+
+/// Moves `e` to the head of the LRU list.
+unsafe fn touch(shared: *mut MemoShared, e: *mut Entry) {
+    if (*shared).lru_head == e {
+        return;
+    }
+    lru_remove(shared, e);
+    lru_push_front(shared, e);
+}
+
+unsafe fn lru_remove(shared: *mut MemoShared, e: *mut Entry) {
+    match (*e).lru_prev.as_mut() {
+        Some(prev) => prev.lru_next = (*e).lru_next,
+        None => (*shared).lru_head = (*e).lru_next,
+    }
+    match (*e).lru_next.as_mut() {
+        Some(next) => next.lru_prev = (*e).lru_prev,
+        None => (*shared).lru_tail = (*e).lru_prev,
+    }
+    (*e).lru_prev = ptr::null_mut();
+    (*e).lru_next = ptr::null_mut();
+}
+
+unsafe fn lru_push_front(shared: *mut MemoShared, e: *mut Entry) {
+    (*e).lru_prev = ptr::null_mut();
+    (*e).lru_next = (*shared).lru_head;
+    match (*shared).lru_head.as_mut() {
+        Some(head) => head.lru_prev = e,
+        None => (*shared).lru_tail = e,
+    }
+    (*shared).lru_head = e;
+}
+
+/// Removes `e` from the cache and frees it.
+unsafe fn unlink(shpool: *mut ngx_slab_pool_t, shared: *mut MemoShared, e: *mut Entry) {
+    let mut link = bucket(shared, (*e).hash);
+    while !(*link).is_null() {
+        if *link == e {
+            *link = (*e).next;
+            break;
+        }
+        link = &mut (**link).next;
+    }
+
+    lru_remove(shared, e);
+    (*shared).count -= 1;
+    ngx_slab_free_locked(shpool, e as *mut c_void);
+}
+
+/// Inserts a new entry, evicting the least recently used entries as needed.
+///
+/// Returns a null pointer if the entry does not fit in the zone.
+unsafe fn insert(
+    shpool: *mut ngx_slab_pool_t,
+    shared: *mut MemoShared,
+    hash: u32,
+    key: &[u8],
+    value: &[u8],
+) -> *mut Entry {
+    while (*shared).count >= (*shared).max_entries && !(*shared).lru_tail.is_null() {
+        unlink(shpool, shared, (*shared).lru_tail);
+    }
+
+    let size = mem::size_of::<Entry>() + key.len() + value.len();
+    let e = loop {
+        let e = ngx_slab_alloc_locked(shpool, size) as *mut Entry;
+        if !e.is_null() {
+            break e;
+        }
+        if (*shared).lru_tail.is_null() {
+            return ptr::null_mut();
+        }
+        unlink(shpool, shared, (*shared).lru_tail);
+    };
+
+    ptr::write(
+        e,
+        Entry {
+            hash,
+            flags: 0,
+            key_len: key.len(),
+            value_len: value.len(),
+            expires: 0,
+            pending_until: 0,
+            next: *bucket(shared, hash),
+            lru_prev: ptr::null_mut(),
+            lru_next: ptr::null_mut(),
+        },
+    );
+    let data = (e as *mut u8).add(mem::size_of::<Entry>());
+    ptr::copy_nonoverlapping(key.as_ptr(), data, key.len());
+    ptr::copy_nonoverlapping(value.as_ptr(), data.add(key.len()), value.len());
+
+    *bucket(shared, hash) = e;
+    lru_push_front(shared, e);
+    (*shared).count += 1;
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(hash: u32) -> Entry {
+        Entry {
+            hash,
+            flags: 0,
+            key_len: 0,
+            value_len: 0,
+            expires: 0,
+            pending_until: 0,
+            next: ptr::null_mut(),
+            lru_prev: ptr::null_mut(),
+            lru_next: ptr::null_mut(),
+        }
+    }
+
+    /// Returns the hashes of the entries from the most to the least recently used, checking the
+    /// backward links on the way.
+    unsafe fn lru_order(shared: *mut MemoShared) -> Vec<u32> {
+        let mut order = Vec::new();
+        let mut prev = ptr::null_mut();
+        let mut e = (*shared).lru_head;
+        while !e.is_null() {
+            assert_eq!((*e).lru_prev, prev);
+            order.push((*e).hash);
+            prev = e;
+            e = (*e).lru_next;
+        }
+        assert_eq!((*shared).lru_tail, prev);
+        order
+    }
+
+    #[test]
+    fn test_msec_before() {
+        assert!(msec_before(1, 2));
+        assert!(!msec_before(2, 1));
+        assert!(!msec_before(2, 2));
+
+        // the clock wraps around
+        assert!(msec_before(ngx_msec_t::MAX, 1));
+        assert!(!msec_before(1, ngx_msec_t::MAX));
+        assert!(msec_before(ngx_msec_t::MAX - 10, 10));
+    }
+
+    #[test]
+    fn test_hash_key() {
+        let seed = [1, 2];
+
+        assert_eq!(hash_key(&seed, b"key"), hash_key(&seed, b"key"));
+        assert_ne!(hash_key(&seed, b"key"), hash_key(&seed, b"other"));
+        // the same key hashes differently in zones with another seed
+        assert_ne!(hash_key(&seed, b"key"), hash_key(&[2, 1], b"key"));
+    }
+
+    #[test]
+    fn test_ticket_holds() {
+        let ticket = MemoTicket {
+            key: b"key".to_vec(),
+            hash: 1,
+            pending_until: 100,
+        };
+
+        let mut e = entry(1);
+        e.flags = ENTRY_PENDING;
+        e.pending_until = 100;
+        assert!(ticket.holds(&e));
+
+        // a later ticket reserved the key
+        e.pending_until = 200;
+        assert!(!ticket.holds(&e));
+
+        // the value was stored
+        e.flags = ENTRY_HAS_VALUE;
+        e.pending_until = 100;
+        assert!(!ticket.holds(&e));
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut shared = MemoShared::default();
+        let shared: *mut MemoShared = &mut shared;
+        let (mut a, mut b, mut c) = (entry(1), entry(2), entry(3));
+        let (a, b, c): (*mut Entry, *mut Entry, *mut Entry) = (&mut a, &mut b, &mut c);
+
+        unsafe {
+            assert!(lru_order(shared).is_empty());
+
+            lru_push_front(shared, a);
+            lru_push_front(shared, b);
+            lru_push_front(shared, c);
+            assert_eq!(lru_order(shared), [3, 2, 1]);
+
+            // the least recently used entry becomes the most recently used one
+            touch(shared, a);
+            assert_eq!(lru_order(shared), [1, 3, 2]);
+
+            touch(shared, a);
+            assert_eq!(lru_order(shared), [1, 3, 2]);
+
+            touch(shared, c);
+            assert_eq!(lru_order(shared), [3, 1, 2]);
+
+            lru_remove(shared, a);
+            assert_eq!(lru_order(shared), [3, 2]);
+            assert!((*a).lru_prev.is_null() && (*a).lru_next.is_null());
+
+            lru_remove(shared, b);
+            lru_remove(shared, c);
+            assert!(lru_order(shared).is_empty());
+            assert!((*shared).lru_head.is_null());
+        }
+    }
+}
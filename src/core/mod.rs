@@ -1,12 +1,70 @@
+mod array;
+#[cfg(target_os = "linux")]
+mod bpf;
 mod buffer;
+mod build_info;
+mod command;
+mod conf;
+mod connection;
+mod cycle;
+mod dump;
+mod env;
+mod histogram;
+mod key_set;
+mod memo;
+mod module;
+mod peer;
 mod pool;
+mod pool_vec;
+#[cfg(feature = "http_v3")]
+mod quic;
+mod random;
+mod resolver;
+mod scan;
+mod secret;
+mod secret_provider;
+mod service;
+mod slab;
 mod status;
 mod string;
+#[cfg(feature = "threads")]
+mod thread_pool;
+mod worker;
+mod zone;
 
+pub use array::*;
+#[cfg(target_os = "linux")]
+pub use bpf::*;
 pub use buffer::*;
+pub use build_info::*;
+pub use command::*;
+pub use conf::*;
+pub use connection::*;
+pub use cycle::*;
+pub use dump::*;
+pub use env::*;
+pub use histogram::*;
+pub use key_set::*;
+pub use memo::*;
+pub use module::*;
+pub use peer::*;
 pub use pool::*;
+pub use pool_vec::*;
+#[cfg(feature = "http_v3")]
+pub use quic::*;
+pub use random::*;
+pub use resolver::*;
+pub use scan::*;
+pub use secret::*;
+pub use secret_provider::*;
+pub use service::*;
+pub use slab::*;
 pub use status::*;
 pub use string::*;
+#[cfg(feature = "threads")]
+pub use thread_pool::*;
+pub use worker::*;
+pub use zone::*;
 
 /// Static empty configuration directive initializer for [`ngx_command_t`].
 ///
@@ -0,0 +1,264 @@
+use crate::core::{ngx_conf_result, ngx_is_config_test, ConfError, Pool, Status};
+use crate::ffi::*;
+
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_void};
+use std::{mem, ptr};
+
+/// The `CoreModule` trait provides the configuration interface of an `NGX_CORE_MODULE`.
+///
+/// Core modules configure the whole NGINX instance from the main context of the configuration
+/// file, outside of the `http` and `stream` blocks, e.g. for resolvers or services shared by the
+/// other modules of the process. The configuration is created before the configuration file is
+/// parsed, so directives declared with `NGX_MAIN_CONF | NGX_DIRECT_CONF` receive it directly.
+///
+/// Modules are declared with [`define_core_module!`](crate::define_core_module):
+///
+/// ```rust,ignore
+/// #[derive(Default)]
+/// struct ResolverConf {
+///     servers: Vec<String>,
+/// }
+///
+/// struct ResolverModule;
+///
+/// impl CoreModule for ResolverModule {
+///     type Conf = ResolverConf;
+///
+///     fn init_conf(_cycle: &mut ngx_cycle_t, conf: &mut ResolverConf) -> Result<(), ConfError> {
+///         if conf.servers.is_empty() {
+///             conf.servers.push("127.0.0.53".to_string());
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// static mut ngx_rs_resolver_commands: [ngx_command_t; 2] = [
+///     Command::new::<ResolverServers>(c"rs_resolver")
+///         .context(NGX_MAIN_CONF | NGX_DIRECT_CONF)
+///         .build(),
+///     ngx_null_command!(),
+/// ];
+///
+/// define_core_module!(ngx_rs_resolver_module, "rs_resolver", ResolverModule, ngx_rs_resolver_commands);
+/// ```
+pub trait CoreModule {
+    /// Configuration of the module.
+    type Conf: Default;
+
+    /// Initializes the configuration once the configuration file is parsed, e.g. applying
+    /// defaults to the directives that were not set.
+    ///
+    /// The error is logged at the `emerg` level and fails the configuration.
+    fn init_conf(_cycle: &mut ngx_cycle_t, _conf: &mut Self::Conf) -> Result<(), ConfError> {
+        Ok(())
+    }
+
+    /// Validates the initialized configuration when NGINX only tests the configuration, as with
+    /// `nginx -t`.
+    ///
+    /// This is the place for checks too expensive or too intrusive to run on each start or
+    /// reload, such as connecting to the configured endpoints or decrypting the configured key
+    /// files. The error is logged at the `emerg` level and fails the configuration test; no
+    /// worker is started in this mode either way.
+    fn check_conf(_cycle: &mut ngx_cycle_t, _conf: &Self::Conf) -> Result<(), ConfError> {
+        Ok(())
+    }
+
+    /// Called in the master process once the configuration is initialized, before the workers
+    /// start, e.g. to open sockets shared by the workers. An error fails the start or reload.
+    fn init_module(_cycle: &mut ngx_cycle_t) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Called in each worker process when it starts, e.g. to start background tasks. An error
+    /// terminates the worker.
+    fn init_process(_cycle: &mut ngx_cycle_t) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Called in each worker process before it exits.
+    fn exit_process(_cycle: &mut ngx_cycle_t) {}
+
+    /// Called in the master process before it exits.
+    fn exit_master(_cycle: &mut ngx_cycle_t) {}
+}
+
+/// Define a static core module.
+///
+/// The arguments are the name of the module static, the module name, the type implementing
+/// [`CoreModule`], and the static array of the module directives, terminated by
+/// [`ngx_null_command!`](crate::ngx_null_command).
+#[macro_export]
+macro_rules! define_core_module {
+    ( $name: ident, $module_name: literal, $module: ty, $commands: ident ) => {
+        #[no_mangle]
+        #[used]
+        pub static mut $name: $crate::ffi::ngx_module_t = $crate::ffi::ngx_module_t {
+            ctx_index: $crate::ffi::ngx_uint_t::MAX,
+            index: $crate::ffi::ngx_uint_t::MAX,
+            name: ::std::ptr::null_mut(),
+            spare0: 0,
+            spare1: 0,
+            version: $crate::ffi::nginx_version as $crate::ffi::ngx_uint_t,
+            signature: $crate::ffi::NGX_RS_MODULE_SIGNATURE.as_ptr() as *const ::std::os::raw::c_char,
+
+            ctx: {
+                static mut CTX: $crate::ffi::ngx_core_module_t = $crate::ffi::ngx_core_module_t {
+                    name: $crate::ngx_string!($module_name),
+                    create_conf: Some($crate::core::ngx_core_module_create_conf::<$module>),
+                    init_conf: Some($crate::core::ngx_core_module_init_conf::<$module>),
+                };
+                ::std::ptr::addr_of_mut!(CTX) as *mut ::std::os::raw::c_void
+            },
+            commands: ::std::ptr::addr_of_mut!($commands) as *mut $crate::ffi::ngx_command_t,
+            type_: $crate::ffi::NGX_CORE_MODULE as $crate::ffi::ngx_uint_t,
+
+            init_master: None,
+            init_module: Some($crate::core::ngx_module_init_module::<$crate::core::CoreModuleHooks<$module>>),
+            init_process: Some($crate::core::ngx_module_init_process::<$crate::core::CoreModuleHooks<$module>>),
+            init_thread: None,
+            exit_thread: None,
+            exit_process: Some($crate::core::ngx_module_exit_process::<$crate::core::CoreModuleHooks<$module>>),
+            exit_master: Some($crate::core::ngx_module_exit_master::<$crate::core::CoreModuleHooks<$module>>),
+
+            spare_hook0: 0,
+            spare_hook1: 0,
+            spare_hook2: 0,
+            spare_hook3: 0,
+            spare_hook4: 0,
+            spare_hook5: 0,
+            spare_hook6: 0,
+            spare_hook7: 0,
+        };
+    };
+}
+
+/// Returns the configuration of the core module `module` in `cycle`.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_cycle_t` pointer with a parsed configuration,
+/// and `T` is the configuration type of `module`.
+pub unsafe fn ngx_core_get_module_conf<'a, T>(cycle: *const ngx_cycle_t, module: &ngx_module_t) -> Option<&'a T> {
+    let conf_ctx = (*cycle).conf_ctx;
+    if conf_ctx.is_null() {
+        return None;
+    }
+    (*conf_ctx.add(module.index) as *const T).as_ref()
+}
+
+/// The `create_conf` handler of a [`CoreModule`].
+///
+/// # Safety
+///
+/// Called by NGINX with a valid non-null `ngx_cycle_t` pointer.
+pub unsafe extern "C" fn ngx_core_module_create_conf<M: CoreModule>(cycle: *mut ngx_cycle_t) -> *mut c_void {
+    let mut pool = Pool::from_ngx_pool((*cycle).pool);
+    pool.allocate::<M::Conf>(Default::default()) as *mut c_void
+}
+
+/// The `init_conf` handler of a [`CoreModule`], also running [`CoreModule::check_conf`] when
+/// the configuration is tested.
+///
+/// # Safety
+///
+/// Called by NGINX with a valid non-null `ngx_cycle_t` pointer and the configuration created by
+/// [`ngx_core_module_create_conf`].
+pub unsafe extern "C" fn ngx_core_module_init_conf<M: CoreModule>(
+    cycle: *mut ngx_cycle_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    let conf = &mut *(conf as *mut M::Conf);
+    let result = M::init_conf(&mut *cycle, conf).and_then(|()| {
+        if ngx_is_config_test() {
+            M::check_conf(&mut *cycle, conf)
+        } else {
+            Ok(())
+        }
+    });
+
+    // the configuration file is parsed already, so the error is logged without a position
+    let mut cf: ngx_conf_t = mem::zeroed();
+    cf.cycle = cycle;
+    cf.pool = (*cycle).pool;
+    cf.log = (*cycle).log;
+    ngx_conf_result(&mut cf, ptr::null(), result)
+}
+
+/// The process lifecycle hooks of a module, called by NGINX through the `init_module`,
+/// `init_process`, `exit_process` and `exit_master` fields of the module.
+///
+/// The module types forward the hooks to their own traits, with [`CoreModuleHooks`] for a
+/// [`CoreModule`] and [`HTTPModuleHooks`](crate::http::HTTPModuleHooks) for an
+/// [`HTTPModule`](crate::http::HTTPModule).
+pub trait ModuleHooks {
+    /// See [`CoreModule::init_module`].
+    fn init_module(cycle: &mut ngx_cycle_t) -> Result<(), Status>;
+
+    /// See [`CoreModule::init_process`].
+    fn init_process(cycle: &mut ngx_cycle_t) -> Result<(), Status>;
+
+    /// See [`CoreModule::exit_process`].
+    fn exit_process(cycle: &mut ngx_cycle_t);
+
+    /// See [`CoreModule::exit_master`].
+    fn exit_master(cycle: &mut ngx_cycle_t);
+}
+
+/// The [`ModuleHooks`] of the [`CoreModule`] `M`.
+pub struct CoreModuleHooks<M>(PhantomData<M>);
+
+impl<M: CoreModule> ModuleHooks for CoreModuleHooks<M> {
+    fn init_module(cycle: &mut ngx_cycle_t) -> Result<(), Status> {
+        M::init_module(cycle)
+    }
+
+    fn init_process(cycle: &mut ngx_cycle_t) -> Result<(), Status> {
+        M::init_process(cycle)
+    }
+
+    fn exit_process(cycle: &mut ngx_cycle_t) {
+        M::exit_process(cycle)
+    }
+
+    fn exit_master(cycle: &mut ngx_cycle_t) {
+        M::exit_master(cycle)
+    }
+}
+
+/// The `init_module` hook of a module.
+///
+/// # Safety
+///
+/// Called by NGINX with a valid non-null `ngx_cycle_t` pointer.
+pub unsafe extern "C" fn ngx_module_init_module<M: ModuleHooks>(cycle: *mut ngx_cycle_t) -> ngx_int_t {
+    M::init_module(&mut *cycle).map_or_else(Into::into, |()| Status::NGX_OK.into())
+}
+
+/// The `init_process` hook of a module.
+///
+/// # Safety
+///
+/// Called by NGINX with a valid non-null `ngx_cycle_t` pointer.
+pub unsafe extern "C" fn ngx_module_init_process<M: ModuleHooks>(cycle: *mut ngx_cycle_t) -> ngx_int_t {
+    M::init_process(&mut *cycle).map_or_else(Into::into, |()| Status::NGX_OK.into())
+}
+
+/// The `exit_process` hook of a module.
+///
+/// # Safety
+///
+/// Called by NGINX with a valid non-null `ngx_cycle_t` pointer.
+pub unsafe extern "C" fn ngx_module_exit_process<M: ModuleHooks>(cycle: *mut ngx_cycle_t) {
+    M::exit_process(&mut *cycle)
+}
+
+/// The `exit_master` hook of a module.
+///
+/// # Safety
+///
+/// Called by NGINX with a valid non-null `ngx_cycle_t` pointer.
+pub unsafe extern "C" fn ngx_module_exit_master<M: ModuleHooks>(cycle: *mut ngx_cycle_t) {
+    M::exit_master(&mut *cycle)
+}
@@ -0,0 +1,212 @@
+use crate::core::{OwnedConnection, Status};
+use crate::ffi::*;
+
+use std::io;
+use std::mem;
+use std::net::SocketAddr;
+use std::os::raw::{c_int, c_void};
+use std::ptr::{self, NonNull};
+
+/// The state of a [`PeerConnection`] after [`PeerConnection::connect`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectState {
+    /// The connection is established, or an idle connection was reused.
+    Connected,
+    /// The connection is being established. The write event of the connection fires once it
+    /// completes or fails, see [`PeerConnection::test_connect`].
+    InProgress,
+}
+
+/// Hooks to keep the connections of [`PeerConnection`]s alive between exchanges, e.g. a cache of
+/// idle connections per address with a limit and an idle timeout.
+pub trait PeerKeepalive {
+    /// Returns an idle connection to `addr`, to use instead of opening a new one.
+    fn get(&mut self, addr: &SocketAddr) -> Option<OwnedConnection>;
+
+    /// Takes the connection to `addr` after a completed exchange. Returns the connection back
+    /// if it cannot be kept, to close it.
+    ///
+    /// An idle connection is still in the event loop: its handlers are expected to be replaced
+    /// to close it when the peer closes it or when it times out.
+    fn free(&mut self, addr: &SocketAddr, conn: OwnedConnection) -> Result<(), OwnedConnection>;
+}
+
+/// An outbound connection opened with the event loop by [`ngx_event_connect_peer`], as the
+/// upstream module does, for modules talking their own protocols to a backend.
+///
+/// ```rust,ignore
+/// let mut peer = PeerConnection::new(addr, log).ok_or(Status::NGX_ERROR)?;
+/// match peer.connect_cached(&mut keepalive)? {
+///     ConnectState::Connected => send_query(&mut peer),
+///     ConnectState::InProgress => {}
+/// }
+/// let conn = peer.connection().unwrap();
+/// conn.set_data(ctx);
+/// conn.set_write_handler(Some(query_write_handler));
+/// conn.set_read_handler(Some(query_read_handler));
+/// conn.set_write_timeout(Some(Duration::from_secs(5)));
+/// ```
+///
+/// On the first write event of a connection in progress, [`PeerConnection::test_connect`]
+/// reports whether it was established. Once the exchange is complete, the connection is either
+/// kept for reuse with [`PeerConnection::keep`] or closed by dropping the peer connection.
+///
+/// [`ngx_event_connect_peer`]: https://nginx.org/en/docs/dev/development_guide.html#event_loop
+pub struct PeerConnection {
+    pc: ngx_peer_connection_t,
+    addr: SocketAddr,
+    // the pool of the address, handed over to the connection once it is opened
+    pool: *mut ngx_pool_t,
+    conn: Option<OwnedConnection>,
+}
+
+impl PeerConnection {
+    /// Creates a peer connection to `addr`, logging to `log`.
+    ///
+    /// Returns `None` if memory allocation fails.
+    pub fn new(addr: SocketAddr, log: *mut ngx_log_t) -> Option<Self> {
+        let pool = unsafe { ngx_create_pool(NGX_DEFAULT_POOL_SIZE as usize, log) };
+        if pool.is_null() {
+            return None;
+        }
+        // destroys the pool on errors
+        let mut peer = PeerConnection {
+            pc: unsafe { mem::zeroed() },
+            addr,
+            pool,
+            conn: None,
+        };
+
+        unsafe {
+            let text = ngx_str_t::from_string(pool, addr.to_string());
+            let parsed = ngx_pcalloc(pool, mem::size_of::<ngx_addr_t>()) as *mut ngx_addr_t;
+            if text.data.is_null()
+                || parsed.is_null()
+                || ngx_parse_addr_port(pool, parsed, text.data, text.len) != NGX_OK as ngx_int_t
+            {
+                return None;
+            }
+
+            let pc = &mut peer.pc;
+            pc.sockaddr = (*parsed).sockaddr;
+            pc.socklen = (*parsed).socklen;
+            pc.name = &mut (*parsed).name;
+            pc.get = Some(ngx_event_get_peer);
+            pc.tries = 1;
+            pc.log = log;
+            // NGX_ERROR_ERR, as for the upstream connections
+            pc.set_log_error(1);
+        }
+
+        Some(peer)
+    }
+
+    /// Returns the address of the peer.
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    /// Opens a new connection to the peer.
+    ///
+    /// Returns `NGX_DECLINED` if the peer refused the connection or could not be reached, and
+    /// `NGX_ERROR` on other failures, e.g. if the worker has no free connections.
+    pub fn connect(&mut self) -> Result<ConnectState, Status> {
+        if self.conn.is_some() {
+            return Ok(ConnectState::Connected);
+        }
+
+        self.pc.start_time = unsafe { ngx_current_msec };
+        let rc = unsafe { ngx_event_connect_peer(&mut self.pc) };
+        // the connection is closed by NGINX on errors
+        let state = match rc {
+            rc if rc == NGX_OK as ngx_int_t => ConnectState::Connected,
+            rc if rc == NGX_AGAIN as ngx_int_t => ConnectState::InProgress,
+            rc => return Err(Status(rc)),
+        };
+
+        let c = NonNull::new(mem::replace(&mut self.pc.connection, ptr::null_mut())).ok_or(Status::NGX_ERROR)?;
+        unsafe {
+            // the pool of the address lives as long as the connection, as it may be kept alive
+            (*c.as_ptr()).pool = mem::replace(&mut self.pool, ptr::null_mut());
+            // SAFETY: the connection was opened by the call above and is not owned elsewhere
+            self.conn = Some(OwnedConnection::from_ngx_connection(c));
+        }
+
+        Ok(state)
+    }
+
+    /// Reuses an idle connection from `keepalive`, or opens a new connection to the peer.
+    pub fn connect_cached<K: PeerKeepalive>(&mut self, keepalive: &mut K) -> Result<ConnectState, Status> {
+        if self.conn.is_none() {
+            self.conn = keepalive.get(&self.addr);
+        }
+        self.connect()
+    }
+
+    /// Returns `true` if the connection was reused from a [`PeerKeepalive`].
+    pub fn is_cached(&self) -> bool {
+        self.conn.is_some() && !self.pool.is_null()
+    }
+
+    /// Checks if a connection in progress was established, on its first write event.
+    pub fn test_connect(&self) -> io::Result<()> {
+        let Some(conn) = self.conn.as_ref() else {
+            return Err(io::ErrorKind::NotConnected.into());
+        };
+
+        let mut err: c_int = 0;
+        let mut len = mem::size_of::<c_int>() as socklen_t;
+        let rc = unsafe {
+            getsockopt(
+                conn.get_inner().fd,
+                SOL_SOCKET as c_int,
+                SO_ERROR as c_int,
+                &mut err as *mut c_int as *mut c_void,
+                &mut len,
+            )
+        };
+        if rc == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+        Ok(())
+    }
+
+    /// Returns the connection to the peer, if connected or in progress.
+    pub fn connection(&mut self) -> Option<&mut OwnedConnection> {
+        self.conn.as_mut()
+    }
+
+    /// Hands the connection over to `keepalive` for reuse after a completed exchange, or closes
+    /// it if it cannot be kept.
+    pub fn keep<K: PeerKeepalive>(mut self, keepalive: &mut K) {
+        if let Some(conn) = self.conn.take() {
+            // closes the connection if it is returned
+            let _ = keepalive.free(&self.addr, conn);
+        }
+    }
+
+    /// Returns the inner data structure that the PeerConnection object is wrapping.
+    pub fn get_inner(&self) -> &ngx_peer_connection_t {
+        &self.pc
+    }
+}
+
+impl Drop for PeerConnection {
+    fn drop(&mut self) {
+        if !self.pool.is_null() {
+            unsafe { ngx_destroy_pool(self.pool) };
+        }
+    }
+}
+
+impl std::fmt::Debug for PeerConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PeerConnection")
+            .field("addr", &self.addr)
+            .field("conn", &self.conn)
+            .finish()
+    }
+}
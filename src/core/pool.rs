@@ -1,6 +1,9 @@
 use crate::core::buffer::{Buffer, MemoryBuffer, TemporaryBuffer};
 use crate::ffi::*;
 
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::os::raw::c_void;
 use std::{mem, ptr};
 
@@ -34,11 +37,18 @@ impl Pool {
     ///
     /// Returns `Some(TemporaryBuffer)` if the buffer is successfully created, or `None` if allocation fails.
     pub fn create_buffer_from_str(&mut self, str: &str) -> Option<TemporaryBuffer> {
-        let mut buffer = self.create_buffer(str.len())?;
+        self.create_buffer_from_bytes(str.as_bytes())
+    }
+
+    /// Creates a buffer holding a copy of `bytes` in the memory pool.
+    ///
+    /// Returns `Some(TemporaryBuffer)` if the buffer is successfully created, or `None` if allocation fails.
+    pub fn create_buffer_from_bytes(&mut self, bytes: &[u8]) -> Option<TemporaryBuffer> {
+        let mut buffer = self.create_buffer(bytes.len())?;
         unsafe {
             let buf = buffer.as_ngx_buf_mut();
-            ptr::copy_nonoverlapping(str.as_ptr(), (*buf).pos, str.len());
-            (*buf).last = (*buf).pos.add(str.len());
+            ptr::copy_nonoverlapping(bytes.as_ptr(), (*buf).pos, bytes.len());
+            (*buf).last = (*buf).pos.add(bytes.len());
         }
         Some(buffer)
     }
@@ -129,6 +139,69 @@ impl Pool {
             p
         }
     }
+
+    /// Returns the value of type `T` attached to the pool with `key` by [`Pool::set_data`].
+    ///
+    /// The pointer is valid until the value is replaced or the pool is destroyed.
+    pub(crate) fn data<T: 'static>(&self, key: usize) -> Option<*mut T> {
+        let data = POOL_DATA.with(|pools| pools.borrow().get(&(self.0 as usize)).copied())?;
+        // SAFETY: the data is removed from the map before it is dropped with its pool
+        let value = unsafe { (*data).values.get_mut(&(TypeId::of::<T>(), key))? };
+        value.downcast_mut::<T>().map(|value| value as *mut T)
+    }
+
+    /// Attaches `value` to the pool, keyed by its type and `key`, replacing the value attached
+    /// with the same type and key.
+    ///
+    /// All the values attached to a pool are owned by a single cleanup handler, and dropped when
+    /// the pool is destroyed. Unlike a cleanup handler of its own, a value is found without
+    /// walking the cleanup list of the pool, e.g. by the request it is attached for as `key`.
+    ///
+    /// Returns a pointer to the value, stable until the value is replaced or the pool is
+    /// destroyed, or `None` if the pool data cannot be allocated.
+    pub(crate) fn set_data<T: 'static>(&mut self, key: usize, value: T) -> Option<*mut T> {
+        let data = match POOL_DATA.with(|pools| pools.borrow().get(&(self.0 as usize)).copied()) {
+            Some(data) => data,
+            None => {
+                let data = self.allocate(PoolData {
+                    pool: self.0,
+                    values: HashMap::new(),
+                });
+                if data.is_null() {
+                    return None;
+                }
+                POOL_DATA.with(|pools| pools.borrow_mut().insert(self.0 as usize, data));
+                data
+            }
+        };
+
+        let mut value: Box<dyn Any> = Box::new(value);
+        let ptr = value.downcast_mut::<T>()? as *mut T;
+        // SAFETY: the data is removed from the map before it is dropped with its pool
+        let previous = unsafe { (*data).values.insert((TypeId::of::<T>(), key), value) };
+        // the previous value may access the pool data when dropped
+        drop(previous);
+        Some(ptr)
+    }
+}
+
+thread_local! {
+    /// The data of the pools of the worker with values attached by [`Pool::set_data`], by the
+    /// address of the pool.
+    static POOL_DATA: RefCell<HashMap<usize, *mut PoolData>> = RefCell::new(HashMap::new());
+}
+
+/// The values attached to a pool, by type and key.
+struct PoolData {
+    pool: *mut ngx_pool_t,
+    values: HashMap<(TypeId, usize), Box<dyn Any>>,
+}
+
+impl Drop for PoolData {
+    fn drop(&mut self) {
+        // the values dropped with the pool no longer find each other
+        POOL_DATA.with(|pools| pools.borrow_mut().remove(&(self.pool as usize)));
+    }
 }
 
 /// Cleanup handler for a specific type `T`.
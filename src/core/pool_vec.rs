@@ -0,0 +1,129 @@
+use crate::core::{ConfError, Pool};
+
+use std::fmt;
+use std::ops::Deref;
+use std::ptr::NonNull;
+
+/// How a [`PoolVec`] of a configuration level is merged with the one of the enclosing level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VecMerge {
+    /// A level with values of its own replaces the values of the enclosing level, and one
+    /// without inherits them, as with the `allow` and `deny` directives of NGINX.
+    #[default]
+    Override,
+    /// The values of the enclosing level come first, followed by the values of the level.
+    Append,
+}
+
+/// The values of a directive that may appear several times, e.g. `my_module_allow 10.0.0.0/8;`
+/// repeated, accumulated in a configuration structure.
+///
+/// The vector is allocated from the configuration pool on the first push, and dropped with it.
+/// A level inheriting the values of the enclosing level shares its vector instead of copying it:
+///
+/// ```rust,ignore
+/// #[derive(Default)]
+/// struct LocConf {
+///     allow: PoolVec<IpNet>,
+/// }
+///
+/// impl Directive for Allow {
+///     type Conf = LocConf;
+///     type Args<'a> = (&'a str,);
+///
+///     fn set(cf: &mut ngx_conf_t, conf: &mut LocConf, (net,): Self::Args<'_>) -> Result<ConfOk, ConfError> {
+///         let net = net.parse().map_err(|err| ConfError::from_error(&err))?;
+///         conf.allow.push(&mut unsafe { Pool::from_ngx_pool(cf.pool) }, net)?;
+///         Ok(ConfOk::Applied)
+///     }
+/// }
+///
+/// impl Merge for LocConf {
+///     fn merge(&mut self, prev: &LocConf) -> Result<(), MergeConfigError> {
+///         self.allow.merge(&prev.allow, VecMerge::Override);
+///         Ok(())
+///     }
+/// }
+/// ```
+pub struct PoolVec<T> {
+    vec: Option<NonNull<Vec<T>>>,
+    /// `false` if the vector is shared with the enclosing level.
+    owned: bool,
+}
+
+impl<T> PoolVec<T> {
+    /// Creates an empty vector, without allocating.
+    pub const fn new() -> Self {
+        PoolVec {
+            vec: None,
+            owned: false,
+        }
+    }
+
+    /// Appends `value`, allocating the vector from `pool` on the first call.
+    ///
+    /// Fails if the vector cannot be allocated.
+    pub fn push(&mut self, pool: &mut Pool, value: T) -> Result<(), ConfError> {
+        let mut vec = match self.vec {
+            Some(vec) if self.owned => vec,
+            _ => NonNull::new(pool.allocate(Vec::new())).ok_or_else(|| ConfError::new("out of memory"))?,
+        };
+        self.vec = Some(vec);
+        self.owned = true;
+        // SAFETY: the vector is owned by this level and allocated from the configuration pool
+        unsafe { vec.as_mut() }.push(value);
+        Ok(())
+    }
+
+    /// Returns `true` if the directive was set at this level, as opposed to empty or inherited.
+    pub fn is_set(&self) -> bool {
+        self.owned
+    }
+
+    /// Returns the values as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the vector is allocated from the configuration pool, which outlives the levels
+        self.vec.map_or(&[], |vec| unsafe { vec.as_ref() }.as_slice())
+    }
+
+    /// Merges the values of the enclosing level `prev` according to `mode`.
+    ///
+    /// This is called from [`Merge::merge`](crate::http::Merge::merge), after the enclosing
+    /// level has been merged itself.
+    pub fn merge(&mut self, prev: &PoolVec<T>, mode: VecMerge)
+    where
+        T: Clone,
+    {
+        match (self.vec, mode) {
+            (None, _) => {
+                self.vec = prev.vec;
+                self.owned = false;
+            }
+            (Some(mut vec), VecMerge::Append) if self.owned => {
+                // SAFETY: the vector is owned by this level, whose nested levels are merged later
+                unsafe { vec.as_mut() }.splice(0..0, prev.iter().cloned());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<T> Default for PoolVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Deref for PoolVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PoolVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,60 @@
+use crate::core::Connection;
+use crate::ffi::*;
+
+/// Read-only view of the QUIC stream behind an HTTP/3 request connection.
+///
+/// NGINX keeps the negotiated transport parameters private to its QUIC implementation; the
+/// stream exposes the flow control limits derived from them. Whether the request was received
+/// as 0-RTT early data is reported by [`Connection::is_early_data`], as for TLS over TCP.
+#[derive(Clone, Copy)]
+pub struct QuicStream<'a>(&'a ngx_quic_stream_t);
+
+impl<'a> QuicStream<'a> {
+    /// Returns the stream identifier.
+    pub fn id(&self) -> u64 {
+        self.0.id
+    }
+
+    /// Returns `true` if the stream was opened by the client.
+    pub fn is_client_initiated(&self) -> bool {
+        self.0.id & 0x01 == 0
+    }
+
+    /// Returns `true` if the stream carries data in both directions.
+    pub fn is_bidirectional(&self) -> bool {
+        self.0.id & 0x02 == 0
+    }
+
+    /// Returns the limit of data the peer allows to be sent on the stream.
+    pub fn send_max_data(&self) -> u64 {
+        self.0.send_max_data
+    }
+
+    /// Returns the limit of data the peer is allowed to send on the stream.
+    pub fn recv_max_data(&self) -> u64 {
+        self.0.recv_max_data
+    }
+
+    /// Returns the number of bytes sent on the stream.
+    pub fn sent(&self) -> u64 {
+        self.0.sent
+    }
+
+    /// Returns the number of sent bytes acknowledged by the peer.
+    pub fn acked(&self) -> u64 {
+        self.0.acked
+    }
+
+    /// Returns the QUIC connection carrying the stream.
+    pub fn parent(&self) -> Option<&'a Connection> {
+        let parent = self.0.parent;
+        (!parent.is_null()).then(|| unsafe { &*Connection::from_ngx_connection(parent) })
+    }
+}
+
+impl Connection {
+    /// Returns the QUIC stream of the connection, for the connections of HTTP/3 requests.
+    pub fn quic_stream(&self) -> Option<QuicStream<'_>> {
+        unsafe { self.get_inner().quic.as_ref() }.map(QuicStream)
+    }
+}
@@ -0,0 +1,87 @@
+pub use ngx_core::{splitmix64, InvalidUuid, Rng, Uuid};
+
+use crate::ffi::{ngx_pid, ngx_pid_t};
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    static WORKER_RNG: RefCell<Option<(ngx_pid_t, Rng)>> = const { RefCell::new(None) };
+}
+
+/// Calls `f` with the random number generator of the current process.
+///
+/// The generator is seeded from the operating system on first use, and reseeded on first use
+/// after a fork, so that the workers forked by the master process never share a sequence, even
+/// if the master used the generator while parsing the configuration. No locking is involved,
+/// which makes it suitable for per-request use:
+///
+/// ```rust,ignore
+/// let index = with_rng(|rng| rng.below(peers.len() as u64)) as usize;
+/// ```
+///
+/// The generator is not cryptographically secure, see [`Rng`].
+pub fn with_rng<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Rng) -> R,
+{
+    WORKER_RNG.with(|cell| {
+        let mut state = cell.borrow_mut();
+        // NGINX updates its process id in each process it forks, which is cheaper to check than
+        // asking the operating system on every draw
+        // SAFETY: the variable is only written by the process itself, before it runs any module code
+        let pid = unsafe { ngx_pid };
+        let (_, rng) = match &mut *state {
+            Some((seeded, rng)) if *seeded == pid => return f(rng),
+            state => state.insert((pid, Rng::from_seed_bytes(os_seed(pid)))),
+        };
+        f(rng)
+    })
+}
+
+/// Returns a random `u64` from the generator of the current process.
+pub fn random_u64() -> u64 {
+    with_rng(Rng::next_u64)
+}
+
+/// Creates a random version 4 [`Uuid`], e.g. for a request identifier.
+pub fn uuid_v4() -> Uuid {
+    Uuid::new_v4(random_bytes())
+}
+
+/// Creates a version 7 [`Uuid`] for the current time, ordered by creation time.
+pub fn uuid_v7() -> Uuid {
+    let unix_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+    Uuid::new_v7(unix_ms, random_bytes())
+}
+
+fn random_bytes() -> [u8; 16] {
+    let mut bytes = [0; 16];
+    with_rng(|rng| rng.fill_bytes(&mut bytes));
+    bytes
+}
+
+/// Reads the seed from the operating system, falling back to the time and the process id.
+fn os_seed(pid: ngx_pid_t) -> [u8; 32] {
+    let mut seed = [0; 32];
+    if File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(&mut seed))
+        .is_ok()
+    {
+        return seed;
+    }
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    let mut x = nanos ^ ((pid as u32 as u64) << 32) ^ (&seed as *const _ as u64);
+    for chunk in seed.chunks_exact_mut(8) {
+        x = splitmix64(x.wrapping_add(0x9e37_79b9_7f4a_7c15));
+        chunk.copy_from_slice(&x.to_le_bytes());
+    }
+    seed
+}
@@ -0,0 +1,260 @@
+use crate::event::duration_to_msec;
+use crate::ffi::*;
+
+use std::ffi::CStr;
+use std::fmt;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ptr::{self, NonNull};
+use std::time::Duration;
+
+/// Wrapper for the asynchronous DNS [resolver] configured with the `resolver` directive,
+/// `ngx_resolver_t`.
+///
+/// Lookups are sent to the configured name servers from the event loop, and their result is
+/// cached by NGINX. The callback of a lookup is called from the event loop once it completes,
+/// or before [`Resolver::resolve_name`] returns for an IP address or a cached name:
+///
+/// ```rust,ignore
+/// let Some(resolver) = request.resolver() else {
+///     return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+/// };
+/// // keeps the request alive until the lookup completes
+/// unsafe { (*request.get_inner().main).set_count((*request.get_inner().main).count() + 1) };
+/// let r: *mut ngx_http_request_t = request.into();
+///
+/// resolver.resolve_name("auth.internal", move |result| {
+///     let request = unsafe { Request::from_ngx_http_request(r) };
+///     let rc = match result {
+///         Ok(addrs) => connect_auth(request, &addrs),
+///         Err(_) => HTTPStatus::BAD_GATEWAY.into(),
+///     };
+///     unsafe { ngx_http_finalize_request(r, rc.0) };
+/// })?;
+/// Status::NGX_DONE
+/// ```
+///
+/// A pending lookup cannot be cancelled, so its callback must own or keep alive the data it
+/// uses, as in the example above.
+///
+/// [resolver]: https://nginx.org/en/docs/http/ngx_http_core_module.html#resolver
+#[derive(Clone, Copy, Debug)]
+pub struct Resolver {
+    resolver: NonNull<ngx_resolver_t>,
+    timeout: ngx_msec_t,
+}
+
+/// An error returned by a [`Resolver`] lookup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolverError {
+    /// No `resolver` is configured.
+    NotConfigured,
+    /// The lookup could not be started, e.g. on memory allocation failures.
+    Failed,
+    /// The lookup failed with an `NGX_RESOLVE_*` code, e.g. `NGX_RESOLVE_NXDOMAIN` or
+    /// `NGX_RESOLVE_TIMEDOUT`.
+    Lookup(ngx_int_t),
+}
+
+impl fmt::Display for ResolverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolverError::NotConfigured => f.write_str("no resolver defined"),
+            ResolverError::Failed => f.write_str("failed to start the lookup"),
+            ResolverError::Lookup(code) => {
+                // SAFETY: the function returns a static string for any code
+                let msg = unsafe { CStr::from_ptr(ngx_resolver_strerror(*code)) };
+                f.write_str(&msg.to_string_lossy())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolverError {}
+
+impl Resolver {
+    /// Creates a [`Resolver`] from an [`ngx_resolver_t`] pointer, with the timeout of its
+    /// lookups.
+    ///
+    /// Returns `None` if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a pointer to a resolver created with `ngx_resolver_create`, which
+    /// lives as long as the configuration cycle.
+    pub unsafe fn from_ngx_resolver(resolver: *mut ngx_resolver_t, timeout: Duration) -> Option<Self> {
+        Some(Resolver {
+            resolver: NonNull::new(resolver)?,
+            timeout: duration_to_msec(timeout),
+        })
+    }
+
+    /// Returns a copy of the resolver with another lookup timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = duration_to_msec(timeout);
+        self
+    }
+
+    /// Returns the raw pointer to the resolver.
+    pub fn as_ptr(&self) -> *mut ngx_resolver_t {
+        self.resolver.as_ptr()
+    }
+
+    /// Looks up the IPv4 and IPv6 addresses of `name`, calling `callback` with them.
+    ///
+    /// IPv6 addresses are only returned if enabled with the `ipv6` parameter of the `resolver`
+    /// directive. Returns an error without calling `callback` if the lookup cannot be started.
+    pub fn resolve_name<F>(&self, name: &str, callback: F) -> Result<(), ResolverError>
+    where
+        F: FnOnce(Result<Vec<IpAddr>, ResolverError>) + 'static,
+    {
+        if let Ok(addr) = name.parse() {
+            callback(Ok(vec![addr]));
+            return Ok(());
+        }
+
+        let ctx = self.start()?;
+        let mut state = Box::new(ResolveName {
+            name: name.to_owned(),
+            callback,
+        });
+
+        unsafe {
+            (*ctx).name = ngx_str_t {
+                len: state.name.len(),
+                data: state.name.as_mut_ptr(),
+            };
+            (*ctx).handler = Some(resolve_name_handler::<F>);
+            let data = Box::into_raw(state);
+            (*ctx).data = data as *mut _;
+
+            if ngx_resolve_name(ctx) != NGX_OK as ngx_int_t {
+                // the context is freed by NGINX
+                drop(Box::from_raw(data));
+                return Err(ResolverError::Failed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up the name of `addr` with a PTR query, calling `callback` with it.
+    ///
+    /// Returns an error without calling `callback` if the lookup cannot be started.
+    pub fn resolve_addr<F>(&self, addr: IpAddr, callback: F) -> Result<(), ResolverError>
+    where
+        F: FnOnce(Result<String, ResolverError>) + 'static,
+    {
+        let ctx = self.start()?;
+        let mut state = Box::new(ResolveAddr {
+            // SAFETY: all-zero bits are a valid socket address
+            sockaddr: unsafe { mem::zeroed() },
+            callback,
+        });
+
+        unsafe {
+            let socklen = match addr {
+                IpAddr::V4(addr) => {
+                    let sin = &mut state.sockaddr.sockaddr_in;
+                    sin.sin_family = AF_INET as _;
+                    sin.sin_addr.s_addr = u32::from(addr).to_be();
+                    mem::size_of::<sockaddr_in>()
+                }
+                IpAddr::V6(addr) => {
+                    let sin6 = &mut state.sockaddr.sockaddr_in6;
+                    sin6.sin6_family = AF_INET6 as _;
+                    ptr::write(ptr::addr_of_mut!(sin6.sin6_addr) as *mut [u8; 16], addr.octets());
+                    mem::size_of::<sockaddr_in6>()
+                }
+            };
+
+            (*ctx).addr.sockaddr = ptr::addr_of_mut!(state.sockaddr.sockaddr);
+            (*ctx).addr.socklen = socklen as socklen_t;
+            (*ctx).handler = Some(resolve_addr_handler::<F>);
+            let data = Box::into_raw(state);
+            (*ctx).data = data as *mut _;
+
+            if ngx_resolve_addr(ctx) != NGX_OK as ngx_int_t {
+                // the context is freed by NGINX
+                drop(Box::from_raw(data));
+                return Err(ResolverError::Failed);
+            }
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Result<*mut ngx_resolver_ctx_t, ResolverError> {
+        let ctx = unsafe { ngx_resolve_start(self.as_ptr(), ptr::null_mut()) };
+        // NGX_NO_RESOLVER
+        if ctx as isize == -1 {
+            return Err(ResolverError::NotConfigured);
+        }
+        if ctx.is_null() {
+            return Err(ResolverError::Failed);
+        }
+        unsafe { (*ctx).timeout = self.timeout };
+        Ok(ctx)
+    }
+}
+
+struct ResolveName<F> {
+    name: String,
+    callback: F,
+}
+
+struct ResolveAddr<F> {
+    sockaddr: ngx_sockaddr_t,
+    callback: F,
+}
+
+unsafe extern "C" fn resolve_name_handler<F>(ctx: *mut ngx_resolver_ctx_t)
+where
+    F: FnOnce(Result<Vec<IpAddr>, ResolverError>),
+{
+    let state = Box::from_raw((*ctx).data as *mut ResolveName<F>);
+
+    let result = if (*ctx).state == NGX_OK as ngx_int_t {
+        let addrs = if (*ctx).naddrs == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts((*ctx).addrs, (*ctx).naddrs)
+        };
+        Ok(addrs.iter().filter_map(|addr| sockaddr_ip(addr.sockaddr)).collect())
+    } else {
+        Err(ResolverError::Lookup((*ctx).state))
+    };
+
+    ngx_resolve_name_done(ctx);
+    (state.callback)(result);
+}
+
+unsafe extern "C" fn resolve_addr_handler<F>(ctx: *mut ngx_resolver_ctx_t)
+where
+    F: FnOnce(Result<String, ResolverError>),
+{
+    let state = Box::from_raw((*ctx).data as *mut ResolveAddr<F>);
+
+    let result = if (*ctx).state == NGX_OK as ngx_int_t {
+        let name = std::slice::from_raw_parts((*ctx).name.data, (*ctx).name.len);
+        Ok(String::from_utf8_lossy(name).into_owned())
+    } else {
+        Err(ResolverError::Lookup((*ctx).state))
+    };
+
+    ngx_resolve_addr_done(ctx);
+    (state.callback)(result);
+}
+
+/// Returns the IP address of a socket address.
+unsafe fn sockaddr_ip(sa: *const sockaddr) -> Option<IpAddr> {
+    match (*sa).sa_family as u32 {
+        AF_INET => {
+            let sin = sa as *const sockaddr_in;
+            Some(Ipv4Addr::from(u32::from_be((*sin).sin_addr.s_addr)).into())
+        }
+        AF_INET6 => {
+            let sin6 = sa as *const sockaddr_in6;
+            Some(Ipv6Addr::from(ptr::read(ptr::addr_of!((*sin6).sin6_addr) as *const [u8; 16])).into())
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,101 @@
+use crate::ffi::*;
+
+use std::marker::PhantomData;
+use std::slice;
+
+pub use ngx_core::{find, find_byte, find_byte2, find_byte3, rfind_byte, Finder, StreamFinder};
+
+/// Iterator over the in-memory contents of the buffers of an `ngx_chain_t`.
+///
+/// Buffers without in-memory data (file-backed buffers and special buffers such as `flush` or
+/// `last_buf`) are skipped.
+pub struct ChainSlices<'a> {
+    cl: *const ngx_chain_t,
+    _chain: PhantomData<&'a ngx_chain_t>,
+}
+
+/// Returns an iterator over the in-memory contents of the chain starting at `cl`.
+///
+/// # Safety
+///
+/// The caller has provided either a null pointer or a valid `ngx_chain_t` whose links and
+/// buffers are not modified for the lifetime of the iterator.
+pub unsafe fn chain_slices<'a>(cl: *const ngx_chain_t) -> ChainSlices<'a> {
+    ChainSlices {
+        cl,
+        _chain: PhantomData,
+    }
+}
+
+impl<'a> Iterator for ChainSlices<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            while let Some(cl) = self.cl.as_ref() {
+                self.cl = cl.next;
+
+                if let Some(bytes) = buf_bytes(cl.buf) {
+                    return Some(bytes);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Equivalent of the `ngx_buf_in_memory` macro, returning the unread part of the buffer.
+unsafe fn buf_bytes<'a>(b: *const ngx_buf_t) -> Option<&'a [u8]> {
+    let b = b.as_ref()?;
+    if b.temporary() == 0 && b.memory() == 0 && b.mmap() == 0 {
+        return None;
+    }
+    if b.pos.is_null() || b.last <= b.pos {
+        return None;
+    }
+    Some(slice::from_raw_parts(b.pos, b.last.offset_from(b.pos) as usize))
+}
+
+/// Finds the first occurrence of `byte` in the in-memory buffers of the chain starting at `cl`.
+///
+/// Returns the chain link holding the byte and its offset from the link buffer `pos`.
+///
+/// # Safety
+///
+/// The caller has provided either a null pointer or a valid `ngx_chain_t`.
+pub unsafe fn chain_find_byte(cl: *mut ngx_chain_t, byte: u8) -> Option<(*mut ngx_chain_t, usize)> {
+    let mut cl = cl;
+    while !cl.is_null() {
+        if let Some(pos) = buf_bytes((*cl).buf).and_then(|bytes| find_byte(byte, bytes)) {
+            return Some((cl, pos));
+        }
+        cl = (*cl).next;
+    }
+    None
+}
+
+/// Finds the first occurrence of `needle` in the in-memory buffers of the chain starting at `cl`,
+/// including occurrences spanning buffer boundaries.
+///
+/// Returns the offset of the occurrence from the beginning of the chain contents, as yielded by
+/// [`chain_slices`]. Use a [`StreamFinder`] directly to search a body that arrives over several
+/// filter invocations.
+///
+/// # Safety
+///
+/// The caller has provided either a null pointer or a valid `ngx_chain_t`.
+pub unsafe fn chain_find(cl: *const ngx_chain_t, needle: &[u8]) -> Option<u64> {
+    let mut finder = StreamFinder::new(needle);
+    let mut found = None;
+
+    for bytes in chain_slices(cl) {
+        finder.feed(bytes, |offset| {
+            found.get_or_insert(offset);
+        });
+        if found.is_some() {
+            break;
+        }
+    }
+
+    found
+}
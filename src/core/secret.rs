@@ -0,0 +1,53 @@
+pub use ngx_core::{Secret, SecretSource, SecretSourceError, REDACTED};
+
+use crate::core::{ConfError, FromArg, NgxStr};
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+
+/// Parses a secret directive argument, resolving `env:` and `file:` indirections.
+///
+/// ```text
+/// my_secret_key  env:AWS_SECRET_ACCESS_KEY;
+/// my_signing_key file:/etc/nginx/keys/signing.key;
+/// ```
+///
+/// The value is read once, when the configuration is parsed by the master process; updating the
+/// variable or the file takes effect on the next reload. Environment variables are available as
+/// long as they are not cleared by the `env` directive handling, which only applies to the
+/// workers. A single trailing newline is removed from file contents, and relative file paths are
+/// resolved from the working directory of the master process, so absolute paths are advised.
+impl FromArg<'_> for Secret {
+    fn from_arg(arg: &NgxStr) -> Result<Self, ConfError> {
+        match SecretSource::parse(arg.as_bytes()).map_err(|err| ConfError::from_error(&err))? {
+            SecretSource::Literal(value) => Ok(Secret::new(value.to_vec())),
+            SecretSource::Env(name) => match env::var_os(name) {
+                Some(value) => Ok(Secret::new(os_string_into_vec(value))),
+                None => Err(ConfError::new(format!("environment variable \"{}\" is not set", name))),
+            },
+            SecretSource::File(path) => {
+                let mut value = fs::read(path)
+                    .map_err(|err| ConfError::new(format!("failed to read secret from \"{}\": {}", path, err)))?;
+                if value.last() == Some(&b'\n') {
+                    value.pop();
+                    if value.last() == Some(&b'\r') {
+                        value.pop();
+                    }
+                }
+                Ok(Secret::new(value))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn os_string_into_vec(value: OsString) -> Vec<u8> {
+    use std::os::unix::ffi::OsStringExt;
+    value.into_vec()
+}
+
+#[cfg(not(unix))]
+fn os_string_into_vec(value: OsString) -> Vec<u8> {
+    value.to_string_lossy().into_owned().into_bytes()
+}
@@ -0,0 +1,207 @@
+use crate::core::{register_named_service, resolve_named_service, ConfError, Secret};
+use crate::ffi::*;
+use crate::sync::{channel, Receiver};
+
+use std::error::Error;
+use std::ffi::CString;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use std::{fmt, io, thread};
+
+/// An error returned by a [`SecretProvider`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecretError {
+    message: String,
+}
+
+impl SecretError {
+    /// Creates an error with `message`, which must not include the secret value.
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        SecretError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for SecretError {}
+
+/// A source of secrets external to the configuration, such as Vault or a cloud KMS.
+///
+/// Providers are registered for a scheme with [`register_secret_provider`], and secrets are
+/// referenced in the configuration as `scheme:name`, resolved with [`resolve_secret`]:
+///
+/// ```rust,ignore
+/// struct VaultProvider { client: VaultClient }
+///
+/// impl SecretProvider for VaultProvider {
+///     fn resolve(&self, name: &str) -> Result<Secret, SecretError> {
+///         self.client.read(name).map(Secret::new).map_err(|err| SecretError::new(err.to_string()))
+///     }
+///
+///     fn refresh_interval(&self) -> Option<Duration> {
+///         Some(Duration::from_secs(300))
+///     }
+/// }
+///
+/// // in the providing module, e.g. in `preconfiguration`
+/// register_secret_provider(cf, "vault", Arc::new(VaultProvider::new()))?;
+///
+/// // in the consuming module's `postconfiguration`, for `my_secret_key vault:secret/aws;`
+/// conf.secret_key = Some(resolve_secret(cf, "vault:secret/aws")?);
+/// ```
+///
+/// Secrets are resolved once when the configuration is parsed, and then periodically on a
+/// helper thread of each worker, see [`ProvidedSecret::start_refresh`]; `resolve` may block.
+pub trait SecretProvider: Send + Sync {
+    /// Returns the current value of the secret `name`.
+    fn resolve(&self, name: &str) -> Result<Secret, SecretError>;
+
+    /// Returns the interval between refreshes of the resolved secrets, or `None` if the secrets
+    /// are only resolved with the configuration.
+    fn refresh_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Registers `provider` for the secrets referenced as `scheme:name` in the cycle being
+/// configured.
+///
+/// Returns an error if a provider is already registered for `scheme`.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer.
+pub unsafe fn register_secret_provider(
+    cf: *mut ngx_conf_t,
+    scheme: &str,
+    provider: Arc<dyn SecretProvider>,
+) -> Result<(), ConfError> {
+    register_named_service::<dyn SecretProvider>(cf, scheme, provider)
+}
+
+/// Resolves the secret referenced as `scheme:name` with the provider registered for `scheme`.
+///
+/// Returns an error if no provider is registered for `scheme` or if the provider fails to
+/// resolve the secret, failing the configuration.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer.
+pub unsafe fn resolve_secret(cf: *const ngx_conf_t, reference: &str) -> Result<ProvidedSecret, ConfError> {
+    let Some((scheme, name)) = reference.split_once(':').filter(|(_, name)| !name.is_empty()) else {
+        return Err(ConfError::new(format!("invalid secret reference \"{}\"", reference)));
+    };
+    let Some(provider) = resolve_named_service::<dyn SecretProvider>(cf, scheme) else {
+        return Err(ConfError::new(format!("unknown secret provider \"{}\"", scheme)));
+    };
+
+    let value = provider
+        .resolve(name)
+        .map_err(|err| ConfError::new(format!("failed to resolve secret \"{}\": {}", reference, err)))?;
+
+    Ok(ProvidedSecret(Arc::new(ProvidedInner {
+        provider,
+        name: name.to_string(),
+        value: RwLock::new(Arc::new(value)),
+    })))
+}
+
+/// A secret resolved by a [`SecretProvider`], updated when the provider refreshes it.
+///
+/// Rotated signing keys are usually kept in a [`KeySet`](crate::core::KeySet) of provided
+/// secrets, verified at the cached time of the worker:
+///
+/// ```rust,ignore
+/// let keys: &KeySet<ProvidedSecret> = &conf.keys;
+/// match keys.verify(token.kid(), ngx_time(), |key| token.verify_hmac(key.get().expose())) {
+///     Ok(_) => Status::NGX_DECLINED,
+///     Err(_) => HTTPStatus::UNAUTHORIZED.into(),
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ProvidedSecret(Arc<ProvidedInner>);
+
+struct ProvidedInner {
+    provider: Arc<dyn SecretProvider>,
+    name: String,
+    value: RwLock<Arc<Secret>>,
+}
+
+impl ProvidedSecret {
+    /// Returns the name of the secret within its provider.
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Returns the current value of the secret.
+    ///
+    /// The value stays valid while it is used, even if the secret is refreshed meanwhile.
+    pub fn get(&self) -> Arc<Secret> {
+        self.0.value.read().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+
+    /// Starts refreshing the secret at the interval of its provider, if any.
+    ///
+    /// The provider is called on a helper thread, and the new value is stored from the event
+    /// loop of the worker, so the worker never blocks on the provider. A failed refresh is
+    /// logged and the previous value is kept. The refresh stops when the returned
+    /// [`SecretRefresh`] is dropped; the helper thread exits after its current interval.
+    ///
+    /// This is meant to be called from the `init_process` hook, keeping the result in the worker
+    /// state, e.g. a [`WorkerState`](crate::core::WorkerState).
+    pub fn start_refresh(&self) -> io::Result<Option<SecretRefresh>> {
+        let Some(interval) = self.0.provider.refresh_interval() else {
+            return Ok(None);
+        };
+
+        let secret = self.0.clone();
+        let (tx, rx) = channel(1, move |result: Result<Secret, SecretError>| match result {
+            Ok(value) => *secret.value.write().unwrap_or_else(|err| err.into_inner()) = Arc::new(value),
+            Err(err) => log_refresh_error(&secret.name, &err),
+        })?;
+
+        let secret = self.0.clone();
+        thread::Builder::new().name("ngx-secret".into()).spawn(move || loop {
+            thread::sleep(interval);
+            if tx.is_closed() || tx.send(secret.provider.resolve(&secret.name)).is_err() {
+                break;
+            }
+        })?;
+
+        Ok(Some(SecretRefresh { _receiver: rx }))
+    }
+}
+
+impl fmt::Debug for ProvidedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProvidedSecret")
+            .field("name", &self.0.name)
+            .field("value", &self.get())
+            .finish()
+    }
+}
+
+/// The periodic refresh of a [`ProvidedSecret`], running until dropped.
+pub struct SecretRefresh {
+    _receiver: Receiver<Result<Secret, SecretError>>,
+}
+
+fn log_refresh_error(name: &str, err: &SecretError) {
+    let message = format!("failed to refresh secret \"{}\": {}", name, err);
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    unsafe {
+        ngx_log_error_core(
+            NGX_LOG_ERR as ngx_uint_t,
+            (*ngx_cycle).log,
+            0,
+            c"%s".as_ptr(),
+            message.as_ptr(),
+        )
+    };
+}
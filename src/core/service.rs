@@ -0,0 +1,171 @@
+use crate::core::{ConfError, CycleLocal};
+use crate::ffi::*;
+
+use std::any::{self, Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Services registered for each live configuration cycle.
+static REGISTRY: CycleLocal<ServiceMap> = CycleLocal::new();
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ServiceKey {
+    type_id: TypeId,
+    name: Option<String>,
+}
+
+/// Typed services of a single configuration cycle.
+#[derive(Default)]
+struct ServiceMap(HashMap<ServiceKey, Box<dyn Any + Send + Sync>>);
+
+impl ServiceMap {
+    fn insert<T: ?Sized + Send + Sync + 'static>(
+        &mut self,
+        name: Option<&str>,
+        service: Arc<T>,
+    ) -> Result<(), ConfError> {
+        let key = ServiceKey {
+            type_id: TypeId::of::<Arc<T>>(),
+            name: name.map(String::from),
+        };
+        if self.0.contains_key(&key) {
+            let message = match name {
+                Some(name) => format!(
+                    "service \"{}\" of type {} is already registered",
+                    name,
+                    any::type_name::<T>()
+                ),
+                None => format!("service of type {} is already registered", any::type_name::<T>()),
+            };
+            return Err(ConfError::new(message));
+        }
+        self.0.insert(key, Box::new(service));
+        Ok(())
+    }
+
+    fn get<T: ?Sized + Send + Sync + 'static>(&self, name: Option<&str>) -> Option<Arc<T>> {
+        let key = ServiceKey {
+            type_id: TypeId::of::<Arc<T>>(),
+            name: name.map(String::from),
+        };
+        self.0.get(&key)?.downcast_ref::<Arc<T>>().cloned()
+    }
+}
+
+/// Registers `service` for the cycle being configured, keyed by its type.
+///
+/// Services let Rust modules share functionality without exchanging module context pointers:
+/// a module registers a trait object while parsing its configuration, and other modules resolve
+/// it with [`resolve_service`] in their `postconfiguration` handler, after all configuration
+/// blocks have been read.
+///
+/// ```rust,ignore
+/// pub trait TokenValidator: Send + Sync {
+///     fn validate(&self, token: &str) -> bool;
+/// }
+///
+/// // in the providing module, e.g. in `init_main_conf`
+/// register_service::<dyn TokenValidator>(cf, Arc::new(JwtValidator::new(keys)))?;
+///
+/// // in a consuming module's `postconfiguration`
+/// let validator = resolve_service::<dyn TokenValidator>(cf).ok_or(...)?;
+/// ```
+///
+/// Services are dropped with the cycle they were registered for, so a reload registers a fresh
+/// set and a failed reload leaves the services of the running cycle untouched.
+///
+/// Returns an error if a service of the same type is already registered for the cycle.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer.
+pub unsafe fn register_service<T: ?Sized + Send + Sync + 'static>(
+    cf: *mut ngx_conf_t,
+    service: Arc<T>,
+) -> Result<(), ConfError> {
+    register(cf, None, service)
+}
+
+/// Registers `service` for the cycle being configured under `name`, allowing several services
+/// of the same type to coexist.
+///
+/// Returns an error if a service of the same type and name is already registered for the cycle.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer.
+pub unsafe fn register_named_service<T: ?Sized + Send + Sync + 'static>(
+    cf: *mut ngx_conf_t,
+    name: &str,
+    service: Arc<T>,
+) -> Result<(), ConfError> {
+    register(cf, Some(name), service)
+}
+
+/// Returns the service of type `T` registered for the cycle being configured.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer.
+pub unsafe fn resolve_service<T: ?Sized + Send + Sync + 'static>(cf: *const ngx_conf_t) -> Option<Arc<T>> {
+    resolve(cf, None)
+}
+
+/// Returns the service of type `T` registered under `name` for the cycle being configured.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer.
+pub unsafe fn resolve_named_service<T: ?Sized + Send + Sync + 'static>(
+    cf: *const ngx_conf_t,
+    name: &str,
+) -> Option<Arc<T>> {
+    resolve(cf, Some(name))
+}
+
+unsafe fn register<T: ?Sized + Send + Sync + 'static>(
+    cf: *mut ngx_conf_t,
+    name: Option<&str>,
+    service: Arc<T>,
+) -> Result<(), ConfError> {
+    REGISTRY.with_mut((*cf).cycle, |services| services.insert(name, service))?
+}
+
+unsafe fn resolve<T: ?Sized + Send + Sync + 'static>(cf: *const ngx_conf_t, name: Option<&str>) -> Option<Arc<T>> {
+    REGISTRY.with((*cf).cycle, |services| services.get(name)).flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    trait Greeter: Send + Sync {
+        fn greet(&self) -> String;
+    }
+
+    struct Hello(&'static str);
+
+    impl Greeter for Hello {
+        fn greet(&self) -> String {
+            format!("hello, {}", self.0)
+        }
+    }
+
+    #[test]
+    fn test_service_map() {
+        let mut services = ServiceMap::default();
+        assert!(services.insert::<dyn Greeter>(None, Arc::new(Hello("world"))).is_ok());
+        assert!(services.insert::<dyn Greeter>(None, Arc::new(Hello("again"))).is_err());
+        assert!(services
+            .insert::<dyn Greeter>(Some("admin"), Arc::new(Hello("admin")))
+            .is_ok());
+
+        assert_eq!(services.get::<dyn Greeter>(None).unwrap().greet(), "hello, world");
+        assert_eq!(
+            services.get::<dyn Greeter>(Some("admin")).unwrap().greet(),
+            "hello, admin"
+        );
+        assert!(services.get::<dyn Greeter>(Some("other")).is_none());
+        assert!(services.get::<Hello>(None).is_none());
+    }
+}
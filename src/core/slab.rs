@@ -0,0 +1,201 @@
+use crate::ffi::*;
+
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::ptr::{self, NonNull};
+
+/// Wrapper for the [slab allocator] of a shared memory zone, `ngx_slab_pool_t`.
+///
+/// The pool is shared by all the processes mapping the zone. The allocation methods take the
+/// pool mutex for each call; [`SlabPool::lock`] holds it for a sequence of operations, e.g. to
+/// update a shared data structure consistently:
+///
+/// ```rust,ignore
+/// unsafe extern "C" fn init_zone(shm_zone: *mut ngx_shm_zone_t, _data: *mut c_void) -> ngx_int_t {
+///     let Some(pool) = SlabPool::from_shm_zone(&*shm_zone) else {
+///         return Status::NGX_ERROR.into();
+///     };
+///     let counters = pool.calloc(mem::size_of::<Counters>());
+///     if counters.is_null() {
+///         return Status::NGX_ERROR.into();
+///     }
+///     pool.set_data(counters);
+///     Status::NGX_OK.into()
+/// }
+///
+/// // in a worker
+/// let locked = pool.lock();
+/// let entry = locked.alloc(mem::size_of::<Entry>()) as *mut Entry;
+/// ```
+///
+/// [slab allocator]: https://nginx.org/en/docs/dev/development_guide.html#shared_memory
+#[derive(Clone, Copy, Debug)]
+pub struct SlabPool(NonNull<ngx_slab_pool_t>);
+
+impl SlabPool {
+    /// Creates a [`SlabPool`] from an [`ngx_slab_pool_t`] pointer.
+    ///
+    /// Returns `None` if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a pointer to an initialized slab pool, mapped for the lifetime of
+    /// the returned value.
+    pub unsafe fn from_ngx_slab_pool(shpool: *mut ngx_slab_pool_t) -> Option<Self> {
+        NonNull::new(shpool).map(SlabPool)
+    }
+
+    /// Creates a [`SlabPool`] for a shared memory zone, once the zone is mapped.
+    ///
+    /// Returns `None` if the zone is not mapped yet, i.e. before its `init` callback.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a zone created with slab allocation, which is not the case for
+    /// zones with the `noslab` flag.
+    pub unsafe fn from_shm_zone(zone: &ngx_shm_zone_t) -> Option<Self> {
+        Self::from_ngx_slab_pool(zone.shm.addr as *mut ngx_slab_pool_t)
+    }
+
+    /// Returns the raw pointer to the pool.
+    pub fn as_ptr(&self) -> *mut ngx_slab_pool_t {
+        self.0.as_ptr()
+    }
+
+    /// Allocates `size` bytes, returning a null pointer if the pool is exhausted.
+    pub fn alloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_slab_alloc(self.as_ptr(), size) }
+    }
+
+    /// Allocates `size` zeroed bytes, returning a null pointer if the pool is exhausted.
+    pub fn calloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_slab_calloc(self.as_ptr(), size) }
+    }
+
+    /// Frees memory allocated from the pool.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a pointer allocated from this pool and not freed yet, which is not
+    /// used afterwards by any process.
+    pub unsafe fn free(&self, p: *mut c_void) {
+        ngx_slab_free(self.as_ptr(), p)
+    }
+
+    /// Returns the user data of the pool, usually the root of the shared data structure.
+    pub fn data(&self) -> *mut c_void {
+        unsafe { (*self.as_ptr()).data }
+    }
+
+    /// Sets the user data of the pool, found again by the zone `init` callback after a reload.
+    pub fn set_data(&self, data: *mut c_void) {
+        unsafe { (*self.as_ptr()).data = data };
+    }
+
+    /// Sets whether exhausting the pool is logged, e.g. disabled when a cache evicts entries on
+    /// allocation failures.
+    pub fn set_log_nomem(&self, log: bool) {
+        unsafe { (*self.as_ptr()).set_log_nomem(log as _) };
+    }
+
+    /// Returns the mutex of the pool.
+    pub fn mutex(&self) -> &ShmMutex {
+        unsafe { ShmMutex::from_ngx_shmtx(ptr::addr_of_mut!((*self.as_ptr()).mutex)) }
+    }
+
+    /// Locks the pool, for allocations and updates of the shared data without interleaving with
+    /// other processes.
+    pub fn lock(&self) -> LockedSlabPool<'_> {
+        LockedSlabPool {
+            pool: *self,
+            _guard: self.mutex().lock(),
+        }
+    }
+}
+
+/// A [`SlabPool`] locked by the current process, unlocked when dropped.
+pub struct LockedSlabPool<'a> {
+    pool: SlabPool,
+    _guard: ShmMutexGuard<'a>,
+}
+
+impl LockedSlabPool<'_> {
+    /// Allocates `size` bytes, returning a null pointer if the pool is exhausted.
+    pub fn alloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_slab_alloc_locked(self.pool.as_ptr(), size) }
+    }
+
+    /// Allocates `size` zeroed bytes, returning a null pointer if the pool is exhausted.
+    pub fn calloc(&self, size: usize) -> *mut c_void {
+        unsafe { ngx_slab_calloc_locked(self.pool.as_ptr(), size) }
+    }
+
+    /// Frees memory allocated from the pool.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a pointer allocated from this pool and not freed yet, which is not
+    /// used afterwards by any process.
+    pub unsafe fn free(&self, p: *mut c_void) {
+        ngx_slab_free_locked(self.pool.as_ptr(), p)
+    }
+
+    /// Returns the user data of the pool.
+    pub fn data(&self) -> *mut c_void {
+        self.pool.data()
+    }
+}
+
+/// Wrapper for a mutex shared between processes, `ngx_shmtx_t`.
+///
+/// The mutex does not protect any data by itself; the data it guards is defined by its user, as
+/// with the mutex of a [`SlabPool`]. It is not reentrant: locking it twice from the same
+/// process deadlocks.
+#[repr(transparent)]
+pub struct ShmMutex(UnsafeCell<ngx_shmtx_t>);
+
+impl ShmMutex {
+    /// Creates a [`ShmMutex`] reference from an [`ngx_shmtx_t`] pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a mutex initialized with
+    /// `ngx_shmtx_create`, which outlives the returned reference.
+    pub unsafe fn from_ngx_shmtx<'a>(mtx: *mut ngx_shmtx_t) -> &'a ShmMutex {
+        &*(mtx as *const ShmMutex)
+    }
+
+    /// Locks the mutex, waiting until it is available.
+    pub fn lock(&self) -> ShmMutexGuard<'_> {
+        unsafe { ngx_shmtx_lock(self.0.get()) };
+        ShmMutexGuard {
+            mutex: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Locks the mutex if it is available.
+    pub fn try_lock(&self) -> Option<ShmMutexGuard<'_>> {
+        if unsafe { ngx_shmtx_trylock(self.0.get()) } == 0 {
+            return None;
+        }
+        Some(ShmMutexGuard {
+            mutex: self,
+            _not_send: PhantomData,
+        })
+    }
+}
+
+/// A lock of a [`ShmMutex`], released when dropped.
+pub struct ShmMutexGuard<'a> {
+    mutex: &'a ShmMutex,
+    // the mutex is owned by the process, and must be unlocked from the locking thread
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ShmMutexGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { ngx_shmtx_unlock(self.mutex.0.get()) };
+    }
+}
@@ -1,8 +1,8 @@
+use crate::core::Pool;
 use crate::ffi::*;
 
-use std::borrow::Cow;
-use std::slice;
-use std::str::{self, Utf8Error};
+use std::fmt;
+use std::ptr;
 
 /// Static string initializer for [`ngx_str_t`].
 ///
@@ -32,70 +32,109 @@ macro_rules! ngx_null_string {
     };
 }
 
-/// Representation of a borrowed [Nginx string].
+pub use ngx_core::NgxStr;
+
+/// A string allocated from a [`Pool`], holding an [`ngx_str_t`] that can be returned to NGINX.
+///
+/// The string is not freed on drop: it lives as long as the pool it was allocated from, e.g. the
+/// request or configuration pool. As the string is not tied to the lifetime of the pool, reading
+/// it is unsafe, while passing it to NGINX is not:
 ///
-/// [Nginx string]: https://nginx.org/en/docs/dev/development_guide.html#string_overview
-pub struct NgxStr([u_char]);
+/// ```rust,ignore
+/// let Some(value) = NgxString::from_str_in(&mut request.pool(), &port.to_string()) else {
+///     return core::Status::NGX_ERROR;
+/// };
+/// // SAFETY: the request pool is alive
+/// assert!(*unsafe { value.as_ngx_str_ref() } == *"8080");
+/// ctx.port = value.into();
+/// ```
+///
+/// [`ngx_str_t`]: https://nginx.org/en/docs/dev/development_guide.html#string_overview
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct NgxString(ngx_str_t);
+
+impl NgxString {
+    /// Creates a string holding a copy of `bytes` allocated from `pool`.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn from_bytes_in(pool: &mut Pool, bytes: &[u8]) -> Option<Self> {
+        if bytes.is_empty() {
+            return Some(Self::default());
+        }
+
+        let data = pool.alloc(bytes.len()) as *mut u8;
+        if data.is_null() {
+            return None;
+        }
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len()) };
+        Some(NgxString(ngx_str_t { len: bytes.len(), data }))
+    }
+
+    /// Creates a string holding a copy of `s` allocated from `pool`.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn from_str_in(pool: &mut Pool, s: &str) -> Option<Self> {
+        Self::from_bytes_in(pool, s.as_bytes())
+    }
 
-impl NgxStr {
-    /// Create an [`NgxStr`] from an [`ngx_str_t`].
+    /// Creates an [`NgxString`] from an [`ngx_str_t`] owned by a pool.
     ///
     /// [`ngx_str_t`]: https://nginx.org/en/docs/dev/development_guide.html#string_overview
     ///
     /// # Safety
     ///
-    /// The caller has provided a valid `ngx_str_t` with a `data` pointer that points
-    /// to range of bytes of at least `len` bytes, whose content remains valid and doesn't
-    /// change for the lifetime of the returned `NgxStr`.
-    pub unsafe fn from_ngx_str<'a>(str: ngx_str_t) -> &'a NgxStr {
-        slice::from_raw_parts(str.data, str.len).into()
+    /// The caller has provided a valid `ngx_str_t` whose `data` points to at least `len` bytes,
+    /// or is null with a zero `len`, which remain unchanged while the pool is alive.
+    pub unsafe fn from_ngx_str(str: ngx_str_t) -> Self {
+        NgxString(str)
     }
 
-    /// Access the [`NgxStr`] as a byte slice.
-    pub fn as_bytes(&self) -> &[u8] {
-        &self.0
-    }
-
-    /// Yields a `&str` slice if the [`NgxStr`] contains valid UTF-8.
-    pub fn to_str(&self) -> Result<&str, Utf8Error> {
-        str::from_utf8(self.as_bytes())
+    /// Returns the [`ngx_str_t`] pointing to the string.
+    ///
+    /// [`ngx_str_t`]: https://nginx.org/en/docs/dev/development_guide.html#string_overview
+    pub fn as_ngx_str(&self) -> ngx_str_t {
+        self.0
     }
 
-    /// Converts an [`NgxStr`] into a [`Cow<str>`], replacing invalid UTF-8 sequences.
+    /// Returns the string as an [`NgxStr`], for comparisons and UTF-8 conversion.
+    ///
+    /// # Safety
     ///
-    /// See [`String::from_utf8_lossy`].
-    pub fn to_string_lossy(&self) -> Cow<str> {
-        String::from_utf8_lossy(self.as_bytes())
+    /// The pool the string was allocated from is not destroyed yet.
+    pub unsafe fn as_ngx_str_ref(&self) -> &NgxStr {
+        NgxStr::from_ngx_str(self.0)
     }
 
-    /// Returns `true` if the [`NgxStr`] is empty, otherwise `false`.
-    pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+    /// Returns the length of the string in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len
     }
-}
 
-impl From<&[u8]> for &NgxStr {
-    fn from(bytes: &[u8]) -> Self {
-        // SAFETY: An `NgxStr` is identical to a `[u8]` slice, given `u_char` is an alias for `u8`.
-        unsafe { &*(bytes as *const [u8] as *const NgxStr) }
+    /// Returns `true` if the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
     }
 }
 
-impl From<&str> for &NgxStr {
-    fn from(s: &str) -> Self {
-        s.as_bytes().into()
+impl Default for NgxString {
+    fn default() -> Self {
+        NgxString(ngx_str_t {
+            len: 0,
+            data: ptr::null_mut(),
+        })
     }
 }
 
-impl AsRef<[u8]> for NgxStr {
-    fn as_ref(&self) -> &[u8] {
-        self.as_bytes()
+impl From<NgxString> for ngx_str_t {
+    fn from(s: NgxString) -> Self {
+        s.0
     }
 }
 
-impl Default for &NgxStr {
-    fn default() -> Self {
-        // SAFETY: The null `ngx_str_t` is always a valid Nginx string.
-        unsafe { NgxStr::from_ngx_str(ngx_null_string!()) }
+impl fmt::Debug for NgxString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the string may outlive its pool, so only its length is shown
+        f.debug_struct("NgxString").field("len", &self.0.len).finish()
     }
 }
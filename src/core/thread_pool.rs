@@ -0,0 +1,147 @@
+use crate::core::ConfError;
+use crate::ffi::*;
+
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::{self, addr_of_mut, NonNull};
+use std::{mem, thread};
+
+/// Wrapper for a [thread pool] defined with the `thread_pool` directive, `ngx_thread_pool_t`.
+///
+/// Blocking or CPU-heavy work, e.g. image processing or password hashing, runs on the threads
+/// of the pool without stalling the event loop of the worker; its result is passed back to a
+/// completion closure running on the event loop:
+///
+/// ```rust,ignore
+/// // in the handler of a module directive, declaring the pool used
+/// conf.pool = Some(ThreadPool::add(cf, Some("hashing"))?);
+///
+/// // in a request handler
+/// let r: *mut ngx_http_request_t = request.into();
+/// let spawned = conf.pool.unwrap().spawn(
+///     move || argon2_verify(&password, &hash),
+///     move |verified| {
+///         let request = unsafe { Request::from_ngx_http_request(r) };
+///         let rc = match verified {
+///             Ok(true) => send_ok(request),
+///             Ok(false) => HTTPStatus::FORBIDDEN.into(),
+///             Err(_) => HTTPStatus::INTERNAL_SERVER_ERROR.into(),
+///         };
+///         unsafe { ngx_http_finalize_request(r, rc.0) };
+///     },
+/// );
+/// ```
+///
+/// The task must not access NGINX data structures, as they are not thread-safe; anything it
+/// needs is moved into its closure. As tasks cannot be cancelled, the completion closure must
+/// keep alive the data it uses, e.g. by holding a reference on the request.
+///
+/// [thread pool]: https://nginx.org/en/docs/ngx_core_module.html#thread_pool
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadPool(NonNull<ngx_thread_pool_t>);
+
+impl ThreadPool {
+    /// Declares the use of the thread pool `name`, or of the `default` pool with `None`, from a
+    /// directive handler.
+    ///
+    /// The pool is defined with the `thread_pool` directive in the main context; the `default`
+    /// pool is created implicitly. NGINX fails to start if the pool is not defined.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer.
+    pub unsafe fn add(cf: *mut ngx_conf_t, name: Option<&str>) -> Result<Self, ConfError> {
+        let mut name = name.map(|name| ngx_str_t::from_str((*cf).pool, name));
+        if name.is_some_and(|name| name.data.is_null()) {
+            return Err(ConfError::new("out of memory"));
+        }
+
+        let name = name.as_mut().map_or(ptr::null_mut(), |name| name as *mut ngx_str_t);
+        NonNull::new(ngx_thread_pool_add(cf, name))
+            .map(ThreadPool)
+            .ok_or_else(|| ConfError::new("failed to add the thread pool"))
+    }
+
+    /// Returns the thread pool `name` of the current cycle, if it is defined.
+    pub fn get(name: &str) -> Option<Self> {
+        let mut name = ngx_str_t {
+            len: name.len(),
+            data: name.as_ptr() as *mut u_char,
+        };
+        NonNull::new(unsafe { ngx_thread_pool_get(ngx_cycle as *mut ngx_cycle_t, &mut name) }).map(ThreadPool)
+    }
+
+    /// Returns the raw pointer to the thread pool.
+    pub fn as_ptr(&self) -> *mut ngx_thread_pool_t {
+        self.0.as_ptr()
+    }
+
+    /// Runs `task` on a thread of the pool, then calls `completion` with its result on the event
+    /// loop of the worker.
+    ///
+    /// A panic of `task` is passed to `completion` as an error. Returns `task` and `completion`
+    /// back if the task cannot be queued, e.g. when the queue of the pool, limited by the
+    /// `max_queue` parameter of the `thread_pool` directive, is full.
+    pub fn spawn<T, R, C>(&self, task: T, completion: C) -> Result<(), (T, C)>
+    where
+        T: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+        C: FnOnce(thread::Result<R>) + 'static,
+    {
+        let state = Box::into_raw(Box::new(TaskState {
+            // SAFETY: all-zero bits are a valid unqueued task
+            task: unsafe { mem::zeroed() },
+            work: Some(task),
+            result: None,
+            completion: Some(completion),
+        }));
+
+        unsafe {
+            let task = addr_of_mut!((*state).task);
+            (*task).ctx = state as *mut c_void;
+            (*task).handler = Some(thread_task_handler::<T, R, C>);
+            (*task).event.data = state as *mut c_void;
+            (*task).event.handler = Some(thread_task_completion::<T, R, C>);
+            (*task).event.log = (*ngx_cycle).log;
+
+            if ngx_thread_task_post(self.as_ptr(), task) != NGX_OK as ngx_int_t {
+                // the task was not queued
+                let state = Box::from_raw(state);
+                return Err((state.work.unwrap(), state.completion.unwrap()));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct TaskState<T, R, C> {
+    task: ngx_thread_task_t,
+    /// Taken by the thread running the task.
+    work: Option<T>,
+    /// Set by the thread running the task.
+    result: Option<thread::Result<R>>,
+    /// Only accessed from the event loop.
+    completion: Option<C>,
+}
+
+unsafe extern "C" fn thread_task_handler<T, R, C>(data: *mut c_void, _log: *mut ngx_log_t)
+where
+    T: FnOnce() -> R,
+{
+    let state = data as *mut TaskState<T, R, C>;
+    // the fields are accessed separately, as the task itself is concurrently updated by NGINX
+    if let Some(work) = (*addr_of_mut!((*state).work)).take() {
+        *addr_of_mut!((*state).result) = Some(panic::catch_unwind(AssertUnwindSafe(work)));
+    }
+}
+
+unsafe extern "C" fn thread_task_completion<T, R, C>(ev: *mut ngx_event_t)
+where
+    C: FnOnce(thread::Result<R>),
+{
+    // the task is not referenced by NGINX once completed
+    let mut state = Box::from_raw((*ev).data as *mut TaskState<T, R, C>);
+    if let (Some(completion), Some(result)) = (state.completion.take(), state.result.take()) {
+        completion(result);
+    }
+}
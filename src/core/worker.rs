@@ -0,0 +1,154 @@
+pub use ngx_core::LruCache;
+
+use std::cell::RefCell;
+use std::sync::OnceLock;
+use std::thread::{self, ThreadId};
+
+/// Module-level state owned by an NGINX worker process.
+///
+/// The state is created in the `init_process` hook of a module, dropped in `exit_process`, and can
+/// be accessed from any handler running on the worker's event loop in between. Use the
+/// [`ngx_worker_state`] macro to generate the hooks.
+///
+/// NGINX runs all module handlers of a worker on a single thread. The state belongs to the first
+/// thread accessing it, normally in `init_process`, and accessing it from another thread, e.g. a
+/// task offloaded to a thread pool, panics.
+///
+/// ```rust,ignore
+/// static STATE: WorkerState<Cache> = WorkerState::new();
+///
+/// ngx_worker_state!(STATE, ngx_http_cache_init_process, ngx_http_cache_exit_process, |_cycle| {
+///     Ok(Cache::default())
+/// });
+///
+/// // in a request handler
+/// STATE.with_mut(|cache| cache.insert(key, value));
+/// ```
+///
+/// [`ngx_worker_state`]: crate::ngx_worker_state
+pub struct WorkerState<T> {
+    owner: OnceLock<ThreadId>,
+    value: RefCell<Option<T>>,
+}
+
+// SAFETY: the value is only accessed from the owner thread, as checked by `WorkerState::value`.
+unsafe impl<T> Sync for WorkerState<T> {}
+
+impl<T> WorkerState<T> {
+    /// Creates an uninitialized state.
+    pub const fn new() -> Self {
+        WorkerState {
+            owner: OnceLock::new(),
+            value: RefCell::new(None),
+        }
+    }
+
+    /// Sets the value of the state, dropping the previous one.
+    ///
+    /// # Panics
+    /// Panics if called from a thread other than the owner thread.
+    pub fn set(&self, value: T) {
+        self.value().replace(Some(value));
+    }
+
+    /// Takes the value out of the state, leaving it uninitialized.
+    ///
+    /// # Panics
+    /// Panics if called from a thread other than the owner thread.
+    pub fn take(&self) -> Option<T> {
+        self.value().take()
+    }
+
+    /// Returns `true` if the state holds a value.
+    ///
+    /// # Panics
+    /// Panics if called from a thread other than the owner thread.
+    pub fn is_set(&self) -> bool {
+        self.value().borrow().is_some()
+    }
+
+    /// Calls `f` with a reference to the value.
+    ///
+    /// Returns `None` if the state is not initialized.
+    ///
+    /// # Panics
+    /// Panics if called from a thread other than the owner thread, or if the value is currently
+    /// borrowed by [`WorkerState::with_mut`].
+    pub fn with<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        self.value().borrow().as_ref().map(f)
+    }
+
+    /// Calls `f` with a mutable reference to the value.
+    ///
+    /// Returns `None` if the state is not initialized.
+    ///
+    /// # Panics
+    /// Panics if called from a thread other than the owner thread, or if the value is currently
+    /// borrowed.
+    pub fn with_mut<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        self.value().borrow_mut().as_mut().map(f)
+    }
+
+    fn value(&self) -> &RefCell<Option<T>> {
+        let current = thread::current().id();
+        assert!(
+            *self.owner.get_or_init(|| current) == current,
+            "worker state accessed from a thread other than the worker thread"
+        );
+        &self.value
+    }
+}
+
+impl<T> Default for WorkerState<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Define the `init_process` and `exit_process` hooks managing a [`WorkerState`].
+///
+/// The initializer takes the `*mut ngx_cycle_t` of the worker and returns
+/// `Result<T, Status>`; an error aborts the worker startup. The value is dropped when the worker
+/// exits.
+///
+/// The generated functions are meant to be used as the `init_process` and `exit_process` fields
+/// of the `ngx_module_t`.
+#[macro_export]
+macro_rules! ngx_worker_state {
+    ( $state: ident, $init_process: ident, $exit_process: ident, $init: expr ) => {
+        #[no_mangle]
+        extern "C" fn $init_process(cycle: *mut $crate::ffi::ngx_cycle_t) -> $crate::ffi::ngx_int_t {
+            let result: Result<_, $crate::core::Status> = $init(cycle);
+            match result {
+                Ok(value) => {
+                    $state.set(value);
+                    $crate::core::Status::NGX_OK.into()
+                }
+                Err(status) => status.into(),
+            }
+        }
+
+        #[no_mangle]
+        extern "C" fn $exit_process(_cycle: *mut $crate::ffi::ngx_cycle_t) {
+            drop($state.take());
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_state_owner() {
+        static STATE: WorkerState<u32> = WorkerState::new();
+
+        STATE.set(1);
+        assert_eq!(STATE.with_mut(|value| *value += 1), Some(()));
+        assert_eq!(STATE.with(|value| *value), Some(2));
+
+        let other = thread::spawn(|| STATE.is_set()).join();
+        assert!(other.is_err());
+        assert_eq!(STATE.take(), Some(2));
+    }
+}
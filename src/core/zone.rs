@@ -0,0 +1,343 @@
+use crate::core::{parse_size, ConfError, FromArg, NgxStr, Pool, SlabPool, Status};
+use crate::ffi::*;
+
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::sync::atomic::{
+    AtomicBool, AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicIsize, AtomicU16, AtomicU32, AtomicU64, AtomicU8,
+    AtomicUsize,
+};
+use std::{mem, ptr};
+
+/// A shared memory zone argument in the `name:size` syntax, e.g. `zone=one:10m` once the
+/// parameter name is stripped. The size can be omitted to refer to a zone declared elsewhere.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZoneSpec {
+    /// The name of the zone.
+    pub name: String,
+    /// The size of the zone in bytes, or 0 if omitted.
+    pub size: usize,
+}
+
+impl ZoneSpec {
+    /// Parses a zone argument, e.g. `one:10m` or `one`.
+    pub fn parse(value: &NgxStr) -> Result<Self, ConfError> {
+        let bytes = value.as_bytes();
+        let (name, size) = match bytes.iter().position(|&b| b == b':') {
+            Some(pos) => (&bytes[..pos], Some(&bytes[pos + 1..])),
+            None => (bytes, None),
+        };
+
+        if name.is_empty() {
+            return Err(ConfError::new(format!("invalid zone name in \"{}\"", value)));
+        }
+        let name = std::str::from_utf8(name).map_err(|err| ConfError::from_error(&err))?;
+
+        let size = match size {
+            Some(size) => match parse_size(size.into()) {
+                Ok(size) if size > 0 => size,
+                _ => return Err(ConfError::new(format!("invalid zone size \"{}\"", value))),
+            },
+            None => 0,
+        };
+
+        Ok(ZoneSpec {
+            name: name.into(),
+            size,
+        })
+    }
+}
+
+impl FromArg<'_> for ZoneSpec {
+    fn from_arg(arg: &NgxStr) -> Result<Self, ConfError> {
+        ZoneSpec::parse(arg)
+    }
+}
+
+/// A shared memory zone holding a value of type `T`, shared by the directives referring to the
+/// zone by name.
+///
+/// Adding a zone that was already added returns the same zone, as long as it was added by the
+/// same module and for the same type. Whichever directive comes first, the zone is declared with
+/// the size of the directive specifying one, and the configuration fails if none does:
+///
+/// ```rust,ignore
+/// // limit_conn_zone $binary_remote_addr zone=addr:10m;
+/// let spec = ZoneSpec::parse(args.require(1)?)?;
+/// conf.zone = Some(SharedZone::<Counters>::add(cf, &spec, &*addr_of!(ngx_http_limits_module))?);
+///
+/// // limit_conn addr 10;
+/// let spec = ZoneSpec::parse(args.require(0)?)?;
+/// conf.zone = Some(SharedZone::<Counters>::add(cf, &spec, &*addr_of!(ngx_http_limits_module))?);
+/// ```
+///
+/// The value is created with [`Default`] when the zone is mapped, and kept on reload if the zone
+/// size did not change and the value has the same type, size and alignment; otherwise a new value
+/// is created. As it lives in shared memory, its type implements [`ShmSafe`].
+pub struct SharedZone<T> {
+    zone: *mut ngx_shm_zone_t,
+    _type: PhantomData<T>,
+}
+
+/// Types that can be stored in a shared memory zone and accessed by all worker processes.
+///
+/// # Safety
+///
+/// The type does not point to memory of a process, i.e. it only holds plain data or pointers to
+/// the slab pool of its zone, and does not need to be dropped. Once the zone is mapped, it is
+/// only updated with atomic operations or under the lock of the zone, e.g. through
+/// [`UnsafeCell`](std::cell::UnsafeCell) or atomic types.
+///
+/// Heap-allocated types like [`String`], [`Vec`] or [`Box`], and process-local locks like
+/// [`Mutex`](std::sync::Mutex) are not safe to share.
+pub unsafe trait ShmSafe {}
+
+macro_rules! impl_shm_safe {
+    ($($ty:ty),* $(,)?) => {
+        $(unsafe impl ShmSafe for $ty {})*
+    };
+}
+
+impl_shm_safe!(
+    (),
+    bool,
+    u8,
+    u16,
+    u32,
+    u64,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    isize,
+    AtomicBool,
+    AtomicU8,
+    AtomicU16,
+    AtomicU32,
+    AtomicU64,
+    AtomicUsize,
+    AtomicI8,
+    AtomicI16,
+    AtomicI32,
+    AtomicI64,
+    AtomicIsize,
+);
+
+unsafe impl<T: ShmSafe, const N: usize> ShmSafe for [T; N] {}
+
+type ZoneHook = Box<dyn Fn(*mut c_void, &SlabPool) -> Option<()>>;
+
+struct ZoneCtx {
+    type_id: TypeId,
+    type_name: &'static str,
+    size: usize,
+    align: usize,
+    init: unsafe fn(*mut c_void),
+    hooks: Vec<ZoneHook>,
+    value: *mut c_void,
+}
+
+impl<T: ShmSafe + Default + 'static> SharedZone<T> {
+    /// Adds the shared memory zone of `spec`, or returns the zone already added with this name.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer.
+    pub unsafe fn add(cf: *mut ngx_conf_t, spec: &ZoneSpec, module: &ngx_module_t) -> Result<Self, ConfError> {
+        Self::add_zone(cf, spec, module, false)
+    }
+
+    /// Adds the shared memory zone of `spec`, failing if a zone with this name was already added,
+    /// e.g. for a zone holding the data of a single directive.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer.
+    pub unsafe fn add_unique(cf: *mut ngx_conf_t, spec: &ZoneSpec, module: &ngx_module_t) -> Result<Self, ConfError> {
+        Self::add_zone(cf, spec, module, true)
+    }
+
+    unsafe fn add_zone(
+        cf: *mut ngx_conf_t,
+        spec: &ZoneSpec,
+        module: &ngx_module_t,
+        unique: bool,
+    ) -> Result<Self, ConfError> {
+        if spec.size != 0 && spec.size < 8 * ngx_pagesize {
+            return Err(ConfError::new(format!(
+                "zone \"{}\" is too small, it must be at least {} bytes",
+                spec.name,
+                8 * ngx_pagesize
+            )));
+        }
+
+        // NGINX checks that the module and the size match the existing zone
+        let mut zone_name = ngx_str_t::from_str((*cf).pool, &spec.name);
+        let zone = ngx_shared_memory_add(cf, &mut zone_name, spec.size, module as *const _ as *mut c_void);
+        if zone.is_null() {
+            return Err(ConfError::new(format!("failed to add zone \"{}\"", spec.name)));
+        }
+
+        if !(*zone).data.is_null() {
+            if unique {
+                return Err(ConfError::new(format!("duplicate zone \"{}\"", spec.name)));
+            }
+            let ours = (*zone)
+                .init
+                .is_some_and(|init| init as usize == shared_zone_init as usize);
+            if !ours {
+                return Err(ConfError::new(format!(
+                    "zone \"{}\" is already declared for a different use",
+                    spec.name
+                )));
+            }
+            let ctx = &*((*zone).data as *const ZoneCtx);
+            if ctx.type_id != TypeId::of::<T>() {
+                return Err(ConfError::new(format!(
+                    "zone \"{}\" is already declared for a different type \"{}\"",
+                    spec.name, ctx.type_name
+                )));
+            }
+            return Ok(SharedZone {
+                zone,
+                _type: PhantomData,
+            });
+        }
+
+        let ctx = Pool::from_ngx_pool((*cf).pool).allocate(ZoneCtx {
+            type_id: TypeId::of::<T>(),
+            type_name: type_name::<T>(),
+            size: mem::size_of::<T>(),
+            align: mem::align_of::<T>(),
+            init: init_value::<T>,
+            hooks: Vec::new(),
+            value: ptr::null_mut(),
+        });
+        if ctx.is_null() {
+            return Err(ConfError::new("failed to allocate zone context"));
+        }
+
+        (*zone).data = ctx as *mut c_void;
+        (*zone).init = Some(shared_zone_init);
+
+        Ok(SharedZone {
+            zone,
+            _type: PhantomData,
+        })
+    }
+
+    /// Adds a hook run each time the zone is mapped, once the value is created or reused on
+    /// reload, e.g. to allocate the parts of the value sized by the configuration from the slab
+    /// pool of the zone. Returning `None` fails the configuration.
+    ///
+    /// The hook runs in the master process, before the zone is returned by [`SharedZone::get`].
+    pub fn on_init<F>(&self, hook: F)
+    where
+        F: Fn(&T, &SlabPool) -> Option<()> + 'static,
+    {
+        let ctx = unsafe { &mut *((*self.zone).data as *mut ZoneCtx) };
+        ctx.hooks.push(Box::new(move |value: *mut c_void, pool: &SlabPool| {
+            hook(unsafe { &*(value as *const T) }, pool)
+        }));
+    }
+
+    /// Returns the name of the zone.
+    pub fn name(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str((*self.zone).shm.name) }
+    }
+
+    /// Returns the value of the zone, once the zone is mapped.
+    pub fn get(&self) -> Option<&T> {
+        unsafe { (self.ctx().value as *const T).as_ref() }
+    }
+
+    /// Returns the slab pool of the zone, once the zone is mapped, e.g. to allocate the entries
+    /// of a shared data structure rooted in the value.
+    pub fn slab_pool(&self) -> Option<SlabPool> {
+        unsafe { SlabPool::from_shm_zone(&*self.zone) }
+    }
+
+    /// Returns the raw pointer to the zone.
+    pub fn as_ptr(&self) -> *mut ngx_shm_zone_t {
+        self.zone
+    }
+
+    fn ctx(&self) -> &ZoneCtx {
+        unsafe { &*((*self.zone).data as *const ZoneCtx) }
+    }
+}
+
+impl<T> Clone for SharedZone<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SharedZone<T> {}
+
+unsafe fn init_value<T: Default>(value: *mut c_void) {
+    ptr::write(value as *mut T, T::default());
+}
+
+unsafe extern "C" fn shared_zone_init(shm_zone: *mut ngx_shm_zone_t, data: *mut c_void) -> ngx_int_t {
+    let ctx = &mut *((*shm_zone).data as *mut ZoneCtx);
+
+    let Some(pool) = SlabPool::from_shm_zone(&*shm_zone) else {
+        return Status::NGX_ERROR.into();
+    };
+
+    // the zone is reused on reload, unless the type of the value changed, including a type of
+    // a rebuilt module keeping its name but not its layout
+    let value = match (data as *const ZoneCtx).as_ref().filter(|old| {
+        !old.value.is_null() && old.type_id == ctx.type_id && old.size == ctx.size && old.align == ctx.align
+    }) {
+        Some(old) => old.value,
+        None if (*shm_zone).shm.exists != 0 => pool.data(),
+        None => {
+            let value = pool.calloc(ctx.size.max(1));
+            if value.is_null() {
+                return Status::NGX_ERROR.into();
+            }
+            (ctx.init)(value);
+            pool.set_data(value);
+            value
+        }
+    };
+
+    if ctx.hooks.iter().any(|hook| hook(value, &pool).is_none()) {
+        return Status::NGX_ERROR.into();
+    }
+    ctx.value = value;
+
+    Status::NGX_OK.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone_spec() {
+        let parse = |s: &'static str| ZoneSpec::parse(s.as_bytes().into());
+
+        assert_eq!(
+            parse("one:10m").unwrap(),
+            ZoneSpec {
+                name: "one".into(),
+                size: 10 * 1024 * 1024
+            }
+        );
+        assert_eq!(
+            parse("one").unwrap(),
+            ZoneSpec {
+                name: "one".into(),
+                size: 0
+            }
+        );
+        assert!(parse(":10m").is_err());
+        assert!(parse("one:").is_err());
+        assert!(parse("one:10q").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::event::{ngx_delete_posted_event, ngx_post_event};
+use crate::ffi::*;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ptr::addr_of_mut;
+use std::rc::Rc;
+
+/// Coalesces concurrent identical operations of a worker, e.g. lookups of the same key in an
+/// upstream service, into a single one.
+///
+/// The first request joining a key becomes the leader and performs the operation. Requests
+/// joining the same key until the leader completes become waiters: they park on an event of
+/// their own, which is posted when the leader completes, and then read the shared result.
+///
+/// ```rust,ignore
+/// static LOOKUPS: WorkerState<SingleFlight<String, Token>> = WorkerState::new();
+///
+/// match LOOKUPS.with(|flights| unsafe { flights.join(key.clone(), &mut ctx.wake_event) }).unwrap() {
+///     Flight::Leader(leader) => start_lookup(request, leader), // later: leader.complete(token)
+///     Flight::Waiter(waiter) => ctx.waiter = Some(waiter),     // read waiter.result() when woken
+/// }
+/// ```
+///
+/// The state is not shared between workers and must only be used from the worker's event loop.
+pub struct SingleFlight<K, V>(Rc<RefCell<FlightGroup<K, V>>>);
+
+impl<K, V> Clone for SingleFlight<K, V> {
+    fn clone(&self) -> Self {
+        SingleFlight(self.0.clone())
+    }
+}
+
+struct FlightGroup<K, V> {
+    flights: HashMap<K, Vec<FlightWaiterEntry<V>>>,
+    next_id: u64,
+}
+
+struct FlightWaiterEntry<V> {
+    id: u64,
+    ev: *mut ngx_event_t,
+    result: Rc<RefCell<FlightResult<V>>>,
+}
+
+/// The result of a coalesced operation, as seen by a [`FlightWaiter`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlightResult<V> {
+    /// The leader has not completed yet.
+    Pending,
+    /// The leader completed with a value.
+    Done(V),
+    /// The leader was dropped without completing, e.g. because its request was aborted.
+    Failed,
+}
+
+/// The role of a request joining a [`SingleFlight`].
+pub enum Flight<K: Eq + Hash + Clone, V: Clone> {
+    /// The caller performs the operation.
+    Leader(FlightLeader<K, V>),
+    /// The caller waits for the result of the leader.
+    Waiter(FlightWaiter<K, V>),
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    /// Creates an empty group.
+    pub fn new() -> Self {
+        SingleFlight(Rc::new(RefCell::new(FlightGroup {
+            flights: HashMap::new(),
+            next_id: 0,
+        })))
+    }
+
+    /// Returns `true` if an operation is in progress for `key`.
+    pub fn in_flight(&self, key: &K) -> bool {
+        self.0.borrow().flights.contains_key(key)
+    }
+
+    /// Joins the operation for `key`, starting it if none is in progress.
+    ///
+    /// If the caller becomes a waiter, `ev` is posted when the leader completes. The event
+    /// handler typically resumes the request, which then reads [`FlightWaiter::result`].
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_event_t` pointer with `handler` set, which
+    /// stays valid until the returned waiter is dropped.
+    pub unsafe fn join(&self, key: K, ev: *mut ngx_event_t) -> Flight<K, V> {
+        let mut group = self.0.borrow_mut();
+
+        if group.flights.contains_key(&key) {
+            let id = group.next_id;
+            group.next_id += 1;
+
+            let result = Rc::new(RefCell::new(FlightResult::Pending));
+            if let Some(waiters) = group.flights.get_mut(&key) {
+                waiters.push(FlightWaiterEntry {
+                    id,
+                    ev,
+                    result: result.clone(),
+                });
+            }
+
+            return Flight::Waiter(FlightWaiter {
+                group: self.clone(),
+                key,
+                id,
+                ev,
+                result,
+            });
+        }
+
+        group.flights.insert(key.clone(), Vec::new());
+        Flight::Leader(FlightLeader {
+            group: self.clone(),
+            key: Some(key),
+        })
+    }
+
+    fn finish(&self, key: &K, value: Option<V>) {
+        let waiters = self.0.borrow_mut().flights.remove(key).unwrap_or_default();
+
+        for waiter in waiters {
+            *waiter.result.borrow_mut() = match value {
+                Some(ref value) => FlightResult::Done(value.clone()),
+                None => FlightResult::Failed,
+            };
+            unsafe { ngx_post_event(waiter.ev, addr_of_mut!(ngx_posted_events)) };
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The request performing a coalesced operation.
+///
+/// Dropping the leader without calling [`FlightLeader::complete`] wakes the waiters with
+/// [`FlightResult::Failed`].
+pub struct FlightLeader<K: Eq + Hash + Clone, V: Clone> {
+    group: SingleFlight<K, V>,
+    key: Option<K>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> FlightLeader<K, V> {
+    /// Returns the key of the operation.
+    pub fn key(&self) -> &K {
+        self.key.as_ref().expect("key of an active leader")
+    }
+
+    /// Completes the operation, waking all waiters with a copy of `value`.
+    pub fn complete(mut self, value: V) {
+        if let Some(key) = self.key.take() {
+            self.group.finish(&key, Some(value));
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Drop for FlightLeader<K, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.group.finish(&key, None);
+        }
+    }
+}
+
+/// A request waiting for the result of a coalesced operation.
+///
+/// Dropping a waiter, e.g. when its request is finalized, stops its event from being posted.
+pub struct FlightWaiter<K: Eq + Hash + Clone, V: Clone> {
+    group: SingleFlight<K, V>,
+    key: K,
+    id: u64,
+    ev: *mut ngx_event_t,
+    result: Rc<RefCell<FlightResult<V>>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> FlightWaiter<K, V> {
+    /// Returns the result of the operation.
+    pub fn result(&self) -> FlightResult<V> {
+        self.result.borrow().clone()
+    }
+
+    /// Returns `true` if the leader has not completed yet.
+    pub fn is_pending(&self) -> bool {
+        matches!(*self.result.borrow(), FlightResult::Pending)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Drop for FlightWaiter<K, V> {
+    fn drop(&mut self) {
+        if self.is_pending() {
+            let mut group = self.group.0.borrow_mut();
+            if let Some(waiters) = group.flights.get_mut(&self.key) {
+                waiters.retain(|waiter| waiter.id != self.id);
+            }
+        } else if unsafe { (*self.ev).posted() } != 0 {
+            unsafe { ngx_delete_posted_event(self.ev) };
+        }
+    }
+}
@@ -0,0 +1,9 @@
+mod flight;
+mod posted;
+mod timer;
+mod wheel;
+
+pub use flight::*;
+pub use posted::*;
+pub use timer::*;
+pub use wheel::*;
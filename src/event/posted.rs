@@ -0,0 +1,144 @@
+use crate::ffi::*;
+
+use std::cell::{RefCell, UnsafeCell};
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr::addr_of_mut;
+
+/// Posts an event to the queue `q`, equivalent to the `ngx_post_event` macro.
+///
+/// The event handler is called after the current round of event processing, when NGINX
+/// processes the queue. Events posted to [`ngx_posted_events`] are processed on every iteration
+/// of the event loop. Posting an already posted event does nothing.
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#posted_events
+///
+/// # Safety
+///
+/// The caller has provided valid non-null `ngx_event_t` and `ngx_queue_t` pointers. The event
+/// must stay valid until it is processed or removed with [`ngx_delete_posted_event`].
+pub unsafe fn ngx_post_event(ev: *mut ngx_event_t, q: *mut ngx_queue_t) {
+    if (*ev).posted() != 0 {
+        return;
+    }
+    (*ev).set_posted(1);
+
+    // ngx_queue_insert_tail
+    let x = &mut (*ev).queue as *mut ngx_queue_t;
+    (*x).prev = (*q).prev;
+    (*(*x).prev).next = x;
+    (*x).next = q;
+    (*q).prev = x;
+}
+
+/// Removes a posted event from its queue, equivalent to the `ngx_delete_posted_event` macro.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null pointer to a posted `ngx_event_t`.
+pub unsafe fn ngx_delete_posted_event(ev: *mut ngx_event_t) {
+    (*ev).set_posted(0);
+
+    // ngx_queue_remove
+    let x = &mut (*ev).queue as *mut ngx_queue_t;
+    (*(*x).next).prev = (*x).prev;
+    (*(*x).prev).next = (*x).next;
+    (*x).prev = std::ptr::null_mut();
+    (*x).next = std::ptr::null_mut();
+}
+
+/// An event running a closure after the current round of event processing.
+///
+/// The event is allocated once and can be posted repeatedly; posting it while it is already
+/// posted runs the closure only once. Dropping the event removes it from the queue; the event
+/// must not be dropped from its own closure.
+///
+/// ```rust,ignore
+/// let flush = PostedEvent::new(move || state.flush());
+/// // batch many updates, flushing them once per event loop iteration
+/// flush.post();
+/// ```
+///
+/// The event must only be used from the worker's event loop.
+pub struct PostedEvent(Box<PostedInner>);
+
+struct PostedInner {
+    event: UnsafeCell<ngx_event_t>,
+    handler: RefCell<Box<dyn FnMut()>>,
+}
+
+impl PostedEvent {
+    /// Creates an event calling `handler` each time it is processed.
+    pub fn new<F: FnMut() + 'static>(handler: F) -> Self {
+        let mut inner = Box::new(PostedInner {
+            // SAFETY: all-zero bits are a valid inactive event
+            event: UnsafeCell::new(unsafe { mem::zeroed() }),
+            handler: RefCell::new(Box::new(handler)),
+        });
+
+        let data = &*inner as *const PostedInner as *mut c_void;
+        let event = inner.event.get_mut();
+        event.handler = Some(posted_event_handler);
+        event.data = data;
+        event.log = unsafe { (*ngx_cycle).log };
+
+        PostedEvent(inner)
+    }
+
+    /// Posts the event to [`ngx_posted_events`].
+    pub fn post(&self) {
+        unsafe { ngx_post_event(self.0.event.get(), addr_of_mut!(ngx_posted_events)) };
+    }
+
+    /// Removes the event from the queue if it is posted.
+    pub fn cancel(&self) {
+        let ev = self.0.event.get();
+        if unsafe { (*ev).posted() } != 0 {
+            unsafe { ngx_delete_posted_event(ev) };
+        }
+    }
+
+    /// Returns `true` if the event is posted and was not processed yet.
+    pub fn is_posted(&self) -> bool {
+        unsafe { (*self.0.event.get()).posted() != 0 }
+    }
+}
+
+impl Drop for PostedEvent {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+unsafe extern "C" fn posted_event_handler(ev: *mut ngx_event_t) {
+    let inner = &*((*ev).data as *const PostedInner);
+    (inner.handler.borrow_mut())();
+}
+
+/// Runs `f` once after the current round of event processing.
+///
+/// The closure is freed after it runs. Closures still pending when the worker exits are not run.
+pub fn post<F: FnOnce() + 'static>(f: F) {
+    struct Task {
+        event: ngx_event_t,
+        f: Box<dyn FnOnce()>,
+    }
+
+    unsafe extern "C" fn task_handler(ev: *mut ngx_event_t) {
+        let task = Box::from_raw((*ev).data as *mut Task);
+        (task.f)();
+    }
+
+    let task = Box::into_raw(Box::new(Task {
+        // SAFETY: all-zero bits are a valid inactive event
+        event: unsafe { mem::zeroed() },
+        f: Box::new(f),
+    }));
+
+    unsafe {
+        (*task).event.handler = Some(task_handler);
+        (*task).event.data = task as *mut c_void;
+        (*task).event.log = (*ngx_cycle).log;
+        ngx_post_event(addr_of_mut!((*task).event), addr_of_mut!(ngx_posted_events));
+    }
+}
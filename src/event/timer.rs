@@ -0,0 +1,162 @@
+use crate::ffi::*;
+
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr::addr_of_mut;
+use std::time::Duration;
+
+/// Converts a [`Duration`] into NGINX milliseconds, saturating at [`ngx_msec_t::MAX`].
+pub fn duration_to_msec(duration: Duration) -> ngx_msec_t {
+    duration.as_millis().min(ngx_msec_t::MAX as u128) as ngx_msec_t
+}
+
+/// Returns the cached wall clock time of the worker, in seconds since the Unix epoch, equivalent
+/// to the `ngx_time` macro.
+///
+/// The time is updated by NGINX on each iteration of the event loop.
+pub fn ngx_time() -> u64 {
+    unsafe { (*ngx_cached_time).sec as u64 }
+}
+
+/// Adds an event to the timer tree, equivalent to the `ngx_add_timer` macro.
+///
+/// An already armed timer is rescheduled, unless the new deadline is within
+/// `NGX_TIMER_LAZY_DELAY` of the current one.
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#timer_events
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_event_t` pointer with `handler` set. The event
+/// must stay valid until the timer expires or is removed with [`ngx_del_timer`].
+pub unsafe fn ngx_add_timer(ev: *mut ngx_event_t, timer: ngx_msec_t) {
+    let key = ngx_current_msec.wrapping_add(timer);
+
+    if (*ev).timer_set() != 0 {
+        let diff = key.wrapping_sub((*ev).timer.key) as ngx_msec_int_t;
+        if diff.unsigned_abs() < NGX_TIMER_LAZY_DELAY as ngx_msec_t {
+            return;
+        }
+
+        ngx_del_timer(ev);
+    }
+
+    (*ev).timer.key = key;
+    ngx_rbtree_insert(addr_of_mut!(ngx_event_timer_rbtree), addr_of_mut!((*ev).timer));
+    (*ev).set_timer_set(1);
+}
+
+/// Removes an event from the timer tree, equivalent to the `ngx_del_timer` macro.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_event_t` pointer with an armed timer.
+pub unsafe fn ngx_del_timer(ev: *mut ngx_event_t) {
+    ngx_rbtree_delete(addr_of_mut!(ngx_event_timer_rbtree), addr_of_mut!((*ev).timer));
+    (*ev).set_timer_set(0);
+}
+
+/// A timer running a closure on the event loop after a delay, wrapping an `ngx_event_t`.
+///
+/// The timer can be scheduled, rescheduled and cancelled repeatedly. With
+/// [`Timer::schedule_repeating`], it is rearmed after each run, for periodic background work:
+///
+/// ```rust,ignore
+/// static FLUSH: WorkerState<Timer> = WorkerState::new();
+///
+/// // in init_process
+/// let timer = Timer::new(|| METRICS.with(|metrics| metrics.flush()));
+/// timer.schedule_repeating(Duration::from_secs(10));
+/// FLUSH.set(timer);
+/// ```
+///
+/// Dropping the timer disarms it, so a timer stored in a value allocated with
+/// [`Pool::allocate`](crate::core::Pool::allocate), e.g. a request context, is disarmed when the
+/// pool is destroyed. The timer must not be dropped from its own closure, and must only be used
+/// from the worker's event loop.
+///
+/// Timers are cancelable by default: pending timers do not delay a graceful shutdown of the
+/// worker, and their closure is not called when the worker exits.
+pub struct Timer(Box<TimerInner>);
+
+struct TimerInner {
+    event: UnsafeCell<ngx_event_t>,
+    period: Cell<Option<ngx_msec_t>>,
+    handler: RefCell<Box<dyn FnMut()>>,
+}
+
+impl Timer {
+    /// Creates an unarmed timer calling `handler` each time it expires.
+    pub fn new<F: FnMut() + 'static>(handler: F) -> Self {
+        let mut inner = Box::new(TimerInner {
+            // SAFETY: all-zero bits are a valid inactive event
+            event: UnsafeCell::new(unsafe { mem::zeroed() }),
+            period: Cell::new(None),
+            handler: RefCell::new(Box::new(handler)),
+        });
+
+        let data = &*inner as *const TimerInner as *mut c_void;
+        let event = inner.event.get_mut();
+        event.handler = Some(timer_event_handler);
+        event.data = data;
+        event.log = unsafe { (*ngx_cycle).log };
+        event.set_cancelable(1);
+
+        Timer(inner)
+    }
+
+    /// Arms the timer to expire once after `delay`, replacing the current schedule.
+    pub fn schedule(&self, delay: Duration) {
+        self.0.period.set(None);
+        unsafe { ngx_add_timer(self.0.event.get(), duration_to_msec(delay)) };
+    }
+
+    /// Arms the timer to expire every `period`, replacing the current schedule.
+    pub fn schedule_repeating(&self, period: Duration) {
+        let period = duration_to_msec(period);
+        self.0.period.set(Some(period));
+        unsafe { ngx_add_timer(self.0.event.get(), period) };
+    }
+
+    /// Disarms the timer.
+    pub fn cancel(&self) {
+        self.0.period.set(None);
+        let ev = self.0.event.get();
+        if unsafe { (*ev).timer_set() } != 0 {
+            unsafe { ngx_del_timer(ev) };
+        }
+    }
+
+    /// Returns `true` if the timer is armed.
+    pub fn is_pending(&self) -> bool {
+        unsafe { (*self.0.event.get()).timer_set() != 0 }
+    }
+
+    /// Sets whether the timer is cancelled on a graceful shutdown of the worker, or delays the
+    /// shutdown until it expires.
+    pub fn set_cancelable(&self, cancelable: bool) {
+        unsafe { (*self.0.event.get()).set_cancelable(cancelable as _) };
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+unsafe extern "C" fn timer_event_handler(ev: *mut ngx_event_t) {
+    // cancelable timers are run without `timedout` when the worker exits
+    if (*ev).timedout() == 0 {
+        return;
+    }
+    (*ev).set_timedout(0);
+
+    let inner = &*((*ev).data as *const TimerInner);
+    // rearm first, so the handler may cancel or reschedule the timer
+    if let Some(period) = inner.period.get() {
+        ngx_add_timer(ev, period);
+    }
+    (inner.handler.borrow_mut())();
+}
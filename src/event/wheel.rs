@@ -0,0 +1,126 @@
+use crate::event::{duration_to_msec, ngx_add_timer, ngx_del_timer};
+use crate::ffi::*;
+
+use std::cell::{RefCell, UnsafeCell};
+use std::mem;
+use std::os::raw::c_void;
+use std::time::Duration;
+
+pub use ngx_core::{TimerKey, TimerWheel};
+
+/// A [`TimerWheel`] driven by a single NGINX event timer, for modules managing thousands of
+/// cheap per-session timeouts without an `ngx_event_t` for each of them.
+///
+/// Entries are scheduled in milliseconds of `ngx_current_msec`. When they expire, the
+/// `on_expire` callback is called with their value from the event loop. The callback may
+/// schedule new entries in the same wheel.
+///
+/// ```rust,ignore
+/// static SESSIONS: WorkerState<EventTimerWheel<SessionId>> = WorkerState::new();
+///
+/// // in init_process
+/// SESSIONS.set(EventTimerWheel::new(|id| expire_session(id)));
+///
+/// // when a session is created
+/// let key = SESSIONS.with(|wheel| wheel.insert(Duration::from_secs(300), id)).unwrap();
+/// ```
+///
+/// The wheel must only be used from the worker's event loop. The timer is cancelable, so it
+/// does not delay a graceful shutdown of the worker.
+pub struct EventTimerWheel<T>(Box<WheelInner<T>>);
+
+struct WheelInner<T> {
+    event: UnsafeCell<ngx_event_t>,
+    wheel: RefCell<TimerWheel<T>>,
+    on_expire: RefCell<Box<dyn FnMut(T)>>,
+}
+
+impl<T: 'static> EventTimerWheel<T> {
+    /// Creates an empty wheel calling `on_expire` for each expired entry.
+    pub fn new<F: FnMut(T) + 'static>(on_expire: F) -> Self {
+        let mut inner = Box::new(WheelInner {
+            // SAFETY: all-zero bits are a valid inactive event
+            event: UnsafeCell::new(unsafe { mem::zeroed() }),
+            wheel: RefCell::new(TimerWheel::new(unsafe { ngx_current_msec } as u64)),
+            on_expire: RefCell::new(Box::new(on_expire)),
+        });
+
+        let data = &*inner as *const WheelInner<T> as *mut c_void;
+        let event = inner.event.get_mut();
+        event.handler = Some(wheel_event_handler::<T>);
+        event.data = data;
+        event.log = unsafe { (*ngx_cycle).log };
+        event.set_cancelable(1);
+
+        EventTimerWheel(inner)
+    }
+
+    /// Schedules `value` to expire after `timeout`.
+    pub fn insert(&self, timeout: Duration, value: T) -> TimerKey {
+        let deadline = unsafe { ngx_current_msec } as u64 + duration_to_msec(timeout) as u64;
+        let key = self.0.wheel.borrow_mut().insert(deadline, value);
+        unsafe { self.0.arm() };
+        key
+    }
+
+    /// Removes a scheduled entry, returning its value, or `None` if it already expired.
+    pub fn remove(&self, key: TimerKey) -> Option<T> {
+        let value = self.0.wheel.borrow_mut().remove(key);
+        unsafe { self.0.arm() };
+        value
+    }
+
+    /// Returns the number of scheduled entries.
+    pub fn len(&self) -> usize {
+        self.0.wheel.borrow().len()
+    }
+
+    /// Returns `true` if no entries are scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.0.wheel.borrow().is_empty()
+    }
+}
+
+impl<T> WheelInner<T> {
+    /// Arms the event timer for the next deadline of the wheel, or removes it if the wheel is
+    /// empty.
+    unsafe fn arm(&self) {
+        let ev = self.event.get();
+
+        match self.wheel.borrow().next_deadline() {
+            Some(deadline) => {
+                let now = ngx_current_msec as u64;
+                ngx_add_timer(ev, deadline.saturating_sub(now) as ngx_msec_t);
+            }
+            None if (*ev).timer_set() != 0 => ngx_del_timer(ev),
+            None => {}
+        }
+    }
+}
+
+impl<T> Drop for WheelInner<T> {
+    fn drop(&mut self) {
+        let ev = self.event.get_mut();
+        if ev.timer_set() != 0 {
+            unsafe { ngx_del_timer(ev) };
+        }
+    }
+}
+
+unsafe extern "C" fn wheel_event_handler<T>(ev: *mut ngx_event_t) {
+    let inner = &*((*ev).data as *const WheelInner<T>);
+    (*ev).set_timedout(0);
+
+    let mut expired = Vec::new();
+    inner
+        .wheel
+        .borrow_mut()
+        .advance(ngx_current_msec as u64, |value| expired.push(value));
+
+    // the callback may schedule new entries
+    for value in expired {
+        (inner.on_expire.borrow_mut())(value);
+    }
+
+    inner.arm();
+}
@@ -1 +1,55 @@
 pub use nginx_sys::*;
+
+/// Curated set of the FFI items commonly needed to write an NGINX module.
+///
+/// The raw bindings are generated by bindgen from the NGINX headers and may change between NGINX
+/// versions or bindgen releases. The items re-exported here are part of the stable API of this
+/// crate: removing or renaming one of them is a breaking change, so module code importing from
+/// the prelude instead of the raw bindings keeps compiling across binding updates.
+///
+/// The HTTP phases are exported under their NGINX names, e.g. `NGX_HTTP_ACCESS_PHASE`, instead of
+/// the names bindgen derives from the `ngx_http_phases` enum.
+///
+/// ```rust,ignore
+/// use ngx::ffi::prelude::*;
+/// ```
+pub mod prelude {
+    pub use nginx_sys::{
+        nginx_version, ngx_array_push, ngx_array_t, ngx_buf_t, ngx_chain_t, ngx_command_t, ngx_conf_log_error,
+        ngx_conf_t, ngx_connection_t, ngx_cycle, ngx_cycle_t, ngx_event_t, ngx_flag_t, ngx_http_add_variable,
+        ngx_http_core_module, ngx_http_handler_pt, ngx_http_module_t, ngx_http_phases, ngx_http_request_t,
+        ngx_http_upstream_srv_conf_t, ngx_http_upstream_t, ngx_http_variable_t, ngx_int_t, ngx_list_t, ngx_log_t,
+        ngx_module_t, ngx_msec_t, ngx_pool_t, ngx_str_t, ngx_table_elt_t, ngx_uint_t, ngx_variable_value_t,
+        NGX_CONF_1MORE, NGX_CONF_2MORE, NGX_CONF_ANY, NGX_CONF_BLOCK, NGX_CONF_FLAG, NGX_CONF_NOARGS, NGX_CONF_TAKE1,
+        NGX_CONF_TAKE12, NGX_CONF_TAKE123, NGX_CONF_TAKE1234, NGX_CONF_TAKE13, NGX_CONF_TAKE2, NGX_CONF_TAKE23,
+        NGX_CONF_TAKE3, NGX_CONF_TAKE4, NGX_CONF_TAKE5, NGX_CONF_TAKE6, NGX_CONF_TAKE7, NGX_CONF_UNSET,
+        NGX_HTTP_LIF_CONF, NGX_HTTP_LOC_CONF, NGX_HTTP_MAIN_CONF, NGX_HTTP_MODULE, NGX_HTTP_SIF_CONF,
+        NGX_HTTP_SRV_CONF, NGX_HTTP_UPS_CONF, NGX_HTTP_VAR_CHANGEABLE, NGX_HTTP_VAR_NOCACHEABLE, NGX_LOG_ALERT,
+        NGX_LOG_CRIT, NGX_LOG_DEBUG, NGX_LOG_EMERG, NGX_LOG_ERR, NGX_LOG_INFO, NGX_LOG_NOTICE, NGX_LOG_WARN,
+        NGX_RS_HTTP_LOC_CONF_OFFSET, NGX_RS_HTTP_MAIN_CONF_OFFSET, NGX_RS_HTTP_SRV_CONF_OFFSET,
+        NGX_RS_MODULE_SIGNATURE,
+    };
+
+    /// The `NGX_HTTP_POST_READ_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_POST_READ_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_POST_READ_PHASE;
+    /// The `NGX_HTTP_SERVER_REWRITE_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_SERVER_REWRITE_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_SERVER_REWRITE_PHASE;
+    /// The `NGX_HTTP_FIND_CONFIG_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_FIND_CONFIG_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_FIND_CONFIG_PHASE;
+    /// The `NGX_HTTP_REWRITE_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_REWRITE_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_REWRITE_PHASE;
+    /// The `NGX_HTTP_POST_REWRITE_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_POST_REWRITE_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_POST_REWRITE_PHASE;
+    /// The `NGX_HTTP_PREACCESS_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_PREACCESS_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_PREACCESS_PHASE;
+    /// The `NGX_HTTP_ACCESS_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_ACCESS_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_ACCESS_PHASE;
+    /// The `NGX_HTTP_POST_ACCESS_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_POST_ACCESS_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_POST_ACCESS_PHASE;
+    /// The `NGX_HTTP_PRECONTENT_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_PRECONTENT_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_PRECONTENT_PHASE;
+    /// The `NGX_HTTP_CONTENT_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_CONTENT_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_CONTENT_PHASE;
+    /// The `NGX_HTTP_LOG_PHASE` of [`ngx_http_phases`].
+    pub const NGX_HTTP_LOG_PHASE: ngx_http_phases = nginx_sys::ngx_http_phases_NGX_HTTP_LOG_PHASE;
+}
@@ -0,0 +1,326 @@
+use crate::core::{Pool, Status};
+use crate::event::{ngx_delete_posted_event, ngx_post_event};
+use crate::ffi::*;
+use crate::http::Request;
+use crate::sync::{channel, Receiver, Sender};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use std::mem::{self, ManuallyDrop};
+use std::os::raw::c_void;
+use std::pin::Pin;
+use std::ptr::{addr_of, addr_of_mut};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, ThreadId};
+
+/// Capacity of the channel carrying wakeups from other threads to the event loop.
+const WAKE_CHANNEL_CAPACITY: usize = 1024;
+
+/// Define a static phase handler running an asynchronous function.
+///
+/// The handler takes an [`AsyncRequest`] and returns a future resolving to the [`Status`] of
+/// the handler. See [`ngx_http_async_handler`] for how the request is suspended and resumed.
+///
+/// ```rust,ignore
+/// http_async_handler!(lookup_access_handler, |mut request: AsyncRequest| async move {
+///     // the header value is copied, as it cannot outlive the access to the request
+///     let token = request.with(|request| request.header_in("Token").map(|token| token.to_string()));
+///     let Some(Some(token)) = token else {
+///         return http::HTTPStatus::FORBIDDEN.into();
+///     };
+///     // resolved on a runtime of helper threads
+///     match RUNTIME.spawn(check_token(token)).await {
+///         Ok(true) => Status::NGX_OK,
+///         _ => http::HTTPStatus::FORBIDDEN.into(),
+///     }
+/// });
+/// ```
+#[macro_export]
+macro_rules! http_async_handler {
+    ( $name: ident, $handler: expr ) => {
+        #[no_mangle]
+        extern "C" fn $name(r: *mut ngx_http_request_t) -> ngx_int_t {
+            let handler = $handler;
+            unsafe { $crate::http::ngx_http_async_handler(r, $name as *const () as usize, handler) }
+        }
+    };
+}
+
+/// Runs the future returned by `handler` as the phase handler of `r`.
+///
+/// The future is polled on the worker's event loop. If it does not complete right away, the
+/// request is suspended with `NGX_DONE`, keeping it alive, and the future is polled again each
+/// time it is woken, from the event loop or from any other thread. Once it completes, the phases
+/// of the request are resumed and the handler returns the status of the future.
+///
+/// This works for the handlers of all phases that can be suspended, including content handlers.
+/// If the request is terminated first, e.g. because the client closed the connection, the future
+/// is dropped without completing.
+///
+/// The future accesses the request through an [`AsyncRequest`], and must not finalize the
+/// request itself.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_http_request_t` pointer, and calls this from a
+/// phase handler identified by `key`, usually its address.
+pub unsafe fn ngx_http_async_handler<F, Fut>(r: *mut ngx_http_request_t, key: usize, handler: F) -> ngx_int_t
+where
+    F: FnOnce(AsyncRequest) -> Fut,
+    Fut: Future<Output = Status> + 'static,
+{
+    let task = match find_task(r, key) {
+        Some(task) if (*task).future.is_some() => {
+            // the phases were run while the future is pending
+            suspend(r, task);
+            return Status::NGX_DONE.0;
+        }
+        Some(task) => match (*task).result.take() {
+            Some(status) => return status.0,
+            // the request runs the phase again, e.g. after an internal redirect
+            None => task,
+        },
+        None => match create_task(r, key) {
+            Some(task) => task,
+            None => return Status::NGX_ERROR.0,
+        },
+    };
+
+    let request = AsyncRequest {
+        request: r,
+        polling: (*task).polling.clone(),
+    };
+    (*task).future = Some(Box::pin(handler(request)));
+
+    match poll_task(task) {
+        Some(status) => status.0,
+        None => {
+            suspend(r, task);
+            Status::NGX_DONE.0
+        }
+    }
+}
+
+/// The request of an asynchronous handler, see [`http_async_handler!`](crate::http_async_handler).
+///
+/// The request is only accessible while the future of the handler is polled, with
+/// [`AsyncRequest::with`]. References to the request and to its data, e.g. header values, cannot
+/// outlive the closure: the data needed across an `.await` is copied out. The handle cannot be
+/// sent to other threads, and accessing the request fails if the handle is used outside of the
+/// future, e.g. after the future completes.
+pub struct AsyncRequest {
+    request: *mut ngx_http_request_t,
+    polling: Rc<Cell<bool>>,
+}
+
+impl AsyncRequest {
+    /// Calls `f` with the request.
+    ///
+    /// Returns `None` if the future of the handler is not being polled, e.g. if the handle was
+    /// moved out of the future, or if the request is terminated.
+    pub fn with<R, F: FnOnce(&mut Request) -> R>(&mut self, f: F) -> Option<R> {
+        // SAFETY: the request is alive while its task is polled, and not otherwise borrowed
+        self.polling
+            .get()
+            .then(|| f(unsafe { Request::from_ngx_http_request(self.request) }))
+    }
+
+    /// Returns `true` if the request is accessible with [`AsyncRequest::with`].
+    pub fn is_accessible(&self) -> bool {
+        self.polling.get()
+    }
+}
+
+struct AsyncTask {
+    request: *mut ngx_http_request_t,
+    future: Option<Pin<Box<dyn Future<Output = Status>>>>,
+    result: Option<Status>,
+    /// `true` while the future is polled, allowing [`AsyncRequest::with`].
+    polling: Rc<Cell<bool>>,
+    /// `true` if the task keeps a reference to the main request, to release when resuming.
+    holds_count: bool,
+    event: ngx_event_t,
+    waker: Arc<TaskWaker>,
+}
+
+impl Drop for AsyncTask {
+    fn drop(&mut self) {
+        self.waker.task.store(0, Ordering::Release);
+        if self.event.posted() != 0 {
+            unsafe { ngx_delete_posted_event(&mut self.event) };
+        }
+    }
+}
+
+/// Waker of an [`AsyncTask`], which may outlive the task on other threads.
+struct TaskWaker {
+    /// Address of the task, or 0 once the task is dropped.
+    task: AtomicUsize,
+    thread: ThreadId,
+    /// `true` if the waker is queued in the wake channel.
+    queued: AtomicBool,
+    sender: Sender<Arc<TaskWaker>>,
+}
+
+impl TaskWaker {
+    /// Posts the event of the task, if it is still alive.
+    ///
+    /// # Safety
+    ///
+    /// Must be called on the event loop thread.
+    unsafe fn post(&self) {
+        let task = self.task.load(Ordering::Acquire) as *mut AsyncTask;
+        if !task.is_null() {
+            ngx_post_event(addr_of_mut!((*task).event), addr_of_mut!(ngx_posted_events));
+        }
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if thread::current().id() == self.thread {
+            unsafe { self.post() };
+        } else if !self.queued.swap(true, Ordering::AcqRel) && self.sender.send(self.clone()).is_err() {
+            self.queued.store(false, Ordering::Release);
+        }
+    }
+}
+
+type WakeChannel = (Sender<Arc<TaskWaker>>, Receiver<Arc<TaskWaker>>);
+
+thread_local! {
+    // never dropped: the worker exits without tearing down its event loop
+    static WAKE_CHANNEL: ManuallyDrop<RefCell<Option<WakeChannel>>> = const {
+        ManuallyDrop::new(RefCell::new(None))
+    };
+}
+
+/// Returns a sender to the wake channel of the worker, creating the channel on first use.
+fn wake_sender() -> Option<Sender<Arc<TaskWaker>>> {
+    WAKE_CHANNEL.with(|wake_channel| {
+        let mut wake_channel = wake_channel.borrow_mut();
+        if wake_channel.is_none() {
+            *wake_channel = channel(WAKE_CHANNEL_CAPACITY, |waker: Arc<TaskWaker>| {
+                waker.queued.store(false, Ordering::Release);
+                unsafe { waker.post() };
+            })
+            .ok();
+        }
+        wake_channel.as_ref().map(|(sender, _)| sender.clone())
+    })
+}
+
+/// The tasks of a request, by the key of their phase handler, attached to the request pool for
+/// the request.
+type AsyncTasks = HashMap<usize, Box<AsyncTask>>;
+
+unsafe fn find_task(r: *mut ngx_http_request_t, key: usize) -> Option<*mut AsyncTask> {
+    let tasks = Pool::from_ngx_pool((*r).pool).data::<AsyncTasks>(r as usize)?;
+    (*tasks).get_mut(&key).map(|task| &mut **task as *mut AsyncTask)
+}
+
+unsafe fn create_task(r: *mut ngx_http_request_t, key: usize) -> Option<*mut AsyncTask> {
+    let sender = wake_sender()?;
+
+    let mut pool = Pool::from_ngx_pool((*r).pool);
+    let tasks = match pool.data::<AsyncTasks>(r as usize) {
+        Some(tasks) => tasks,
+        None => pool.set_data(r as usize, AsyncTasks::new())?,
+    };
+
+    let task = Box::new(AsyncTask {
+        request: r,
+        future: None,
+        result: None,
+        polling: Rc::new(Cell::new(false)),
+        holds_count: false,
+        // SAFETY: all-zero bits are a valid inactive event
+        event: mem::zeroed(),
+        waker: Arc::new(TaskWaker {
+            task: AtomicUsize::new(0),
+            thread: thread::current().id(),
+            queued: AtomicBool::new(false),
+            sender,
+        }),
+    });
+    // the task is boxed, so its address is stable for the waker and the event
+    (*tasks).insert(key, task);
+    let task = &mut **(*tasks).get_mut(&key)? as *mut AsyncTask;
+    (*task).waker.task.store(task as usize, Ordering::Release);
+    (*task).event.handler = Some(async_task_event_handler);
+    (*task).event.data = task as *mut c_void;
+    (*task).event.log = (*(*r).connection).log;
+
+    Some(task)
+}
+
+/// Polls the future of the task, returning its status once it completes.
+unsafe fn poll_task(task: *mut AsyncTask) -> Option<Status> {
+    let future = (*task).future.as_mut()?;
+    let waker = Waker::from((*task).waker.clone());
+
+    (*task).polling.set(true);
+    let poll = future.as_mut().poll(&mut Context::from_waker(&waker));
+    (*task).polling.set(false);
+
+    match poll {
+        Poll::Ready(status) => {
+            (*task).future = None;
+            Some(status)
+        }
+        Poll::Pending => None,
+    }
+}
+
+/// Keeps the main request alive while the task is pending.
+unsafe fn suspend(r: *mut ngx_http_request_t, task: *mut AsyncTask) {
+    // the content phase finalizes the request with `NGX_DONE`, which releases a reference
+    if !in_content_phase(r) {
+        if (*task).holds_count {
+            return;
+        }
+        (*task).holds_count = true;
+    }
+
+    let main = (*r).main;
+    (*main).set_count((*main).count() + 1);
+}
+
+unsafe fn in_content_phase(r: *mut ngx_http_request_t) -> bool {
+    let cmcf = *(*r).main_conf.add((*addr_of!(ngx_http_core_module)).ctx_index) as *mut ngx_http_core_main_conf_t;
+    let engine = &(*cmcf).phase_engine;
+    if engine.handlers.is_null() || (*r).phase_handler < 0 {
+        return false;
+    }
+
+    let checker = (*engine.handlers.offset((*r).phase_handler)).checker;
+    checker.map(|c| c as usize) == Some(ngx_http_core_content_phase as *const () as usize)
+}
+
+unsafe extern "C" fn async_task_event_handler(ev: *mut ngx_event_t) {
+    let task = (*ev).data as *mut AsyncTask;
+    let r = (*task).request;
+    let c = (*r).connection;
+
+    let Some(status) = poll_task(task) else {
+        return;
+    };
+
+    (*task).result = Some(status);
+    if mem::take(&mut (*task).holds_count) {
+        let main = (*r).main;
+        (*main).set_count((*main).count() - 1);
+    }
+
+    // the phase handler is called again and returns the result
+    ngx_http_core_run_phases(r);
+    ngx_http_run_posted_requests(c);
+}
@@ -0,0 +1,77 @@
+use crate::core::NgxStr;
+use crate::http::Request;
+
+use std::os::raw::c_int;
+use std::slice;
+
+/// The capture groups of the last regular expression matched for a request, usually the regex
+/// location selected for it.
+///
+/// A content handler of a location such as `location ~ ^/users/(\d+)/posts/(?<post>\d+)$` reads
+/// the path parameters without matching the URI again:
+///
+/// ```rust,ignore
+/// let captures = request.captures();
+/// let (Some(user), Some(post)) = (captures.get(1), captures.get(2)) else {
+///     return HTTPStatus::NOT_FOUND.into();
+/// };
+/// ```
+///
+/// Named groups are numbered like the other groups, and are also available as variables, e.g.
+/// `$post`. The captures are replaced by the next regular expression matched for the request,
+/// e.g. by the `rewrite` or `if` directives, or by a [`Condition`](crate::http::Condition).
+#[derive(Clone, Copy, Debug)]
+pub struct Captures<'a> {
+    data: *const u8,
+    offsets: &'a [c_int],
+}
+
+impl<'a> Captures<'a> {
+    /// Returns the number of groups, including the whole match, or 0 if nothing was matched.
+    pub fn len(&self) -> usize {
+        self.offsets.len() / 2
+    }
+
+    /// Returns `true` if no regular expression was matched, or if its captures were not kept.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Returns the group `index`, or the whole match with 0.
+    ///
+    /// Returns `None` if there is no such group, or if the group did not participate in the
+    /// match, e.g. an unmatched alternative.
+    pub fn get(&self, index: usize) -> Option<&'a NgxStr> {
+        let (start, end) = (*self.offsets.get(2 * index)?, *self.offsets.get(2 * index + 1)?);
+        if start < 0 || end < start {
+            return None;
+        }
+        // SAFETY: the offsets were returned by the match of the captured data
+        let bytes = unsafe { slice::from_raw_parts(self.data.add(start as usize), (end - start) as usize) };
+        Some(bytes.into())
+    }
+
+    /// Returns an iterator over the groups, starting with the whole match.
+    pub fn iter(&self) -> impl Iterator<Item = Option<&'a NgxStr>> + '_ {
+        (0..self.len()).map(|index| self.get(index))
+    }
+}
+
+impl Request {
+    /// Returns the capture groups of the last regular expression matched for the request, see
+    /// [`Captures`].
+    pub fn captures(&self) -> Captures<'_> {
+        let r = self.get_inner();
+        if r.captures.is_null() || r.captures_data.is_null() || r.ncaptures == 0 {
+            return Captures {
+                data: r.captures_data,
+                offsets: &[],
+            };
+        }
+        Captures {
+            data: r.captures_data,
+            // SAFETY: `ncaptures` offsets are set by `ngx_http_regex_exec` in the request pool
+            offsets: unsafe { slice::from_raw_parts(r.captures, r.ncaptures as usize) },
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::core::{ConfError, NgxStr, Status};
+use crate::ffi::*;
+use crate::http::Request;
+use crate::ngx_null_string;
+
+use std::mem;
+use std::ptr::NonNull;
+
+/// A directive argument that may contain variables, compiled at configuration time and evaluated
+/// for each request, e.g. `my_header "$remote_addr-$request_id";`.
+///
+/// ```rust,ignore
+/// struct MyHeader;
+///
+/// impl Directive for MyHeader {
+///     type Conf = LocConf;
+///     type Args<'a> = Args<'a>;
+///
+///     fn set(cf: &mut ngx_conf_t, conf: &mut LocConf, args: Args<'_>) -> Result<ConfOk, ConfError> {
+///         conf.header = Some(ComplexValue::compile(cf, args.require(0)?)?);
+///         Ok(ConfOk::Applied)
+///     }
+/// }
+///
+/// // in the handler
+/// if let Some(header) = conf.header {
+///     let value = header.evaluate(request)?.to_string_lossy().into_owned();
+///     request.add_header_out("X-My-Header", &value).map_err(|_| Status::NGX_ERROR)?;
+/// }
+/// ```
+///
+/// The compiled value is allocated from the configuration pool, so it can be copied freely
+/// between configuration levels, e.g. when merging.
+#[derive(Clone, Copy, Debug)]
+pub struct ComplexValue(NonNull<ngx_http_complex_value_t>);
+
+impl ComplexValue {
+    /// Compiles `value`, resolving the variables it refers to.
+    ///
+    /// Unknown variables are reported by NGINX, and fail the configuration.
+    pub fn compile(cf: &mut ngx_conf_t, value: &NgxStr) -> Result<Self, ConfError> {
+        let cv = unsafe { ngx_pcalloc(cf.pool, mem::size_of::<ngx_http_complex_value_t>()) };
+        let cv = NonNull::new(cv as *mut ngx_http_complex_value_t).ok_or_else(|| ConfError::new("out of memory"))?;
+
+        let value = value.as_bytes();
+        let mut value = ngx_str_t {
+            len: value.len(),
+            data: value.as_ptr() as *mut u_char,
+        };
+        // SAFETY: all-zero bits are valid compilation options
+        let mut ccv: ngx_http_compile_complex_value_t = unsafe { mem::zeroed() };
+        ccv.cf = cf;
+        ccv.value = &mut value;
+        ccv.complex_value = cv.as_ptr();
+
+        if unsafe { ngx_http_compile_complex_value(&mut ccv) } != NGX_OK as ngx_int_t {
+            return Err(ConfError::new("invalid value"));
+        }
+        Ok(ComplexValue(cv))
+    }
+
+    /// Returns the value if it contains no variables, e.g. to check it at configuration time.
+    pub fn as_constant(&self) -> Option<&NgxStr> {
+        let cv = unsafe { self.0.as_ref() };
+        // SAFETY: the value is allocated from the configuration pool
+        cv.lengths.is_null().then(|| unsafe { NgxStr::from_ngx_str(cv.value) })
+    }
+
+    /// Evaluates the value for `request`.
+    ///
+    /// Returns an error status if a variable cannot be evaluated. The value is allocated from the
+    /// request pool, unless it is constant.
+    pub fn evaluate<'r>(&self, request: &'r mut Request) -> Result<&'r NgxStr, Status> {
+        // SAFETY: the request is valid, and the value lives as long as the request pool
+        unsafe {
+            let value = self.evaluate_raw(request.into())?;
+            Ok(NgxStr::from_ngx_str(value))
+        }
+    }
+
+    /// Returns the raw pointer to the compiled value.
+    pub fn as_ptr(&self) -> *mut ngx_http_complex_value_t {
+        self.0.as_ptr()
+    }
+
+    /// Evaluates the value for the request `r`, as [`ComplexValue::evaluate`].
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_http_request_t` pointer, which is not
+    /// otherwise borrowed while the value is evaluated.
+    pub(crate) unsafe fn evaluate_raw(&self, r: *mut ngx_http_request_t) -> Result<ngx_str_t, Status> {
+        let mut value = ngx_null_string!();
+        match ngx_http_complex_value(r, self.0.as_ptr(), &mut value) {
+            rc if rc == NGX_OK as ngx_int_t => Ok(value),
+            rc => Err(Status(rc)),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::core::{Args, ConfError, NgxStr, Status};
+use crate::ffi::*;
+use crate::http::{ComplexValue, Request};
+
+#[cfg(feature = "regex")]
+use std::mem;
+#[cfg(feature = "regex")]
+use std::ptr::NonNull;
+
+/// A condition evaluated for each request, compiled from directive arguments with the syntax of
+/// the [`if`] directive.
+///
+/// This gives modules a standard way to be enabled for a subset of requests, e.g. with a
+/// `my_module_enable_if $http_x_debug ~ ^1$;` directive, instead of users wrapping the module
+/// directives in `if` blocks. The supported forms are:
+///
+/// - `$value`: true if the value is neither empty nor `0`;
+/// - `$value = string` and `$value != string`: comparison with a string;
+/// - `$value ~ regex`, `~*` (case-insensitive), `!~` and `!~*`: matching with a regular
+///   expression, setting the `$1`..`$9` and named captures on a match. Requires the `regex`
+///   feature.
+///
+/// Both sides may contain variables.
+///
+/// ```rust,ignore
+/// struct EnableIf;
+///
+/// impl Directive for EnableIf {
+///     type Conf = LocConf;
+///     type Args<'a> = Args<'a>;
+///
+///     fn set(cf: &mut ngx_conf_t, conf: &mut LocConf, args: Args<'_>) -> Result<ConfOk, ConfError> {
+///         conf.enable_if = Some(Condition::compile(cf, args)?);
+///         Ok(ConfOk::Applied)
+///     }
+/// }
+///
+/// // in the handler
+/// match conf.enable_if.map(|cond| cond.evaluate(request)) {
+///     None | Some(Ok(true)) => {}
+///     Some(Ok(false)) => return Status::NGX_DECLINED,
+///     Some(Err(rc)) => return rc,
+/// }
+/// ```
+///
+/// The compiled condition is allocated from the configuration pool, so it can be copied freely
+/// between configuration levels, e.g. when merging.
+///
+/// [`if`]: https://nginx.org/en/docs/http/ngx_http_rewrite_module.html#if
+#[derive(Clone, Copy, Debug)]
+pub struct Condition {
+    value: ComplexValue,
+    op: ConditionOp,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ConditionOp {
+    NotEmpty,
+    Equal {
+        other: ComplexValue,
+        negate: bool,
+    },
+    #[cfg(feature = "regex")]
+    Match {
+        regex: NonNull<ngx_http_regex_t>,
+        negate: bool,
+    },
+}
+
+impl Condition {
+    /// Compiles a condition from one or three directive arguments.
+    pub fn compile(cf: &mut ngx_conf_t, args: Args<'_>) -> Result<Self, ConfError> {
+        if args.len() != 1 && args.len() != 3 {
+            return Err(ConfError::new("invalid number of arguments in condition"));
+        }
+
+        let value = ComplexValue::compile(cf, args.require(0)?).map_err(|err| err.with_arg(0))?;
+        let Some(op) = args.arg(1) else {
+            return Ok(Condition {
+                value,
+                op: ConditionOp::NotEmpty,
+            });
+        };
+        let operand = args.require(2)?;
+
+        let op = match op.as_bytes() {
+            b"=" | b"!=" => ConditionOp::Equal {
+                other: ComplexValue::compile(cf, operand).map_err(|err| err.with_arg(2))?,
+                negate: op.as_bytes() == b"!=",
+            },
+            #[cfg(feature = "regex")]
+            b"~" | b"~*" | b"!~" | b"!~*" => ConditionOp::Match {
+                regex: compile_regex(cf, operand, op.as_bytes().ends_with(b"*")).map_err(|err| err.with_arg(2))?,
+                negate: op.as_bytes().starts_with(b"!"),
+            },
+            #[cfg(not(feature = "regex"))]
+            b"~" | b"~*" | b"!~" | b"!~*" => {
+                return Err(ConfError::new("regular expressions require the \"regex\" feature").with_arg(1))
+            }
+            _ => return Err(ConfError::new(format!("unexpected operator \"{op}\"")).with_arg(1)),
+        };
+
+        Ok(Condition { value, op })
+    }
+
+    /// Evaluates the condition for `request`.
+    ///
+    /// Returns an error status if a variable or the regular expression cannot be evaluated.
+    pub fn evaluate(&self, request: &mut Request) -> Result<bool, Status> {
+        // SAFETY: the request is valid, and the value is allocated from the request pool
+        let mut value = unsafe { self.value.evaluate_raw(request.into()) }?;
+        let bytes = unsafe { NgxStr::from_ngx_str(value) }.as_bytes();
+
+        match self.op {
+            ConditionOp::NotEmpty => Ok(!bytes.is_empty() && bytes != b"0"),
+            ConditionOp::Equal { other, negate } => {
+                let other = unsafe { other.evaluate_raw(request.into()) }?;
+                Ok((bytes == unsafe { NgxStr::from_ngx_str(other) }.as_bytes()) != negate)
+            }
+            #[cfg(feature = "regex")]
+            ConditionOp::Match { regex, negate } => {
+                match unsafe { ngx_http_regex_exec(request.into(), regex.as_ptr(), &mut value) } {
+                    rc if rc == NGX_OK as ngx_int_t => Ok(!negate),
+                    rc if rc == NGX_DECLINED as ngx_int_t => Ok(negate),
+                    rc => Err(Status(rc)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "regex")]
+fn compile_regex(
+    cf: &mut ngx_conf_t,
+    pattern: &NgxStr,
+    caseless: bool,
+) -> Result<NonNull<ngx_http_regex_t>, ConfError> {
+    let mut errstr = [0u8; NGX_MAX_CONF_ERRSTR as usize];
+    // SAFETY: all-zero bits are valid compilation options
+    let mut rc: ngx_regex_compile_t = unsafe { mem::zeroed() };
+    let pattern = pattern.as_bytes();
+    rc.pattern = ngx_str_t {
+        len: pattern.len(),
+        data: pattern.as_ptr() as *mut u_char,
+    };
+    rc.pool = cf.pool;
+    rc.err = ngx_str_t {
+        len: errstr.len(),
+        data: errstr.as_mut_ptr(),
+    };
+    if caseless {
+        rc.options = NGX_REGEX_CASELESS as ngx_int_t;
+    }
+
+    // the compilation error is logged by NGINX
+    let regex = unsafe { ngx_http_regex_compile(cf, &mut rc) };
+    NonNull::new(regex).ok_or_else(|| ConfError::new("invalid regular expression"))
+}
@@ -1,6 +1,8 @@
+use crate::core::{ConfError, CycleLocal};
 use crate::ffi::*;
 
 use std::os::raw::c_void;
+use std::ptr::addr_of;
 
 /// # Safety
 ///
@@ -61,3 +63,149 @@ pub unsafe fn ngx_http_conf_upstream_srv_conf_mutable<T>(
     }
     Some(*(*us).srv_conf.add(module.ctx_index) as *mut T)
 }
+
+/// Addresses of the handlers and filters registered for each live configuration cycle.
+static REGISTERED: CycleLocal<Vec<usize>> = CycleLocal::new();
+
+/// Records the registration of `handler` for the cycle being configured.
+///
+/// Returns an error if the same function was already registered for this cycle, which would make
+/// it run twice for each request, or loop forever for a filter linked to itself.
+pub(crate) unsafe fn register_once(cf: *mut ngx_conf_t, handler: usize, kind: &str) -> Result<(), ConfError> {
+    let added = REGISTERED.with_mut((*cf).cycle, |handlers| {
+        if handlers.contains(&handler) {
+            return false;
+        }
+        handlers.push(handler);
+        true
+    })?;
+
+    if !added {
+        return Err(ConfError::new(format!("{} {:#x} is already registered", kind, handler)));
+    }
+    Ok(())
+}
+
+/// Adds `handler` to the handlers of an HTTP request processing `phase`.
+///
+/// This is meant to be called from the `postconfiguration` handler of a module. Registering the
+/// same handler twice for a configuration cycle, e.g. because the postconfiguration code is
+/// shared between modules, fails with an error instead of running the handler twice per request.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer within the `http` block.
+pub unsafe fn ngx_http_add_phase_handler(
+    cf: *mut ngx_conf_t,
+    phase: ngx_http_phases,
+    handler: unsafe extern "C" fn(*mut ngx_http_request_t) -> ngx_int_t,
+) -> Result<(), ConfError> {
+    register_once(cf, handler as usize, "phase handler")?;
+
+    let cmcf = ngx_http_conf_get_module_main_conf(cf, &*addr_of!(ngx_http_core_module));
+    let h = ngx_array_push(&mut (*cmcf).phases[phase as usize].handlers) as *mut ngx_http_handler_pt;
+    if h.is_null() {
+        return Err(ConfError::new("failed to add phase handler"));
+    }
+    *h = Some(handler);
+    Ok(())
+}
+
+/// Inserts `filter` at the top of the header filter chain, storing the previous top filter in
+/// `next`.
+///
+/// Registering the same filter twice for a configuration cycle fails with an error: the second
+/// registration would link the filter to itself.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer and a valid `next` pointer, and
+/// calls this from the `postconfiguration` handler of an HTTP module.
+pub unsafe fn ngx_http_add_header_filter(
+    cf: *mut ngx_conf_t,
+    filter: unsafe extern "C" fn(*mut ngx_http_request_t) -> ngx_int_t,
+    next: *mut ngx_http_output_header_filter_pt,
+) -> Result<(), ConfError> {
+    register_once(cf, filter as usize, "header filter")?;
+
+    *next = ngx_http_top_header_filter;
+    ngx_http_top_header_filter = Some(filter);
+    Ok(())
+}
+
+/// Inserts `filter` at the top of the body filter chain, storing the previous top filter in
+/// `next`.
+///
+/// Registering the same filter twice for a configuration cycle fails with an error: the second
+/// registration would link the filter to itself.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer and a valid `next` pointer, and
+/// calls this from the `postconfiguration` handler of an HTTP module.
+pub unsafe fn ngx_http_add_body_filter(
+    cf: *mut ngx_conf_t,
+    filter: unsafe extern "C" fn(*mut ngx_http_request_t, *mut ngx_chain_t) -> ngx_int_t,
+    next: *mut ngx_http_output_body_filter_pt,
+) -> Result<(), ConfError> {
+    register_once(cf, filter as usize, "body filter")?;
+
+    *next = ngx_http_top_body_filter;
+    ngx_http_top_body_filter = Some(filter);
+    Ok(())
+}
+
+/// Inserts `filter` at the top of the request body filter chain, storing the previous top filter
+/// in `next`.
+///
+/// Request body filters process the body as it is read from the client, before it is buffered
+/// in memory or to a temporary file. Registering the same filter twice for a configuration cycle
+/// fails with an error: the second registration would link the filter to itself.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer and a valid `next` pointer, and
+/// calls this from the `postconfiguration` handler of an HTTP module.
+pub unsafe fn ngx_http_add_request_body_filter(
+    cf: *mut ngx_conf_t,
+    filter: unsafe extern "C" fn(*mut ngx_http_request_t, *mut ngx_chain_t) -> ngx_int_t,
+    next: *mut ngx_http_request_body_filter_pt,
+) -> Result<(), ConfError> {
+    register_once(cf, filter as usize, "request body filter")?;
+
+    *next = ngx_http_top_request_body_filter;
+    ngx_http_top_request_body_filter = Some(filter);
+    Ok(())
+}
+
+/// Sets `handler` as the content handler of the `location` block being parsed.
+///
+/// This is meant to be called from the handler of a directive in the `location` context, the way
+/// `proxy_pass` or `empty_gif` do. The content handler replaces the content phase handlers for
+/// the requests of the location, including the static file handler:
+///
+/// ```rust,ignore
+/// http_request_handler!(hello_handler, |request: &mut Request| {
+///     request.send_response(HTTPStatus::OK, &[("Content-Type", "text/plain")], b"hello\n")
+/// });
+///
+/// fn set(cf: &mut ngx_conf_t, _conf: &mut LocConf, _args: ()) -> Result<ConfOk, ConfError> {
+///     unsafe { ngx_http_set_content_handler(cf, hello_handler) }.map(ConfOk::from)
+/// }
+/// ```
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer within a `location` block of
+/// the `http` block.
+pub unsafe fn ngx_http_set_content_handler(
+    cf: *mut ngx_conf_t,
+    handler: unsafe extern "C" fn(*mut ngx_http_request_t) -> ngx_int_t,
+) -> Result<(), ConfError> {
+    let clcf = ngx_http_conf_get_module_loc_conf(cf, &*addr_of!(ngx_http_core_module));
+    if (*clcf).handler.is_some() {
+        return Err(ConfError::new("the location already has a content handler"));
+    }
+    (*clcf).handler = Some(handler);
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use crate::core::{chain_slices, Buffer, Pool, Status};
+use crate::ffi::*;
+use crate::http::{BodyChain, NextBodyFilter, NextHeaderFilter, Request, RequestError};
+
+use std::ptr;
+
+/// How the response body is delimited once a filter changes its length, as returned by
+/// [`Request::prepare_length_change`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyLengthMode {
+    /// The body is sent as it is produced, delimited by the chunked transfer encoding for
+    /// HTTP/1.1, or by the framing of HTTP/2 and HTTP/3. The header filter calls the next
+    /// header filter as usual.
+    Streamed,
+    /// The body is held back until complete to send its new length, for HTTP/1.0 clients that
+    /// would otherwise lose the keepalive connection. The header filter does not call the next
+    /// header filter, and the body filter sends its output with a [`LengthBuffer`].
+    Buffered,
+    /// The end of the body is signaled by closing the connection, for HTTP/1.0 clients or with
+    /// `chunked_transfer_encoding off`. The header filter calls the next header filter as usual.
+    CloseDelimited,
+}
+
+impl Request {
+    /// Returns `true` if a response body of unknown length can be sent without closing the
+    /// connection, i.e. with HTTP/2, HTTP/3, or with the chunked encoding of HTTP/1.1.
+    pub fn supports_unknown_length(&self) -> bool {
+        let r = self.get_inner();
+        let version = r.http_version as u32;
+        if version >= NGX_HTTP_VERSION_20 {
+            return true;
+        }
+        if version < NGX_HTTP_VERSION_11 {
+            return false;
+        }
+        unsafe {
+            let clcf = *r.loc_conf.add(ngx_http_core_module.ctx_index) as *mut ngx_http_core_loc_conf_t;
+            (*clcf).chunked_transfer_encoding != 0
+        }
+    }
+
+    /// Prepares the response header in the header filter of a filter changing the length of the
+    /// body, e.g. compressing or rewriting it, and returns how the body is delimited.
+    ///
+    /// The `Content-Length` and `Accept-Ranges` headers are removed, as the new length is not
+    /// known yet and byte ranges of the original body do not apply, and a strong `ETag` becomes
+    /// weak. Responses of unknown length are then [streamed](BodyLengthMode::Streamed) when the
+    /// protocol allows it. Otherwise, a response whose original length is at most
+    /// `max_buffered` bytes is [buffered](BodyLengthMode::Buffered), and others are
+    /// [delimited by closing the connection](BodyLengthMode::CloseDelimited):
+    ///
+    /// ```rust,ignore
+    /// unsafe extern "C" fn my_header_filter(r: *mut ngx_http_request_t) -> ngx_int_t {
+    ///     let request = Request::from_ngx_http_request(r);
+    ///     // ...
+    ///     let Ok(mode) = request.prepare_length_change(64 * 1024) else {
+    ///         return NGX_ERROR as ngx_int_t;
+    ///     };
+    ///     ctx.length = (mode == BodyLengthMode::Buffered).then(|| LengthBuffer::new(64 * 1024));
+    ///     match mode {
+    ///         BodyLengthMode::Buffered => NGX_OK as ngx_int_t,
+    ///         _ => next_header_filter(r),
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Fails if the response header has already been sent.
+    pub fn prepare_length_change(&mut self, max_buffered: u64) -> Result<BodyLengthMode, RequestError> {
+        let length = self.get_inner().headers_out.content_length_n;
+        self.set_content_length(None)?;
+
+        let r: *mut ngx_http_request_t = (&mut *self).into();
+        unsafe {
+            // equivalent of the `ngx_http_clear_accept_ranges` macro
+            (*r).set_allow_ranges(0);
+            if let Some(h) = (*r).headers_out.accept_ranges.as_mut() {
+                h.hash = 0;
+                (*r).headers_out.accept_ranges = ptr::null_mut();
+            }
+            ngx_http_weak_etag(r);
+        }
+
+        if self.supports_unknown_length() {
+            return Ok(BodyLengthMode::Streamed);
+        }
+        if length >= 0 && length as u64 <= max_buffered {
+            return Ok(BodyLengthMode::Buffered);
+        }
+        unsafe { (*r).set_keepalive(0) };
+        Ok(BodyLengthMode::CloseDelimited)
+    }
+}
+
+/// The output of a body filter in [`BodyLengthMode::Buffered`] mode, held back until complete.
+///
+/// The body filter passes its transformed output to [`LengthBuffer::body_filter`] instead of the
+/// next body filter. Once the last buffer is seen, the header is sent with the `Content-Length`
+/// of the buffered body, followed by the body. If the output grows beyond the size limit, the
+/// response falls back to [`BodyLengthMode::CloseDelimited`]: the header is sent without a
+/// length, followed by the output buffered so far and the rest of the body as it comes.
+#[derive(Debug)]
+pub struct LengthBuffer {
+    body: Option<Vec<u8>>,
+    max_size: usize,
+}
+
+impl LengthBuffer {
+    /// Creates a buffer holding up to `max_size` bytes of output.
+    pub fn new(max_size: usize) -> Self {
+        LengthBuffer {
+            body: Some(Vec::new()),
+            max_size,
+        }
+    }
+
+    /// Returns `true` once the header has been sent, after which the output passes through.
+    pub fn is_flushed(&self) -> bool {
+        self.body.is_none()
+    }
+
+    /// Returns the size of the output buffered so far.
+    pub fn buffered_size(&self) -> usize {
+        self.body.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Buffers or sends the output `chain` of a [`BodyFilter`], with the next filters of the
+    /// header and body filters of the module.
+    ///
+    /// The response body must be in memory, e.g. with `filter_need_in_memory` set by the header
+    /// filter, as buffers in files are not buffered.
+    ///
+    /// [`BodyFilter`]: crate::http::BodyFilter
+    pub fn filter(
+        &mut self,
+        request: &mut Request,
+        chain: BodyChain<'_>,
+        next_header: &NextHeaderFilter,
+        next_body: &NextBodyFilter,
+    ) -> Status {
+        let r: *mut ngx_http_request_t = (&mut *request).into();
+        Status(unsafe { self.body_filter(r, chain.as_ptr(), next_header.get(), next_body.get()) })
+    }
+
+    /// Buffers or sends the output `chain` of the body filter.
+    ///
+    /// # Safety
+    ///
+    /// Called from a body filter with a valid non-null `ngx_http_request_t` pointer, a valid
+    /// chain of in-memory buffers, and the next header and body filters.
+    pub unsafe fn body_filter(
+        &mut self,
+        r: *mut ngx_http_request_t,
+        chain: *mut ngx_chain_t,
+        next_header: ngx_http_output_header_filter_pt,
+        next_body: ngx_http_output_body_filter_pt,
+    ) -> ngx_int_t {
+        let (Some(next_header), Some(next_body)) = (next_header, next_body) else {
+            return NGX_ERROR as ngx_int_t;
+        };
+        let Some(body) = self.body.as_mut() else {
+            return next_body(r, chain);
+        };
+
+        let size: usize = chain_slices(chain).map(<[u8]>::len).sum();
+        if body.len() + size > self.max_size {
+            let body = self.body.take().unwrap_or_default();
+            (*r).set_keepalive(0);
+            return flush(r, body, chain, next_header, next_body);
+        }
+
+        for bytes in chain_slices(chain) {
+            body.extend_from_slice(bytes);
+        }
+
+        let mut last = false;
+        let mut cl = chain;
+        while let Some(link) = cl.as_ref() {
+            let b = link.buf;
+            last |= (*b).last_buf() != 0;
+            // the buffered data is sent later, the buffer can be reused
+            (*b).pos = (*b).last;
+            cl = link.next;
+        }
+
+        if !last {
+            return NGX_OK as ngx_int_t;
+        }
+
+        let body = self.body.take().unwrap_or_default();
+        (*r).headers_out.content_length_n = body.len() as off_t;
+        flush(r, body, ptr::null_mut(), next_header, next_body)
+    }
+}
+
+/// Sends the header, then `body` followed by `chain`, or the last buffer if `chain` is null.
+unsafe fn flush(
+    r: *mut ngx_http_request_t,
+    body: Vec<u8>,
+    chain: *mut ngx_chain_t,
+    next_header: unsafe extern "C" fn(*mut ngx_http_request_t) -> ngx_int_t,
+    next_body: unsafe extern "C" fn(*mut ngx_http_request_t, *mut ngx_chain_t) -> ngx_int_t,
+) -> ngx_int_t {
+    let rc = next_header(r);
+    if rc == NGX_ERROR as ngx_int_t || rc > NGX_OK as ngx_int_t || (*r).header_only() != 0 {
+        return rc;
+    }
+
+    if body.is_empty() && !chain.is_null() {
+        return next_body(r, chain);
+    }
+
+    let mut pool = Pool::from_ngx_pool((*r).pool);
+    let buf = if body.is_empty() {
+        pool.calloc_type::<ngx_buf_t>()
+    } else {
+        pool.create_buffer_from_bytes(&body)
+            .map_or(ptr::null_mut(), |mut buf| buf.as_ngx_buf_mut())
+    };
+    if buf.is_null() {
+        return NGX_ERROR as ngx_int_t;
+    }
+    if chain.is_null() {
+        (*buf).set_last_buf(((*r).main == r) as _);
+        (*buf).set_last_in_chain(1);
+    }
+
+    let mut out = ngx_chain_t { buf, next: chain };
+    next_body(r, &mut out)
+}
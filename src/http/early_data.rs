@@ -0,0 +1,170 @@
+use crate::core::{Connection, Status};
+use crate::ffi::*;
+use crate::http::{AsyncRequest, HTTPStatus, Request, RequestTimer};
+
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// Interval at which a delayed request checks whether the handshake is complete.
+const EARLY_DATA_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Replay protection for requests received as TLS 1.3 early data (0-RTT).
+///
+/// Early data can be captured and replayed by an attacker, so requests with unsafe methods,
+/// e.g. `POST`, should only be processed once the handshake is complete. Requests with safe
+/// methods and requests received after the handshake always pass.
+///
+/// The guard either rejects unsafe early data requests with `425 Too Early`, asking the client
+/// to retry after the handshake (RFC 8470), or delays them for a while, waiting for the handshake
+/// to complete. Delaying is mostly effective for HTTP/3, where the handshake progresses
+/// independently of the request; over TCP, NGINX only completes the handshake when it reads
+/// further from the connection.
+///
+/// ```rust,ignore
+/// static GUARD: EarlyDataGuard = EarlyDataGuard::delay(Duration::from_millis(200));
+///
+/// http_async_handler!(early_data_access_handler, |request| GUARD.check(request));
+/// ```
+///
+/// For the rejecting variant, [`ngx_http_early_data_access_handler`] can be added to the access
+/// phase as is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EarlyDataGuard {
+    delay: Option<Duration>,
+}
+
+/// The decision of an [`EarlyDataGuard`] for a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EarlyDataVerdict {
+    /// The request is safe to process.
+    Allow,
+    /// The request must be delayed until the handshake is complete.
+    Delay,
+    /// The request must be rejected with `425 Too Early`.
+    Reject,
+}
+
+impl EarlyDataGuard {
+    /// Creates a guard rejecting unsafe early data requests.
+    pub const fn reject() -> Self {
+        EarlyDataGuard { delay: None }
+    }
+
+    /// Creates a guard delaying unsafe early data requests for at most `max`, and rejecting them
+    /// if the handshake is still not complete.
+    pub const fn delay(max: Duration) -> Self {
+        EarlyDataGuard { delay: Some(max) }
+    }
+
+    /// Returns `true` if the request is received as early data.
+    pub fn is_early_data(request: &Request) -> bool {
+        unsafe { Connection::from_ngx_connection(request.connection()) }.is_early_data()
+    }
+
+    /// Returns the decision of the guard for the current state of the request.
+    pub fn verdict(&self, request: &Request) -> EarlyDataVerdict {
+        if request.method().is_safe() || !Self::is_early_data(request) {
+            EarlyDataVerdict::Allow
+        } else if self.delay.is_some() {
+            EarlyDataVerdict::Delay
+        } else {
+            EarlyDataVerdict::Reject
+        }
+    }
+
+    /// Checks the request, waiting for the handshake if the guard delays requests.
+    ///
+    /// Returns `NGX_DECLINED` to let the other access phase handlers run, or the
+    /// `425 Too Early` status. The future is meant to be run with
+    /// [`ngx_http_async_handler`](crate::http::ngx_http_async_handler).
+    pub async fn check(&self, mut request: AsyncRequest) -> Status {
+        match request.with(|request| self.verdict(request)) {
+            Some(EarlyDataVerdict::Allow) => return Status::NGX_DECLINED,
+            Some(EarlyDataVerdict::Reject) => return HTTPStatus::TOO_EARLY.into(),
+            Some(EarlyDataVerdict::Delay) => {}
+            None => return Status::NGX_ERROR,
+        }
+
+        let deadline = Instant::now() + self.delay.unwrap_or_default();
+        let mut sleep = RequestSleep::default();
+        while request.with(|request| Self::is_early_data(request)) == Some(true) {
+            if Instant::now() >= deadline {
+                return HTTPStatus::TOO_EARLY.into();
+            }
+            match request.with(|request| sleep.arm(request, EARLY_DATA_POLL_INTERVAL)) {
+                Some(Some(())) => (&mut sleep).await,
+                _ => return Status::NGX_ERROR,
+            }
+        }
+
+        Status::NGX_DECLINED
+    }
+}
+
+/// Access phase handler rejecting unsafe requests received as early data with `425 Too Early`.
+///
+/// Add it to the access phase of a module, e.g. with
+/// [`ngx_http_add_phase_handler`](crate::http::ngx_http_add_phase_handler).
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_http_request_t` pointer.
+pub unsafe extern "C" fn ngx_http_early_data_access_handler(r: *mut ngx_http_request_t) -> ngx_int_t {
+    let request = Request::from_ngx_http_request(r);
+
+    match EarlyDataGuard::reject().verdict(request) {
+        EarlyDataVerdict::Allow => Status::NGX_DECLINED.0,
+        _ => HTTPStatus::TOO_EARLY.0 as ngx_int_t,
+    }
+}
+
+/// Future completing after a request timer expires, reusing the timer for each sleep.
+#[derive(Default)]
+struct RequestSleep {
+    timer: Option<RequestTimer>,
+    state: Rc<SleepState>,
+}
+
+#[derive(Default)]
+struct SleepState {
+    fired: Cell<bool>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl RequestSleep {
+    /// Arms the timer of the request for the next sleep.
+    fn arm(&mut self, request: &mut Request, timeout: Duration) -> Option<()> {
+        self.state.fired.set(false);
+
+        let state = self.state.clone();
+        let on_timeout = move |_: &mut Request| {
+            state.fired.set(true);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        };
+
+        match &self.timer {
+            // SAFETY: the timer was armed for the request, which is alive
+            Some(timer) => unsafe { timer.rearm(timeout, on_timeout) },
+            None => self.timer = Some(request.set_module_timeout(timeout, on_timeout)?),
+        }
+        Some(())
+    }
+}
+
+impl Future for &mut RequestSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.state.fired.get() {
+            return Poll::Ready(());
+        }
+        *self.state.waker.borrow_mut() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
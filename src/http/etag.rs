@@ -0,0 +1,67 @@
+pub use ngx_core::{Etag, EtagHasher, EtagPolicy, InvalidEtagPolicy};
+
+use crate::core::{ConfError, FromArg, NgxStr};
+use crate::http::{Request, RequestError};
+
+impl FromArg<'_> for EtagPolicy {
+    fn from_arg(arg: &NgxStr) -> Result<Self, ConfError> {
+        let arg = arg.to_str().map_err(|err| ConfError::from_error(&err))?;
+        arg.parse().map_err(|err| ConfError::from_error(&err))
+    }
+}
+
+impl Request {
+    /// Sets the `ETag` of a response with the body `body` according to `policy`, e.g. in a
+    /// content handler generating the body in memory:
+    ///
+    /// ```rust,ignore
+    /// // my_etag off | mtime | crc32 | sha256;
+    /// impl Directive for EtagDirective {
+    ///     type Conf = LocConf;
+    ///     type Args<'a> = (EtagPolicy,);
+    ///
+    ///     fn set(_cf: &mut ngx_conf_t, conf: &mut LocConf, (policy,): Self::Args<'_>) -> Result<ConfOk, ConfError> {
+    ///         conf.etag = policy;
+    ///         Ok(ConfOk::Applied)
+    ///     }
+    /// }
+    ///
+    /// // in the content handler
+    /// let body = render(request);
+    /// request.set_content_length(Some(body.len() as u64))?;
+    /// request.apply_etag_policy(conf.etag, &body)?;
+    /// ```
+    ///
+    /// With [`EtagPolicy::LengthMtime`], the tag is built from the length of `body` and the
+    /// `Last-Modified` time of the response, and no tag is set if the latter is unknown. Any
+    /// previous `ETag` is replaced, or removed with [`EtagPolicy::Off`].
+    ///
+    /// Fails if the response header has already been sent.
+    pub fn apply_etag_policy(&mut self, policy: EtagPolicy, body: &[u8]) -> Result<(), RequestError> {
+        let mtime = self.get_inner().headers_out.last_modified_time;
+        let etag = policy.etag(body, (mtime >= 0).then_some(mtime as i64));
+        self.set_etag(etag.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::{ngx_http_request_t, ngx_table_elt_t};
+    use std::{mem, ptr};
+
+    #[test]
+    fn test_apply_etag_policy() {
+        let mut etag: ngx_table_elt_t = unsafe { mem::zeroed() };
+        etag.hash = 1;
+        let mut r: ngx_http_request_t = unsafe { mem::zeroed() };
+        r.headers_out.etag = ptr::addr_of_mut!(etag);
+        r.headers_out.last_modified_time = -1;
+
+        // an unknown `Last-Modified` time clears the previous tag
+        let request = unsafe { Request::from_ngx_http_request(&mut r) };
+        assert!(request.apply_etag_policy(EtagPolicy::LengthMtime, b"abc").is_ok());
+        assert!(r.headers_out.etag.is_null());
+        assert_eq!(etag.hash, 0);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::core::{chain_slices, ChainSlices};
+use crate::ffi::*;
+use crate::http::Request;
+
+/// Per-request decision of a body filter on how much of the response body it still inspects.
+///
+/// NGINX filter chains are static, so a filter cannot unlink itself for a single response. The
+/// equivalent is to keep an inspection state in the request context and, once it reports
+/// [`is_passthrough`](BodyInspection::is_passthrough), forward each chain to the next body filter
+/// right away, without walking the buffers:
+///
+/// ```rust,ignore
+/// unsafe extern "C" fn my_body_filter(r: *mut ngx_http_request_t, chain: *mut ngx_chain_t) -> ngx_int_t {
+///     let ctx = get_ctx(r);
+///     if !ctx.inspection.is_passthrough() {
+///         for bytes in ctx.inspection.inspect(chain) {
+///             if ctx.scanner.scan(bytes) {
+///                 // decision made, stop looking at the rest of the body
+///                 ctx.inspection.passthrough();
+///             }
+///         }
+///     }
+///     next_body_filter(r, chain)
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BodyInspection {
+    limit: Option<u64>,
+    inspected: u64,
+    passthrough: bool,
+}
+
+impl BodyInspection {
+    /// Inspects the whole response body, until [`passthrough`](Self::passthrough) is called.
+    pub const fn new() -> Self {
+        BodyInspection {
+            limit: None,
+            inspected: 0,
+            passthrough: false,
+        }
+    }
+
+    /// Inspects at most the first `limit` bytes of the response body.
+    pub const fn with_limit(limit: u64) -> Self {
+        BodyInspection {
+            limit: Some(limit),
+            inspected: 0,
+            passthrough: limit == 0,
+        }
+    }
+
+    /// Stops inspecting: the rest of the body passes through the filter untouched.
+    pub fn passthrough(&mut self) {
+        self.passthrough = true;
+    }
+
+    /// Returns `true` if the filter is no longer interested in the body.
+    pub fn is_passthrough(&self) -> bool {
+        self.passthrough
+    }
+
+    /// Number of body bytes handed out for inspection so far.
+    pub fn inspected(&self) -> u64 {
+        self.inspected
+    }
+
+    /// Returns an iterator over the in-memory contents of `chain` that are still to be inspected.
+    ///
+    /// The last slice is truncated at the inspection limit, after which the state switches to
+    /// passthrough. Nothing is yielded in passthrough mode. File-backed buffers are not
+    /// inspected.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided either a null pointer or a valid `ngx_chain_t` whose links and
+    /// buffers are not modified while the iterator is in use.
+    pub unsafe fn inspect<'a>(&'a mut self, chain: *const ngx_chain_t) -> InspectedSlices<'a> {
+        InspectedSlices {
+            state: self,
+            slices: chain_slices(chain),
+        }
+    }
+}
+
+/// Iterator returned by [`BodyInspection::inspect`].
+pub struct InspectedSlices<'a> {
+    state: &'a mut BodyInspection,
+    slices: ChainSlices<'a>,
+}
+
+impl<'a> Iterator for InspectedSlices<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.state.passthrough {
+            return None;
+        }
+
+        let mut bytes = self.slices.next()?;
+        if let Some(limit) = self.state.limit {
+            let remaining = limit.saturating_sub(self.state.inspected);
+            if bytes.len() as u64 >= remaining {
+                bytes = &bytes[..remaining as usize];
+                self.state.passthrough = true;
+            }
+        }
+
+        self.state.inspected += bytes.len() as u64;
+        Some(bytes)
+    }
+}
+
+/// The standard checks deciding which responses a filter leaves untouched.
+///
+/// Most filters only transform responses sent to the client, and pass others to the next
+/// filter as is. The scope states which responses are skipped, instead of each filter
+/// reimplementing the checks:
+///
+/// ```rust,ignore
+/// const SCOPE: FilterScope = FilterScope::new().main_only().skip_header_only().status_range(200, 299);
+///
+/// unsafe extern "C" fn my_header_filter(r: *mut ngx_http_request_t) -> ngx_int_t {
+///     let request = Request::from_ngx_http_request(r);
+///     if SCOPE.skips(request) {
+///         return next_header_filter(r);
+///     }
+///     // ...
+/// }
+/// ```
+///
+/// The checks depend on the response status and headers, so a body filter usually relies on a
+/// context created by the header filter instead of repeating them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FilterScope {
+    subrequests: bool,
+    internal: bool,
+    header_only: bool,
+    ranges: bool,
+    status: Option<(u16, u16)>,
+}
+
+impl Default for FilterScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FilterScope {
+    /// A scope including all responses.
+    pub const fn new() -> Self {
+        FilterScope {
+            subrequests: true,
+            internal: true,
+            header_only: true,
+            ranges: true,
+            status: None,
+        }
+    }
+
+    /// Skips the responses of subrequests, which are parts of the main response, e.g. SSI
+    /// includes, or are not sent to the client at all, e.g. `auth_request`.
+    pub const fn main_only(mut self) -> Self {
+        self.subrequests = false;
+        self
+    }
+
+    /// Skips the responses of internally redirected requests, e.g. `error_page` or
+    /// `try_files` fallbacks.
+    pub const fn skip_internal(mut self) -> Self {
+        self.internal = false;
+        self
+    }
+
+    /// Skips responses without a body, e.g. for `HEAD` requests or with a `304 Not Modified`
+    /// status.
+    pub const fn skip_header_only(mut self) -> Self {
+        self.header_only = false;
+        self
+    }
+
+    /// Skips partial responses, with a `206 Partial Content` status or to be cut by the range
+    /// filter, as transforming only a part of the body produces a corrupted response.
+    pub const fn skip_ranges(mut self) -> Self {
+        self.ranges = false;
+        self
+    }
+
+    /// Skips responses with a status outside `min..=max`, e.g. `200, 299` for successful
+    /// responses only.
+    pub const fn status_range(mut self, min: u16, max: u16) -> Self {
+        self.status = Some((min, max));
+        self
+    }
+
+    /// Returns `true` if the filter leaves the response of `request` untouched.
+    pub fn skips(&self, request: &Request) -> bool {
+        let r = request.get_inner();
+
+        if !self.subrequests && !request.is_main() {
+            return true;
+        }
+        if !self.internal && r.internal() != 0 {
+            return true;
+        }
+        if !self.header_only && r.header_only() != 0 {
+            return true;
+        }
+
+        let status = r.headers_out.status;
+        if !self.ranges
+            && (status == NGX_HTTP_PARTIAL_CONTENT as ngx_uint_t
+                || (r.allow_ranges() != 0 && !r.headers_in.range.is_null()))
+        {
+            return true;
+        }
+        if let Some((min, max)) = self.status {
+            if status < min as ngx_uint_t || status > max as ngx_uint_t {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{mem, ptr};
+
+    fn memory_buf(data: &mut [u8]) -> ngx_buf_t {
+        let mut b: ngx_buf_t = unsafe { mem::zeroed() };
+        b.pos = data.as_mut_ptr();
+        b.last = unsafe { b.pos.add(data.len()) };
+        b.set_memory(1);
+        b
+    }
+
+    #[test]
+    fn test_inspect_limit() {
+        let (mut d1, mut d2) = (*b"hello ", *b"world");
+        let (mut b1, mut b2) = (memory_buf(&mut d1), memory_buf(&mut d2));
+        let mut cl2 = ngx_chain_t {
+            buf: &mut b2,
+            next: ptr::null_mut(),
+        };
+        let cl1 = ngx_chain_t {
+            buf: &mut b1,
+            next: &mut cl2,
+        };
+
+        let mut inspection = BodyInspection::with_limit(8);
+        let slices: Vec<&[u8]> = unsafe { inspection.inspect(&cl1) }.collect();
+        assert_eq!(slices, [&b"hello "[..], b"wo"]);
+        assert!(inspection.is_passthrough());
+        assert_eq!(inspection.inspected(), 8);
+        assert_eq!(unsafe { inspection.inspect(&cl1) }.count(), 0);
+
+        let mut inspection = BodyInspection::new();
+        assert_eq!(unsafe { inspection.inspect(&cl1) }.count(), 2);
+        assert!(!inspection.is_passthrough());
+    }
+
+    #[test]
+    fn test_filter_scope() {
+        let mut main: ngx_http_request_t = unsafe { mem::zeroed() };
+        main.main = ptr::addr_of_mut!(main);
+        main.headers_out.status = NGX_HTTP_OK as ngx_uint_t;
+        let mut sub: ngx_http_request_t = unsafe { mem::zeroed() };
+        sub.main = ptr::addr_of_mut!(main);
+        sub.headers_out.status = NGX_HTTP_OK as ngx_uint_t;
+
+        let scope = FilterScope::new().main_only().status_range(200, 299);
+        assert!(!scope.skips(unsafe { Request::from_ngx_http_request(&mut main) }));
+        assert!(scope.skips(unsafe { Request::from_ngx_http_request(&mut sub) }));
+        assert!(!FilterScope::new().skips(unsafe { Request::from_ngx_http_request(&mut sub) }));
+
+        main.headers_out.status = NGX_HTTP_NOT_FOUND as ngx_uint_t;
+        assert!(scope.skips(unsafe { Request::from_ngx_http_request(&mut main) }));
+    }
+}
@@ -0,0 +1,415 @@
+use crate::core::{ConfError, Memo, MemoConfig, MemoLookup, MemoTicket, MemoValue, NgxStr, Status};
+use crate::ffi::*;
+use crate::http::{BodyChain, ComplexValue, HTTPStatus, Method, Request};
+
+pub use ngx_core::{request_fingerprint, BodyHash, IdempotencyRecord};
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::mem::ManuallyDrop;
+use std::os::fd::FromRawFd;
+use std::os::unix::fs::FileExt;
+use std::slice;
+use std::time::Duration;
+
+impl MemoValue for IdempotencyRecord {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.encode())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        IdempotencyRecord::decode(bytes)
+    }
+}
+
+/// Settings of an [`Idempotency`] store.
+#[derive(Clone, Debug)]
+pub struct IdempotencyConfig {
+    /// The request header field holding the key.
+    pub header: String,
+    /// The methods for which the key is honored.
+    pub methods: Vec<Method>,
+    /// Whether requests with these methods are rejected without a key.
+    pub require_key: bool,
+    /// Maximum length of a key; longer keys are rejected.
+    pub max_key_len: usize,
+    /// Maximum size of a response body stored for replay. Larger responses are replayed with
+    /// their status only.
+    pub max_body_size: usize,
+    /// Maximum number of stored responses.
+    pub max_entries: usize,
+    /// Time a response is replayed for.
+    pub ttl: Duration,
+    /// Time after which a request that did not complete no longer blocks its key.
+    pub lock_timeout: Duration,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        IdempotencyConfig {
+            header: "Idempotency-Key".into(),
+            methods: vec![Method::POST, Method::PATCH],
+            require_key: false,
+            max_key_len: 255,
+            max_body_size: 64 * 1024,
+            max_entries: 10_000,
+            ttl: Duration::from_secs(24 * 60 * 60),
+            lock_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Handling of the `Idempotency-Key` request header for API gateways.
+///
+/// The first request with a key is processed normally, and its response is stored in a shared
+/// memory zone; retries with the same key get the stored response instead of being processed
+/// again, marked with an `Idempotency-Replayed: true` header. A retry arriving while the first
+/// request is still processed is rejected with `409 Conflict`, and a key reused for a different
+/// method, URI or request body is rejected with `422 Unprocessable Content`. Server errors (5xx)
+/// are not stored, so such requests can be retried. Neither are responses of requests internally
+/// redirected, e.g. with `error_page`.
+///
+/// The keys are chosen by the clients, so they are scoped to the client sending them with a
+/// complex value, e.g. `$remote_user` or `$http_authorization`: a client reusing the key of
+/// another client neither gets the response stored for the other client nor blocks its key.
+/// Requests for which the scope evaluates to an empty string, e.g. anonymous requests, are
+/// processed without idempotency handling. The request body is read by the access handler, to
+/// include it in the fingerprint of the request.
+///
+/// The component is an access phase handler, calling [`Idempotency::access_handler`], and a
+/// [`ResponseFilter`] storing the responses, delegating to [`Idempotency::header_filter`] and
+/// [`IdempotencyResponse::record`]:
+///
+/// ```rust,ignore
+/// // in the handler of the `idempotency_zone` directive, with the scope as an argument
+/// let scope = ComplexValue::compile(cf, args.require(1)?)?;
+/// conf.idempotency = Some(Idempotency::add(
+///     cf,
+///     "idempotency",
+///     10 * 1024 * 1024,
+///     module,
+///     scope,
+///     IdempotencyConfig::default(),
+/// )?);
+///
+/// http_request_handler!(idempotency_access_handler, |request: &mut Request| {
+///     let module = unsafe { &*addr_of!(ngx_http_idempotency_module) };
+///     match request.get_module_loc_conf::<LocConf>(module).and_then(|conf| conf.idempotency.as_ref()) {
+///         Some(idempotency) => idempotency.access_handler(request),
+///         None => Status::NGX_DECLINED,
+///     }
+/// });
+///
+/// struct IdempotencyFilter;
+///
+/// static NEXT_HEADER_FILTER: NextHeaderFilter = NextHeaderFilter::new();
+/// static NEXT_BODY_FILTER: NextBodyFilter = NextBodyFilter::new();
+///
+/// impl ResponseFilter for IdempotencyFilter {
+///     type Ctx = IdempotencyResponse;
+///
+///     fn module() -> &'static ngx_module_t {
+///         unsafe { &*addr_of!(ngx_http_idempotency_module) }
+///     }
+///
+///     fn next_header_filter() -> &'static NextHeaderFilter {
+///         &NEXT_HEADER_FILTER
+///     }
+///
+///     fn next_body_filter() -> &'static NextBodyFilter {
+///         &NEXT_BODY_FILTER
+///     }
+///
+///     fn header_filter(request: &mut Request) -> Result<Option<IdempotencyResponse>, Status> {
+///         Ok(Idempotency::header_filter(request))
+///     }
+///
+///     fn body_filter(request: &mut Request, response: &mut IdempotencyResponse, chain: BodyChain<'_>) -> Status {
+///         response.record(chain);
+///         NEXT_BODY_FILTER.call(request, chain)
+///     }
+/// }
+///
+/// // in `postconfiguration`
+/// (*cf).install_response_filter::<IdempotencyFilter>()?;
+/// ```
+///
+/// [`ResponseFilter`]: crate::http::ResponseFilter
+pub struct Idempotency {
+    memo: Memo<[u8], IdempotencyRecord>,
+    scope: ComplexValue,
+    config: IdempotencyConfig,
+}
+
+/// The response of a request processed with an idempotency key, recorded to be stored once
+/// complete.
+///
+/// The response is created by [`Idempotency::access_handler`] for the requests it accepts, and
+/// is the context of the filter storing the responses, see [`Idempotency`].
+pub struct IdempotencyResponse {
+    memo: Memo<[u8], IdempotencyRecord>,
+    ticket: Option<MemoTicket>,
+    record: IdempotencyRecord,
+    hash: BodyHash,
+    max_body_size: usize,
+}
+
+impl Drop for IdempotencyResponse {
+    fn drop(&mut self) {
+        // the response was not completed, e.g. the client closed the connection
+        if let Some(ticket) = self.ticket.take() {
+            self.memo.abandon(ticket);
+        }
+    }
+}
+
+impl IdempotencyResponse {
+    /// Adds the next chain of response body buffers to the response, storing it once the last
+    /// buffer is seen.
+    pub fn record(&mut self, chain: BodyChain<'_>) {
+        if self.ticket.is_none() {
+            return;
+        }
+
+        for bytes in chain.slices() {
+            self.hash.update(bytes);
+            if let Some(body) = self.record.body.as_mut() {
+                if body.len() + bytes.len() > self.max_body_size {
+                    self.record.body = None;
+                } else {
+                    body.extend_from_slice(bytes);
+                }
+            }
+        }
+
+        if chain.buffers().any(|buf| buf.last_buf() != 0) {
+            self.complete();
+        }
+    }
+
+    fn complete(&mut self) {
+        let Some(ticket) = self.ticket.take() else {
+            return;
+        };
+        self.record.body_hash = self.hash.finish();
+        // a response not fitting in the zone is not stored, and its key is released
+        let _ = self.memo.complete(ticket, &self.record);
+    }
+}
+
+/// The response of a request accepted by the access handler, until its response header is sent.
+type PendingResponse = Option<IdempotencyResponse>;
+
+impl Idempotency {
+    /// Adds the shared memory zone `name` of `size` bytes storing the responses of the requests
+    /// with a key, scoped by the value of `scope` for the request.
+    ///
+    /// A constant scope shares the keys between all clients, which is only safe if the clients
+    /// trust each other.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer.
+    pub unsafe fn add(
+        cf: *mut ngx_conf_t,
+        name: &str,
+        size: usize,
+        module: &ngx_module_t,
+        scope: ComplexValue,
+        config: IdempotencyConfig,
+    ) -> Result<Self, ConfError> {
+        let memo_config = MemoConfig {
+            max_entries: config.max_entries,
+            ttl: config.ttl,
+            compute_timeout: config.lock_timeout,
+        };
+        let memo = Memo::add(cf, name, size, module, memo_config)?;
+        Ok(Idempotency { memo, scope, config })
+    }
+
+    /// Returns the settings of the store.
+    pub fn config(&self) -> &IdempotencyConfig {
+        &self.config
+    }
+
+    /// The access phase handler, replaying or rejecting duplicate requests.
+    pub fn access_handler(&self, request: &mut Request) -> Status {
+        if !request.is_main() || !self.config.methods.contains(&request.method()) {
+            return Status::NGX_DECLINED;
+        }
+        // the key of a redirected request is held by its first pass through the access phase
+        if request.get_inner().internal() != 0 {
+            return Status::NGX_DECLINED;
+        }
+
+        let header = self.config.header.as_bytes();
+        let key = request
+            .headers_in_iterator()
+            .find(|(name, _)| name.as_bytes().eq_ignore_ascii_case(header))
+            .map(|(_, value)| value.as_bytes().to_vec());
+        let Some(key) = key else {
+            return if self.config.require_key {
+                HTTPStatus::BAD_REQUEST.into()
+            } else {
+                Status::NGX_DECLINED
+            };
+        };
+        if key.is_empty() || key.len() > self.config.max_key_len {
+            return HTTPStatus::BAD_REQUEST.into();
+        }
+
+        let scope = match self.scope.evaluate(request) {
+            Ok(scope) if scope.is_empty() => return Status::NGX_DECLINED,
+            Ok(scope) => scope.as_bytes(),
+            Err(rc) => return rc,
+        };
+        // the length prefix keeps the scopes apart
+        let mut memo_key = Vec::with_capacity(4 + scope.len() + key.len());
+        memo_key.extend_from_slice(&(scope.len() as u32).to_le_bytes());
+        memo_key.extend_from_slice(scope);
+        memo_key.extend_from_slice(&key);
+
+        let r: *mut ngx_http_request_t = (&mut *request).into();
+        // the phase runs again once the body is read
+        if unsafe { (*r).request_body.is_null() } {
+            return unsafe { read_body(r) };
+        }
+        let body_hash = match unsafe { hash_body(r) } {
+            Some(hash) => hash,
+            None => return HTTPStatus::INTERNAL_SERVER_ERROR.into(),
+        };
+
+        let r = request.get_inner();
+        let fingerprint = unsafe {
+            request_fingerprint(
+                NgxStr::from_ngx_str(r.method_name).as_bytes(),
+                NgxStr::from_ngx_str(r.unparsed_uri).as_bytes(),
+                body_hash,
+            )
+        };
+
+        match self.memo.lookup(&memo_key) {
+            MemoLookup::Hit(record) if record.fingerprint != fingerprint => HTTPStatus::UNPROCESSABLE_CONTENT.into(),
+            MemoLookup::Hit(record) => replay(request, &record),
+            MemoLookup::Pending | MemoLookup::Stale(_) => HTTPStatus::CONFLICT.into(),
+            // the response could not be recorded, and a retry would run the request again
+            MemoLookup::Bypass => HTTPStatus::SERVICE_UNAVAILABLE.into(),
+            MemoLookup::Compute(ticket) => {
+                let response = IdempotencyResponse {
+                    memo: self.memo,
+                    ticket: Some(ticket),
+                    record: IdempotencyRecord {
+                        fingerprint,
+                        status: 0,
+                        content_type: String::new(),
+                        body_hash: 0,
+                        body: Some(Vec::new()),
+                    },
+                    hash: BodyHash::new(),
+                    max_body_size: self.config.max_body_size,
+                };
+                let r: *mut ngx_http_request_t = (&mut *request).into();
+                let pending: PendingResponse = Some(response);
+                match request.pool().set_data(r as usize, pending) {
+                    Some(_) => Status::NGX_DECLINED,
+                    None => Status::NGX_ERROR,
+                }
+            }
+        }
+    }
+
+    /// The header filter storing the responses, returning the response to record with
+    /// [`IdempotencyResponse::record`] if the request was accepted by
+    /// [`Idempotency::access_handler`].
+    ///
+    /// Server errors (5xx) and responses of requests internally redirected are not stored, and
+    /// release their key.
+    pub fn header_filter(request: &mut Request) -> Option<IdempotencyResponse> {
+        let r: *mut ngx_http_request_t = (&mut *request).into();
+        let pending = request.pool().data::<PendingResponse>(r as usize)?;
+
+        unsafe {
+            let mut response = (*pending).take()?;
+            let status = (*r).headers_out.status;
+            if (*r).internal() != 0 || status >= NGX_HTTP_INTERNAL_SERVER_ERROR as ngx_uint_t {
+                return None;
+            }
+
+            response.record.status = status as u16;
+            response.record.content_type = NgxStr::from_ngx_str((*r).headers_out.content_type)
+                .to_string_lossy()
+                .into_owned();
+            if (*r).header_only() != 0 {
+                response.complete();
+                return None;
+            }
+            (*r).set_filter_need_in_memory(1);
+            Some(response)
+        }
+    }
+}
+
+/// Reads the request body from the access phase, running the phase again once it is read.
+unsafe fn read_body(r: *mut ngx_http_request_t) -> Status {
+    let rc = ngx_http_read_client_request_body(r, Some(read_body_handler));
+    if rc >= NGX_HTTP_SPECIAL_RESPONSE as ngx_int_t {
+        return Status(rc);
+    }
+    // releases the reference taken by reading the body
+    ngx_http_finalize_request(r, NGX_DONE as ngx_int_t);
+    Status::NGX_DONE
+}
+
+unsafe extern "C" fn read_body_handler(r: *mut ngx_http_request_t) {
+    // the body is passed to the content handler, e.g. to an upstream server
+    (*r).set_preserve_body(1);
+    (*r).write_event_handler = Some(ngx_http_core_run_phases);
+    ngx_http_core_run_phases(r);
+}
+
+/// Returns the [`BodyHash`] of the request body read by [`read_body`], including the part of the
+/// body buffered in a temporary file, or `None` if the file cannot be read.
+unsafe fn hash_body(r: *mut ngx_http_request_t) -> Option<u64> {
+    let mut hash = BodyHash::new();
+    let mut cl = (*(*r).request_body).bufs;
+    let mut chunk = [0u8; 8192];
+
+    while let Some(link) = cl.as_ref() {
+        let b = &*link.buf;
+        if b.in_file() != 0 {
+            // the descriptor is owned by the temporary file of the body
+            let file = ManuallyDrop::new(File::from_raw_fd((*b.file).fd));
+            let mut offset = b.file_pos;
+            while offset < b.file_last {
+                let len = chunk.len().min((b.file_last - offset) as usize);
+                let n = file.read_at(&mut chunk[..len], offset as u64).ok()?;
+                if n == 0 {
+                    return None;
+                }
+                hash.update(&chunk[..n]);
+                offset += n as off_t;
+            }
+        } else if !b.pos.is_null() && b.last > b.pos {
+            hash.update(slice::from_raw_parts(b.pos, b.last.offset_from(b.pos) as usize));
+        }
+        cl = link.next;
+    }
+
+    Some(hash.finish())
+}
+
+/// Sends a stored response, finalizing the request from the access phase.
+fn replay(request: &mut Request, record: &IdempotencyRecord) -> Status {
+    let Ok(status) = HTTPStatus::from_u16(record.status) else {
+        return Status::NGX_ERROR;
+    };
+
+    let mut headers = vec![("Idempotency-Replayed", "true")];
+    if !record.content_type.is_empty() {
+        headers.push(("Content-Type", record.content_type.as_str()));
+    }
+    let rc = request.send_response(status, &headers, record.body.as_deref().unwrap_or_default());
+
+    unsafe { ngx_http_finalize_request(request.into(), rc.0) };
+    Status::NGX_DONE
+}
@@ -0,0 +1,152 @@
+use crate::core::{Array, NgxStr};
+use crate::ffi::*;
+use crate::http::ngx_http_conf_get_module_main_conf;
+
+use std::ptr::addr_of;
+
+/// The main configuration of the `http` block, as seen by the modules during configuration.
+///
+/// Modules keeping state per virtual server, e.g. metrics or certificates, can enumerate the
+/// servers at postconfiguration to key the state by the server names and listen addresses:
+///
+/// ```rust,ignore
+/// unsafe extern "C" fn postconfiguration(cf: *mut ngx_conf_t) -> ngx_int_t {
+///     let main_conf = NgxMainConf::from_conf(cf);
+///     for server in main_conf.servers() {
+///         let conf = server.srv_conf::<SrvConf>(&*addr_of!(ngx_http_metrics_module));
+///         for addr in server.listen() {
+///             register(server.server_name(), addr.addr_text(), conf);
+///         }
+///     }
+///     Status::NGX_OK.into()
+/// }
+/// ```
+#[repr(transparent)]
+pub struct NgxMainConf(ngx_http_core_main_conf_t);
+
+impl NgxMainConf {
+    /// Returns the main configuration of the `http` block `cf` is parsing.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer within the `http` block, and
+    /// does not keep the reference past the configuration, as the listen addresses are released
+    /// with the temporary pool of the configuration.
+    pub unsafe fn from_conf<'a>(cf: *mut ngx_conf_t) -> &'a NgxMainConf {
+        let cmcf = ngx_http_conf_get_module_main_conf(cf, &*addr_of!(ngx_http_core_module));
+        &*(cmcf as *const NgxMainConf)
+    }
+
+    /// Returns an iterator over the `server` blocks, in the order of the configuration.
+    pub fn servers(&self) -> impl Iterator<Item = HttpServer<'_>> {
+        // SAFETY: the servers are allocated from the configuration pool
+        let servers: Array<'_, *mut ngx_http_core_srv_conf_t> = unsafe { Array::from_ngx_array(&self.0.servers) };
+        servers.into_iter().map(move |&cscf| HttpServer {
+            main: self,
+            conf: unsafe { &*cscf },
+        })
+    }
+
+    /// Returns the inner data structure that the NgxMainConf object is wrapping.
+    pub fn get_inner(&self) -> &ngx_http_core_main_conf_t {
+        &self.0
+    }
+
+    /// Returns the addresses of the `listen` directives and the ports they belong to.
+    fn listen_addrs(&self) -> impl Iterator<Item = (&ngx_http_conf_port_t, &ngx_http_conf_addr_t)> {
+        // SAFETY: the ports are allocated from the temporary pool of the configuration, which
+        // outlives the main configuration reference
+        let ports: Array<'_, ngx_http_conf_port_t> = unsafe { Array::from_ngx_array(self.0.ports) };
+        ports.into_iter().flat_map(|port| {
+            let addrs: Array<'_, ngx_http_conf_addr_t> = unsafe { Array::from_ngx_array(&port.addrs) };
+            addrs.into_iter().map(move |addr| (port, addr))
+        })
+    }
+}
+
+/// A `server` block of the `http` block, see [`NgxMainConf::servers`].
+#[derive(Clone, Copy)]
+pub struct HttpServer<'a> {
+    main: &'a NgxMainConf,
+    conf: &'a ngx_http_core_srv_conf_t,
+}
+
+impl<'a> HttpServer<'a> {
+    /// Returns the primary server name, the first of the `server_name` directive, or an empty
+    /// string.
+    pub fn server_name(&self) -> &'a NgxStr {
+        // SAFETY: the name is allocated from the configuration pool
+        unsafe { NgxStr::from_ngx_str(self.conf.server_name) }
+    }
+
+    /// Returns an iterator over the names of the `server_name` directive, including wildcard
+    /// names and the sources of regular expressions, e.g. `~^www\d+\.example\.net$`.
+    pub fn server_names(&self) -> impl Iterator<Item = &'a NgxStr> {
+        // SAFETY: the names are allocated from the configuration pool
+        let names: Array<'a, ngx_http_server_name_t> = unsafe { Array::from_ngx_array(&self.conf.server_names) };
+        names.into_iter().map(|sn| unsafe { NgxStr::from_ngx_str(sn.name) })
+    }
+
+    /// Returns an iterator over the addresses the server listens on.
+    ///
+    /// The implicit `listen` of a server without one is only added once the server
+    /// configurations are merged, so the list is complete from postconfiguration on.
+    pub fn listen(&self) -> impl Iterator<Item = ListenAddr<'a>> {
+        let cscf: *const ngx_http_core_srv_conf_t = self.conf;
+        self.main.listen_addrs().filter_map(move |(port, addr)| {
+            // SAFETY: the servers of an address are allocated from the configuration pool
+            let servers: Array<'_, *mut ngx_http_core_srv_conf_t> = unsafe { Array::from_ngx_array(&addr.servers) };
+            servers.iter().any(|&s| s as *const _ == cscf).then_some(ListenAddr {
+                port,
+                addr,
+                default: addr.default_server as *const _ == cscf,
+            })
+        })
+    }
+
+    /// Returns the configuration of `module` for the server.
+    ///
+    /// # Safety
+    ///
+    /// `T` is the server configuration type of `module`.
+    pub unsafe fn srv_conf<T>(&self, module: &ngx_module_t) -> Option<&'a T> {
+        let ctx = self.conf.ctx;
+        if ctx.is_null() {
+            return None;
+        }
+        (*(*ctx).srv_conf.add(module.ctx_index) as *const T).as_ref()
+    }
+
+    /// Returns the core module configuration of the server.
+    pub fn get_inner(&self) -> &'a ngx_http_core_srv_conf_t {
+        self.conf
+    }
+}
+
+/// An address a server listens on, see [`HttpServer::listen`].
+#[derive(Clone, Copy)]
+pub struct ListenAddr<'a> {
+    port: &'a ngx_http_conf_port_t,
+    addr: &'a ngx_http_conf_addr_t,
+    default: bool,
+}
+
+impl<'a> ListenAddr<'a> {
+    /// Returns the address as written in the `listen` directive after resolution, e.g.
+    /// `127.0.0.1:8080`, `[::]:443` or `unix:/run/nginx.sock`.
+    pub fn addr_text(&self) -> &'a NgxStr {
+        // SAFETY: the text is allocated from the configuration pool
+        unsafe { NgxStr::from_ngx_str(self.addr.opt.addr_text) }
+    }
+
+    /// Returns the port, or 0 for a UNIX-domain socket.
+    pub fn port(&self) -> u16 {
+        self.port.port
+    }
+
+    /// Returns `true` if the server is the default server of the address, by the
+    /// `default_server` parameter or by being the first server listening on it.
+    pub fn is_default(&self) -> bool {
+        self.default
+    }
+}
@@ -1,10 +1,56 @@
+mod async_handler;
+#[cfg(feature = "regex")]
+mod captures;
+mod complex_value;
+mod condition;
 mod conf;
+mod content_length;
+#[cfg(feature = "ssl")]
+mod early_data;
+mod etag;
+mod filter;
+mod idempotency;
+mod main_conf;
 mod module;
+mod module_safe;
 mod request;
+mod request_body;
+mod server_stats;
+mod signing;
 mod status;
+mod subrequest;
+mod try_files;
 mod upstream;
+mod upstream_signing;
+#[cfg(feature = "http_v2")]
+mod v2;
+mod variable;
+mod version;
 
+pub use async_handler::*;
+#[cfg(feature = "regex")]
+pub use captures::*;
+pub use complex_value::*;
+pub use condition::*;
 pub use conf::*;
+pub use content_length::*;
+#[cfg(feature = "ssl")]
+pub use early_data::*;
+pub use etag::*;
+pub use filter::*;
+pub use idempotency::*;
+pub use main_conf::*;
 pub use module::*;
+pub use module_safe::*;
 pub use request::*;
+pub use request_body::*;
+pub use server_stats::*;
+pub use signing::*;
 pub use status::*;
+pub use subrequest::*;
+pub use upstream::*;
+pub use upstream_signing::*;
+#[cfg(feature = "http_v2")]
+pub use v2::*;
+pub use variable::*;
+pub use version::*;
@@ -1,8 +1,10 @@
 use crate::core::NGX_CONF_ERROR;
 use crate::core::*;
 use crate::ffi::*;
+use crate::http::NgxMainConf;
 
 use core::ptr;
+use std::marker::PhantomData;
 use std::os::raw::{c_char, c_void};
 
 /// MergeConfigError - configuration cannot be merged with levels above.
@@ -32,6 +34,17 @@ pub trait Merge {
     /// # Returns
     /// Result, Ok on success or MergeConfigError on failure.
     fn merge(&mut self, prev: &Self) -> Result<(), MergeConfigError>;
+
+    /// Finalizes the configuration once it is merged, e.g. building the lookup structures or
+    /// compiling the matchers used by the requests, instead of doing it lazily per request.
+    ///
+    /// This is called once for each server and location configuration, right after its merge,
+    /// when no directive can change it anymore. The configurations of the `http` block itself are
+    /// not merged, and not finalized either; requests only use merged configurations. The error
+    /// is logged at the `emerg` level and fails the configuration.
+    fn finalize(&mut self, _cf: &mut ngx_conf_t) -> Result<(), ConfError> {
+        Ok(())
+    }
 }
 
 impl Merge for () {
@@ -83,8 +96,58 @@ pub trait HTTPModule {
     ///
     /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
     /// guard against null inputs or risk runtime errors.
-    unsafe extern "C" fn init_main_conf(_cf: *mut ngx_conf_t, _conf: *mut c_void) -> *mut c_char {
-        ptr::null_mut()
+    unsafe extern "C" fn init_main_conf(cf: *mut ngx_conf_t, conf: *mut c_void) -> *mut c_char {
+        let conf = &mut *(conf as *mut Self::MainConf);
+        let http = NgxMainConf::from_conf(cf);
+        let result = Self::validate_main_conf(&mut *cf, conf, http).and_then(|()| {
+            if ngx_is_config_test() {
+                Self::check_conf(&mut *cf, conf, http)
+            } else {
+                Ok(())
+            }
+        });
+        ngx_conf_result(cf, ptr::null(), result)
+    }
+
+    /// Validates the main configuration against the configurations of all the servers, called
+    /// from [`HTTPModule::init_main_conf`] once the `http` block is parsed, e.g. to check that
+    /// zone names are unique across servers:
+    ///
+    /// ```rust,ignore
+    /// fn validate_main_conf(_cf: &mut ngx_conf_t, _conf: &mut MainConf, http: &NgxMainConf) -> Result<(), ConfError> {
+    ///     let mut zones = HashSet::new();
+    ///     for server in http.servers() {
+    ///         let conf = unsafe { server.srv_conf::<SrvConf>(&*addr_of!(ngx_http_zone_module)) };
+    ///         if let Some(zone) = conf.and_then(|conf| conf.zone.as_deref()) {
+    ///             if !zones.insert(zone) {
+    ///                 return Err(ConfError::new(format!("duplicate zone \"{zone}\"")));
+    ///             }
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// The server configurations are not merged yet: they only hold the directives of their own
+    /// `server` block. The error is logged at the `emerg` level and fails the configuration.
+    fn validate_main_conf(
+        _cf: &mut ngx_conf_t,
+        _conf: &mut Self::MainConf,
+        _http: &NgxMainConf,
+    ) -> Result<(), ConfError> {
+        Ok(())
+    }
+
+    /// Validates the configuration when NGINX only tests the configuration, as with `nginx -t`,
+    /// called from [`HTTPModule::init_main_conf`] after [`HTTPModule::validate_main_conf`].
+    ///
+    /// This is the place for checks too expensive or too intrusive to run on each start or
+    /// reload, such as connecting to the configured upstream endpoints. As for
+    /// [`HTTPModule::validate_main_conf`], the server configurations are not merged yet. The
+    /// error is logged at the `emerg` level and fails the configuration test, see also
+    /// [`CoreModule::check_conf`].
+    fn check_conf(_cf: &mut ngx_conf_t, _conf: &Self::MainConf, _http: &NgxMainConf) -> Result<(), ConfError> {
+        Ok(())
     }
 
     /// # Safety
@@ -100,13 +163,13 @@ pub trait HTTPModule {
     ///
     /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
     /// guard against null inputs or risk runtime errors.
-    unsafe extern "C" fn merge_srv_conf(_cf: *mut ngx_conf_t, prev: *mut c_void, conf: *mut c_void) -> *mut c_char {
+    unsafe extern "C" fn merge_srv_conf(cf: *mut ngx_conf_t, prev: *mut c_void, conf: *mut c_void) -> *mut c_char {
         let prev = &mut *(prev as *mut Self::SrvConf);
         let conf = &mut *(conf as *mut Self::SrvConf);
-        match conf.merge(prev) {
-            Ok(_) => ptr::null_mut(),
-            Err(_) => NGX_CONF_ERROR as _,
+        if conf.merge(prev).is_err() {
+            return NGX_CONF_ERROR as _;
         }
+        ngx_conf_result(cf, ptr::null(), conf.finalize(&mut *cf))
     }
 
     /// # Safety
@@ -122,12 +185,147 @@ pub trait HTTPModule {
     ///
     /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
     /// guard against null inputs or risk runtime errors.
-    unsafe extern "C" fn merge_loc_conf(_cf: *mut ngx_conf_t, prev: *mut c_void, conf: *mut c_void) -> *mut c_char {
+    unsafe extern "C" fn merge_loc_conf(cf: *mut ngx_conf_t, prev: *mut c_void, conf: *mut c_void) -> *mut c_char {
         let prev = &mut *(prev as *mut Self::LocConf);
         let conf = &mut *(conf as *mut Self::LocConf);
-        match conf.merge(prev) {
-            Ok(_) => ptr::null_mut(),
-            Err(_) => NGX_CONF_ERROR as _,
+        if conf.merge(prev).is_err() {
+            return NGX_CONF_ERROR as _;
         }
+        ngx_conf_result(cf, ptr::null(), conf.finalize(&mut *cf))
+    }
+
+    /// Called in the master process once the configuration is initialized, before the workers
+    /// start, e.g. to open sockets shared by the workers. An error fails the start or reload.
+    fn init_module(_cycle: &mut ngx_cycle_t) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Called in each worker process when it starts, e.g. to start background tasks. An error
+    /// terminates the worker.
+    fn init_process(_cycle: &mut ngx_cycle_t) -> Result<(), Status> {
+        Ok(())
     }
+
+    /// Called in each worker process before it exits.
+    fn exit_process(_cycle: &mut ngx_cycle_t) {}
+
+    /// Called in the master process before it exits.
+    fn exit_master(_cycle: &mut ngx_cycle_t) {}
+}
+
+/// The [`ModuleHooks`] of the [`HTTPModule`] `M`.
+pub struct HTTPModuleHooks<M>(PhantomData<M>);
+
+impl<M: HTTPModule> ModuleHooks for HTTPModuleHooks<M> {
+    fn init_module(cycle: &mut ngx_cycle_t) -> Result<(), Status> {
+        M::init_module(cycle)
+    }
+
+    fn init_process(cycle: &mut ngx_cycle_t) -> Result<(), Status> {
+        M::init_process(cycle)
+    }
+
+    fn exit_process(cycle: &mut ngx_cycle_t) {
+        M::exit_process(cycle)
+    }
+
+    fn exit_master(cycle: &mut ngx_cycle_t) {
+        M::exit_master(cycle)
+    }
+}
+
+/// Define a static HTTP module.
+///
+/// The arguments are the name of the module static, the type implementing [`HTTPModule`], and
+/// the commands of the module. Each command is a [`Command`] without its configuration offset,
+/// listed after the configuration level it is stored in, `MainConf:`, `SrvConf:` or `LocConf:`,
+/// which applies to the commands following it. The commands table, terminated by the null
+/// command, and the module context are generated along with the module:
+///
+/// ```rust,ignore
+/// define_http_module!(ngx_http_example_module, Module, [
+///     MainConf:
+///         Command::size(c"example_zone_size", |conf: &mut MainConf| &mut conf.zone_size)
+///             .context(NGX_HTTP_MAIN_CONF),
+///     LocConf:
+///         Command::flag(c"example", |conf: &mut LocConf| &mut conf.enable)
+///             .context(NGX_HTTP_LOC_CONF),
+///         Command::new::<ExampleUpstream>(c"example_upstream")
+///             .context(NGX_HTTP_SRV_CONF | NGX_HTTP_LOC_CONF),
+/// ]);
+/// ```
+#[macro_export]
+macro_rules! define_http_module {
+    (@commands $name: ident, $module: ty, [$( $offset: tt )*] [$( $acc: expr, )*] MainConf: $( $rest: tt )*) => {
+        $crate::define_http_module!(@commands $name, $module, [$crate::ffi::NGX_RS_HTTP_MAIN_CONF_OFFSET] [$( $acc, )*] $( $rest )*);
+    };
+    (@commands $name: ident, $module: ty, [$( $offset: tt )*] [$( $acc: expr, )*] SrvConf: $( $rest: tt )*) => {
+        $crate::define_http_module!(@commands $name, $module, [$crate::ffi::NGX_RS_HTTP_SRV_CONF_OFFSET] [$( $acc, )*] $( $rest )*);
+    };
+    (@commands $name: ident, $module: ty, [$( $offset: tt )*] [$( $acc: expr, )*] LocConf: $( $rest: tt )*) => {
+        $crate::define_http_module!(@commands $name, $module, [$crate::ffi::NGX_RS_HTTP_LOC_CONF_OFFSET] [$( $acc, )*] $( $rest )*);
+    };
+    (@commands $name: ident, $module: ty, [] [$( $acc: expr, )*] $command: expr $(, $( $rest: tt )* )?) => {
+        ::std::compile_error!("a command must follow its configuration level, `MainConf:`, `SrvConf:` or `LocConf:`");
+    };
+    (@commands $name: ident, $module: ty, [$( $offset: tt )+] [$( $acc: expr, )*] $command: expr $(, $( $rest: tt )* )?) => {
+        $crate::define_http_module!(
+            @commands $name, $module, [$( $offset )+] [$( $acc, )* $command.conf($( $offset )+).build(),] $($( $rest )*)?
+        );
+    };
+    (@commands $name: ident, $module: ty, [$( $offset: tt )*] [$( $acc: expr, )*]) => {
+        #[no_mangle]
+        #[used]
+        pub static mut $name: $crate::ffi::ngx_module_t = $crate::ffi::ngx_module_t {
+            ctx_index: $crate::ffi::ngx_uint_t::MAX,
+            index: $crate::ffi::ngx_uint_t::MAX,
+            name: ::std::ptr::null_mut(),
+            spare0: 0,
+            spare1: 0,
+            version: $crate::ffi::nginx_version as $crate::ffi::ngx_uint_t,
+            signature: $crate::ffi::NGX_RS_MODULE_SIGNATURE.as_ptr() as *const ::std::os::raw::c_char,
+
+            ctx: {
+                static mut CTX: $crate::ffi::ngx_http_module_t = $crate::ffi::ngx_http_module_t {
+                    preconfiguration: Some(<$module as $crate::http::HTTPModule>::preconfiguration),
+                    postconfiguration: Some(<$module as $crate::http::HTTPModule>::postconfiguration),
+                    create_main_conf: Some(<$module as $crate::http::HTTPModule>::create_main_conf),
+                    init_main_conf: Some(<$module as $crate::http::HTTPModule>::init_main_conf),
+                    create_srv_conf: Some(<$module as $crate::http::HTTPModule>::create_srv_conf),
+                    merge_srv_conf: Some(<$module as $crate::http::HTTPModule>::merge_srv_conf),
+                    create_loc_conf: Some(<$module as $crate::http::HTTPModule>::create_loc_conf),
+                    merge_loc_conf: Some(<$module as $crate::http::HTTPModule>::merge_loc_conf),
+                };
+                ::std::ptr::addr_of_mut!(CTX) as *mut ::std::os::raw::c_void
+            },
+            commands: {
+                static mut COMMANDS: [$crate::ffi::ngx_command_t; $crate::count!($( $acc ),*) + 1] = [
+                    $( $acc, )*
+                    $crate::core::Command::null(),
+                ];
+                ::std::ptr::addr_of_mut!(COMMANDS) as *mut $crate::ffi::ngx_command_t
+            },
+            type_: $crate::ffi::NGX_HTTP_MODULE as $crate::ffi::ngx_uint_t,
+
+            init_master: None,
+            init_module: Some($crate::core::ngx_module_init_module::<$crate::http::HTTPModuleHooks<$module>>),
+            init_process: Some($crate::core::ngx_module_init_process::<$crate::http::HTTPModuleHooks<$module>>),
+            init_thread: None,
+            exit_thread: None,
+            exit_process: Some($crate::core::ngx_module_exit_process::<$crate::http::HTTPModuleHooks<$module>>),
+            exit_master: Some($crate::core::ngx_module_exit_master::<$crate::http::HTTPModuleHooks<$module>>),
+
+            spare_hook0: 0,
+            spare_hook1: 0,
+            spare_hook2: 0,
+            spare_hook3: 0,
+            spare_hook4: 0,
+            spare_hook5: 0,
+            spare_hook6: 0,
+            spare_hook7: 0,
+        };
+    };
+    ( $name: ident, $module: ty, [ $( $commands: tt )* ] ) => {
+        $crate::define_http_module!(@commands $name, $module, [] [] $( $commands )*);
+    };
 }
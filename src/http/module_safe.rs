@@ -0,0 +1,382 @@
+use crate::core::{chain_slices, ChainSlices, ConfError, Status};
+use crate::ffi::*;
+use crate::http::{ngx_http_add_body_filter, ngx_http_add_header_filter, Request};
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::{mem, ptr};
+
+/// An HTTP output header filter.
+///
+/// The filter is called once per response, before the response header is sent, and can inspect
+/// and modify the status and `headers_out` of the request, e.g. with
+/// [`Request::add_header_out`], before passing the request on with [`NextHeaderFilter::call`].
+/// Install it from the `postconfiguration` handler of the module with
+/// [`FilterInstaller::install_header_filter`].
+///
+/// ```rust,ignore
+/// struct ServerTimingFilter;
+///
+/// static NEXT_HEADER_FILTER: NextHeaderFilter = NextHeaderFilter::new();
+///
+/// impl HeaderFilter for ServerTimingFilter {
+///     fn next_filter() -> &'static NextHeaderFilter {
+///         &NEXT_HEADER_FILTER
+///     }
+///
+///     fn filter(request: &mut Request) -> Status {
+///         if request.is_main() && request.add_header_out("Server-Timing", "app;dur=12").is_err() {
+///             return Status::NGX_ERROR;
+///         }
+///         NEXT_HEADER_FILTER.call(request)
+///     }
+/// }
+///
+/// // in postconfiguration
+/// (*cf).install_header_filter::<ServerTimingFilter>()?;
+/// ```
+pub trait HeaderFilter {
+    /// Returns the storage for the next filter in the chain, a static of the module.
+    fn next_filter() -> &'static NextHeaderFilter;
+
+    /// Processes the response header of a request.
+    fn filter(request: &mut Request) -> Status;
+}
+
+/// The next header filter in the chain, saved when a [`HeaderFilter`] is installed.
+pub struct NextHeaderFilter(AtomicPtr<()>);
+
+impl NextHeaderFilter {
+    /// Creates an empty slot for the next header filter.
+    pub const fn new() -> Self {
+        NextHeaderFilter(AtomicPtr::new(ptr::null_mut()))
+    }
+
+    /// Passes the request to the next header filter.
+    pub fn call(&self, request: &mut Request) -> Status {
+        match self.get() {
+            Some(next) => Status(unsafe { next(request.into()) }),
+            None => Status::NGX_ERROR,
+        }
+    }
+
+    pub(crate) fn get(&self) -> ngx_http_output_header_filter_pt {
+        // SAFETY: the pointer is either null or a header filter stored by `NextHeaderFilter::set`
+        unsafe { mem::transmute::<*mut (), ngx_http_output_header_filter_pt>(self.0.load(Ordering::Relaxed)) }
+    }
+
+    fn set(&self, next: ngx_http_output_header_filter_pt) {
+        // the filter chain is built during configuration, before it is used
+        self.0
+            .store(next.map_or(ptr::null_mut(), |next| next as *mut ()), Ordering::Relaxed);
+    }
+}
+
+impl Default for NextHeaderFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An HTTP output body filter.
+///
+/// The filter is called for every chain of response buffers sent through the body filter chain
+/// and usually ends by passing the chain on with [`NextBodyFilter::call`]. Install it from the
+/// `postconfiguration` handler of the module with [`FilterInstaller::install_body_filter`].
+///
+/// ```rust,ignore
+/// struct CountingFilter;
+///
+/// static NEXT_BODY_FILTER: NextBodyFilter = NextBodyFilter::new();
+///
+/// impl BodyFilter for CountingFilter {
+///     fn next_filter() -> &'static NextBodyFilter {
+///         &NEXT_BODY_FILTER
+///     }
+///
+///     fn filter(request: &mut Request, chain: BodyChain<'_>) -> Status {
+///         let bytes: usize = chain.slices().map(<[u8]>::len).sum();
+///         ngx_log_debug_http!(request, "counting filter: {} bytes", bytes);
+///         NEXT_BODY_FILTER.call(request, chain)
+///     }
+/// }
+///
+/// // in postconfiguration
+/// (*cf).install_body_filter::<CountingFilter>()?;
+/// ```
+pub trait BodyFilter {
+    /// Returns the storage for the next filter in the chain, a static of the module.
+    fn next_filter() -> &'static NextBodyFilter;
+
+    /// Processes a chain of response body buffers.
+    fn filter(request: &mut Request, chain: BodyChain<'_>) -> Status;
+}
+
+/// The next body filter in the chain, saved when a [`BodyFilter`] is installed.
+pub struct NextBodyFilter(AtomicPtr<()>);
+
+impl NextBodyFilter {
+    /// Creates an empty slot for the next body filter.
+    pub const fn new() -> Self {
+        NextBodyFilter(AtomicPtr::new(ptr::null_mut()))
+    }
+
+    /// Passes `chain` to the next body filter.
+    pub fn call(&self, request: &mut Request, chain: BodyChain<'_>) -> Status {
+        match self.get() {
+            Some(next) => Status(unsafe { next(request.into(), chain.cl) }),
+            None => Status::NGX_ERROR,
+        }
+    }
+
+    pub(crate) fn get(&self) -> ngx_http_output_body_filter_pt {
+        // SAFETY: the pointer is either null or a body filter stored by `NextBodyFilter::set`
+        unsafe { mem::transmute::<*mut (), ngx_http_output_body_filter_pt>(self.0.load(Ordering::Relaxed)) }
+    }
+
+    fn set(&self, next: ngx_http_output_body_filter_pt) {
+        // the filter chain is built during configuration, before it is used
+        self.0
+            .store(next.map_or(ptr::null_mut(), |next| next as *mut ()), Ordering::Relaxed);
+    }
+}
+
+impl Default for NextBodyFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A chain of response body buffers passed to a [`BodyFilter`].
+#[derive(Clone, Copy)]
+pub struct BodyChain<'a> {
+    cl: *mut ngx_chain_t,
+    _chain: PhantomData<&'a mut ngx_chain_t>,
+}
+
+impl<'a> BodyChain<'a> {
+    /// Creates a [`BodyChain`] from an `ngx_chain_t` pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided either a null pointer or a valid `ngx_chain_t` which stays valid
+    /// for the lifetime `'a`.
+    pub unsafe fn from_ngx_chain(cl: *mut ngx_chain_t) -> Self {
+        BodyChain {
+            cl,
+            _chain: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the buffers of the chain.
+    pub fn buffers(&self) -> impl Iterator<Item = &'a ngx_buf_t> {
+        let mut cl = self.cl as *const ngx_chain_t;
+        std::iter::from_fn(move || unsafe {
+            let link = cl.as_ref()?;
+            cl = link.next;
+            link.buf.as_ref()
+        })
+    }
+
+    /// Returns an iterator over the in-memory contents of the buffers of the chain.
+    pub fn slices(&self) -> ChainSlices<'a> {
+        unsafe { chain_slices(self.cl) }
+    }
+
+    /// Returns `true` if the chain has no buffers, e.g. when NGINX flushes pending output.
+    pub fn is_empty(&self) -> bool {
+        self.cl.is_null()
+    }
+
+    /// Returns `true` if the chain contains the last buffer of the response, or of the
+    /// subrequest.
+    pub fn is_last(&self) -> bool {
+        self.buffers()
+            .any(|buf| buf.last_buf() != 0 || buf.last_in_chain() != 0)
+    }
+
+    /// Returns the raw `ngx_chain_t` pointer, e.g. for [`BodyInspection::inspect`].
+    ///
+    /// [`BodyInspection::inspect`]: crate::http::BodyInspection::inspect
+    pub fn as_ptr(&self) -> *mut ngx_chain_t {
+        self.cl
+    }
+}
+
+/// A pair of HTTP output header and body filters sharing a per-request context.
+///
+/// The header filter decides whether the response is filtered, and returns the context of the
+/// body filter, e.g. the state of a transformation. The context is stored before the next header
+/// filter is called, and the body filter is only called for responses with a context: other
+/// responses, including those whose header filter failed or returned no context, pass through
+/// the body filter untouched. Install it from the `postconfiguration` handler of the module with
+/// [`FilterInstaller::install_response_filter`].
+///
+/// ```rust,ignore
+/// struct UppercaseFilter;
+///
+/// static NEXT_HEADER_FILTER: NextHeaderFilter = NextHeaderFilter::new();
+/// static NEXT_BODY_FILTER: NextBodyFilter = NextBodyFilter::new();
+///
+/// impl ResponseFilter for UppercaseFilter {
+///     type Ctx = UppercaseCtx;
+///
+///     fn module() -> &'static ngx_module_t {
+///         unsafe { &*addr_of!(ngx_http_uppercase_module) }
+///     }
+///
+///     fn next_header_filter() -> &'static NextHeaderFilter {
+///         &NEXT_HEADER_FILTER
+///     }
+///
+///     fn next_body_filter() -> &'static NextBodyFilter {
+///         &NEXT_BODY_FILTER
+///     }
+///
+///     fn header_filter(request: &mut Request) -> Result<Option<UppercaseCtx>, Status> {
+///         if SCOPE.skips(request) {
+///             return Ok(None);
+///         }
+///         Ok(Some(UppercaseCtx::default()))
+///     }
+///
+///     fn body_filter(request: &mut Request, ctx: &mut UppercaseCtx, chain: BodyChain<'_>) -> Status {
+///         // ...
+///         NEXT_BODY_FILTER.call(request, chain)
+///     }
+/// }
+///
+/// // in postconfiguration
+/// (*cf).install_response_filter::<UppercaseFilter>()?;
+/// ```
+///
+/// The context slot of the module is reserved for the filter: the header filter clears it for
+/// responses without a context, and handlers of the module must not store another type in it.
+pub trait ResponseFilter {
+    /// The per-request context of the body filter, allocated from the request pool.
+    type Ctx: 'static;
+
+    /// Returns the module whose request context slot holds [`Self::Ctx`].
+    fn module() -> &'static ngx_module_t;
+
+    /// Returns the storage for the next header filter in the chain, a static of the module.
+    fn next_header_filter() -> &'static NextHeaderFilter;
+
+    /// Returns the storage for the next body filter in the chain, a static of the module.
+    fn next_body_filter() -> &'static NextBodyFilter;
+
+    /// Processes the response header of a request, returning the context of the body filter if
+    /// the response body is filtered.
+    ///
+    /// The next header filter is called after the context is stored. An error status is
+    /// returned as is, without calling the next header filter.
+    fn header_filter(request: &mut Request) -> Result<Option<Self::Ctx>, Status>;
+
+    /// Processes a chain of response body buffers of a request with a context.
+    fn body_filter(request: &mut Request, ctx: &mut Self::Ctx, chain: BodyChain<'_>) -> Status;
+}
+
+/// Registration of filters implemented with safe traits, from the `postconfiguration` handler
+/// of an HTTP module.
+pub trait FilterInstaller {
+    /// Inserts the header filter `F` at the top of the header filter chain.
+    ///
+    /// Installing the same filter twice for a configuration cycle fails with an error.
+    ///
+    /// # Safety
+    ///
+    /// The configuration is the valid `ngx_conf_t` passed to the `postconfiguration` handler of
+    /// an HTTP module, and this is called from that handler.
+    unsafe fn install_header_filter<F: HeaderFilter>(&mut self) -> Result<(), ConfError>;
+
+    /// Inserts the body filter `F` at the top of the body filter chain.
+    ///
+    /// Installing the same filter twice for a configuration cycle fails with an error.
+    ///
+    /// # Safety
+    ///
+    /// The configuration is the valid `ngx_conf_t` passed to the `postconfiguration` handler of
+    /// an HTTP module, and this is called from that handler.
+    unsafe fn install_body_filter<F: BodyFilter>(&mut self) -> Result<(), ConfError>;
+
+    /// Inserts the header and body filters of `F` at the top of their filter chains.
+    ///
+    /// Installing the same filter twice for a configuration cycle fails with an error.
+    ///
+    /// # Safety
+    ///
+    /// The configuration is the valid `ngx_conf_t` passed to the `postconfiguration` handler of
+    /// an HTTP module, and this is called from that handler.
+    unsafe fn install_response_filter<F: ResponseFilter>(&mut self) -> Result<(), ConfError>;
+}
+
+impl FilterInstaller for ngx_conf_t {
+    unsafe fn install_header_filter<F: HeaderFilter>(&mut self) -> Result<(), ConfError> {
+        let mut next = None;
+        ngx_http_add_header_filter(self, header_filter_handler::<F>, &mut next)?;
+        F::next_filter().set(next);
+        Ok(())
+    }
+
+    unsafe fn install_body_filter<F: BodyFilter>(&mut self) -> Result<(), ConfError> {
+        let mut next = None;
+        ngx_http_add_body_filter(self, body_filter_handler::<F>, &mut next)?;
+        F::next_filter().set(next);
+        Ok(())
+    }
+
+    unsafe fn install_response_filter<F: ResponseFilter>(&mut self) -> Result<(), ConfError> {
+        let mut next = None;
+        ngx_http_add_header_filter(self, response_header_filter_handler::<F>, &mut next)?;
+        F::next_header_filter().set(next);
+
+        let mut next = None;
+        ngx_http_add_body_filter(self, response_body_filter_handler::<F>, &mut next)?;
+        F::next_body_filter().set(next);
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn header_filter_handler<F: HeaderFilter>(r: *mut ngx_http_request_t) -> ngx_int_t {
+    let request = Request::from_ngx_http_request(r);
+    F::filter(request).0
+}
+
+unsafe extern "C" fn body_filter_handler<F: BodyFilter>(r: *mut ngx_http_request_t, cl: *mut ngx_chain_t) -> ngx_int_t {
+    let request = Request::from_ngx_http_request(r);
+    F::filter(request, BodyChain::from_ngx_chain(cl)).0
+}
+
+unsafe extern "C" fn response_header_filter_handler<F: ResponseFilter>(r: *mut ngx_http_request_t) -> ngx_int_t {
+    let request = Request::from_ngx_http_request(r);
+    // a context left from a previous header, e.g. of an error page, must not be reused
+    request.set_module_ctx(ptr::null_mut(), F::module());
+
+    match F::header_filter(request) {
+        Ok(Some(ctx)) => {
+            if request.insert_module_ctx(F::module(), ctx).is_none() {
+                return Status::NGX_ERROR.0;
+            }
+        }
+        Ok(None) => {}
+        Err(rc) => return rc.0,
+    }
+    F::next_header_filter().call(request).0
+}
+
+unsafe extern "C" fn response_body_filter_handler<F: ResponseFilter>(
+    r: *mut ngx_http_request_t,
+    cl: *mut ngx_chain_t,
+) -> ngx_int_t {
+    let request = Request::from_ngx_http_request(r);
+    let chain = BodyChain::from_ngx_chain(cl);
+
+    // the context is allocated from the request pool, not borrowed from the request
+    let ctx = request
+        .get_module_ctx_mut::<F::Ctx>(F::module())
+        .map(|ctx| ctx as *mut F::Ctx);
+    match ctx {
+        Some(ctx) => F::body_filter(request, &mut *ctx, chain).0,
+        None => F::next_body_filter().call(request, chain).0,
+    }
+}
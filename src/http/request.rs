@@ -1,12 +1,18 @@
 use crate::core::*;
+use crate::event::{duration_to_msec, ngx_add_timer, ngx_del_timer};
 use crate::ffi::*;
 use crate::http::status::*;
-use crate::ngx_null_string;
+use crate::http::upstream::*;
+use crate::http::Etag;
+use crate::{ngx_null_string, ngx_string};
 use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::c_void;
+use std::time::Duration;
 
-use std::error::Error;
-use std::str::FromStr;
+pub use ngx_core::{InvalidMethod, Method, QueryArgs};
+pub use ngx_core::{JsonError, JsonErrorKind, JsonValidator, JSON_DEFAULT_MAX_DEPTH};
 
 /// Define a static request handler.
 ///
@@ -91,6 +97,33 @@ impl<'a> From<&'a mut Request> for *mut ngx_http_request_t {
     }
 }
 
+/// Error returned when a [`Request`] method is used at a point of request processing where it
+/// would corrupt the request or the response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestError {
+    /// The response header has already been sent, so the response can no longer be changed.
+    HeaderSent,
+    /// The method is not allowed in the current request processing phase.
+    InvalidPhase,
+    /// The value passed to the method is not valid.
+    InvalidValue,
+    /// Memory allocation failed.
+    Allocation,
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::HeaderSent => f.write_str("response header already sent"),
+            RequestError::InvalidPhase => f.write_str("not allowed in the current request phase"),
+            RequestError::InvalidValue => f.write_str("invalid value"),
+            RequestError::Allocation => f.write_str("memory allocation failed"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
 impl Request {
     /// Create a [`Request`] from an [`ngx_http_request_t`].
     ///
@@ -110,6 +143,30 @@ impl Request {
         std::ptr::eq(self, main)
     }
 
+    /// Is this a subrequest, e.g. of SSI or `auth_request`?
+    ///
+    /// Filters usually act on the main request only, as the output of a subrequest is a part of
+    /// the main response, or is not sent to the client at all.
+    pub fn is_subrequest(&self) -> bool {
+        !self.is_main()
+    }
+
+    /// The main request, the request itself if it is not a subrequest.
+    pub fn main(&self) -> &Request {
+        // SAFETY: the main request outlives its subrequests, and shares the representation of
+        // `Request`.
+        unsafe { &*self.0.main.cast::<Request>() }
+    }
+
+    /// The request that created this subrequest, or `None` for the main request.
+    ///
+    /// The parent of a nested subrequest is another subrequest.
+    pub fn parent(&self) -> Option<&Request> {
+        // SAFETY: the parent request outlives its subrequests, and shares the representation of
+        // `Request`.
+        unsafe { self.0.parent.cast::<Request>().as_ref() }
+    }
+
     /// Request pool.
     pub fn pool(&self) -> Pool {
         // SAFETY: This request is allocated from `pool`, thus must be a valid pool.
@@ -131,6 +188,76 @@ impl Request {
         Some(self.0.upstream)
     }
 
+    /// Overrides the upstream server of the request.
+    ///
+    /// The request must have an upstream created with `ngx_http_upstream_create`, and the target
+    /// is only used if set before the upstream is started with `ngx_http_upstream_init`.
+    ///
+    /// Returns `None` if the request has no upstream, the target cannot be parsed, or memory
+    /// cannot be allocated.
+    pub fn set_upstream_target(&mut self, target: UpstreamTarget) -> Option<()> {
+        let u = self.upstream()?;
+        let pool = self.0.pool;
+
+        unsafe {
+            let ur =
+                ngx_pcalloc(pool, mem::size_of::<ngx_http_upstream_resolved_t>()) as *mut ngx_http_upstream_resolved_t;
+            if ur.is_null() {
+                return None;
+            }
+
+            match target {
+                UpstreamTarget::Group(name) => {
+                    (*ur).host = ngx_str_t::from_str(pool, name);
+                    (*ur).no_port = 1;
+                }
+                UpstreamTarget::Host(host, port) => {
+                    (*ur).host = ngx_str_t::from_str(pool, host);
+                    (*ur).port = port as in_port_t;
+                }
+                UpstreamTarget::Addr(addr) => {
+                    let text = ngx_str_t::from_string(pool, addr.to_string());
+                    let mut parsed: ngx_addr_t = mem::zeroed();
+                    if text.data.is_null()
+                        || ngx_parse_addr_port(pool, &mut parsed, text.data, text.len) != NGX_OK as ngx_int_t
+                    {
+                        return None;
+                    }
+
+                    (*ur).sockaddr = parsed.sockaddr;
+                    (*ur).socklen = parsed.socklen;
+                    (*ur).name = parsed.name;
+                    (*ur).naddrs = 1;
+                    (*ur).host = parsed.name;
+                    (*ur).port = addr.port() as in_port_t;
+                }
+            }
+
+            if (*ur).host.data.is_null() {
+                return None;
+            }
+
+            (*u).resolved = ur;
+        }
+
+        Some(())
+    }
+
+    /// Per-try upstream records of the request, in the order the tries were made.
+    ///
+    /// The records are complete once the upstream is finalized, e.g. in the log phase.
+    pub fn upstream_states(&self) -> &[UpstreamState] {
+        let states = self.0.upstream_states;
+        // SAFETY: `upstream_states` is an array of `ngx_http_upstream_state_t` allocated from the
+        // request pool, which shares the representation of `UpstreamState`.
+        unsafe {
+            if states.is_null() || (*states).nelts == 0 {
+                return &[];
+            }
+            std::slice::from_raw_parts((*states).elts as *const UpstreamState, (*states).nelts)
+        }
+    }
+
     /// Pointer to a [`ngx_connection_t`] client connection object.
     ///
     /// [`ngx_connection_t`]: https://nginx.org/en/docs/dev/development_guide.html#connection
@@ -145,6 +272,18 @@ impl Request {
         unsafe { (*self.connection()).log }
     }
 
+    /// The [`Resolver`] configured for the location with the `resolver` and `resolver_timeout`
+    /// directives.
+    ///
+    /// Lookups fail with [`ResolverError::NotConfigured`] if no `resolver` is configured.
+    pub fn resolver(&self) -> Option<Resolver> {
+        unsafe {
+            let clcf = *self.0.loc_conf.add(ngx_http_core_module.ctx_index) as *mut ngx_http_core_loc_conf_t;
+            let timeout = Duration::from_millis((*clcf).resolver_timeout as u64);
+            Resolver::from_ngx_resolver((*clcf).resolver, timeout)
+        }
+    }
+
     /// Module location configuration.
     fn get_module_loc_conf_ptr(&self, module: &ngx_module_t) -> *mut c_void {
         unsafe { *self.0.loc_conf.add(module.ctx_index) }
@@ -185,6 +324,42 @@ impl Request {
         };
     }
 
+    /// Get Module context for modification
+    pub fn get_module_ctx_mut<T>(&mut self, module: &ngx_module_t) -> Option<&mut T> {
+        let cf = self.get_module_ctx_ptr(module) as *mut T;
+        unsafe { cf.as_mut() }
+    }
+
+    /// Allocates `value` from the request pool and sets it as the module's context, returning a
+    /// reference to it.
+    ///
+    /// The value is dropped when the request pool is destroyed, even if the context is replaced
+    /// in the meantime or reset by an internal redirect. The context is found again with
+    /// [`Request::get_module_ctx`] and [`Request::get_module_ctx_mut`] with the same type:
+    ///
+    /// ```rust,ignore
+    /// let module = unsafe { &*addr_of!(ngx_http_my_module) };
+    /// let ctx = match request.get_module_ctx_mut::<MyCtx>(module) {
+    ///     Some(ctx) => ctx,
+    ///     None => match request.insert_module_ctx(module, MyCtx::default()) {
+    ///         Some(ctx) => ctx,
+    ///         None => return Status::NGX_ERROR,
+    ///     },
+    /// };
+    /// ```
+    ///
+    /// Returns `None` if the value cannot be allocated.
+    ///
+    /// See https://nginx.org/en/docs/dev/development_guide.html#http_request
+    pub fn insert_module_ctx<T>(&mut self, module: &ngx_module_t, value: T) -> Option<&mut T> {
+        let ctx = self.pool().allocate(value);
+        if ctx.is_null() {
+            return None;
+        }
+        self.set_module_ctx(ctx as *mut c_void, module);
+        unsafe { ctx.as_mut() }
+    }
+
     /// Get the value of a [complex value].
     ///
     /// [complex value]: https://nginx.org/en/docs/dev/development_guide.html#http_complex_values
@@ -220,9 +395,37 @@ impl Request {
         }
     }
 
-    /// Set HTTP status of response.
-    pub fn set_status(&mut self, status: HTTPStatus) {
+    /// Set HTTP status of response, optionally overriding the reason phrase.
+    ///
+    /// The reason phrase is sent in the HTTP/1.x status line (`headers_out.status_line`) instead
+    /// of the default phrase for the status code; HTTP/2 and HTTP/3 responses carry no reason
+    /// phrase. Without a reason, any previously set status line is cleared.
+    ///
+    /// Fails if the response header has already been sent, or if the reason contains control
+    /// characters or cannot be allocated.
+    pub fn set_status(&mut self, status: HTTPStatus, reason: Option<&str>) -> Result<(), RequestError> {
+        self.check_header_not_sent()?;
+
+        let status_line = match reason {
+            Some(reason) => {
+                if reason.bytes().any(|b| b.is_ascii_control()) {
+                    return Err(RequestError::InvalidValue);
+                }
+
+                let line = format!("{} {}", status.0, reason);
+                let data = self.pool().alloc(line.len()) as *mut u_char;
+                if data.is_null() {
+                    return Err(RequestError::Allocation);
+                }
+                unsafe { std::ptr::copy_nonoverlapping(line.as_ptr(), data, line.len()) };
+                ngx_str_t { len: line.len(), data }
+            }
+            None => ngx_null_string!(),
+        };
+
         self.0.headers_out.status = status.into();
+        self.0.headers_out.status_line = status_line;
+        Ok(())
     }
 
     /// Add header to the `headers_in` object.
@@ -233,28 +436,326 @@ impl Request {
         unsafe { add_to_ngx_table(table, self.0.pool, key, value) }
     }
 
+    /// Set a header of the `headers_in` object, replacing the value of the existing fields with
+    /// the same name, e.g. to override a header passed to the upstream server.
+    ///
+    /// The header is added if the request has no such field, and is then linked from the
+    /// `headers_in` member NGINX keeps for it, e.g. `headers_in.authorization`. Values derived
+    /// from the header when it was parsed, such as the content length, are not updated.
+    pub fn set_header_in(&mut self, key: &str, value: &str) -> Result<(), RequestError> {
+        let value = self.alloc_field_value(value)?;
+
+        let mut found = false;
+        let mut part: *mut ngx_list_part_t = &mut self.0.headers_in.headers.part;
+        unsafe {
+            while !part.is_null() {
+                let elts = (*part).elts as *mut ngx_table_elt_t;
+                for i in 0..(*part).nelts {
+                    let h = &mut *elts.add(i);
+                    // deleted fields are kept in the list with a zero hash
+                    if h.hash != 0
+                        && NgxStr::from_ngx_str(h.key)
+                            .as_bytes()
+                            .eq_ignore_ascii_case(key.as_bytes())
+                    {
+                        h.value = value;
+                        found = true;
+                    }
+                }
+                part = (*part).next;
+            }
+        }
+
+        if !found {
+            let h = unsafe { ngx_list_push(&mut self.0.headers_in.headers) as *mut ngx_table_elt_t };
+            if h.is_null() {
+                return Err(RequestError::Allocation);
+            }
+            unsafe {
+                std::ptr::write_bytes(h, 0, 1);
+                add_to_ngx_table(h, self.0.pool, key, "").ok_or(RequestError::Allocation)?;
+                (*h).value = value;
+                (*h).hash = ngx_hash_key((*h).lowcase_key, (*h).key.len);
+                self.link_header_in(h);
+            }
+        }
+        Ok(())
+    }
+
+    /// Links the added header field `h` from its member of `headers_in`, as NGINX does for the
+    /// fields of the client request.
+    ///
+    /// # Safety
+    ///
+    /// `h` is a valid field of the `headers_in` list with its hash and lowercase key set.
+    unsafe fn link_header_in(&mut self, h: *mut ngx_table_elt_t) {
+        let cmcf = *self.0.main_conf.add(ngx_http_core_module.ctx_index) as *mut ngx_http_core_main_conf_t;
+        let hh = ngx_hash_find(&mut (*cmcf).headers_in_hash, (*h).hash, (*h).lowcase_key, (*h).key.len)
+            as *mut ngx_http_header_t;
+        if hh.is_null() || (*hh).offset == 0 {
+            return;
+        }
+
+        // the fields with the same name are chained, see `ngx_http_process_header_line`
+        let mut ph =
+            (std::ptr::addr_of_mut!(self.0.headers_in) as *mut u8).add((*hh).offset) as *mut *mut ngx_table_elt_t;
+        while !(*ph).is_null() {
+            ph = std::ptr::addr_of_mut!((**ph).next);
+        }
+        *ph = h;
+        (*h).next = std::ptr::null_mut();
+    }
+
     /// Add header to the `headers_out` object.
     ///
+    /// Fails if the response header has already been sent or memory cannot be allocated.
+    ///
     /// See https://nginx.org/en/docs/dev/development_guide.html#http_request
-    pub fn add_header_out(&mut self, key: &str, value: &str) -> Option<()> {
-        let table: *mut ngx_table_elt_t = unsafe { ngx_list_push(&mut self.0.headers_out.headers) as _ };
-        unsafe { add_to_ngx_table(table, self.0.pool, key, value) }
+    pub fn add_header_out(&mut self, key: &str, value: &str) -> Result<(), RequestError> {
+        self.check_header_not_sent()?;
+        self.push_header_out(key, value).map(|_| ())
     }
 
     /// Set response body [Content-Length].
     ///
+    /// Fails if the response header has already been sent.
+    ///
     /// [Content-Length]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Length
-    pub fn set_content_length_n(&mut self, n: usize) {
+    pub fn set_content_length_n(&mut self, n: usize) -> Result<(), RequestError> {
+        self.check_header_not_sent()?;
         self.0.headers_out.content_length_n = n as off_t;
+        Ok(())
+    }
+
+    /// Set the response [Content-Length], or remove it with `None`, e.g. when a filter changes
+    /// the length of the body.
+    ///
+    /// Fails if the response header has already been sent.
+    ///
+    /// [Content-Length]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Length
+    pub fn set_content_length(&mut self, length: Option<u64>) -> Result<(), RequestError> {
+        self.check_header_not_sent()?;
+
+        // equivalent of the `ngx_http_clear_content_length` macro
+        if let Some(h) = unsafe { self.0.headers_out.content_length.as_mut() } {
+            h.hash = 0;
+            self.0.headers_out.content_length = std::ptr::null_mut();
+        }
+        self.0.headers_out.content_length_n = match length {
+            Some(length) => off_t::try_from(length).map_err(|_| RequestError::InvalidValue)?,
+            None => -1,
+        };
+        Ok(())
+    }
+
+    /// Set the response [Content-Type], e.g. `text/html; charset=utf-8`.
+    ///
+    /// Fails if the response header has already been sent, or if the value contains control
+    /// characters or cannot be allocated.
+    ///
+    /// [Content-Type]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Type
+    pub fn set_content_type(&mut self, content_type: &str) -> Result<(), RequestError> {
+        self.check_header_not_sent()?;
+        let value = self.alloc_field_value(content_type)?;
+
+        self.0.headers_out.content_type = value;
+        self.0.headers_out.content_type_len = content_type.find(';').unwrap_or(content_type.len());
+        self.0.headers_out.content_type_lowcase = std::ptr::null_mut();
+        self.0.headers_out.content_type_hash = 0;
+        Ok(())
+    }
+
+    /// Set the response [ETag], or remove it with `None`.
+    ///
+    /// NGINX compares the tag with the `If-None-Match` and `If-Match` request headers, answering
+    /// with `304 Not Modified` or `412 Precondition Failed`. A weak tag never matches `If-Match`.
+    ///
+    /// Fails if the response header has already been sent.
+    ///
+    /// [ETag]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/ETag
+    pub fn set_etag(&mut self, etag: Option<&Etag>) -> Result<(), RequestError> {
+        self.check_header_not_sent()?;
+
+        // equivalent of the `ngx_http_clear_etag` macro
+        if let Some(h) = unsafe { self.0.headers_out.etag.as_mut() } {
+            h.hash = 0;
+            self.0.headers_out.etag = std::ptr::null_mut();
+        }
+        if let Some(etag) = etag {
+            self.0.headers_out.etag = self.push_header_out("ETag", &etag.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Set the HTTP/1.x status line of the response, e.g. `200 OK`, sent instead of the status
+    /// line derived from the response status. Prefer [`Request::set_status`], which keeps the
+    /// status code and the status line consistent.
+    ///
+    /// Fails if the response header has already been sent, or if the line contains control
+    /// characters or cannot be allocated.
+    pub fn set_status_line(&mut self, status_line: &str) -> Result<(), RequestError> {
+        self.check_header_not_sent()?;
+        self.0.headers_out.status_line = self.alloc_field_value(status_line)?;
+        Ok(())
+    }
+
+    /// Set the response [Location], replacing the current one.
+    ///
+    /// Relative locations are made absolute by NGINX according to the
+    /// `absolute_redirect` directive.
+    ///
+    /// Fails if the response header has already been sent, or if the location contains control
+    /// characters or cannot be allocated.
+    ///
+    /// [Location]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Location
+    pub fn set_location(&mut self, location: &str) -> Result<(), RequestError> {
+        self.check_header_not_sent()?;
+        let value = self.alloc_field_value(location)?;
+
+        if let Some(h) = unsafe { self.0.headers_out.location.as_mut() } {
+            h.value = value;
+            return Ok(());
+        }
+
+        let h = self.push_header_out("Location", location)?;
+        self.0.headers_out.location = h;
+        Ok(())
+    }
+
+    /// Redirects the client to `location` with a redirect `status`, as the `return` directive
+    /// does, and returns the status to return from the handler.
+    ///
+    /// The response body is generated by NGINX, or by an `error_page` for the status. A
+    /// location computed from a complex value is obtained with [`Request::get_complex_value`]:
+    ///
+    /// ```rust,ignore
+    /// let location = request.get_complex_value(&conf.location).ok_or(Status::NGX_ERROR)?;
+    /// request.redirect(HTTPStatus::MOVED_PERMANENTLY, location.to_str()?)
+    /// ```
+    ///
+    /// Fails with [`RequestError::InvalidValue`] if `status` is not one of 301, 302, 303, 307
+    /// and 308, or as [`Request::set_location`].
+    pub fn redirect(&mut self, status: HTTPStatus, location: &str) -> Result<Status, RequestError> {
+        if !status.is_redirect() {
+            return Err(RequestError::InvalidValue);
+        }
+        self.set_location(location)?;
+        Ok(status.into())
+    }
+
+    /// Copies a header field value to the request pool, rejecting control characters.
+    fn alloc_field_value(&self, value: &str) -> Result<ngx_str_t, RequestError> {
+        if value.bytes().any(|b| b.is_ascii_control()) {
+            return Err(RequestError::InvalidValue);
+        }
+        let value = unsafe { ngx_str_t::from_str(self.0.pool, value) };
+        if value.data.is_null() {
+            return Err(RequestError::Allocation);
+        }
+        Ok(value)
+    }
+
+    /// Adds a zero-initialized header field to the `headers_out` list.
+    fn push_header_out(&mut self, key: &str, value: &str) -> Result<*mut ngx_table_elt_t, RequestError> {
+        unsafe {
+            let h = ngx_list_push(&mut self.0.headers_out.headers) as *mut ngx_table_elt_t;
+            if h.is_null() {
+                return Err(RequestError::Allocation);
+            }
+            std::ptr::write_bytes(h, 0, 1);
+            add_to_ngx_table(h, self.0.pool, key, value).ok_or(RequestError::Allocation)?;
+            Ok(h)
+        }
+    }
+
+    /// Returns `true` if the response header has been sent and can no longer be changed.
+    pub fn header_sent(&self) -> bool {
+        self.0.header_sent() != 0
+    }
+
+    fn check_header_not_sent(&self) -> Result<(), RequestError> {
+        if self.header_sent() {
+            return Err(RequestError::HeaderSent);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the request is in the server or location rewrite phase.
+    pub fn in_rewrite_phase(&self) -> bool {
+        // SAFETY: the core module main configuration always exists, and `phase_handler` indexes
+        // the phase engine while the request runs the phases.
+        unsafe {
+            let cmcf = *self.0.main_conf.add(ngx_http_core_module.ctx_index) as *mut ngx_http_core_main_conf_t;
+            if cmcf.is_null() || (*cmcf).phase_engine.handlers.is_null() || self.0.phase_handler < 0 {
+                return false;
+            }
+
+            let checker = (*(*cmcf).phase_engine.handlers.add(self.0.phase_handler as usize)).checker;
+            checker.map(|checker| checker as usize) == Some(ngx_http_core_rewrite_phase as usize)
+        }
+    }
+
+    /// Rewrites the URI path of the request, like the `rewrite` directive without arguments.
+    ///
+    /// Only allowed in the server and location rewrite phases, before the location is selected
+    /// or while it is still possible to select another one. A new location is searched for if
+    /// the URI is changed in the location rewrite phase.
+    pub fn set_uri(&mut self, uri: &str) -> Result<(), RequestError> {
+        if !self.in_rewrite_phase() {
+            return Err(RequestError::InvalidPhase);
+        }
+        if !uri.starts_with('/') || uri.bytes().any(|b| b.is_ascii_control() || b == b'?') {
+            return Err(RequestError::InvalidValue);
+        }
+
+        let data = self.pool().alloc(uri.len()) as *mut u_char;
+        if data.is_null() {
+            return Err(RequestError::Allocation);
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(uri.as_ptr(), data, uri.len());
+            self.0.uri = ngx_str_t { len: uri.len(), data };
+            self.0.set_valid_unparsed_uri(0);
+            self.0.set_uri_changed(1);
+            ngx_http_set_exten(&mut self.0);
+        }
+
+        Ok(())
     }
 
     /// Send the output header.
     ///
-    /// Do not call this function until all output headers are set.
+    /// Do not call this function until all output headers are set. Calling it a second time
+    /// makes NGINX log a "header already sent" alert; see [`Request::try_send_header`].
     pub fn send_header(&mut self) -> Status {
         unsafe { Status(ngx_http_send_header(&mut self.0)) }
     }
 
+    /// Send the output header, unless it has already been sent.
+    ///
+    /// Fails with [`RequestError::HeaderSent`] without calling NGINX if the header has been
+    /// sent, or if part of the body is already waiting to be written to the client. Otherwise,
+    /// returns the status of `ngx_http_send_header`: the body must not be sent if it is
+    /// `NGX_ERROR`, greater than `NGX_OK`, or if the request is [header only](Self::header_only).
+    ///
+    /// ```rust,ignore
+    /// let rc = match request.try_send_header() {
+    ///     Ok(rc) => rc,
+    ///     Err(_) => return Status::NGX_ERROR,
+    /// };
+    /// if rc == Status::NGX_ERROR || rc.0 > Status::NGX_OK.0 || request.header_only() {
+    ///     return rc;
+    /// }
+    /// ```
+    pub fn try_send_header(&mut self) -> Result<Status, RequestError> {
+        self.check_header_not_sent()?;
+        if !self.0.out.is_null() {
+            return Err(RequestError::HeaderSent);
+        }
+        Ok(self.send_header())
+    }
+
     /// Flag indicating that the output does not require a body.
     ///
     /// For example, this flag is used by `HTTP HEAD` requests.
@@ -264,7 +765,7 @@ impl Request {
 
     /// request method
     pub fn method(&self) -> Method {
-        Method::from_ngx(self.0.method)
+        method_from_ngx(self.0.method)
     }
 
     /// path part of request only
@@ -277,6 +778,41 @@ impl Request {
         unsafe { NgxStr::from_ngx_str(self.0.unparsed_uri) }
     }
 
+    /// The normalized URI of the request, without the arguments, as in the `$uri` variable.
+    ///
+    /// Unlike [`Request::unparsed_uri`], the URI is unescaped and changes with internal
+    /// redirects and rewrites.
+    pub fn uri(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.uri) }
+    }
+
+    /// The arguments of the request line, without the `?`, as in the `$args` variable.
+    pub fn args(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.args) }
+    }
+
+    /// Returns an iterator over the key/value pairs of the arguments, not unescaped.
+    pub fn args_iter(&self) -> QueryArgs<'_> {
+        QueryArgs::new(self.args())
+    }
+
+    /// The extension of the URI, without the dot, e.g. `html`, or an empty string.
+    pub fn exten(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.exten) }
+    }
+
+    /// The original request line, as in the `$request` variable.
+    ///
+    /// HTTP/2 and HTTP/3 requests have a request line built from the pseudo-headers.
+    pub fn request_line(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.request_line) }
+    }
+
+    /// The protocol of the request line, e.g. `HTTP/1.1`, or an empty string for HTTP/0.9.
+    pub fn http_protocol(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.http_protocol) }
+    }
+
     /// Send the [response body].
     ///
     /// This function can be called multiple times.
@@ -287,6 +823,114 @@ impl Request {
         unsafe { Status(ngx_http_output_filter(&mut self.0, body)) }
     }
 
+    /// Send the buffers `bufs` as a part of the [response body], linking them in a chain.
+    ///
+    /// This function can be called multiple times, as [`Request::output_filter`]. Set the
+    /// `last_buf` flag in the last body buffer of the main request.
+    ///
+    /// [response body]: https://nginx.org/en/docs/dev/development_guide.html#http_request_body
+    pub fn output<B: Buffer>(&mut self, bufs: &mut [B]) -> Status {
+        let mut links: Vec<ngx_chain_t> = bufs
+            .iter_mut()
+            .map(|buf| ngx_chain_t {
+                buf: buf.as_ngx_buf_mut(),
+                next: std::ptr::null_mut(),
+            })
+            .collect();
+        for i in 1..links.len() {
+            let next: *mut ngx_chain_t = &mut links[i];
+            links[i - 1].next = next;
+        }
+
+        match links.first_mut() {
+            Some(out) => self.output_filter(out),
+            None => Status::NGX_OK,
+        }
+    }
+
+    /// Sends a complete response with `status`, the header fields `headers`, and `body`.
+    ///
+    /// A `Content-Type` field sets the response content type, and the `Content-Length` is set to
+    /// the length of the body. The body is copied to the request pool and is not sent for `HEAD`
+    /// requests.
+    ///
+    /// Returns the status of the output, to be returned from a content handler or passed to
+    /// `ngx_http_finalize_request`.
+    ///
+    /// ```rust,ignore
+    /// http_request_handler!(hello_handler, |request: &mut Request| {
+    ///     request.send_response(HTTPStatus::OK, &[("Content-Type", "text/plain")], b"Hello\n")
+    /// });
+    /// ```
+    pub fn send_response(&mut self, status: HTTPStatus, headers: &[(&str, &str)], body: &[u8]) -> Status {
+        if self.set_status(status, None).is_err() || self.set_content_length_n(body.len()).is_err() {
+            return Status::NGX_ERROR;
+        }
+        for (name, value) in headers {
+            let result = if name.eq_ignore_ascii_case("content-type") {
+                self.set_content_type(value)
+            } else {
+                self.add_header_out(name, value)
+            };
+            if result.is_err() {
+                return Status::NGX_ERROR;
+            }
+        }
+
+        let Ok(rc) = self.try_send_header() else {
+            return Status::NGX_ERROR;
+        };
+        if rc == Status::NGX_ERROR || rc.0 > Status::NGX_OK.0 || self.header_only() {
+            return rc;
+        }
+
+        if body.is_empty() {
+            return unsafe { Status(ngx_http_send_special(&mut self.0, NGX_HTTP_LAST as ngx_uint_t)) };
+        }
+
+        let Some(mut buf) = self.pool().create_buffer_from_bytes(body) else {
+            return Status::NGX_ERROR;
+        };
+        buf.set_last_buf(self.is_main());
+        buf.set_last_in_chain(true);
+        self.output(&mut [buf])
+    }
+
+    /// Sends a `400 Bad Request` response with the JSON description of `err` as the body.
+    ///
+    /// Returns the status of the output, to be passed to `ngx_http_finalize_request` or
+    /// returned from a content handler.
+    pub fn send_json_error(&mut self, err: &JsonError) -> Status {
+        let body = err.to_json();
+        self.send_response(
+            HTTPStatus::BAD_REQUEST,
+            &[("Content-Type", "application/json")],
+            body.as_bytes(),
+        )
+    }
+
+    /// Sends a `200 OK` response with the location configuration of `module` for the request, after
+    /// merging, as a JSON object described by [`DescribeConf`].
+    ///
+    /// This is the content handler of an admin endpoint showing the values a location inherited,
+    /// so the configuration must redact its secrets, see [`RedactedConf`]:
+    ///
+    /// ```rust,ignore
+    /// http_request_handler!(conf_dump_handler, |request: &mut Request| {
+    ///     request.send_conf_dump::<LocConf>(unsafe { &*addr_of!(ngx_http_my_module) })
+    /// });
+    /// ```
+    ///
+    /// The endpoint should be restricted, e.g. with `allow` and `deny`, as the description still
+    /// exposes the upstream addresses and paths of the configuration.
+    pub fn send_conf_dump<C: RedactedConf>(&mut self, module: &ngx_module_t) -> Status {
+        let Some(conf) = self.get_module_loc_conf::<C>(module) else {
+            return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+        };
+        let body = conf.dump().to_json();
+        self.send_response(HTTPStatus::OK, &[("Content-Type", "application/json")], body.as_bytes())
+    }
+
     /// Perform internal redirect to a location
     pub fn internal_redirect(&self, location: &str) -> Status {
         assert!(!location.is_empty(), "uri location is empty");
@@ -309,63 +953,153 @@ impl Request {
         Status::NGX_DONE
     }
 
-    /// Send a subrequest
-    pub fn subrequest(
-        &self,
-        uri: &str,
-        module: &ngx_module_t,
-        post_callback: unsafe extern "C" fn(*mut ngx_http_request_t, *mut c_void, ngx_int_t) -> ngx_int_t,
-    ) -> Status {
-        let uri_ptr = unsafe { &mut ngx_str_t::from_str(self.0.pool, uri) as *mut _ };
-        // -------------
-        // allocate memory and set values for ngx_http_post_subrequest_t
-        let sub_ptr = self.pool().alloc(std::mem::size_of::<ngx_http_post_subrequest_t>());
-
-        // assert!(sub_ptr.is_null());
-        let post_subreq = sub_ptr as *const ngx_http_post_subrequest_t as *mut ngx_http_post_subrequest_t;
+    /// Arms a timer that invokes `on_timeout` if it expires before the request terminates.
+    ///
+    /// The timer is allocated from the request pool and removed from the event loop by a request
+    /// cleanup handler once the request is freed or terminated. Request cleanups do not run when
+    /// the request is finalized, e.g. the request is kept during lingering close, so a timer
+    /// expiring after that is ignored, and `on_timeout` never runs for a finalized request.
+    /// Handlers waiting for external events can thus bound their latency without managing an
+    /// `ngx_event_t` themselves. Posted requests are run after `on_timeout` returns.
+    ///
+    /// Returns `None` if the timer cannot be allocated.
+    pub fn set_module_timeout<F>(&mut self, timeout: Duration, on_timeout: F) -> Option<RequestTimer>
+    where
+        F: FnOnce(&mut Request) + 'static,
+    {
+        let mut pool = self.pool();
+        let timer = pool.allocate(RequestTimeout {
+            // SAFETY: `ngx_event_t` is a plain C structure, all zeroes is its initial state.
+            event: unsafe { mem::zeroed() },
+            request: &mut self.0,
+            handler: Some(Box::new(on_timeout)),
+            finalized: false,
+        });
+        if timer.is_null() {
+            return None;
+        }
+
         unsafe {
-            (*post_subreq).handler = Some(post_callback);
-            (*post_subreq).data = self.get_module_ctx_ptr(module); // WARN: safety! ensure that ctx is already set
-        }
-        // -------------
-
-        let mut psr: *mut ngx_http_request_t = std::ptr::null_mut();
-        let r = unsafe {
-            ngx_http_subrequest(
-                (self as *const Request as *mut Request).cast(),
-                uri_ptr,
-                std::ptr::null_mut(),
-                &mut psr as *mut _,
-                sub_ptr as *mut _,
-                NGX_HTTP_SUBREQUEST_WAITED as _,
-            )
-        };
+            // the timer is not armed yet, and is dropped with the pool on failure
+            let cln = ngx_http_cleanup_add(&mut self.0, 0);
+            if cln.is_null() {
+                return None;
+            }
+            (*cln).handler = Some(ngx_http_request_timeout_cleanup);
+            (*cln).data = timer as *mut c_void;
 
-        // previously call of ngx_http_subrequest() would ensure that the pointer is not null anymore
-        let sr = unsafe { &mut *psr };
+            (*timer).event.data = timer as *mut c_void;
+            (*timer).event.handler = Some(ngx_http_request_timeout_handler);
+            (*timer).event.log = self.log();
+            ngx_add_timer(&mut (*timer).event, duration_to_msec(timeout));
+        }
 
-        /*
-         * allocate fake request body to avoid attempts to read it and to make
-         * sure real body file (if already read) won't be closed by upstream
-         */
-        sr.request_body = self.pool().alloc(std::mem::size_of::<ngx_http_request_body_t>()) as *mut _;
+        Some(RequestTimer(timer))
+    }
 
-        if sr.request_body.is_null() {
-            return Status::NGX_ERROR;
+    /// Registers `handler` to be invoked when the request terminates.
+    ///
+    /// Request cleanup handlers are registered with `ngx_http_cleanup_add` and run when the main
+    /// request is freed or terminated, before the request pool is destroyed. This makes them
+    /// suitable for releasing external resources, such as file locks or sidecar sessions, that
+    /// must not outlive the request. They do not run when the request is finalized, as a
+    /// finalized request can be kept, e.g. during lingering close.
+    ///
+    /// Returns `None` if the handler cannot be registered.
+    pub fn add_cleanup<F>(&mut self, handler: F) -> Option<()>
+    where
+        F: FnOnce() + 'static,
+    {
+        let mut pool = self.pool();
+        let data = pool.allocate(Some(handler));
+        if data.is_null() {
+            return None;
         }
-        sr.set_header_only(1 as _);
-        Status(r)
+
+        unsafe {
+            let cln = ngx_http_cleanup_add(&mut self.0, 0);
+            if cln.is_null() {
+                return None;
+            }
+            (*cln).handler = Some(ngx_http_cleanup_handler::<F>);
+            (*cln).data = data as *mut c_void;
+        }
+
+        Some(())
+    }
+
+    /// Registers `callback` to be invoked if the client closes the connection before the request
+    /// is complete.
+    ///
+    /// The read event handler of the request is replaced with `ngx_http_test_reading`, which
+    /// terminates the request once the client goes away; long-running handlers can use the
+    /// callback to cancel their background work. The callback is not invoked if the request
+    /// finishes normally.
+    ///
+    /// This must not be used before the request body is fully read or discarded: the read event
+    /// handler is then the one reading the body, and replacing it would stall the request.
+    ///
+    /// Returns `None` if the callback cannot be registered, including while the request body is
+    /// being read or discarded. The read event handler of the request is then left unchanged.
+    pub fn on_client_abort<F>(&mut self, callback: F) -> Option<()>
+    where
+        F: FnOnce(&mut Request) + 'static,
+    {
+        let rb = unsafe { self.0.request_body.as_ref() };
+        if self.0.reading_body() != 0 || self.0.discard_body() != 0 || rb.is_some_and(|rb| rb.rest > 0) {
+            return None;
+        }
+
+        // arm the read event first, so that a failure leaves no callback registered
+        let read_event_handler = self.0.read_event_handler;
+        self.0.read_event_handler = Some(ngx_http_test_reading);
+        // level-triggered event methods only report the close with an active read event
+        if unsafe { ngx_handle_read_event((*self.connection()).read, 0) } != NGX_OK as ngx_int_t {
+            self.0.read_event_handler = read_event_handler;
+            return None;
+        }
+
+        let r: *mut ngx_http_request_t = &mut self.0;
+        let registered = self.add_cleanup(move || unsafe {
+            // `ngx_http_test_reading` flags the connection before terminating the request
+            if (*(*r).connection).error() != 0 {
+                callback(Request::from_ngx_http_request(r));
+            }
+        });
+        if registered.is_none() {
+            self.0.read_event_handler = read_event_handler;
+        }
+
+        registered
     }
 
     /// Iterate over headers_in
-    /// each header item is (String, String) (copied)
-    pub fn headers_in_iterator(&self) -> NgxListIterator {
+    /// each header item is (&NgxStr, &NgxStr), borrowed from the request
+    pub fn headers_in_iterator(&self) -> NgxListIterator<'_> {
         unsafe { list_iterator(&self.0.headers_in.headers) }
     }
 
+    /// Returns the value of the first request header field named `name`, ignoring case.
+    ///
+    /// Header fields repeated in the request, e.g. `Cookie` or `X-Forwarded-For`, are not
+    /// combined; [`Request::headers_in_multi`] returns all of them.
+    pub fn header_in(&self, name: &str) -> Option<&NgxStr> {
+        self.headers_in_iterator()
+            .find(|(key, _)| key.as_bytes().eq_ignore_ascii_case(name.as_bytes()))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over the values of the request header fields named `name`, ignoring
+    /// case, in the order of the request.
+    pub fn headers_in_multi<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a NgxStr> + 'a {
+        self.headers_in_iterator()
+            .filter(move |(key, _)| key.as_bytes().eq_ignore_ascii_case(name.as_bytes()))
+            .map(|(_, value)| value)
+    }
+
     /// Iterate over headers_out
-    /// each header item is (String, String) (copied)
-    pub fn headers_out_iterator(&self) -> NgxListIterator {
+    /// each header item is (&NgxStr, &NgxStr), borrowed from the request
+    pub fn headers_out_iterator(&self) -> NgxListIterator<'_> {
         unsafe { list_iterator(&self.0.headers_out.headers) }
     }
 
@@ -375,325 +1109,205 @@ impl Request {
     }
 }
 
-// trait OnSubRequestDone {
-
-// }
-
-impl fmt::Debug for Request {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Request").field("request_", &self.0).finish()
-    }
-}
-
-/// Iterator for `ngx_list_t` types.
-///
-/// Implementes the std::iter::Iterator trait.
-pub struct NgxListIterator {
-    done: bool,
-    part: *const ngx_list_part_t,
-    h: *const ngx_table_elt_t,
-    i: ngx_uint_t,
+/// State of a timer armed with [`Request::set_module_timeout`], allocated from the request pool.
+struct RequestTimeout {
+    event: ngx_event_t,
+    request: *mut ngx_http_request_t,
+    handler: Option<Box<dyn FnOnce(&mut Request)>>,
+    /// `true` once the request cleanups ran, after which the timer is no longer armed.
+    finalized: bool,
 }
 
-// create new http request iterator
-/// # Safety
-///
-/// The caller has provided a valid `ngx_str_t` which can be dereferenced validly.
-pub unsafe fn list_iterator(list: *const ngx_list_t) -> NgxListIterator {
-    let part: *const ngx_list_part_t = &(*list).part;
-
-    NgxListIterator {
-        done: false,
-        part,
-        h: (*part).elts as *const ngx_table_elt_t,
-        i: 0,
+impl RequestTimeout {
+    /// Returns `true` if the request or its main request is finalized.
+    ///
+    /// # Safety
+    ///
+    /// The request the timer was armed for has not been freed yet.
+    unsafe fn is_finalized(&self) -> bool {
+        self.finalized || (*self.request).done() != 0 || (*(*self.request).main).done() != 0
     }
 }
 
-// iterator for ngx_list_t
-impl Iterator for NgxListIterator {
-    // type Item = (&str,&str);
-    // TODO: try to use str instead of string
-    // something like pub struct Header(ngx_table_elt_t);
-    // then header would have key and value
-
-    type Item = (String, String);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        unsafe {
-            if self.done {
-                None
-            } else {
-                if self.i >= (*self.part).nelts {
-                    if (*self.part).next.is_null() {
-                        self.done = true;
-                        return None;
-                    }
-
-                    // loop back
-                    self.part = (*self.part).next;
-                    self.h = (*self.part).elts as *mut ngx_table_elt_t;
-                    self.i = 0;
-                }
-
-                let header: *const ngx_table_elt_t = self.h.add(self.i);
-                let header_name: ngx_str_t = (*header).key;
-                let header_value: ngx_str_t = (*header).value;
-                self.i += 1;
-                Some((header_name.to_string(), header_value.to_string()))
-            }
+impl Drop for RequestTimeout {
+    fn drop(&mut self) {
+        // the timer is normally removed by the request cleanup handler already
+        if self.event.timer_set() != 0 {
+            // SAFETY: the event was armed by `set_module_timeout` and is still in the timer tree.
+            unsafe { ngx_del_timer(&mut self.event) };
         }
     }
 }
 
-/// A possible error value when converting `Method`
-pub struct InvalidMethod {
-    _priv: (),
-}
-
-/// Request method verb
-#[derive(Clone, PartialEq, Eq, Hash)]
-pub struct Method(MethodInner);
-
-impl Method {
-    /// UNKNOWN
-    pub const UNKNOWN: Method = Method(MethodInner::Unknown);
-
-    /// GET
-    pub const GET: Method = Method(MethodInner::Get);
-
-    /// HEAD
-    pub const HEAD: Method = Method(MethodInner::Head);
-
-    /// POST
-    pub const POST: Method = Method(MethodInner::Post);
-
-    /// PUT
-    pub const PUT: Method = Method(MethodInner::Put);
-
-    /// DELETE
-    pub const DELETE: Method = Method(MethodInner::Delete);
-
-    /// MKCOL
-    pub const MKCOL: Method = Method(MethodInner::Mkcol);
-
-    /// COPY
-    pub const COPY: Method = Method(MethodInner::Copy);
-
-    /// MOVE
-    pub const MOVE: Method = Method(MethodInner::Move);
+unsafe extern "C" fn ngx_http_request_timeout_handler(ev: *mut ngx_event_t) {
+    let timeout = (*ev).data as *mut RequestTimeout;
+    let r = (*timeout).request;
+    let c = (*r).connection;
 
-    /// OPTIONS
-    pub const OPTIONS: Method = Method(MethodInner::Options);
-
-    /// PROPFIND
-    pub const PROPFIND: Method = Method(MethodInner::Propfind);
-
-    /// PROPPATCH
-    pub const PROPPATCH: Method = Method(MethodInner::Proppatch);
-
-    /// LOCK
-    pub const LOCK: Method = Method(MethodInner::Lock);
-
-    /// UNLOCK
-    pub const UNLOCK: Method = Method(MethodInner::Unlock);
-
-    /// PATCH
-    pub const PATCH: Method = Method(MethodInner::Patch);
-
-    /// TRACE
-    pub const TRACE: Method = Method(MethodInner::Trace);
-
-    /// CONNECT
-    pub const CONNECT: Method = Method(MethodInner::Connect);
-
-    /// Convert a Method to a &str.
-    #[inline]
-    pub fn as_str(&self) -> &str {
-        match self.0 {
-            MethodInner::Unknown => "UNKNOWN",
-            MethodInner::Get => "GET",
-            MethodInner::Head => "HEAD",
-            MethodInner::Post => "POST",
-            MethodInner::Put => "PUT",
-            MethodInner::Delete => "DELETE",
-            MethodInner::Mkcol => "MKCOL",
-            MethodInner::Copy => "COPY",
-            MethodInner::Move => "MOVE",
-            MethodInner::Options => "OPTIONS",
-            MethodInner::Propfind => "PROPFIND",
-            MethodInner::Proppatch => "PROPPATCH",
-            MethodInner::Lock => "LOCK",
-            MethodInner::Unlock => "UNLOCK",
-            MethodInner::Patch => "PATCH",
-            MethodInner::Trace => "TRACE",
-            MethodInner::Connect => "CONNECT",
-        }
+    // the request is kept after it is finalized, e.g. during lingering close
+    if (*timeout).is_finalized() {
+        (*timeout).handler = None;
+        return;
     }
 
-    fn from_bytes(_t: &[u8]) -> Result<Method, InvalidMethod> {
-        todo!()
+    if let Some(handler) = (*timeout).handler.take() {
+        handler(Request::from_ngx_http_request(r));
     }
 
-    fn from_ngx(t: ngx_uint_t) -> Method {
-        let t = t as _;
-        match t {
-            NGX_HTTP_GET => Method(MethodInner::Get),
-            NGX_HTTP_HEAD => Method(MethodInner::Head),
-            NGX_HTTP_POST => Method(MethodInner::Post),
-            NGX_HTTP_PUT => Method(MethodInner::Put),
-            NGX_HTTP_DELETE => Method(MethodInner::Delete),
-            NGX_HTTP_MKCOL => Method(MethodInner::Mkcol),
-            NGX_HTTP_COPY => Method(MethodInner::Copy),
-            NGX_HTTP_MOVE => Method(MethodInner::Move),
-            NGX_HTTP_OPTIONS => Method(MethodInner::Options),
-            NGX_HTTP_PROPFIND => Method(MethodInner::Propfind),
-            NGX_HTTP_PROPPATCH => Method(MethodInner::Proppatch),
-            NGX_HTTP_LOCK => Method(MethodInner::Lock),
-            NGX_HTTP_UNLOCK => Method(MethodInner::Unlock),
-            NGX_HTTP_PATCH => Method(MethodInner::Patch),
-            NGX_HTTP_TRACE => Method(MethodInner::Trace),
-            NGX_HTTP_CONNECT => Method(MethodInner::Connect),
-            _ => Method(MethodInner::Unknown),
-        }
-    }
+    ngx_http_run_posted_requests(c);
 }
 
-impl AsRef<str> for Method {
-    #[inline]
-    fn as_ref(&self) -> &str {
-        self.as_str()
+/// Request cleanup handler removing a timer armed with [`Request::set_module_timeout`] once the
+/// request is freed or terminated. The timer itself is dropped with the request pool.
+unsafe extern "C" fn ngx_http_request_timeout_cleanup(data: *mut c_void) {
+    let timeout = &mut *(data as *mut RequestTimeout);
+    timeout.finalized = true;
+    if timeout.event.timer_set() != 0 {
+        ngx_del_timer(&mut timeout.event);
     }
+    timeout.handler = None;
 }
 
-impl<'a> PartialEq<&'a Method> for Method {
-    #[inline]
-    fn eq(&self, other: &&'a Method) -> bool {
-        self == *other
-    }
-}
-
-impl<'a> PartialEq<Method> for &'a Method {
-    #[inline]
-    fn eq(&self, other: &Method) -> bool {
-        *self == other
+/// Request cleanup handler invoking a closure registered with [`Request::add_cleanup`].
+///
+/// The closure itself is dropped with the request pool.
+unsafe extern "C" fn ngx_http_cleanup_handler<F: FnOnce()>(data: *mut c_void) {
+    if let Some(handler) = (*(data as *mut Option<F>)).take() {
+        handler();
     }
 }
 
-impl PartialEq<str> for Method {
-    #[inline]
-    fn eq(&self, other: &str) -> bool {
-        self.as_ref() == other
-    }
-}
+/// Handle to a timer armed with [`Request::set_module_timeout`].
+pub struct RequestTimer(*mut RequestTimeout);
 
-impl PartialEq<Method> for str {
-    #[inline]
-    fn eq(&self, other: &Method) -> bool {
-        self == other.as_ref()
+impl RequestTimer {
+    /// Returns `true` if the timer has neither expired nor been cancelled.
+    ///
+    /// # Safety
+    ///
+    /// The request the timer was armed for has not been freed yet.
+    pub unsafe fn is_pending(&self) -> bool {
+        (*self.0).event.timer_set() != 0
     }
-}
 
-impl<'a> PartialEq<&'a str> for Method {
-    #[inline]
-    fn eq(&self, other: &&'a str) -> bool {
-        self.as_ref() == *other
+    /// Arms the timer again with `on_timeout`, replacing the previous callback, e.g. to poll at
+    /// an interval without allocating a timer from the request pool each time.
+    ///
+    /// Nothing is done once the request is finalized.
+    ///
+    /// # Safety
+    ///
+    /// The request the timer was armed for has not been freed yet.
+    pub unsafe fn rearm<F>(&self, timeout: Duration, on_timeout: F)
+    where
+        F: FnOnce(&mut Request) + 'static,
+    {
+        let timeout_event = &mut *self.0;
+        if timeout_event.is_finalized() {
+            return;
+        }
+        timeout_event.handler = Some(Box::new(on_timeout));
+        ngx_add_timer(&mut timeout_event.event, duration_to_msec(timeout));
     }
-}
 
-impl<'a> PartialEq<Method> for &'a str {
-    #[inline]
-    fn eq(&self, other: &Method) -> bool {
-        *self == other.as_ref()
+    /// Disarms the timer, the timeout callback will not be invoked.
+    ///
+    /// # Safety
+    ///
+    /// The request the timer was armed for has not been freed yet.
+    pub unsafe fn cancel(self) {
+        let timeout = &mut *self.0;
+        if timeout.event.timer_set() != 0 {
+            ngx_del_timer(&mut timeout.event);
+        }
+        timeout.handler = None;
     }
 }
 
-impl fmt::Debug for Method {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(self.as_ref())
-    }
-}
+// trait OnSubRequestDone {
 
-impl fmt::Display for Method {
-    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt.write_str(self.as_ref())
-    }
-}
+// }
 
-impl<'a> From<&'a Method> for Method {
-    #[inline]
-    fn from(t: &'a Method) -> Self {
-        t.clone()
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request").field("request_", &self.0).finish()
     }
 }
 
-impl<'a> TryFrom<&'a [u8]> for Method {
-    type Error = InvalidMethod;
-
-    #[inline]
-    fn try_from(t: &'a [u8]) -> Result<Self, Self::Error> {
-        Method::from_bytes(t)
-    }
+/// Iterator for `ngx_list_t` types.
+///
+/// Implementes the std::iter::Iterator trait. Header names and values are borrowed from the list,
+/// no allocation is made while iterating.
+pub struct NgxListIterator<'a> {
+    part: *const ngx_list_part_t,
+    h: *const ngx_table_elt_t,
+    i: ngx_uint_t,
+    _list: PhantomData<&'a ngx_list_t>,
 }
 
-impl<'a> TryFrom<&'a str> for Method {
-    type Error = InvalidMethod;
+// create new http request iterator
+/// # Safety
+///
+/// The caller has provided a valid `ngx_list_t` of `ngx_table_elt_t` elements, which is not
+/// modified for the lifetime of the iterator.
+pub unsafe fn list_iterator<'a>(list: *const ngx_list_t) -> NgxListIterator<'a> {
+    let part: *const ngx_list_part_t = &(*list).part;
 
-    #[inline]
-    fn try_from(t: &'a str) -> Result<Self, Self::Error> {
-        TryFrom::try_from(t.as_bytes())
+    NgxListIterator {
+        part,
+        h: (*part).elts as *const ngx_table_elt_t,
+        i: 0,
+        _list: PhantomData,
     }
 }
 
-impl FromStr for Method {
-    type Err = InvalidMethod;
+// iterator for ngx_list_t
+impl<'a> Iterator for NgxListIterator<'a> {
+    type Item = (&'a NgxStr, &'a NgxStr);
 
-    #[inline]
-    fn from_str(t: &str) -> Result<Self, Self::Err> {
-        TryFrom::try_from(t)
-    }
-}
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            while self.i >= (*self.part).nelts {
+                if (*self.part).next.is_null() {
+                    return None;
+                }
 
-impl InvalidMethod {
-    #[allow(dead_code)]
-    fn new() -> InvalidMethod {
-        InvalidMethod { _priv: () }
-    }
-}
+                // loop back
+                self.part = (*self.part).next;
+                self.h = (*self.part).elts as *mut ngx_table_elt_t;
+                self.i = 0;
+            }
 
-impl fmt::Debug for InvalidMethod {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("InvalidMethod")
-            // skip _priv noise
-            .finish()
+            let header: *const ngx_table_elt_t = self.h.add(self.i);
+            self.i += 1;
+            Some((
+                NgxStr::from_ngx_str((*header).key),
+                NgxStr::from_ngx_str((*header).value),
+            ))
+        }
     }
 }
 
-impl fmt::Display for InvalidMethod {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("invalid HTTP method")
+/// Converts the `NGX_HTTP_*` method bit of a request into a [`Method`].
+fn method_from_ngx(t: ngx_uint_t) -> Method {
+    let t = t as _;
+    match t {
+        NGX_HTTP_GET => Method::GET,
+        NGX_HTTP_HEAD => Method::HEAD,
+        NGX_HTTP_POST => Method::POST,
+        NGX_HTTP_PUT => Method::PUT,
+        NGX_HTTP_DELETE => Method::DELETE,
+        NGX_HTTP_MKCOL => Method::MKCOL,
+        NGX_HTTP_COPY => Method::COPY,
+        NGX_HTTP_MOVE => Method::MOVE,
+        NGX_HTTP_OPTIONS => Method::OPTIONS,
+        NGX_HTTP_PROPFIND => Method::PROPFIND,
+        NGX_HTTP_PROPPATCH => Method::PROPPATCH,
+        NGX_HTTP_LOCK => Method::LOCK,
+        NGX_HTTP_UNLOCK => Method::UNLOCK,
+        NGX_HTTP_PATCH => Method::PATCH,
+        NGX_HTTP_TRACE => Method::TRACE,
+        NGX_HTTP_CONNECT => Method::CONNECT,
+        _ => Method::UNKNOWN,
     }
 }
-
-impl Error for InvalidMethod {}
-
-#[derive(Clone, PartialEq, Eq, Hash)]
-enum MethodInner {
-    Unknown,
-    Get,
-    Head,
-    Post,
-    Put,
-    Delete,
-    Mkcol,
-    Copy,
-    Move,
-    Options,
-    Propfind,
-    Proppatch,
-    Lock,
-    Unlock,
-    Patch,
-    Trace,
-    Connect,
-}
@@ -0,0 +1,518 @@
+pub use ngx_core::{gunzip, inflate_raw, inflate_zlib, InflateError, InflateFormat, InflateLimits, Inflater};
+
+use crate::core::{chain_slices, Buffer, ChainSlices, ConfError, Pool, Status};
+use crate::ffi::*;
+use crate::http::{ngx_http_add_request_body_filter, HTTPStatus, JsonError, JsonValidator, Request};
+use crate::log::{Log, LogLevel};
+use crate::ngx_log_error;
+
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::{mem, ptr, slice};
+
+type BodyHandler = Box<dyn FnOnce(&mut Request, RequestBody<'_>) -> Status>;
+
+/// The client request body, as read by [`Request::read_body`].
+///
+/// The body is kept in memory as long as it fits in `client_body_buffer_size`; larger bodies
+/// are written to a temporary file, unless `client_body_in_single_buffer` is set. Only the
+/// in-memory part of the body is available through [`RequestBody::chunks`].
+#[derive(Clone, Copy)]
+pub struct RequestBody<'a> {
+    rb: Option<&'a ngx_http_request_body_t>,
+}
+
+impl<'a> RequestBody<'a> {
+    /// Returns an iterator over the in-memory buffers of the body.
+    pub fn chunks(&self) -> ChainSlices<'a> {
+        let bufs = self.rb.map_or(ptr::null_mut(), |rb| rb.bufs);
+        // SAFETY: the chain of the request body is not modified while the handler runs
+        unsafe { chain_slices(bufs) }
+    }
+
+    /// Returns `true` if the body, or a part of it, is buffered in a temporary file.
+    pub fn in_file(&self) -> bool {
+        let Some(rb) = self.rb else {
+            return false;
+        };
+        if !rb.temp_file.is_null() {
+            return true;
+        }
+
+        let mut cl = rb.bufs;
+        // SAFETY: the links of the chain are valid
+        while let Some(link) = unsafe { cl.as_ref() } {
+            if unsafe { (*link.buf).in_file() } != 0 {
+                return true;
+            }
+            cl = link.next;
+        }
+        false
+    }
+
+    /// Returns the length of the in-memory part of the body.
+    pub fn len(&self) -> usize {
+        self.chunks().map(<[u8]>::len).sum()
+    }
+
+    /// Returns `true` if the body has no in-memory data.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the in-memory part of the body.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(self.len());
+        for chunk in self.chunks() {
+            body.extend_from_slice(chunk);
+        }
+        body
+    }
+}
+
+impl std::fmt::Debug for RequestBody<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestBody")
+            .field("len", &self.len())
+            .field("in_file", &self.in_file())
+            .finish()
+    }
+}
+
+impl Request {
+    /// Discards the [request body], reading and ignoring it, for content handlers that do not
+    /// use the body.
+    ///
+    /// Discarding must start before the response is sent, so the connection can be kept alive.
+    /// Returns the status to be returned from the handler if the body cannot be discarded, e.g.
+    /// `400 Bad Request` for a malformed chunked body:
+    ///
+    /// ```rust,ignore
+    /// http_request_handler!(hello_handler, |request: &mut Request| {
+    ///     if let Err(rc) = request.discard_body() {
+    ///         return rc;
+    ///     }
+    ///     send_hello(request)
+    /// });
+    /// ```
+    ///
+    /// [request body]: https://nginx.org/en/docs/dev/development_guide.html#http_request_body
+    pub fn discard_body(&mut self) -> Result<(), Status> {
+        match unsafe { ngx_http_discard_request_body(self.into()) } {
+            rc if rc == NGX_OK as ngx_int_t => Ok(()),
+            rc => Err(Status(rc)),
+        }
+    }
+
+    /// Reads the [request body] and calls `handler` with it once it is fully read.
+    ///
+    /// This is meant to be called from a content handler, returning the status from the
+    /// handler. Reading continues on the event loop if the body is not yet available, in which
+    /// case `NGX_DONE` is returned; the handler may also be called before `read_body` returns.
+    /// The request is finalized with the status returned by the handler, e.g. the result of
+    /// [`Request::output_filter`] once the response is sent.
+    ///
+    /// ```rust,ignore
+    /// http_request_handler!(inspect_body_handler, |request: &mut Request| {
+    ///     request.read_body(|request, body| {
+    ///         if body.in_file() || body.chunks().any(|chunk| find(b"<script", chunk).is_some()) {
+    ///             return HTTPStatus::FORBIDDEN.into();
+    ///         }
+    ///         send_response(request)
+    ///     })
+    /// });
+    /// ```
+    ///
+    /// Errors reading the body, such as a client closing the connection or a body exceeding
+    /// `client_max_body_size`, are returned as the corresponding special response status without
+    /// calling `handler`.
+    ///
+    /// [request body]: https://nginx.org/en/docs/dev/development_guide.html#http_request_body
+    pub fn read_body<F>(&mut self, handler: F) -> Status
+    where
+        F: FnOnce(&mut Request, RequestBody<'_>) -> Status + 'static,
+    {
+        unsafe {
+            let r: *mut ngx_http_request_t = self.into();
+
+            let handler: BodyHandler = Box::new(handler);
+            let mut pool = Pool::from_ngx_pool((*r).pool);
+            if pool.set_data(r as usize, Some(handler)).is_none() {
+                return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+            }
+
+            let rc = ngx_http_read_client_request_body(r, Some(read_body_handler));
+            if rc >= NGX_HTTP_SPECIAL_RESPONSE as ngx_int_t {
+                return Status(rc);
+            }
+            Status::NGX_DONE
+        }
+    }
+}
+
+/// A request body content coding supported by [`Request::decode_body`].
+#[derive(Clone, Copy)]
+enum ContentCoding {
+    Identity,
+    Gzip,
+    Deflate,
+}
+
+impl ContentCoding {
+    /// Returns the coding of the `Content-Encoding` header values, or `None` if it is not
+    /// supported.
+    fn from_header_values<'a>(mut values: impl Iterator<Item = &'a [u8]>) -> Option<Self> {
+        let Some(value) = values.next() else {
+            return Some(ContentCoding::Identity);
+        };
+        // codings applied in sequence are not supported
+        if values.next().is_some() {
+            return None;
+        }
+
+        let value = value.trim_ascii();
+        if value.eq_ignore_ascii_case(b"gzip") || value.eq_ignore_ascii_case(b"x-gzip") {
+            Some(ContentCoding::Gzip)
+        } else if value.eq_ignore_ascii_case(b"deflate") {
+            Some(ContentCoding::Deflate)
+        } else if value.eq_ignore_ascii_case(b"identity") {
+            Some(ContentCoding::Identity)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decoder of a request body, attached to the request pool for the request.
+struct BodyDecoder {
+    /// The decompressor, if the body has a content coding, until the last buffer of the body.
+    inflater: Option<Inflater>,
+    /// The validator of the decompressed body, until the last buffer of the body.
+    validator: Option<JsonValidator>,
+    /// The validation error, after which the rest of the body is dropped.
+    error: Option<JsonError>,
+    output: usize,
+    done: bool,
+}
+
+/// The request body filter following the decoder.
+static NEXT_REQUEST_BODY_FILTER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Installs the request body filter decompressing the bodies of the requests for which
+/// [`Request::decode_body`] is called, and validating the ones read with
+/// [`Request::read_json_body`].
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer, and calls this from the
+/// `postconfiguration` handler of an HTTP module.
+pub unsafe fn ngx_http_add_request_body_decoder(cf: *mut ngx_conf_t) -> Result<(), ConfError> {
+    let mut next = None;
+    ngx_http_add_request_body_filter(cf, request_body_decode_filter, &mut next)?;
+    // the filter chain is built during configuration, before it is used
+    NEXT_REQUEST_BODY_FILTER.store(next.map_or(ptr::null_mut(), |next| next as *mut ()), Ordering::Relaxed);
+    Ok(())
+}
+
+impl Request {
+    /// Decompresses the request body according to its `Content-Encoding` as it is read, for all
+    /// the consumers of the body: [`Request::read_body`], the temporary file the body is
+    /// buffered to, or a proxied upstream.
+    ///
+    /// The `gzip` and `deflate` content codings are decompressed within `limits`, protecting
+    /// against small bodies expanding to large amounts of memory; bodies without a coding are
+    /// left as is. Call this before the body is read, e.g. from an access phase handler, with the
+    /// decoder installed by [`ngx_http_add_request_body_decoder`]:
+    ///
+    /// ```rust,ignore
+    /// // in postconfiguration
+    /// ngx_http_add_request_body_decoder(cf)?;
+    ///
+    /// http_request_handler!(decode_access_handler, |request: &mut Request| {
+    ///     match request.decode_body(InflateLimits::default()) {
+    ///         Ok(()) => Status::NGX_DECLINED,
+    ///         Err(rc) => rc,
+    ///     }
+    /// });
+    /// ```
+    ///
+    /// Returns `415 Unsupported Media Type` for other or multiple codings. While the body is
+    /// read, the request is finalized with `413 Payload Too Large` if the decompressed body
+    /// exceeds `limits`, and with `400 Bad Request` for corrupted compressed data.
+    ///
+    /// Once the body is read, the content length of the request is the decompressed length,
+    /// while the `Content-Encoding` header is kept as sent. Proxying the body requires
+    /// `proxy_request_buffering on`, and clearing the header, e.g. with
+    /// `proxy_set_header Content-Encoding "";`.
+    pub fn decode_body(&mut self, limits: InflateLimits) -> Result<(), Status> {
+        let coding = ContentCoding::from_header_values(
+            self.headers_in_iterator()
+                .filter(|(name, _)| name.as_bytes().eq_ignore_ascii_case(b"content-encoding"))
+                .map(|(_, value)| value.as_bytes()),
+        );
+        let format = match coding {
+            Some(ContentCoding::Identity) => return Ok(()),
+            Some(ContentCoding::Gzip) => InflateFormat::Gzip,
+            Some(ContentCoding::Deflate) => InflateFormat::Deflate,
+            None => return Err(HTTPStatus::UNSUPPORTED_MEDIA_TYPE.into()),
+        };
+
+        let decoder = self.body_decoder()?;
+        decoder.inflater = Some(Inflater::new(format, limits));
+        Ok(())
+    }
+
+    /// Returns the decoder of the request body, added to the request pool on the first call.
+    fn body_decoder(&mut self) -> Result<&mut BodyDecoder, Status> {
+        // the body of a subrequest is the body of the main request, which may be read already
+        if !self.is_main() || !self.0.request_body.is_null() || next_request_body_filter().is_none() {
+            return Err(HTTPStatus::INTERNAL_SERVER_ERROR.into());
+        }
+
+        unsafe {
+            let r: *mut ngx_http_request_t = self.into();
+            if let Some(decoder) = find_body_decoder(r) {
+                return Ok(decoder);
+            }
+
+            let decoder = BodyDecoder {
+                inflater: None,
+                validator: None,
+                error: None,
+                output: 0,
+                done: false,
+            };
+            match Pool::from_ngx_pool((*r).pool).set_data(r as usize, decoder) {
+                Some(decoder) => Ok(&mut *decoder),
+                None => Err(HTTPStatus::INTERNAL_SERVER_ERROR.into()),
+            }
+        }
+    }
+
+    /// Reads the request body like [`Request::read_body`], decompressed according to its
+    /// `Content-Encoding` by [`Request::decode_body`].
+    ///
+    /// ```rust,ignore
+    /// http_request_handler!(ingest_handler, |request: &mut Request| {
+    ///     let limits = InflateLimits {
+    ///         max_output: 1024 * 1024,
+    ///         max_ratio: 50,
+    ///     };
+    ///     request.read_body_decoded(limits, |request, body| store_events(request, body))
+    /// });
+    /// ```
+    ///
+    /// A large decompressed body is buffered to a temporary file, as for [`Request::read_body`].
+    pub fn read_body_decoded<F>(&mut self, limits: InflateLimits, handler: F) -> Status
+    where
+        F: FnOnce(&mut Request, RequestBody<'_>) -> Status + 'static,
+    {
+        if let Err(rc) = self.decode_body(limits) {
+            return rc;
+        }
+        self.read_body(handler)
+    }
+
+    /// Reads the request body like [`Request::read_body`], validating it as a JSON document with
+    /// `validator` as it is read.
+    ///
+    /// The body is validated once, by the request body filter installed by
+    /// [`ngx_http_add_request_body_decoder`], after its decompression by
+    /// [`Request::decode_body`], if any, and whether it is kept in memory or buffered to a
+    /// temporary file. The handler gets the first validation error instead of the body, whose
+    /// remaining data is read and dropped:
+    ///
+    /// ```rust,ignore
+    /// http_request_handler!(create_handler, |request: &mut Request| {
+    ///     let validator = JsonValidator::new().max_depth(8).max_size(64 * 1024).require("id");
+    ///     request.read_json_body(validator, |request, body| match body {
+    ///         Ok(body) => create(request, body),
+    ///         Err(err) => request.send_json_error(&err),
+    ///     })
+    /// });
+    /// ```
+    pub fn read_json_body<F>(&mut self, validator: JsonValidator, handler: F) -> Status
+    where
+        F: FnOnce(&mut Request, Result<RequestBody<'_>, JsonError>) -> Status + 'static,
+    {
+        match self.body_decoder() {
+            Ok(decoder) => decoder.validator = Some(validator),
+            Err(rc) => return rc,
+        }
+
+        self.read_body(move |request, body| {
+            // SAFETY: the decoder lives in the request pool
+            let result = match unsafe { find_body_decoder((&mut *request).into()) } {
+                Some(decoder) => decoder.finish_validation(),
+                None => Ok(()),
+            };
+            handler(request, result.map(|()| body))
+        })
+    }
+}
+
+impl BodyDecoder {
+    /// Decompresses and validates the buffers of `cl`, returning the chain of the buffers to pass
+    /// to the next filter.
+    unsafe fn decode(
+        &mut self,
+        r: *mut ngx_http_request_t,
+        mut cl: *mut ngx_chain_t,
+    ) -> Result<*mut ngx_chain_t, Status> {
+        let mut out: *mut ngx_chain_t = ptr::null_mut();
+        let mut ll = ptr::addr_of_mut!(out);
+
+        while let Some(link) = cl.as_ref() {
+            let buf = &mut *link.buf;
+            cl = link.next;
+
+            if self.done {
+                // data after the last buffer
+                if buf.last > buf.pos {
+                    return Err(HTTPStatus::BAD_REQUEST.into());
+                }
+                continue;
+            }
+            let last = buf.last_buf() != 0;
+
+            // the buffers are read from the client to memory
+            let input = if buf.last > buf.pos {
+                slice::from_raw_parts(buf.pos, buf.last.offset_from(buf.pos) as usize)
+            } else {
+                &[]
+            };
+            let decoded = match self.inflater.as_mut() {
+                Some(inflater) => {
+                    let mut decoded = inflater.feed(input);
+                    if let Some(inflater) = self.inflater.take_if(|_| last) {
+                        decoded = decoded.and_then(|mut decoded| {
+                            decoded.extend(inflater.finish()?);
+                            Ok(decoded)
+                        });
+                    }
+                    Some(decoded.map_err(|err| self.error(r, err))?)
+                }
+                None => None,
+            };
+            self.validate(decoded.as_deref().unwrap_or(input), last);
+            self.done = last;
+
+            let b = if self.error.is_none() && decoded.is_none() {
+                // the buffer is passed as is
+                link.buf
+            } else {
+                // the buffer is consumed, the decompressed data is passed, or dropped on errors
+                buf.pos = buf.last;
+                let decoded = decoded.filter(|_| self.error.is_none()).unwrap_or_default();
+                if decoded.is_empty() && !last {
+                    continue;
+                }
+
+                let mut pool = Pool::from_ngx_pool((*r).pool);
+                let b = if decoded.is_empty() {
+                    pool.calloc_type::<ngx_buf_t>()
+                } else {
+                    pool.create_buffer_from_bytes(&decoded)
+                        .map_or(ptr::null_mut(), |mut b| b.as_ngx_buf_mut())
+                };
+                if b.is_null() {
+                    return Err(HTTPStatus::INTERNAL_SERVER_ERROR.into());
+                }
+                (*b).set_last_buf(last as _);
+                b
+            };
+            self.output += (*b).last.offset_from((*b).pos) as usize;
+            if last && b != link.buf {
+                (*r).headers_in.content_length_n = self.output as off_t;
+            }
+
+            let link = ngx_alloc_chain_link((*r).pool);
+            if link.is_null() {
+                return Err(HTTPStatus::INTERNAL_SERVER_ERROR.into());
+            }
+            (*link).buf = b;
+            (*link).next = ptr::null_mut();
+            *ll = link;
+            ll = ptr::addr_of_mut!((*link).next);
+        }
+
+        Ok(out)
+    }
+
+    /// Feeds `data` to the validator, keeping the first error.
+    fn validate(&mut self, data: &[u8], last: bool) {
+        let Some(validator) = self.validator.as_mut() else {
+            return;
+        };
+        let mut result = validator.feed(data);
+        if last {
+            result = result.and_then(|()| validator.finish());
+        }
+        if let Err(err) = result {
+            self.error = Some(err);
+            self.validator = None;
+        } else if last {
+            self.validator = None;
+        }
+    }
+
+    /// Returns the result of the validation of the body once it is read.
+    fn finish_validation(&mut self) -> Result<(), JsonError> {
+        // the filter is not called with the last buffer of an empty body
+        if let Some(mut validator) = self.validator.take() {
+            validator.finish()?;
+        }
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    unsafe fn error(&self, r: *mut ngx_http_request_t, err: InflateError) -> Status {
+        // SAFETY: the log of the connection outlives the request
+        if let Some(log) = Log::from_ngx_log((*(*r).connection).log) {
+            ngx_log_error!(LogLevel::Info, log, "client sent invalid compressed body: {}", err);
+        }
+        match err {
+            InflateError::TooLarge => HTTPStatus::REQUEST_ENTITY_TOO_LARGE.into(),
+            _ => HTTPStatus::BAD_REQUEST.into(),
+        }
+    }
+}
+
+fn next_request_body_filter() -> ngx_http_request_body_filter_pt {
+    // SAFETY: the pointer is either null or the filter stored by `ngx_http_add_request_body_decoder`
+    unsafe {
+        mem::transmute::<*mut (), ngx_http_request_body_filter_pt>(NEXT_REQUEST_BODY_FILTER.load(Ordering::Relaxed))
+    }
+}
+
+unsafe extern "C" fn request_body_decode_filter(r: *mut ngx_http_request_t, cl: *mut ngx_chain_t) -> ngx_int_t {
+    let Some(next) = next_request_body_filter() else {
+        return NGX_ERROR as ngx_int_t;
+    };
+
+    match find_body_decoder(r) {
+        Some(decoder) => match decoder.decode(r, cl) {
+            Ok(out) => next(r, out),
+            Err(rc) => rc.0,
+        },
+        None => next(r, cl),
+    }
+}
+
+/// Returns the decoder of the body of `r`, if any.
+unsafe fn find_body_decoder<'a>(r: *mut ngx_http_request_t) -> Option<&'a mut BodyDecoder> {
+    let decoder = Pool::from_ngx_pool((*r).pool).data::<BodyDecoder>(r as usize)?;
+    Some(&mut *decoder)
+}
+
+unsafe extern "C" fn read_body_handler(r: *mut ngx_http_request_t) {
+    let handler = Pool::from_ngx_pool((*r).pool).data::<Option<BodyHandler>>(r as usize);
+    let Some(handler) = handler.and_then(|handler| (*handler).take()) else {
+        ngx_http_finalize_request(r, NGX_HTTP_INTERNAL_SERVER_ERROR as ngx_int_t);
+        return;
+    };
+
+    let body = RequestBody {
+        rb: (*r).request_body.as_ref(),
+    };
+    let rc = handler(Request::from_ngx_http_request(r), body);
+    ngx_http_finalize_request(r, rc.0);
+}
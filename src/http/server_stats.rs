@@ -0,0 +1,283 @@
+use crate::core::{ConfError, Pool, SharedZone, ShmSafe, ZoneSpec};
+use crate::ffi::*;
+use crate::http::{ngx_http_conf_get_module_main_conf, Request};
+
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr::addr_of;
+use std::slice;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+/// Maximum length of a server name stored in the zone; longer names are truncated.
+const SERVER_NAME_LEN: usize = 64;
+
+/// Connection and traffic counters of each virtual server, shared by all workers through a
+/// shared memory zone.
+///
+/// Counters are aggregated by the primary `server_name` of each `server` block, so servers
+/// sharing a name share their counters, and kept across reloads for the servers whose name did
+/// not change. Updates are lock-free atomic increments, cheap enough to be done for each request:
+///
+/// ```rust,ignore
+/// // in the postconfiguration handler, with `stats` kept in the main configuration
+/// amcf.stats = Some(ServerStats::add(cf, "server_stats", 64, &*addr_of!(my_module))?);
+///
+/// // in a post-read phase handler
+/// stats.track(request);
+///
+/// // in a log phase handler
+/// stats.log(request);
+///
+/// // in a content handler exporting the metrics
+/// for server in stats.snapshot() {
+///     writeln!(body, "{} {} {}", server.name, server.handled, server.bytes_sent)?;
+/// }
+/// ```
+///
+/// Only main requests are counted. Once the zone is full, servers with new names are not counted.
+#[derive(Clone, Copy)]
+pub struct ServerStats {
+    zone: SharedZone<StatsShared>,
+    ctx: *const StatsZone,
+}
+
+/// Counters of a virtual server at a point in time, as returned by [`ServerStats::snapshot`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerStatsSnapshot {
+    /// The primary server name, possibly truncated.
+    pub name: String,
+    /// Number of requests accepted by the server.
+    pub accepted: u64,
+    /// Number of requests completed by the server.
+    pub handled: u64,
+    /// Number of requests currently processed by the server.
+    pub active: u64,
+    /// Number of bytes received, including request lines and headers.
+    pub bytes_received: u64,
+    /// Number of bytes sent, including status lines and headers.
+    pub bytes_sent: u64,
+    /// Number of responses by status class, from `1xx` to `5xx`.
+    pub responses: [u64; 5],
+}
+
+/// Process-local data of a stats zone, allocated from the configuration pool.
+struct StatsZone {
+    cmcf: *mut ngx_http_core_main_conf_t,
+    capacity: usize,
+    /// Slot of each server configuration, sorted by address.
+    servers: Vec<(usize, *mut ServerSlot)>,
+}
+
+/// Root of the zone, pointing to the server slots.
+#[derive(Default)]
+#[repr(C)]
+struct StatsShared {
+    used: AtomicUsize,
+    capacity: AtomicUsize,
+    slots: AtomicPtr<ServerSlot>,
+}
+
+// SAFETY: the slots are allocated from the slab pool of the zone, and the counters are only
+// updated with atomic operations
+unsafe impl ShmSafe for StatsShared {}
+
+/// Counters of a server in the zone.
+#[repr(C)]
+struct ServerSlot {
+    name: [u8; SERVER_NAME_LEN],
+    name_len: usize,
+    accepted: AtomicU64,
+    handled: AtomicU64,
+    active: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    responses: [AtomicU64; 5],
+}
+
+impl ServerStats {
+    /// Adds a shared memory zone named `name` holding the counters of up to `max_servers` server
+    /// names.
+    ///
+    /// The servers are assigned their counters when the configuration is applied, so this can be
+    /// called at any time while parsing the `http` block.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null `ngx_conf_t` pointer in the `http` context.
+    pub unsafe fn add(
+        cf: *mut ngx_conf_t,
+        name: &str,
+        max_servers: usize,
+        module: &ngx_module_t,
+    ) -> Result<Self, ConfError> {
+        if max_servers == 0 {
+            return Err(ConfError::new(format!(
+                "zone \"{}\" must hold at least one server",
+                name
+            )));
+        }
+
+        // the slots are allocated from the slab pool, which needs a few pages for itself
+        let size = mem::size_of::<StatsShared>() + max_servers * mem::size_of::<ServerSlot>();
+        let spec = ZoneSpec {
+            name: name.into(),
+            size: (size + ngx_pagesize - 1) / ngx_pagesize * ngx_pagesize + 8 * ngx_pagesize,
+        };
+        let zone = SharedZone::<StatsShared>::add_unique(cf, &spec, module)?;
+
+        let ctx = Pool::from_ngx_pool((*cf).pool).allocate(StatsZone {
+            cmcf: ngx_http_conf_get_module_main_conf(cf, &*addr_of!(ngx_http_core_module)),
+            capacity: max_servers,
+            servers: Vec::new(),
+        });
+        if ctx.is_null() {
+            return Err(ConfError::new("failed to allocate zone context"));
+        }
+
+        zone.on_init(move |shared, pool| {
+            // the counters are zeroed when the zone is created, and kept as is when it is reused
+            if shared.slots.load(Ordering::Acquire).is_null() {
+                let capacity = unsafe { (*ctx).capacity };
+                let slots = pool.calloc(capacity * mem::size_of::<ServerSlot>()) as *mut ServerSlot;
+                if slots.is_null() {
+                    return None;
+                }
+                shared.capacity.store(capacity, Ordering::Relaxed);
+                shared.slots.store(slots, Ordering::Release);
+            }
+            unsafe { assign_slots(&mut *ctx, shared) };
+            Some(())
+        });
+
+        Ok(ServerStats { zone, ctx })
+    }
+
+    /// Counts a request accepted by its server, and as active until the request is freed.
+    ///
+    /// Call this from a post-read phase handler. Returns `false` if the request is not counted.
+    pub fn track(&self, request: &mut Request) -> bool {
+        let Some(slot) = self.slot(request) else {
+            return false;
+        };
+
+        unsafe {
+            let cln = ngx_pool_cleanup_add(request.get_inner().pool, 0);
+            if cln.is_null() {
+                return false;
+            }
+            (*cln).handler = Some(server_stats_request_cleanup);
+            (*cln).data = slot as *const ServerSlot as *mut c_void;
+        }
+
+        slot.accepted.fetch_add(1, Ordering::Relaxed);
+        slot.active.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Counts a completed request, its traffic and the class of its response status.
+    ///
+    /// Call this from a log phase handler.
+    pub fn log(&self, request: &Request) {
+        let Some(slot) = self.slot(request) else {
+            return;
+        };
+        let r = request.get_inner();
+
+        slot.handled.fetch_add(1, Ordering::Relaxed);
+        slot.bytes_received
+            .fetch_add(r.request_length as u64, Ordering::Relaxed);
+        if let Some(c) = unsafe { r.connection.as_ref() } {
+            slot.bytes_sent.fetch_add(c.sent as u64, Ordering::Relaxed);
+        }
+        if let 100..=599 = r.headers_out.status {
+            slot.responses[r.headers_out.status / 100 - 1].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the counters of all servers known to the zone, including the servers removed from
+    /// the configuration by a reload.
+    pub fn snapshot(&self) -> Vec<ServerStatsSnapshot> {
+        self.slots()
+            .iter()
+            .map(|slot| ServerStatsSnapshot {
+                name: String::from_utf8_lossy(&slot.name[..slot.name_len]).into_owned(),
+                accepted: slot.accepted.load(Ordering::Relaxed),
+                handled: slot.handled.load(Ordering::Relaxed),
+                active: slot.active.load(Ordering::Relaxed),
+                bytes_received: slot.bytes_received.load(Ordering::Relaxed),
+                bytes_sent: slot.bytes_sent.load(Ordering::Relaxed),
+                responses: [0, 1, 2, 3, 4].map(|i| slot.responses[i].load(Ordering::Relaxed)),
+            })
+            .collect()
+    }
+
+    fn slots(&self) -> &[ServerSlot] {
+        let Some(shared) = self.zone.get() else {
+            return &[];
+        };
+        unsafe { slice::from_raw_parts(shared.slots.load(Ordering::Acquire), used_slots(shared)) }
+    }
+
+    fn slot(&self, request: &Request) -> Option<&ServerSlot> {
+        if !request.is_main() {
+            return None;
+        }
+
+        // the servers are assigned their slots once the zone is mapped
+        self.zone.get()?;
+        let ctx = unsafe { &*self.ctx };
+        let cscf = unsafe {
+            *request
+                .get_inner()
+                .srv_conf
+                .add((*addr_of!(ngx_http_core_module)).ctx_index)
+        } as usize;
+        let i = ctx.servers.binary_search_by_key(&cscf, |(key, _)| *key).ok()?;
+        unsafe { ctx.servers[i].1.as_ref() }
+    }
+}
+
+fn used_slots(shared: &StatsShared) -> usize {
+    shared
+        .used
+        .load(Ordering::Acquire)
+        .min(shared.capacity.load(Ordering::Relaxed))
+}
+
+/// Assigns a slot to each server of the configuration, by name.
+unsafe fn assign_slots(ctx: &mut StatsZone, shared: &StatsShared) {
+    let slots = shared.slots.load(Ordering::Acquire);
+    let capacity = shared.capacity.load(Ordering::Relaxed);
+
+    let servers = &(*ctx.cmcf).servers;
+    let servers = slice::from_raw_parts(servers.elts as *const *mut ngx_http_core_srv_conf_t, servers.nelts);
+
+    for &cscf in servers {
+        let name: &[u8] = (*cscf).server_name.into();
+        let name = &name[..name.len().min(SERVER_NAME_LEN)];
+
+        let used = used_slots(shared);
+        let slot = match (0..used)
+            .map(|i| slots.add(i))
+            .find(|&s| &(*s).name[..(*s).name_len] == name)
+        {
+            Some(slot) => slot,
+            None if used < capacity => {
+                let slot = slots.add(used);
+                (*slot).name[..name.len()].copy_from_slice(name);
+                (*slot).name_len = name.len();
+                shared.used.store(used + 1, Ordering::Release);
+                slot
+            }
+            // the zone is full
+            None => continue,
+        };
+        ctx.servers.push((cscf as usize, slot));
+    }
+    ctx.servers.sort_unstable_by_key(|(key, _)| *key);
+}
+
+unsafe extern "C" fn server_stats_request_cleanup(data: *mut c_void) {
+    let slot = &*(data as *const ServerSlot);
+    slot.active.fetch_sub(1, Ordering::Relaxed);
+}
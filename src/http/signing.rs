@@ -0,0 +1,226 @@
+use crate::core::Status;
+use crate::ffi::*;
+use crate::http::{BodyChain, BodyFilter, HeaderFilter, LengthBuffer, NextBodyFilter, NextHeaderFilter, Request};
+
+use std::marker::PhantomData;
+
+/// Where a response signature is emitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignaturePlacement {
+    /// In a response header field. The response body is buffered until it is complete, so that
+    /// the header can be sent with the signature.
+    Header,
+    /// In a response trailer field, sent after the body with chunked HTTP/1.1 responses and with
+    /// HTTP/2 and HTTP/3 responses. The body is streamed without buffering.
+    Trailer,
+}
+
+/// A signature algorithm applied to response bodies, e.g. HMAC-SHA256 or Ed25519 provided by a
+/// cryptography crate.
+///
+/// The signer is usually part of the location configuration, and is installed as a header and
+/// body filter pair with a [`SigningFilter`].
+pub trait ResponseSigner {
+    /// The incremental signature state of a response.
+    type State: 'static;
+
+    /// Returns the name of the header or trailer field holding the signature.
+    fn field_name(&self) -> &str;
+
+    /// Returns where the signature is emitted.
+    fn placement(&self) -> SignaturePlacement {
+        SignaturePlacement::Trailer
+    }
+
+    /// Returns the maximum size of a response body buffered for [`SignaturePlacement::Header`];
+    /// larger responses fail with an error.
+    ///
+    /// The body is buffered with a [`LengthBuffer`], and is sent with its `Content-Length`.
+    fn max_buffered_size(&self) -> usize {
+        1024 * 1024
+    }
+
+    /// Starts the signature of the response to `request`, e.g. including its method and URI.
+    fn begin(&self, request: &Request) -> Self::State;
+
+    /// Adds the next part of the response body to the signature.
+    fn update(&self, state: &mut Self::State, bytes: &[u8]);
+
+    /// Completes the signature, returning the value of the field, e.g. encoded with base64.
+    fn finish(&self, state: Self::State) -> String;
+}
+
+/// The signing of the responses of a module, installed as a [`SigningFilter`].
+///
+/// ```rust,ignore
+/// struct SignResponses;
+///
+/// static NEXT_HEADER_FILTER: NextHeaderFilter = NextHeaderFilter::new();
+/// static NEXT_BODY_FILTER: NextBodyFilter = NextBodyFilter::new();
+///
+/// impl ResponseSigning for SignResponses {
+///     type Signer = HmacSigner;
+///
+///     fn module() -> &'static ngx_module_t {
+///         unsafe { &*addr_of!(ngx_http_sign_module) }
+///     }
+///
+///     fn signer(request: &Request) -> Option<&HmacSigner> {
+///         let conf = request.get_module_loc_conf::<LocConf>(Self::module())?;
+///         conf.signer.as_ref()
+///     }
+///
+///     fn next_header_filter() -> &'static NextHeaderFilter {
+///         &NEXT_HEADER_FILTER
+///     }
+///
+///     fn next_body_filter() -> &'static NextBodyFilter {
+///         &NEXT_BODY_FILTER
+///     }
+/// }
+///
+/// // in `postconfiguration`
+/// (*cf).install_header_filter::<SigningFilter<SignResponses>>()?;
+/// (*cf).install_body_filter::<SigningFilter<SignResponses>>()?;
+/// ```
+///
+/// The request context of the module is used by the filters.
+pub trait ResponseSigning: 'static {
+    /// The signer of the responses.
+    type Signer: ResponseSigner + 'static;
+
+    /// Returns the module whose request context slot holds the signature state.
+    fn module() -> &'static ngx_module_t;
+
+    /// Returns the signer for `request`, or `None` to leave the response untouched.
+    fn signer(request: &Request) -> Option<&Self::Signer>;
+
+    /// Returns the storage for the next header filter in the chain, a static of the module.
+    fn next_header_filter() -> &'static NextHeaderFilter;
+
+    /// Returns the storage for the next body filter in the chain, a static of the module.
+    fn next_body_filter() -> &'static NextBodyFilter;
+}
+
+/// The [`HeaderFilter`] and [`BodyFilter`] pair signing the responses with the
+/// [`ResponseSigning`] `S`.
+///
+/// Only main requests are signed. Responses to `HEAD` requests and responses without a body are
+/// not signed.
+pub struct SigningFilter<S>(PhantomData<S>);
+
+struct SigningCtx<S: ResponseSigner> {
+    signer: *const S,
+    state: Option<S::State>,
+    /// The body of a response signed in a header.
+    buffered: Option<LengthBuffer>,
+}
+
+impl<S: ResponseSigning> HeaderFilter for SigningFilter<S> {
+    fn next_filter() -> &'static NextHeaderFilter {
+        S::next_header_filter()
+    }
+
+    fn filter(request: &mut Request) -> Status {
+        let next = S::next_header_filter();
+        let status = request.get_inner().headers_out.status;
+        if !request.is_main() || request.header_only() || status < NGX_HTTP_OK as ngx_uint_t {
+            return next.call(request);
+        }
+        if status == NGX_HTTP_NO_CONTENT as ngx_uint_t || status == NGX_HTTP_NOT_MODIFIED as ngx_uint_t {
+            return next.call(request);
+        }
+        // the signer is part of the configuration, which outlives the request
+        let Some(signer) = S::signer(request).map(|signer| signer as *const S::Signer) else {
+            return next.call(request);
+        };
+        let signer = unsafe { &*signer };
+
+        let placement = signer.placement();
+        let ctx = SigningCtx::<S::Signer> {
+            signer,
+            state: Some(signer.begin(request)),
+            buffered: (placement == SignaturePlacement::Header).then(|| LengthBuffer::new(signer.max_buffered_size())),
+        };
+        if request.insert_module_ctx(S::module(), ctx).is_none() {
+            return Status::NGX_ERROR;
+        }
+
+        let r: *mut ngx_http_request_t = (&mut *request).into();
+        unsafe { (*r).set_filter_need_in_memory(1) };
+
+        match placement {
+            SignaturePlacement::Trailer => {
+                unsafe { (*r).set_expect_trailers(1) };
+                next.call(request)
+            }
+            // the header is sent with the last part of the body
+            SignaturePlacement::Header => Status::NGX_OK,
+        }
+    }
+}
+
+impl<S: ResponseSigning> BodyFilter for SigningFilter<S> {
+    fn next_filter() -> &'static NextBodyFilter {
+        S::next_body_filter()
+    }
+
+    fn filter(request: &mut Request, chain: BodyChain<'_>) -> Status {
+        let next = S::next_body_filter();
+        // the context is allocated from the request pool, not borrowed from the request
+        let ctx = request
+            .get_module_ctx_mut::<SigningCtx<S::Signer>>(S::module())
+            .map(|ctx| ctx as *mut SigningCtx<S::Signer>);
+        let Some(ctx) = ctx.map(|ctx| unsafe { &mut *ctx }) else {
+            return next.call(request, chain);
+        };
+        let Some(state) = ctx.state.as_mut() else {
+            return next.call(request, chain);
+        };
+        let signer = unsafe { &*ctx.signer };
+
+        if let Some(buffered) = ctx.buffered.as_ref() {
+            let size: usize = chain.slices().map(<[u8]>::len).sum();
+            if buffered.buffered_size() + size > signer.max_buffered_size() {
+                unsafe {
+                    ngx_log_error_core(
+                        NGX_LOG_ERR as ngx_uint_t,
+                        request.log(),
+                        0,
+                        c"response too large to be signed in a header".as_ptr(),
+                    )
+                };
+                return Status::NGX_ERROR;
+            }
+        }
+        for bytes in chain.slices() {
+            signer.update(state, bytes);
+        }
+
+        if chain.buffers().any(|buf| buf.last_buf() != 0) {
+            let Some(state) = ctx.state.take() else {
+                return Status::NGX_ERROR;
+            };
+            let signature = signer.finish(state);
+
+            if ctx.buffered.is_some() {
+                if request.add_header_out(signer.field_name(), &signature).is_err() {
+                    return Status::NGX_ERROR;
+                }
+            } else {
+                let r: *mut ngx_http_request_t = (&mut *request).into();
+                unsafe {
+                    let trailer = ngx_list_push(&mut (*r).headers_out.trailers) as *mut ngx_table_elt_t;
+                    if add_to_ngx_table(trailer, (*r).pool, signer.field_name(), &signature).is_none() {
+                        return Status::NGX_ERROR;
+                    }
+                }
+            }
+        }
+
+        match ctx.buffered.as_mut() {
+            Some(buffered) => buffered.filter(request, chain, S::next_header_filter(), next),
+            None => next.call(request, chain),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use crate::core::{chain_slices, Status};
+use crate::ffi::*;
+use crate::http::{HTTPStatus, Request, RequestError};
+
+use std::cell::RefCell;
+use std::future::{Future, IntoFuture};
+use std::mem;
+use std::ops::BitOr;
+use std::os::raw::c_void;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll, Waker};
+
+/// Flags of a subrequest created with [`Request::subrequest`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubrequestFlags(u32);
+
+impl SubrequestFlags {
+    /// No flags: the output of the subrequest is sent to the client with the main response.
+    pub const NONE: SubrequestFlags = SubrequestFlags(0);
+    /// Keep the output of the subrequest in memory instead of sending it to the client, making it
+    /// available as [`SubrequestResult::body`].
+    pub const IN_MEMORY: SubrequestFlags = SubrequestFlags(NGX_HTTP_SUBREQUEST_IN_MEMORY);
+    /// Finalize the subrequest even if it completes before it becomes active.
+    pub const WAITED: SubrequestFlags = SubrequestFlags(NGX_HTTP_SUBREQUEST_WAITED);
+    /// Run the subrequest in the same location and phase as the parent request.
+    pub const CLONE: SubrequestFlags = SubrequestFlags(NGX_HTTP_SUBREQUEST_CLONE);
+    /// Run the subrequest in the background, without delaying the main response.
+    pub const BACKGROUND: SubrequestFlags = SubrequestFlags(NGX_HTTP_SUBREQUEST_BACKGROUND);
+
+    /// Returns `true` if all flags of `other` are set.
+    pub fn contains(&self, other: SubrequestFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for SubrequestFlags {
+    type Output = SubrequestFlags;
+
+    fn bitor(self, rhs: SubrequestFlags) -> SubrequestFlags {
+        SubrequestFlags(self.0 | rhs.0)
+    }
+}
+
+/// The outcome of a finished subrequest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubrequestResult {
+    rc: ngx_int_t,
+    status: usize,
+    body: Vec<u8>,
+}
+
+impl SubrequestResult {
+    /// Returns the code the subrequest was finalized with, e.g. `NGX_OK` or `NGX_ERROR`.
+    pub fn rc(&self) -> Status {
+        Status(self.rc)
+    }
+
+    /// Returns the response status of the subrequest, or `None` if no response was produced.
+    pub fn status(&self) -> Option<HTTPStatus> {
+        (self.status != 0).then_some(HTTPStatus(self.status))
+    }
+
+    /// Returns the response body of a subrequest created with [`SubrequestFlags::IN_MEMORY`].
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+type SubrequestCallback = Box<dyn FnOnce(&mut Request, &SubrequestResult) -> Status>;
+
+struct SubrequestState {
+    callback: Option<SubrequestCallback>,
+    /// `true` once the post subrequest handler ran, which may be called again on finalization.
+    done: bool,
+    result: Option<SubrequestResult>,
+    waker: Option<Waker>,
+}
+
+/// Handle to a subrequest created with [`Request::subrequest`].
+///
+/// The subrequest runs once the parent request yields to the event loop. Its outcome is passed
+/// to the callback registered with [`Subrequest::on_done`], or returned by awaiting the handle,
+/// e.g. in a handler run by [`ngx_http_async_handler`](crate::http::ngx_http_async_handler):
+///
+/// ```rust,ignore
+/// http_async_handler!(auth_access_handler, |mut request: AsyncRequest| async move {
+///     let flags = SubrequestFlags::IN_MEMORY | SubrequestFlags::WAITED;
+///     let auth = match request.with(|request| request.subrequest("/auth", None, flags)) {
+///         Some(Ok(subrequest)) => subrequest.await,
+///         _ => return Status::NGX_ERROR,
+///     };
+///     match auth.status() {
+///         Some(HTTPStatus::OK) => Status::NGX_OK,
+///         _ => HTTPStatus::FORBIDDEN.into(),
+///     }
+/// });
+/// ```
+pub struct Subrequest {
+    request: *mut ngx_http_request_t,
+    state: *mut RefCell<SubrequestState>,
+}
+
+impl Request {
+    /// Creates a subrequest for `uri` with the optional query string `args`.
+    ///
+    /// The state of the subrequest is allocated from the request pool. Returns an error if the
+    /// subrequest cannot be created, e.g. because the limit of subrequests is reached.
+    pub fn subrequest(
+        &mut self,
+        uri: &str,
+        args: Option<&str>,
+        flags: SubrequestFlags,
+    ) -> Result<Subrequest, RequestError> {
+        let mut pool = self.pool();
+        let state = pool.allocate(RefCell::new(SubrequestState {
+            callback: None,
+            done: false,
+            result: None,
+            waker: None,
+        }));
+        let ps = pool.calloc_type::<ngx_http_post_subrequest_t>();
+        if state.is_null() || ps.is_null() {
+            return Err(RequestError::Allocation);
+        }
+
+        unsafe {
+            (*ps).handler = Some(ngx_http_subrequest_done_handler);
+            (*ps).data = state as *mut c_void;
+
+            let r: *mut ngx_http_request_t = self.into();
+            let mut uri = ngx_str_t::from_str((*r).pool, uri);
+            let mut args = args.map(|args| ngx_str_t::from_str((*r).pool, args));
+            let args = args.as_mut().map_or(ptr::null_mut(), |args| args as *mut ngx_str_t);
+
+            let mut sr: *mut ngx_http_request_t = ptr::null_mut();
+            if ngx_http_subrequest(r, &mut uri, args, &mut sr, ps, flags.0 as ngx_uint_t) != NGX_OK as ngx_int_t {
+                return Err(RequestError::InvalidValue);
+            }
+
+            // a fake request body avoids attempts to read it, and makes sure the real body file, if
+            // already read, is not closed by the subrequest
+            (*sr).request_body = pool.calloc_type::<ngx_http_request_body_t>();
+            if (*sr).request_body.is_null() {
+                return Err(RequestError::Allocation);
+            }
+
+            Ok(Subrequest { request: sr, state })
+        }
+    }
+}
+
+impl Subrequest {
+    /// Returns the subrequest, e.g. to adjust it before it runs.
+    pub fn request(&mut self) -> &mut Request {
+        unsafe { Request::from_ngx_http_request(self.request) }
+    }
+
+    /// Registers `callback` to be invoked with the subrequest and its outcome when it finishes.
+    ///
+    /// The status returned by the callback replaces the code the subrequest is finalized with.
+    pub fn on_done<F>(self, callback: F)
+    where
+        F: FnOnce(&mut Request, &SubrequestResult) -> Status + 'static,
+    {
+        unsafe { (*self.state).borrow_mut().callback = Some(Box::new(callback)) };
+    }
+}
+
+impl IntoFuture for Subrequest {
+    type Output = SubrequestResult;
+    type IntoFuture = SubrequestFuture;
+
+    fn into_future(self) -> SubrequestFuture {
+        SubrequestFuture { state: self.state }
+    }
+}
+
+/// Future resolving to the outcome of a [`Subrequest`].
+///
+/// The future must be awaited on the event loop, while the parent request is alive.
+pub struct SubrequestFuture {
+    state: *mut RefCell<SubrequestState>,
+}
+
+impl Future for SubrequestFuture {
+    type Output = SubrequestResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<SubrequestResult> {
+        let mut state = unsafe { (*self.state).borrow_mut() };
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn ngx_http_subrequest_done_handler(
+    r: *mut ngx_http_request_t,
+    data: *mut c_void,
+    rc: ngx_int_t,
+) -> ngx_int_t {
+    let state = &*(data as *const RefCell<SubrequestState>);
+    if mem::replace(&mut state.borrow_mut().done, true) {
+        return rc;
+    }
+
+    let body = if (*r).subrequest_in_memory() != 0 {
+        chain_slices((*r).out).flatten().copied().collect()
+    } else {
+        Vec::new()
+    };
+    let result = SubrequestResult {
+        rc,
+        status: (*r).headers_out.status,
+        body,
+    };
+
+    let callback = state.borrow_mut().callback.take();
+    if let Some(callback) = callback {
+        let status = callback(Request::from_ngx_http_request(r), &result);
+        state.borrow_mut().result = Some(result);
+        return status.0;
+    }
+
+    let waker = {
+        let mut state = state.borrow_mut();
+        state.result = Some(result);
+        state.waker.take()
+    };
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+
+    rc
+}
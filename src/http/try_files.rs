@@ -0,0 +1,115 @@
+use crate::core::Status;
+use crate::ffi::*;
+use crate::http::{HTTPStatus, Request, RequestError};
+
+use std::mem;
+
+impl Request {
+    /// Returns `true` if the file `uri` maps to with the `root` or `alias` of the location exists,
+    /// as the `try_files` directive checks it.
+    ///
+    /// A `uri` ending with a slash must be a directory, and a regular file otherwise. The check
+    /// goes through the `open_file_cache` of the location and honors `disable_symlinks`.
+    pub fn uri_file_exists(&mut self, uri: &str) -> Result<bool, RequestError> {
+        if !uri.starts_with('/') {
+            return Err(RequestError::InvalidValue);
+        }
+        let test_dir = uri.ends_with('/');
+        let uri = uri.trim_end_matches('/');
+
+        unsafe {
+            let clcf = *self.0.loc_conf.add(ngx_http_core_module.ctx_index) as *mut ngx_http_core_loc_conf_t;
+
+            // the path is mapped from the URI of the request
+            let saved = self.0.uri;
+            self.0.uri = ngx_str_t::from_str(self.0.pool, if uri.is_empty() { "/" } else { uri });
+            let mut path: ngx_str_t = mem::zeroed();
+            let mut root = 0;
+            let last = ngx_http_map_uri_to_path(&mut self.0, &mut path, &mut root, 0);
+            self.0.uri = saved;
+            if last.is_null() {
+                return Err(RequestError::Allocation);
+            }
+            path.len = last.offset_from(path.data) as usize;
+
+            let mut of: ngx_open_file_info_t = mem::zeroed();
+            of.read_ahead = (*clcf).read_ahead;
+            of.directio = (*clcf).directio;
+            of.valid = (*clcf).open_file_cache_valid;
+            of.min_uses = (*clcf).open_file_cache_min_uses;
+            of.set_test_only(1);
+            of.set_errors((*clcf).open_file_cache_errors as _);
+            of.set_events((*clcf).open_file_cache_events as _);
+
+            if ngx_http_set_disable_symlinks(&mut self.0, clcf, &mut path, &mut of) != Status::NGX_OK.0 {
+                return Err(RequestError::Allocation);
+            }
+
+            if ngx_open_cached_file((*clcf).open_file_cache, &mut path, &mut of, self.0.pool) != Status::NGX_OK.0 {
+                // not found, or not accessible as logged by NGINX
+                if of.err == 0 {
+                    return Err(RequestError::Allocation);
+                }
+                return Ok(false);
+            }
+
+            Ok((of.is_dir() != 0) == test_dir)
+        }
+    }
+
+    /// Returns the first of `candidates` whose file exists, see [`Request::uri_file_exists`].
+    pub fn try_files<'a>(&mut self, candidates: &[&'a str]) -> Result<Option<&'a str>, RequestError> {
+        for &candidate in candidates {
+            if self.uri_file_exists(candidate)? {
+                return Ok(Some(candidate));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Redirects the request internally to the first of `candidates` whose file exists, or to
+    /// `fallback` otherwise, and returns the status to return from the handler.
+    ///
+    /// As for the last parameter of the `try_files` directive, `fallback` is a URI, a named
+    /// location like `@backend`, or a status code like `=404`:
+    ///
+    /// ```rust,ignore
+    /// http_request_handler!(spa_handler, |request: &mut Request| {
+    ///     let uri = request.uri().to_string();
+    ///     let versioned = format!("/v2{uri}");
+    ///     request.try_files_redirect(&[&versioned, &uri], "/index.html")
+    /// });
+    /// ```
+    ///
+    /// Unlike the directive, the redirect searches the location again, and the arguments of the
+    /// request are kept.
+    pub fn try_files_redirect(&mut self, candidates: &[&str], fallback: &str) -> Status {
+        let target = match self.try_files(candidates) {
+            Ok(Some(candidate)) => candidate,
+            Ok(None) => fallback,
+            Err(_) => return HTTPStatus::INTERNAL_SERVER_ERROR.into(),
+        };
+
+        if let Some(code) = target.strip_prefix('=') {
+            return match HTTPStatus::from_bytes(code.as_bytes()) {
+                Ok(status) => status.into(),
+                Err(_) => HTTPStatus::INTERNAL_SERVER_ERROR.into(),
+            };
+        }
+        if target.is_empty() {
+            return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+        }
+
+        // keep the arguments, which are not part of the candidate URIs
+        if !target.starts_with('@') && self.0.args.len != 0 {
+            let mut args = self.0.args;
+            unsafe {
+                let mut uri = ngx_str_t::from_str(self.0.pool, target);
+                ngx_http_internal_redirect(&mut self.0, &mut uri, &mut args);
+            }
+            return Status::NGX_DONE;
+        }
+
+        self.internal_redirect(target)
+    }
+}
@@ -1,3 +1,15 @@
+use crate::core::{ConfError, NgxStr, Status};
+use crate::event::ngx_time;
+use crate::ffi::*;
+use crate::http::{ngx_http_conf_get_module_srv_conf, HTTPStatus, Request};
+
+use std::mem;
+use std::net::SocketAddr;
+use std::os::raw::c_void;
+use std::ptr::{self, addr_of, addr_of_mut};
+use std::slice;
+use std::time::Duration;
+
 /// Define a static upstream peer initializer
 ///
 /// Initializes the upstream 'get', 'free', and 'session' callbacks and gives the module writer an
@@ -19,3 +31,481 @@ macro_rules! http_upstream_init_peer_pt {
         }
     };
 }
+
+/// A load balancing method for `upstream` blocks, such as `least_conn` or `hash`.
+///
+/// The balancer is the server configuration of its module: the directive selecting the method
+/// calls [`ngx_http_upstream_set_balancer`], and the framework takes care of the NGINX glue. The
+/// round-robin peers are created for the upstream group and for each request first, so a
+/// balancer can choose among them with [`RoundRobinPeer::try_select`], or fall back to the
+/// round-robin selection with [`RoundRobinPeer::get`].
+///
+/// ```rust,ignore
+/// #[derive(Default)]
+/// struct RandomBalancer {
+///     enabled: bool,
+/// }
+///
+/// impl LoadBalancer for RandomBalancer {
+///     type Peer = ();
+///
+///     fn module() -> &'static ngx_module_t {
+///         unsafe { &*addr_of!(ngx_http_upstream_random_module) }
+///     }
+///
+///     fn init_peer(&self, _request: &mut Request, _us: &UpstreamSrvConf) -> Result<(), Status> {
+///         Ok(())
+///     }
+///
+///     fn get_peer(&self, _peer: &mut (), pc: &mut ngx_peer_connection_t, rr: &mut RoundRobinPeer) -> Status {
+///         for _ in 0..20 {
+///             if rr.try_select(pc, random_index(rr.len())) {
+///                 return Status::NGX_OK;
+///             }
+///         }
+///         rr.get(pc)
+///     }
+/// }
+///
+/// // the `random;` directive in an `upstream` block
+/// impl Directive for Random {
+///     type Conf = RandomBalancer;
+///     type Args<'a> = ();
+///
+///     fn set(cf: &mut ngx_conf_t, conf: &mut RandomBalancer, _args: ()) -> Result<ConfOk, ConfError> {
+///         conf.enabled = true;
+///         unsafe { ngx_http_upstream_set_balancer::<RandomBalancer>(cf) }.map(ConfOk::from)
+///     }
+/// }
+/// ```
+pub trait LoadBalancer: Sized + 'static {
+    /// The per-request state of the balancer.
+    type Peer: 'static;
+
+    /// The `server` parameters supported with the balancer, as `NGX_HTTP_UPSTREAM_*` flags.
+    const FLAGS: u32 = NGX_HTTP_UPSTREAM_CREATE
+        | NGX_HTTP_UPSTREAM_WEIGHT
+        | NGX_HTTP_UPSTREAM_MAX_CONNS
+        | NGX_HTTP_UPSTREAM_MAX_FAILS
+        | NGX_HTTP_UPSTREAM_FAIL_TIMEOUT
+        | NGX_HTTP_UPSTREAM_DOWN
+        | NGX_HTTP_UPSTREAM_BACKUP;
+
+    /// Returns the module whose server configuration is the balancer.
+    fn module() -> &'static ngx_module_t;
+
+    /// Initializes the balancer of the upstream group `us`, once its servers are known.
+    fn init(&mut self, _cf: &mut ngx_conf_t, _us: &mut UpstreamSrvConf) -> Result<(), ConfError> {
+        Ok(())
+    }
+
+    /// Creates the state of the balancer for a request. The error status fails the request.
+    fn init_peer(&self, request: &mut Request, us: &UpstreamSrvConf) -> Result<Self::Peer, Status>;
+
+    /// Selects the peer of the next try of the request, setting the peer address of `pc`.
+    ///
+    /// Returns `NGX_OK` once a peer is selected, or `NGX_BUSY` if none is available.
+    fn get_peer(&self, _peer: &mut Self::Peer, pc: &mut ngx_peer_connection_t, rr: &mut RoundRobinPeer) -> Status {
+        rr.get(pc)
+    }
+
+    /// Releases the peer of a try, with `state` set to `NGX_PEER_FAILED` if the try failed.
+    fn free_peer(
+        &self,
+        _peer: &mut Self::Peer,
+        pc: &mut ngx_peer_connection_t,
+        rr: &mut RoundRobinPeer,
+        state: ngx_uint_t,
+    ) {
+        rr.free(pc, state)
+    }
+}
+
+/// Sets the balancer `B` as the load balancing method of the `upstream` block being parsed.
+///
+/// This is meant to be called from the handler of the directive selecting the method. As with
+/// the NGINX methods, a warning is logged if the block already has a method.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer within an `upstream` block.
+pub unsafe fn ngx_http_upstream_set_balancer<B: LoadBalancer>(cf: *mut ngx_conf_t) -> Result<(), ConfError> {
+    let uscf = ngx_http_conf_get_module_srv_conf(cf, &*addr_of!(ngx_http_upstream_module))
+        as *mut ngx_http_upstream_srv_conf_t;
+    if uscf.is_null() {
+        return Err(ConfError::new("no upstream block"));
+    }
+
+    if (*uscf).peer.init_upstream.is_some() {
+        ngx_conf_log_error(
+            NGX_LOG_WARN as ngx_uint_t,
+            cf,
+            0,
+            c"load balancing method redefined".as_ptr(),
+        );
+    }
+
+    (*uscf).peer.init_upstream = Some(balancer_init_upstream::<B>);
+    (*uscf).flags = B::FLAGS as ngx_uint_t;
+    Ok(())
+}
+
+unsafe extern "C" fn balancer_init_upstream<B: LoadBalancer>(
+    cf: *mut ngx_conf_t,
+    us: *mut ngx_http_upstream_srv_conf_t,
+) -> ngx_int_t {
+    if ngx_http_upstream_init_round_robin(cf, us) != NGX_OK as ngx_int_t {
+        return NGX_ERROR as ngx_int_t;
+    }
+    (*us).peer.init = Some(balancer_init_peer::<B>);
+
+    let Some(balancer) = ngx_http_conf_upstream_srv_conf_mutable::<B>(us, B::module()) else {
+        return NGX_ERROR as ngx_int_t;
+    };
+    match (*balancer).init(&mut *cf, UpstreamSrvConf::from_ngx_upstream_srv_conf(us)) {
+        Ok(()) => NGX_OK as ngx_int_t,
+        Err(err) => {
+            err.log(cf, ptr::null());
+            NGX_ERROR as ngx_int_t
+        }
+    }
+}
+
+struct BalancerPeer<B: LoadBalancer> {
+    balancer: *const B,
+    peer: B::Peer,
+    rr: RoundRobinPeer,
+}
+
+unsafe extern "C" fn balancer_init_peer<B: LoadBalancer>(
+    r: *mut ngx_http_request_t,
+    us: *mut ngx_http_upstream_srv_conf_t,
+) -> ngx_int_t {
+    if ngx_http_upstream_init_round_robin_peer(r, us) != NGX_OK as ngx_int_t {
+        return NGX_ERROR as ngx_int_t;
+    }
+
+    let Some(balancer) = ngx_http_conf_upstream_srv_conf_immutable::<B>(us, B::module()) else {
+        return NGX_ERROR as ngx_int_t;
+    };
+    let request = Request::from_ngx_http_request(r);
+    let peer = match (*balancer).init_peer(request, UpstreamSrvConf::from_ngx_upstream_srv_conf(us)) {
+        Ok(peer) => peer,
+        Err(status) => return status.0,
+    };
+
+    let u = (*r).upstream;
+    let data = request.pool().allocate(BalancerPeer::<B> {
+        balancer,
+        peer,
+        rr: RoundRobinPeer {
+            data: (*u).peer.data as *mut ngx_http_upstream_rr_peer_data_t,
+        },
+    });
+    if data.is_null() {
+        return NGX_ERROR as ngx_int_t;
+    }
+
+    (*u).peer.data = data as *mut c_void;
+    (*u).peer.get = Some(balancer_get_peer::<B>);
+    (*u).peer.free = Some(balancer_free_peer::<B>);
+    NGX_OK as ngx_int_t
+}
+
+unsafe extern "C" fn balancer_get_peer<B: LoadBalancer>(
+    pc: *mut ngx_peer_connection_t,
+    data: *mut c_void,
+) -> ngx_int_t {
+    let data = &mut *(data as *mut BalancerPeer<B>);
+    (*data.balancer).get_peer(&mut data.peer, &mut *pc, &mut data.rr).0
+}
+
+unsafe extern "C" fn balancer_free_peer<B: LoadBalancer>(
+    pc: *mut ngx_peer_connection_t,
+    data: *mut c_void,
+    state: ngx_uint_t,
+) {
+    let data = &mut *(data as *mut BalancerPeer<B>);
+    (*data.balancer).free_peer(&mut data.peer, &mut *pc, &mut data.rr, state)
+}
+
+/// The round-robin state of a request, created for the [`LoadBalancer`] of its upstream group.
+pub struct RoundRobinPeer {
+    data: *mut ngx_http_upstream_rr_peer_data_t,
+}
+
+impl RoundRobinPeer {
+    /// Returns the number of primary peers.
+    pub fn len(&self) -> usize {
+        unsafe { (*(*self.data).peers).number }
+    }
+
+    /// Returns `true` if there are no primary peers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Selects the next peer with the weighted round-robin method, as if no balancer was
+    /// configured.
+    pub fn get(&mut self, pc: &mut ngx_peer_connection_t) -> Status {
+        unsafe { Status(ngx_http_upstream_get_round_robin_peer(pc, self.data as *mut c_void)) }
+    }
+
+    /// Releases the peer of a try, accounting for failures.
+    pub fn free(&mut self, pc: &mut ngx_peer_connection_t, state: ngx_uint_t) {
+        unsafe { ngx_http_upstream_free_round_robin_peer(pc, self.data as *mut c_void, state) }
+    }
+
+    /// Selects the primary peer at `index` for the next try, if it is available.
+    ///
+    /// Returns `false` if the peer does not exist, was already tried for the request, is
+    /// marked `down`, has reached its `max_conns`, or is failed according to its `max_fails`
+    /// and `fail_timeout`.
+    pub fn try_select(&mut self, pc: &mut ngx_peer_connection_t, index: usize) -> bool {
+        unsafe {
+            let rrp = &mut *self.data;
+            let peers = &mut *rrp.peers;
+            if index >= peers.number {
+                return false;
+            }
+
+            let bits = 8 * mem::size_of::<usize>();
+            let (n, m) = (index / bits, 1usize << (index % bits));
+            if *rrp.tried.add(n) & m != 0 {
+                return false;
+            }
+
+            let zone = !peers.shpool.is_null();
+            if zone {
+                ngx_rwlock_rlock(addr_of_mut!(peers.rwlock));
+            }
+
+            let mut peer = peers.peer;
+            for _ in 0..index {
+                peer = (*peer).next;
+            }
+            let peer = &mut *peer;
+            if zone {
+                ngx_rwlock_wlock(addr_of_mut!(peer.lock));
+            }
+
+            let now = ngx_time() as time_t;
+            let available = peer.down == 0
+                && !(peer.max_fails != 0 && peer.fails >= peer.max_fails && now - peer.checked <= peer.fail_timeout)
+                && !(peer.max_conns != 0 && peer.conns >= peer.max_conns);
+
+            if available {
+                rrp.current = peer;
+                pc.sockaddr = peer.sockaddr;
+                pc.socklen = peer.socklen;
+                pc.name = &mut peer.name;
+                pc.connection = ptr::null_mut();
+
+                peer.conns += 1;
+                if now - peer.checked > peer.fail_timeout {
+                    peer.checked = now;
+                }
+                *rrp.tried.add(n) |= m;
+            }
+
+            if zone {
+                ngx_rwlock_unlock(addr_of_mut!(peer.lock));
+                ngx_rwlock_unlock(addr_of_mut!(peers.rwlock));
+            }
+            available
+        }
+    }
+}
+
+/// Wrapper for an `upstream` block configuration, `ngx_http_upstream_srv_conf_t`.
+#[repr(transparent)]
+pub struct UpstreamSrvConf(ngx_http_upstream_srv_conf_t);
+
+impl UpstreamSrvConf {
+    /// Creates an [`UpstreamSrvConf`] from an [`ngx_http_upstream_srv_conf_t`].
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to an `ngx_http_upstream_srv_conf_t`.
+    pub unsafe fn from_ngx_upstream_srv_conf<'a>(us: *mut ngx_http_upstream_srv_conf_t) -> &'a mut UpstreamSrvConf {
+        &mut *us.cast::<UpstreamSrvConf>()
+    }
+
+    /// Returns the name of the upstream group.
+    pub fn host(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.host) }
+    }
+
+    /// Returns the servers of the group, as configured by the `server` directives.
+    ///
+    /// Implicit upstream groups, e.g. from `proxy_pass http://host:port`, have no servers.
+    pub fn servers(&self) -> &[UpstreamServer] {
+        match unsafe { self.0.servers.as_ref() } {
+            Some(servers) if servers.nelts > 0 => unsafe {
+                slice::from_raw_parts(servers.elts as *const UpstreamServer, servers.nelts)
+            },
+            _ => &[],
+        }
+    }
+
+    /// Returns the server configuration of `module` for the group.
+    pub fn get_module_srv_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        unsafe { (*self.0.srv_conf.add(module.ctx_index) as *const T).as_ref() }
+    }
+
+    /// Returns the inner [`ngx_http_upstream_srv_conf_t`].
+    pub fn get_inner(&self) -> &ngx_http_upstream_srv_conf_t {
+        &self.0
+    }
+}
+
+/// A `server` of an `upstream` block.
+#[repr(transparent)]
+pub struct UpstreamServer(ngx_http_upstream_server_t);
+
+impl UpstreamServer {
+    /// Returns the server name, as written in the directive.
+    pub fn name(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.name) }
+    }
+
+    /// Returns the names of the resolved addresses of the server.
+    pub fn addresses(&self) -> impl Iterator<Item = &NgxStr> {
+        let addrs: &[ngx_addr_t] = if self.0.addrs.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.0.addrs, self.0.naddrs) }
+        };
+        addrs.iter().map(|addr| unsafe { NgxStr::from_ngx_str(addr.name) })
+    }
+
+    /// Returns the `weight` of the server.
+    pub fn weight(&self) -> usize {
+        self.0.weight
+    }
+
+    /// Returns the `max_conns` of the server, 0 if unlimited.
+    pub fn max_conns(&self) -> usize {
+        self.0.max_conns
+    }
+
+    /// Returns the `max_fails` of the server, 0 if failures are not accounted.
+    pub fn max_fails(&self) -> usize {
+        self.0.max_fails
+    }
+
+    /// Returns the `fail_timeout` of the server.
+    pub fn fail_timeout(&self) -> Duration {
+        Duration::from_secs(self.0.fail_timeout.max(0) as u64)
+    }
+
+    /// Returns `true` if the server is a `backup` server.
+    pub fn is_backup(&self) -> bool {
+        self.0.backup() != 0
+    }
+
+    /// Returns `true` if the server is marked `down`.
+    pub fn is_down(&self) -> bool {
+        self.0.down() != 0
+    }
+}
+
+/// Per-request upstream server, overriding the `upstream` configured for the location.
+///
+/// This is the runtime equivalent of a `proxy_pass` with variables: see
+/// [`Request::set_upstream_target`](crate::http::Request::set_upstream_target).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpstreamTarget<'a> {
+    /// An `upstream {}` block with the given name.
+    ///
+    /// If no such block exists, the name is resolved with the `resolver` of the location and
+    /// the request fails, as there is no port to connect to.
+    Group(&'a str),
+    /// A host name and port.
+    ///
+    /// Matches an implicit upstream defined elsewhere in the configuration with the same host and
+    /// port (e.g. `proxy_pass http://host:port`); otherwise the name is resolved with the
+    /// `resolver` of the location.
+    Host(&'a str, u16),
+    /// A server address, connected to directly.
+    Addr(SocketAddr),
+}
+
+/// Record of a single upstream try, as reported by the `$upstream_*` log variables.
+///
+/// A request stores one record per contacted upstream server in `r->upstream_states`, see
+/// [`Request::upstream_states`]. Records without a peer separate tries made to different
+/// upstream groups after an internal redirect.
+///
+/// [`Request::upstream_states`]: crate::http::Request::upstream_states
+#[repr(transparent)]
+pub struct UpstreamState(ngx_http_upstream_state_t);
+
+impl UpstreamState {
+    /// Address of the contacted peer (`$upstream_addr`).
+    ///
+    /// Returns `None` for records separating upstream groups.
+    pub fn peer(&self) -> Option<&NgxStr> {
+        if self.0.peer.is_null() {
+            return None;
+        }
+        // SAFETY: the peer name is allocated from the request or configuration pool.
+        unsafe { Some(NgxStr::from_ngx_str(*self.0.peer)) }
+    }
+
+    /// Status of the upstream response (`$upstream_status`), if a response header was received.
+    pub fn status(&self) -> Option<HTTPStatus> {
+        if self.0.status == 0 {
+            return None;
+        }
+        Some(HTTPStatus(self.0.status))
+    }
+
+    /// Time spent processing the try (`$upstream_response_time`).
+    pub fn response_time(&self) -> Option<Duration> {
+        msec_to_duration(self.0.response_time)
+    }
+
+    /// Time spent establishing the connection (`$upstream_connect_time`).
+    pub fn connect_time(&self) -> Option<Duration> {
+        msec_to_duration(self.0.connect_time)
+    }
+
+    /// Time spent until the response header was received (`$upstream_header_time`).
+    pub fn header_time(&self) -> Option<Duration> {
+        msec_to_duration(self.0.header_time)
+    }
+
+    /// Time the request spent in the upstream queue (`$upstream_queue_time`).
+    pub fn queue_time(&self) -> Option<Duration> {
+        msec_to_duration(self.0.queue_time)
+    }
+
+    /// Length of the upstream response body (`$upstream_response_length`).
+    pub fn response_length(&self) -> u64 {
+        self.0.response_length.max(0) as u64
+    }
+
+    /// Number of bytes received from the upstream server (`$upstream_bytes_received`).
+    pub fn bytes_received(&self) -> u64 {
+        self.0.bytes_received.max(0) as u64
+    }
+
+    /// Number of bytes sent to the upstream server (`$upstream_bytes_sent`).
+    pub fn bytes_sent(&self) -> u64 {
+        self.0.bytes_sent.max(0) as u64
+    }
+
+    /// Returns the inner data structure that the UpstreamState object is wrapping.
+    pub fn get_inner(&self) -> &ngx_http_upstream_state_t {
+        &self.0
+    }
+}
+
+/// Unset timings are stored as `(ngx_msec_t) -1`.
+fn msec_to_duration(msec: ngx_msec_t) -> Option<Duration> {
+    if msec == ngx_msec_t::MAX {
+        return None;
+    }
+    Some(Duration::from_millis(msec as u64))
+}
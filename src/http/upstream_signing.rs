@@ -0,0 +1,222 @@
+use crate::core::{NgxStr, Pool, Status};
+use crate::ffi::*;
+use crate::http::{HTTPStatus, Method, NgxListIterator, Request};
+
+use std::fmt;
+
+/// An authentication scheme for requests passed to an upstream server, such as AWS Signature
+/// Version 4, Google Cloud identity tokens or a custom HMAC scheme.
+///
+/// The signer is the location configuration of a module. Once enabled for a request by
+/// [`Request::sign_upstream_request`], it is called with the method, URI and header fields of the
+/// request right before the content handler of the location, e.g. the one of `proxy_pass`, runs,
+/// and adds the authentication fields:
+///
+/// ```rust,ignore
+/// impl UpstreamRequestSigner for S3Signer {
+///     fn sign(&self, request: &OutboundRequest<'_>, auth: &mut AuthHeaders) -> Result<(), Status> {
+///         let date = amz_date(SystemTime::now());
+///         let host = request.header("host").ok_or(HTTPStatus::BAD_REQUEST)?;
+///         let signature = self.sigv4(request.method_name(), request.uri(), host, &date);
+///
+///         auth.insert("X-Amz-Date", date);
+///         auth.insert("X-Amz-Content-Sha256", "UNSIGNED-PAYLOAD");
+///         auth.insert("Authorization", signature);
+///         Ok(())
+///     }
+/// }
+///
+/// // a precontent phase handler, before the request is passed with `proxy_pass`
+/// http_request_handler!(s3_sign_handler, |request: &mut Request| {
+///     let module = unsafe { &*addr_of!(ngx_http_s3_module) };
+///     if !request.get_module_loc_conf::<S3Signer>(module).is_some_and(|conf| conf.enabled) {
+///         return Status::NGX_DECLINED;
+///     }
+///     match request.sign_upstream_request::<S3Signer>(module) {
+///         Ok(()) => Status::NGX_DECLINED,
+///         Err(rc) => rc,
+///     }
+/// });
+/// ```
+///
+/// The request body is not read yet when the signer is called, so schemes signing the payload
+/// have to use their unsigned payload variant. The body is then passed to the upstream server as
+/// configured by `proxy_request_buffering`.
+pub trait UpstreamRequestSigner {
+    /// Computes the authentication fields of `request`, adding them to `auth`.
+    ///
+    /// Returns the status to fail the request with if the request cannot be signed.
+    fn sign(&self, request: &OutboundRequest<'_>, auth: &mut AuthHeaders) -> Result<(), Status>;
+}
+
+/// A request about to be passed to an upstream server, as seen by an [`UpstreamRequestSigner`].
+pub struct OutboundRequest<'a> {
+    request: &'a Request,
+}
+
+impl<'a> OutboundRequest<'a> {
+    /// Returns the request method.
+    pub fn method(&self) -> Method {
+        self.request.method()
+    }
+
+    /// Returns the request method as sent, including methods unknown to NGINX.
+    pub fn method_name(&self) -> &'a NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.request.get_inner().method_name) }
+    }
+
+    /// Returns the request URI, including the arguments.
+    ///
+    /// This is the URI passed to the upstream server unless it is changed by the `proxy_pass`
+    /// URI or by a rewrite after signing.
+    pub fn uri(&self) -> &'a NgxStr {
+        self.request.unparsed_uri()
+    }
+
+    /// Returns the first header field named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&'a NgxStr> {
+        self.request.header_in(name)
+    }
+
+    /// Returns an iterator over the request header fields.
+    pub fn headers(&self) -> NgxListIterator<'a> {
+        self.request.headers_in_iterator()
+    }
+
+    /// Returns the request, e.g. to evaluate variables or get the module configuration.
+    pub fn request(&self) -> &'a Request {
+        self.request
+    }
+}
+
+impl fmt::Debug for OutboundRequest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutboundRequest")
+            .field("method", &self.method_name())
+            .field("uri", &self.uri())
+            .finish()
+    }
+}
+
+/// The authentication header fields added by an [`UpstreamRequestSigner`].
+#[derive(Clone, Debug, Default)]
+pub struct AuthHeaders {
+    fields: Vec<(String, String)>,
+}
+
+impl AuthHeaders {
+    /// Sets the field `name` to `value`, replacing a value set before.
+    pub fn insert<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        let name = name.into();
+        let value = value.into();
+        match self.fields.iter_mut().find(|(key, _)| key.eq_ignore_ascii_case(&name)) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((name, value)),
+        }
+    }
+
+    /// Returns the value of the field `name`, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns an iterator over the fields.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+impl Request {
+    /// Signs the request passed to the upstream server with the location configuration `C` of
+    /// `module`.
+    ///
+    /// This is meant to be called from a phase handler running before the content phase, such
+    /// as a precontent handler. The content handler of the location, e.g. the one of
+    /// `proxy_pass`, is wrapped so that the signer is called right before it creates the upstream
+    /// request. The authentication fields replace the fields of the client request with the same
+    /// name, so a client cannot supply its own credentials.
+    ///
+    /// Returns the status to return from the handler if the request cannot be set up for
+    /// signing. A request the signer fails to sign is finalized with the status returned by the
+    /// signer.
+    pub fn sign_upstream_request<C>(&mut self, module: &'static ngx_module_t) -> Result<(), Status>
+    where
+        C: UpstreamRequestSigner + 'static,
+    {
+        let Some(content_handler) = self.0.content_handler else {
+            // nothing is passed to an upstream server
+            return Ok(());
+        };
+        if content_handler as usize == signing_content_handler as usize {
+            return Ok(());
+        }
+
+        let sign: SignHandler = Box::new(move |request: &Request| {
+            let signer = request.get_module_loc_conf::<C>(module).ok_or(Status::NGX_ERROR)?;
+            let mut auth = AuthHeaders::default();
+            signer.sign(&OutboundRequest { request }, &mut auth)?;
+            Ok(auth)
+        });
+
+        unsafe {
+            let r: *mut ngx_http_request_t = self.into();
+
+            // replaces the signing of a request redirected to another location
+            let state = SigningState { content_handler, sign };
+            if Pool::from_ngx_pool((*r).pool).set_data(r as usize, state).is_none() {
+                return Err(HTTPStatus::INTERNAL_SERVER_ERROR.into());
+            }
+            (*r).content_handler = Some(signing_content_handler);
+        }
+        Ok(())
+    }
+}
+
+type SignHandler = Box<dyn Fn(&Request) -> Result<AuthHeaders, Status>>;
+type RequestHandler = unsafe extern "C" fn(*mut ngx_http_request_t) -> ngx_int_t;
+
+/// The signing of a request, attached to the request pool for the request.
+struct SigningState {
+    /// The content handler of the location.
+    content_handler: RequestHandler,
+    sign: SignHandler,
+}
+
+/// Signs the request, then runs the content handler of the location.
+unsafe extern "C" fn signing_content_handler(r: *mut ngx_http_request_t) -> ngx_int_t {
+    let Some(state) = Pool::from_ngx_pool((*r).pool).data::<SigningState>(r as usize) else {
+        return NGX_HTTP_INTERNAL_SERVER_ERROR as ngx_int_t;
+    };
+    let state = &*state;
+
+    let request = Request::from_ngx_http_request(r);
+    let auth = match (state.sign)(&*request) {
+        Ok(auth) => auth,
+        Err(rc) => {
+            ngx_log_error_core(
+                NGX_LOG_ERR as ngx_uint_t,
+                request.log(),
+                0,
+                c"failed to sign upstream request: %i".as_ptr(),
+                rc.0,
+            );
+            return rc.0;
+        }
+    };
+
+    for (name, value) in auth.iter() {
+        if request.set_header_in(name, value).is_err() {
+            ngx_log_error_core(
+                NGX_LOG_ERR as ngx_uint_t,
+                request.log(),
+                0,
+                c"failed to set upstream authentication header".as_ptr(),
+            );
+            return NGX_HTTP_INTERNAL_SERVER_ERROR as ngx_int_t;
+        }
+    }
+    (state.content_handler)(r)
+}
@@ -0,0 +1,118 @@
+use crate::ffi::*;
+use crate::http::Request;
+
+/// Read-only view of the HTTP/2 stream of a request.
+///
+/// Modules adapting their responses to multiplexed clients, e.g. by lowering the priority of
+/// background work, can inspect the stream priority and the flow control windows. The values
+/// are a snapshot: NGINX updates them as frames are exchanged with the client.
+#[derive(Clone, Copy)]
+pub struct Http2Stream<'a>(&'a ngx_http_v2_stream_t);
+
+impl<'a> Http2Stream<'a> {
+    /// Returns the stream identifier.
+    pub fn id(&self) -> u32 {
+        self.node().map_or(0, |node| node.id as u32)
+    }
+
+    /// Returns the weight of the stream, between 1 and 256, if the client sent priority
+    /// information.
+    pub fn weight(&self) -> Option<u32> {
+        let node = self.node()?;
+        (node.weight != 0).then_some(node.weight as u32)
+    }
+
+    /// Returns the identifier of the stream this stream depends on, if any.
+    pub fn parent_id(&self) -> Option<u32> {
+        let parent = unsafe { self.node()?.parent.as_ref()? };
+        Some(parent.id as u32)
+    }
+
+    /// Returns the stream flow control window for sending response data, which can be negative
+    /// after the client reduced the initial window size.
+    pub fn send_window(&self) -> isize {
+        self.0.send_window as isize
+    }
+
+    /// Returns the stream flow control window for receiving request body data.
+    pub fn recv_window(&self) -> usize {
+        self.0.recv_window
+    }
+
+    /// Returns the connection flow control window for sending response data, shared by all
+    /// streams of the connection.
+    pub fn connection_send_window(&self) -> usize {
+        unsafe { self.0.connection.as_ref() }.map_or(0, |h2c| h2c.send_window)
+    }
+
+    /// Returns `true` if sending response data is blocked by flow control.
+    pub fn is_exhausted(&self) -> bool {
+        self.0.exhausted() != 0
+    }
+
+    /// Returns the HTTP/2 connection carrying the stream.
+    pub fn connection(&self) -> Option<Http2Connection<'a>> {
+        unsafe { self.0.connection.as_ref() }.map(Http2Connection)
+    }
+
+    fn node(&self) -> Option<&'a ngx_http_v2_node_t> {
+        unsafe { self.0.node.as_ref() }
+    }
+}
+
+/// Read-only view of an HTTP/2 connection, shared by the streams multiplexed on it.
+///
+/// The settings are the ones announced by the client. As for the streams, the values are a
+/// snapshot of the state of the connection.
+#[derive(Clone, Copy)]
+pub struct Http2Connection<'a>(&'a ngx_http_v2_connection_t);
+
+impl<'a> Http2Connection<'a> {
+    /// Returns the number of streams being processed on the connection.
+    pub fn processing(&self) -> usize {
+        self.0.processing
+    }
+
+    /// Returns the identifier of the last stream opened by the client.
+    pub fn last_stream_id(&self) -> u32 {
+        self.0.last_sid as u32
+    }
+
+    /// Returns the initial flow control window of the streams, from the
+    /// `SETTINGS_INITIAL_WINDOW_SIZE` setting of the client.
+    pub fn initial_window(&self) -> usize {
+        self.0.init_window
+    }
+
+    /// Returns the largest frame the client accepts, from its `SETTINGS_MAX_FRAME_SIZE` setting.
+    pub fn max_frame_size(&self) -> usize {
+        self.0.frame_size
+    }
+
+    /// Returns the number of bytes received on the connection, including the frame headers.
+    pub fn total_bytes(&self) -> u64 {
+        self.0.total_bytes as u64
+    }
+
+    /// Returns the number of bytes of request bodies received on the connection.
+    pub fn payload_bytes(&self) -> u64 {
+        self.0.payload_bytes as u64
+    }
+
+    /// Returns `true` once the client acknowledged the settings of the server.
+    pub fn is_settings_acked(&self) -> bool {
+        self.0.settings_ack() != 0
+    }
+
+    /// Returns `true` if a `GOAWAY` frame was sent, and no new streams are accepted.
+    pub fn is_goaway(&self) -> bool {
+        self.0.goaway() != 0
+    }
+}
+
+impl Request {
+    /// Returns the HTTP/2 stream of the request, or `None` for other protocol versions.
+    pub fn http2_stream(&self) -> Option<Http2Stream<'_>> {
+        unsafe { self.get_inner().stream.as_ref() }.map(Http2Stream)
+    }
+}
@@ -0,0 +1,532 @@
+use crate::core::{BuildInfo, ConfError, Pool, RedactedConf, Status};
+use crate::ffi::*;
+use crate::http::{Request, RequestError};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::ptr::{self, addr_of};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Largest value length representable in the 28-bit `len` field of [`ngx_variable_value_t`].
+const VARIABLE_VALUE_MAX_LEN: usize = (1 << 28) - 1;
+
+/// A well-formed value returned from a variable get handler.
+///
+/// The constructors take care of the length and flag bitfields, so a get handler can simply
+/// store the result:
+///
+/// ```rust,ignore
+/// http_variable_get!(get_my_var, |request: &mut Request, v: *mut ngx_variable_value_t, _: usize| {
+///     let Some(value) = VariableValue::from_str_in(&mut request.pool(), "hello") else {
+///         return core::Status::NGX_ERROR;
+///     };
+///     unsafe { *v = value.cacheable(false).into() };
+///     core::Status::NGX_OK
+/// });
+/// ```
+///
+/// [`ngx_variable_value_t`]: https://nginx.org/en/docs/dev/development_guide.html#http_variables
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct VariableValue(ngx_variable_value_t);
+
+impl VariableValue {
+    /// Creates a valid value pointing to a copy of `bytes` allocated from `pool`.
+    ///
+    /// Returns `None` if the allocation fails or `bytes` is too long for a variable value.
+    pub fn from_bytes_in(pool: &mut Pool, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() > VARIABLE_VALUE_MAX_LEN {
+            return None;
+        }
+
+        let data = if bytes.is_empty() {
+            ptr::null_mut()
+        } else {
+            let data = pool.alloc(bytes.len()) as *mut u8;
+            if data.is_null() {
+                return None;
+            }
+            unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len()) };
+            data
+        };
+
+        Some(Self::from_raw_parts(data, bytes.len()))
+    }
+
+    /// Creates a valid value pointing to a copy of `s` allocated from `pool`.
+    ///
+    /// Returns `None` if the allocation fails or `s` is too long for a variable value.
+    pub fn from_str_in(pool: &mut Pool, s: &str) -> Option<Self> {
+        Self::from_bytes_in(pool, s.as_bytes())
+    }
+
+    /// Creates a valid value from a static string, without copying it.
+    pub fn from_static(s: &'static str) -> Self {
+        assert!(s.len() <= VARIABLE_VALUE_MAX_LEN);
+        Self::from_raw_parts(s.as_ptr() as *mut u8, s.len())
+    }
+
+    /// Creates a valid value holding the decimal representation of `value`, allocated from `pool`.
+    ///
+    /// Returns `None` if the allocation fails.
+    pub fn from_int(pool: &mut Pool, value: i64) -> Option<Self> {
+        Self::from_str_in(pool, &value.to_string())
+    }
+
+    /// Creates a value marking the variable as not found, which is logged as `-` and evaluates to
+    /// an empty string.
+    pub fn not_found() -> Self {
+        // SAFETY: all-zero bits are a valid empty, invalid value with a null data pointer
+        let mut value: ngx_variable_value_t = unsafe { mem::zeroed() };
+        value.set_not_found(1);
+        VariableValue(value)
+    }
+
+    /// Sets whether NGINX may cache the value for the rest of the request.
+    ///
+    /// Values are cacheable by default. Variables registered with `NGX_HTTP_VAR_NOCACHEABLE`
+    /// are evaluated on every access regardless of this flag.
+    pub fn cacheable(mut self, cacheable: bool) -> Self {
+        self.0.set_no_cacheable(if cacheable { 0 } else { 1 });
+        self
+    }
+
+    /// Returns `true` if the value marks the variable as not found.
+    pub fn is_not_found(&self) -> bool {
+        self.0.not_found() != 0
+    }
+
+    /// Returns the value bytes, or an empty slice for a not found value.
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.0.valid() == 0 || self.0.data.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.0.data, self.0.len() as usize) }
+    }
+
+    /// Returns the raw [`ngx_variable_value_t`].
+    ///
+    /// [`ngx_variable_value_t`]: https://nginx.org/en/docs/dev/development_guide.html#http_variables
+    pub fn into_inner(self) -> ngx_variable_value_t {
+        self.0
+    }
+
+    fn from_raw_parts(data: *mut u8, len: usize) -> Self {
+        // SAFETY: all-zero bits are a valid empty, invalid value with a null data pointer
+        let mut value: ngx_variable_value_t = unsafe { mem::zeroed() };
+        value.set_len(len as _);
+        value.set_valid(1);
+        value.data = data;
+        VariableValue(value)
+    }
+}
+
+impl From<VariableValue> for ngx_variable_value_t {
+    fn from(value: VariableValue) -> Self {
+        value.into_inner()
+    }
+}
+
+/// A get handler of a variable registered with [`VariableRegistrar::add_variable`].
+type VariableGetter = Box<dyn Fn(&mut Request) -> Option<VariableValue>>;
+
+/// What the value of a variable registered with [`VariableRegistrar::add_cached_variable`]
+/// depends on, besides the request.
+///
+/// The value is computed on the first access, and computed again on an access after the
+/// dependency changed. NGINX itself only caches the values of indexed variables, for the whole
+/// request, including after the URI was changed by a rewrite or an internal redirect.
+#[derive(Clone, Copy, Debug)]
+pub enum VariableCache {
+    /// Nothing else, the value is computed once per request.
+    Request,
+    /// The URI of the request, changed by rewrites and internal redirects.
+    Uri,
+    /// The location of the request, changed by internal redirects and by rewrites with `last`.
+    Location,
+    /// The key returned by the function, e.g. a hash of the request headers the value is
+    /// computed from.
+    Key(fn(&Request) -> u64),
+}
+
+impl VariableCache {
+    fn key(&self, request: &Request) -> u64 {
+        match self {
+            VariableCache::Request => 0,
+            VariableCache::Uri => {
+                let mut hasher = DefaultHasher::new();
+                request.uri().as_bytes().hash(&mut hasher);
+                hasher.finish()
+            }
+            VariableCache::Location => request.get_inner().loc_conf as u64,
+            VariableCache::Key(key) => key(request),
+        }
+    }
+}
+
+/// Identifiers of the variables registered with [`VariableRegistrar::add_cached_variable`].
+static CACHED_VARIABLE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Registration of HTTP variables with Rust get handlers, from the `preconfiguration` handler of
+/// an HTTP module.
+pub trait VariableRegistrar {
+    /// Adds the variable `$name`, evaluated by `getter`.
+    ///
+    /// `flags` is a combination of the `NGX_HTTP_VAR_*` flags, e.g. `NGX_HTTP_VAR_NOCACHEABLE`.
+    /// The getter returns the value, usually allocated from the request pool, or `None` if the
+    /// variable is not found for the request.
+    ///
+    /// This is called from the `preconfiguration` handler, so the variable can be used in the
+    /// configuration.
+    ///
+    /// ```rust,ignore
+    /// // in preconfiguration
+    /// (*cf).add_variable("request_tag", NGX_HTTP_VAR_NOCACHEABLE as ngx_uint_t, |request| {
+    ///     let tag = format!("{:x}", request.get_inner().start_msec);
+    ///     VariableValue::from_str_in(&mut request.pool(), &tag)
+    /// })?;
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The configuration is the valid `ngx_conf_t` passed to the `preconfiguration` handler of
+    /// an HTTP module, and this is called from that handler.
+    unsafe fn add_variable<F>(&mut self, name: &str, flags: ngx_uint_t, getter: F) -> Result<(), ConfError>
+    where
+        F: Fn(&mut Request) -> Option<VariableValue> + 'static;
+
+    /// Adds the variable `$name`, evaluated by `getter` once per request and again on an access
+    /// after the dependency `cache` changed.
+    ///
+    /// This suits expensive variables, e.g. a lookup in an external database, accessed several
+    /// times per request by the log and by other modules. The variable is registered with
+    /// `NGX_HTTP_VAR_NOCACHEABLE`, so NGINX asks for the value on each access and the cached
+    /// value is returned until the dependency changes.
+    ///
+    /// ```rust,ignore
+    /// // in preconfiguration
+    /// (*cf).add_cached_variable("route_owner", VariableCache::Uri, |request| {
+    ///     let owner = lookup_owner(request.uri())?;
+    ///     VariableValue::from_str_in(&mut request.pool(), &owner)
+    /// })?;
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The configuration is the valid `ngx_conf_t` passed to the `preconfiguration` handler of
+    /// an HTTP module, and this is called from that handler.
+    unsafe fn add_cached_variable<F>(&mut self, name: &str, cache: VariableCache, getter: F) -> Result<(), ConfError>
+    where
+        F: Fn(&mut Request) -> Option<VariableValue> + 'static,
+    {
+        let id = CACHED_VARIABLE_ID.fetch_add(1, Ordering::Relaxed);
+        self.add_variable(name, NGX_HTTP_VAR_NOCACHEABLE as ngx_uint_t, move |request| {
+            let key = cache.key(request);
+            if let Some(entry) = request.cached_variable(id) {
+                if entry.key == key {
+                    return entry.value;
+                }
+            }
+
+            let value = getter(request);
+            request.cache_variable(id, key, value);
+            value
+        })
+    }
+
+    /// Returns the index of the variable `$name`, for [`Request::variable_indexed`].
+    ///
+    /// The variable must be defined by the end of the configuration; an unknown variable fails
+    /// the configuration when it is applied.
+    ///
+    /// # Safety
+    ///
+    /// The configuration is the valid `ngx_conf_t` of the HTTP configuration being parsed, e.g.
+    /// passed to a directive handler.
+    unsafe fn variable_index(&mut self, name: &str) -> Result<usize, ConfError>;
+
+    /// Adds the variable `$name` evaluating to the build information of the module, e.g.
+    /// `ngx_http_auth_module/0.3.1 (9f2c1e4)`.
+    ///
+    /// # Safety
+    ///
+    /// The configuration is the valid `ngx_conf_t` passed to the `preconfiguration` handler of
+    /// an HTTP module, and this is called from that handler.
+    unsafe fn add_build_info_variable(&mut self, name: &str, info: &'static BuildInfo) -> Result<(), ConfError> {
+        self.add_variable(name, 0, move |request| {
+            VariableValue::from_str_in(&mut request.pool(), &info.to_string())
+        })
+    }
+
+    /// Adds the variable `$name` evaluating to the location configuration of `module` for the
+    /// request, after merging, as a JSON object described by [`DescribeConf`](crate::core::DescribeConf).
+    ///
+    /// This is an opt-in debugging aid, answering which values a location actually inherited,
+    /// e.g. with `add_header X-Debug-Conf $my_module_conf always;` in a test configuration.
+    /// The variable is not cacheable, so it follows internal redirects to other locations.
+    ///
+    /// As the value can end up in responses and access logs, the configuration must redact its
+    /// secrets, see [`RedactedConf`], e.g. with `#[derive(DescribeConf)]` and `#[describe(redacted)]`.
+    ///
+    /// ```rust,ignore
+    /// // in preconfiguration
+    /// (*cf).add_conf_dump_variable::<LocConf>("my_module_conf", &*addr_of!(ngx_http_my_module))?;
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The configuration is the valid `ngx_conf_t` passed to the `preconfiguration` handler of
+    /// an HTTP module, and this is called from that handler.
+    unsafe fn add_conf_dump_variable<C>(&mut self, name: &str, module: &'static ngx_module_t) -> Result<(), ConfError>
+    where
+        C: RedactedConf + 'static,
+    {
+        self.add_variable(name, NGX_HTTP_VAR_NOCACHEABLE as ngx_uint_t, move |request| {
+            let dump = request.get_module_loc_conf::<C>(module)?.dump();
+            VariableValue::from_str_in(&mut request.pool(), &dump.to_json())
+        })
+    }
+}
+
+impl VariableRegistrar for ngx_conf_t {
+    unsafe fn add_variable<F>(&mut self, name: &str, flags: ngx_uint_t, getter: F) -> Result<(), ConfError>
+    where
+        F: Fn(&mut Request) -> Option<VariableValue> + 'static,
+    {
+        let mut pool = Pool::from_ngx_pool(self.pool);
+        let getter = pool.allocate::<VariableGetter>(Box::new(getter));
+        if getter.is_null() {
+            return Err(ConfError::new("failed to allocate variable handler"));
+        }
+
+        let mut ngx_name = ngx_str_t::from_str(self.pool, name);
+        let v = ngx_http_add_variable(self, &mut ngx_name, flags);
+        if v.is_null() {
+            return Err(ConfError::new(format!("failed to add variable \"${}\"", name)));
+        }
+        (*v).get_handler = Some(variable_get_handler);
+        (*v).data = getter as usize;
+
+        Ok(())
+    }
+
+    unsafe fn variable_index(&mut self, name: &str) -> Result<usize, ConfError> {
+        let mut ngx_name = ngx_str_t::from_str(self.pool, name);
+        let index = ngx_http_get_variable_index(self, &mut ngx_name);
+        if index == Status::NGX_ERROR.0 {
+            return Err(ConfError::new(format!("failed to add variable \"${}\"", name)));
+        }
+        Ok(index as usize)
+    }
+}
+
+/// A value of a variable registered with [`VariableRegistrar::add_cached_variable`].
+#[derive(Clone, Copy)]
+struct CachedVariable {
+    key: u64,
+    value: Option<VariableValue>,
+}
+
+/// The values of the variables registered with [`VariableRegistrar::add_cached_variable`] for a
+/// request, by variable, attached to the request pool, which is shared with the subrequests.
+type CachedVariables = HashMap<usize, CachedVariable>;
+
+impl Request {
+    fn cached_variable(&mut self, id: usize) -> Option<&mut CachedVariable> {
+        let variables = self.pool().data::<CachedVariables>(&self.0 as *const _ as usize)?;
+        unsafe { (*variables).get_mut(&id) }
+    }
+
+    fn cache_variable(&mut self, id: usize, key: u64, value: Option<VariableValue>) {
+        let request = &self.0 as *const _ as usize;
+        let mut pool = self.pool();
+        // the value is computed again on the next access if it cannot be cached
+        let Some(variables) = pool
+            .data::<CachedVariables>(request)
+            .or_else(|| pool.set_data(request, CachedVariables::new()))
+        else {
+            return;
+        };
+        unsafe { (*variables).insert(id, CachedVariable { key, value }) };
+    }
+}
+
+unsafe extern "C" fn variable_get_handler(
+    r: *mut ngx_http_request_t,
+    v: *mut ngx_variable_value_t,
+    data: usize,
+) -> ngx_int_t {
+    let getter = &*(data as *const VariableGetter);
+    let value = getter(Request::from_ngx_http_request(r)).unwrap_or_else(VariableValue::not_found);
+    *v = value.into();
+    Status::NGX_OK.into()
+}
+
+impl Request {
+    /// Returns the value of the variable `$name`, e.g. `remote_addr`, or `None` if the variable
+    /// is unknown or not found for the request.
+    ///
+    /// Prefixed variables such as `$http_user_agent` or `$arg_id` are supported. For frequent
+    /// lookups, [`Request::variable_indexed`] avoids hashing the name on each call.
+    pub fn variable(&mut self, name: &str) -> Option<&[u8]> {
+        let mut lowcase = name.to_ascii_lowercase().into_bytes();
+        unsafe {
+            let key = ngx_hash_key(lowcase.as_mut_ptr(), lowcase.len());
+            let mut name = ngx_str_t {
+                len: lowcase.len(),
+                data: lowcase.as_mut_ptr(),
+            };
+            value_bytes(ngx_http_get_variable(self.into(), &mut name, key))
+        }
+    }
+
+    /// Returns the value of the indexed variable `index`, as returned by
+    /// [`VariableRegistrar::variable_index`], or `None` if it is not found for the request.
+    pub fn variable_indexed(&mut self, index: usize) -> Option<&[u8]> {
+        unsafe { value_bytes(ngx_http_get_indexed_variable(self.into(), index)) }
+    }
+
+    /// Sets the value of the changeable variable `$name`, e.g. `limit_rate` or a variable
+    /// declared with the `set` directive.
+    ///
+    /// The value is copied to the request pool. Returns [`RequestError::InvalidValue`] if the
+    /// variable is unknown or not declared changeable with `NGX_HTTP_VAR_CHANGEABLE`, so the
+    /// values of built-in variables such as `$uri` or `$remote_addr` seen by the later phases
+    /// and by the log cannot be overwritten.
+    pub fn set_variable(&mut self, name: &str, value: &[u8]) -> Result<(), RequestError> {
+        let mut lowcase = name.to_ascii_lowercase().into_bytes();
+        let value = VariableValue::from_bytes_in(&mut self.pool(), value).ok_or(RequestError::Allocation)?;
+
+        unsafe {
+            let cmcf =
+                *self.0.main_conf.add((*addr_of!(ngx_http_core_module)).ctx_index) as *mut ngx_http_core_main_conf_t;
+            let key = ngx_hash_key(lowcase.as_mut_ptr(), lowcase.len());
+            let v = ngx_hash_find(&mut (*cmcf).variables_hash, key, lowcase.as_mut_ptr(), lowcase.len())
+                as *mut ngx_http_variable_t;
+            let Some(v) = v.as_mut().filter(|v| is_changeable(v)) else {
+                return Err(RequestError::InvalidValue);
+            };
+
+            if let Some(set_handler) = v.set_handler {
+                let vv = self.pool().alloc_type::<ngx_variable_value_t>();
+                if vv.is_null() {
+                    return Err(RequestError::Allocation);
+                }
+                *vv = value.into_inner();
+                set_handler(self.into(), vv, v.data);
+            } else if v.flags & NGX_HTTP_VAR_INDEXED as ngx_uint_t != 0 && !self.0.variables.is_null() {
+                *self.0.variables.add(v.index) = value.into_inner();
+            } else {
+                return Err(RequestError::InvalidValue);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `true` if the value of the variable can be set by [`Request::set_variable`].
+fn is_changeable(v: &ngx_http_variable_t) -> bool {
+    v.flags & NGX_HTTP_VAR_CHANGEABLE as ngx_uint_t != 0
+}
+
+/// Returns the bytes of a variable value returned by NGINX, or `None` if it is not found.
+unsafe fn value_bytes<'a>(v: *mut ngx_variable_value_t) -> Option<&'a [u8]> {
+    let v = v.as_ref()?;
+    if v.not_found() != 0 || v.valid() == 0 {
+        return None;
+    }
+    if v.data.is_null() {
+        return Some(&[]);
+    }
+    Some(std::slice::from_raw_parts(v.data, v.len() as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::raw::c_void;
+
+    fn ngx_str(s: &'static str) -> ngx_str_t {
+        ngx_str_t {
+            len: s.len(),
+            data: s.as_ptr() as *mut u8,
+        }
+    }
+
+    #[test]
+    fn test_variable_value() {
+        let value = VariableValue::from_static("abc");
+        assert_eq!(value.as_bytes(), b"abc");
+        assert!(!value.is_not_found());
+
+        let raw: ngx_variable_value_t = value.into();
+        assert_eq!((raw.len(), raw.valid(), raw.no_cacheable()), (3, 1, 0));
+
+        assert_eq!(VariableValue::from_static("").as_bytes(), b"");
+
+        let value = VariableValue::not_found();
+        assert!(value.is_not_found());
+        assert_eq!(value.as_bytes(), b"");
+        assert_eq!(value.into_inner().valid(), 0);
+    }
+
+    #[test]
+    fn test_variable_value_cacheable() {
+        let value = VariableValue::from_static("abc").cacheable(false);
+        assert_eq!(value.into_inner().no_cacheable(), 1);
+        assert_eq!(value.as_bytes(), b"abc");
+
+        let value = value.cacheable(true);
+        assert_eq!(value.into_inner().no_cacheable(), 0);
+
+        // a not found value stays not found
+        let value = VariableValue::not_found().cacheable(false);
+        assert!(value.is_not_found());
+        assert_eq!(value.into_inner().no_cacheable(), 1);
+    }
+
+    #[test]
+    fn test_variable_cache() {
+        let mut loc_conf: [*mut c_void; 2] = [ptr::null_mut(); 2];
+        let mut r: ngx_http_request_t = unsafe { mem::zeroed() };
+        r.uri = ngx_str("/a");
+        r.loc_conf = ptr::addr_of_mut!(loc_conf[0]);
+
+        let key =
+            |cache: VariableCache, r: &mut ngx_http_request_t| cache.key(unsafe { Request::from_ngx_http_request(r) });
+        let (request, uri, location) = (
+            key(VariableCache::Request, &mut r),
+            key(VariableCache::Uri, &mut r),
+            key(VariableCache::Location, &mut r),
+        );
+        assert_eq!(key(VariableCache::Uri, &mut r), uri);
+
+        // a rewrite changes the URI only
+        r.uri = ngx_str("/b");
+        assert_eq!(key(VariableCache::Request, &mut r), request);
+        assert_ne!(key(VariableCache::Uri, &mut r), uri);
+        assert_eq!(key(VariableCache::Location, &mut r), location);
+
+        // an internal redirect changes the location
+        r.loc_conf = ptr::addr_of_mut!(loc_conf[1]);
+        assert_eq!(key(VariableCache::Request, &mut r), request);
+        assert_ne!(key(VariableCache::Location, &mut r), location);
+
+        let cache = VariableCache::Key(|request| request.uri().len() as u64 + 1);
+        assert_eq!(key(cache, &mut r), 3);
+    }
+
+    #[test]
+    fn test_is_changeable() {
+        let mut v = crate::ngx_http_null_variable!();
+        v.flags = NGX_HTTP_VAR_INDEXED as ngx_uint_t;
+        // e.g. `$uri`, cached in the indexed values of the request
+        assert!(!is_changeable(&v));
+
+        v.flags |= NGX_HTTP_VAR_CHANGEABLE as ngx_uint_t;
+        assert!(is_changeable(&v));
+    }
+}
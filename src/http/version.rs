@@ -0,0 +1,94 @@
+use crate::ffi::*;
+use crate::http::Request;
+
+/// The HTTP protocol version of a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HttpVersion {
+    /// HTTP/0.9, a request line without a version.
+    Http09,
+    /// HTTP/1.0.
+    Http10,
+    /// HTTP/1.1, or a later HTTP/1.x version.
+    Http11,
+    /// HTTP/2.
+    Http2,
+    /// HTTP/3.
+    Http3,
+}
+
+impl HttpVersion {
+    /// Returns the version from the `http_version` field of a request, e.g. 1001 for HTTP/1.1.
+    pub fn from_ngx_version(version: ngx_uint_t) -> HttpVersion {
+        match version as u32 {
+            v if v >= NGX_HTTP_VERSION_30 => HttpVersion::Http3,
+            v if v >= NGX_HTTP_VERSION_20 => HttpVersion::Http2,
+            v if v >= NGX_HTTP_VERSION_11 => HttpVersion::Http11,
+            v if v >= NGX_HTTP_VERSION_10 => HttpVersion::Http10,
+            _ => HttpVersion::Http09,
+        }
+    }
+
+    /// Returns the version as in the `$server_protocol` variable, e.g. `HTTP/1.1` or `HTTP/2.0`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpVersion::Http09 => "HTTP/0.9",
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+            HttpVersion::Http2 => "HTTP/2.0",
+            HttpVersion::Http3 => "HTTP/3.0",
+        }
+    }
+
+    /// Returns `true` for the versions multiplexing requests as streams of a connection.
+    pub fn is_multiplexed(&self) -> bool {
+        *self >= HttpVersion::Http2
+    }
+}
+
+impl std::fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Request {
+    /// Returns the HTTP protocol version of the request.
+    pub fn http_version(&self) -> HttpVersion {
+        HttpVersion::from_ngx_version(self.get_inner().http_version)
+    }
+
+    /// Returns the identifier of the HTTP/2 or HTTP/3 stream of the request, or `None` for
+    /// HTTP/1.x and for protocols whose support is not enabled with the `http_v2` and `http_v3`
+    /// features.
+    pub fn stream_id(&self) -> Option<u64> {
+        #[cfg(feature = "http_v2")]
+        if let Some(stream) = self.http2_stream() {
+            return Some(stream.id() as u64);
+        }
+        #[cfg(feature = "http_v3")]
+        if let Some(stream) = unsafe { crate::core::Connection::from_ngx_connection(self.connection()) }.quic_stream() {
+            return Some(stream.id());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_version() {
+        let version = |v: u32| HttpVersion::from_ngx_version(v as ngx_uint_t);
+
+        assert_eq!(version(NGX_HTTP_VERSION_9), HttpVersion::Http09);
+        assert_eq!(version(NGX_HTTP_VERSION_10), HttpVersion::Http10);
+        assert_eq!(version(NGX_HTTP_VERSION_11), HttpVersion::Http11);
+        assert_eq!(version(1005), HttpVersion::Http11);
+        assert_eq!(version(NGX_HTTP_VERSION_20), HttpVersion::Http2);
+        assert_eq!(version(NGX_HTTP_VERSION_30), HttpVersion::Http3);
+        assert!(!HttpVersion::Http11.is_multiplexed());
+        assert!(HttpVersion::Http3.is_multiplexed());
+        assert_eq!(HttpVersion::Http2.to_string(), "HTTP/2.0");
+    }
+}
@@ -33,6 +33,8 @@
 //! ```
 
 #![warn(missing_docs)]
+// handlers report to the error log, the standard output is not seen once NGINX is a daemon
+#![warn(clippy::print_stdout, clippy::print_stderr)]
 /// The core module.
 ///
 /// This module provides fundamental utilities needed to interface with many NGINX primitives.
@@ -40,6 +42,11 @@
 /// utilities will generally align with the NGINX 'core' files and APIs.
 pub mod core;
 
+/// The event module.
+///
+/// This module provides wrappers and utilities for the NGINX event loop, such as timers.
+pub mod event;
+
 /// The ffi module.
 ///
 /// This module provides scoped FFI bindings for NGINX symbols.
@@ -56,6 +63,19 @@ pub mod http;
 /// This module provides an interface into the NGINX logger framework.
 pub mod log;
 
+/// The stream module.
+///
+/// This module provides wrappers and utilities to NGINX stream (TCP/UDP) APIs, such as sessions,
+/// configuration access, and phase handlers.
+#[cfg(feature = "stream")]
+pub mod stream;
+
+/// The sync module.
+///
+/// This module provides primitives for handing work between helper threads and the NGINX event
+/// loop.
+pub mod sync;
+
 /// Define modules exported by this library.
 ///
 /// These are normally generated by the Nginx module system, but need to be
@@ -86,5 +106,5 @@ macro_rules! ngx_modules {
 #[macro_export]
 macro_rules! count {
     () => { 0usize };
-    ($x:tt, $( $xs:tt ),*) => { 1usize + $crate::count!($( $xs, )*) };
+    ($x:tt $(, $xs:tt )* $(,)?) => { 1usize + $crate::count!($( $xs ),*) };
 }
@@ -1,3 +1,8 @@
+use crate::ffi::*;
+
+use std::fmt;
+use std::ptr::NonNull;
+
 /// Utility function to provide typed checking of the mask's field state.
 #[inline(always)]
 pub fn check_mask(mask: DebugMask, log_level: usize) -> bool {
@@ -27,6 +32,22 @@ macro_rules! ngx_log_debug {
     }
 }
 
+/// Write to a [`Log`] at a specified [`LogLevel`], e.g. from a request handler.
+///
+/// Unlike the debug macros, the message is written in release builds of NGINX, and only
+/// formatted if the level is enabled for the log:
+///
+/// ```rust,ignore
+/// let log = unsafe { Log::from_ngx_log(request.log()) }.unwrap();
+/// ngx_log_error!(LogLevel::Info, log, "client sent invalid compressed body: {}", err);
+/// ```
+#[macro_export]
+macro_rules! ngx_log_error {
+    ( $level:expr, $log:expr, $($arg:tt)+ ) => {
+        $crate::log::Log::log(&$log, $level, 0, format_args!($($arg)+))
+    };
+}
+
 /// Log to request connection log at level [`NGX_LOG_DEBUG_HTTP`].
 ///
 /// [`NGX_LOG_DEBUG_HTTP`]: https://nginx.org/en/docs/dev/development_guide.html#logging
@@ -178,6 +199,191 @@ macro_rules! ngx_log_debug_mask {
     });
 }
 
+/// Severity of a log message, from the most to the least severe.
+///
+/// See [Logging](https://nginx.org/en/docs/dev/development_guide.html#logging) for details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Aligns to the NGX_LOG_EMERG level.
+    Emerg,
+    /// Aligns to the NGX_LOG_ALERT level.
+    Alert,
+    /// Aligns to the NGX_LOG_CRIT level.
+    Crit,
+    /// Aligns to the NGX_LOG_ERR level.
+    Error,
+    /// Aligns to the NGX_LOG_WARN level.
+    Warn,
+    /// Aligns to the NGX_LOG_NOTICE level.
+    Notice,
+    /// Aligns to the NGX_LOG_INFO level.
+    Info,
+}
+
+impl From<LogLevel> for u32 {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Emerg => crate::ffi::NGX_LOG_EMERG,
+            LogLevel::Alert => crate::ffi::NGX_LOG_ALERT,
+            LogLevel::Crit => crate::ffi::NGX_LOG_CRIT,
+            LogLevel::Error => crate::ffi::NGX_LOG_ERR,
+            LogLevel::Warn => crate::ffi::NGX_LOG_WARN,
+            LogLevel::Notice => crate::ffi::NGX_LOG_NOTICE,
+            LogLevel::Info => crate::ffi::NGX_LOG_INFO,
+        }
+    }
+}
+
+/// Wrapper for an NGINX log, `ngx_log_t`.
+///
+/// Unlike the debug macros, the leveled methods write messages to the error log in release
+/// builds of NGINX, e.g. from a request handler or a timer of a background task:
+///
+/// ```rust,ignore
+/// let log = unsafe { Log::from_ngx_log(request.log()) }.unwrap();
+/// log.warn(format_args!("upstream token expires in {}s", ttl.as_secs()));
+///
+/// // without a request or a connection at hand
+/// if let Some(log) = Log::cycle() {
+///     log.error(format_args!("failed to refresh the token: {err}"));
+/// }
+/// ```
+///
+/// The message is only formatted if the log level of the log enables it. While parsing the
+/// configuration, use [`conf_log`] instead, which adds the position in the configuration file.
+#[derive(Clone, Copy, Debug)]
+pub struct Log(NonNull<ngx_log_t>);
+
+impl Log {
+    /// Creates a [`Log`] from an [`ngx_log_t`] pointer.
+    ///
+    /// Returns `None` if the pointer is null.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a pointer to a valid `ngx_log_t`, which outlives the returned
+    /// value.
+    pub unsafe fn from_ngx_log(log: *mut ngx_log_t) -> Option<Self> {
+        NonNull::new(log).map(Log)
+    }
+
+    /// Returns the log of the current cycle, the `error_log` of the main context.
+    ///
+    /// Returns `None` before the cycle is initialized.
+    pub fn cycle() -> Option<Self> {
+        // SAFETY: the cycle and its log are valid for the lifetime of the process once set
+        unsafe { ngx_cycle.as_ref().and_then(|cycle| Self::from_ngx_log(cycle.log)) }
+    }
+
+    /// Returns the raw pointer to the log.
+    pub fn as_ptr(&self) -> *mut ngx_log_t {
+        self.0.as_ptr()
+    }
+
+    /// Returns `true` if messages of `level` are written to the log.
+    pub fn is_enabled(&self, level: LogLevel) -> bool {
+        // SAFETY: the log is valid per the contract of `from_ngx_log`
+        unsafe { self.0.as_ref() }.log_level >= u32::from(level) as ngx_uint_t
+    }
+
+    /// Writes `message` at `level`, with the description of the system error `err` if it is not 0.
+    pub fn log(&self, level: LogLevel, err: ngx_err_t, message: impl fmt::Display) {
+        if self.is_enabled(level) {
+            self.write(u32::from(level), err, message);
+        }
+    }
+
+    /// Writes `message` at the `error` level.
+    pub fn error(&self, message: impl fmt::Display) {
+        self.log(LogLevel::Error, 0, message)
+    }
+
+    /// Writes `message` at the `warn` level.
+    pub fn warn(&self, message: impl fmt::Display) {
+        self.log(LogLevel::Warn, 0, message)
+    }
+
+    /// Writes `message` at the `info` level.
+    pub fn info(&self, message: impl fmt::Display) {
+        self.log(LogLevel::Info, 0, message)
+    }
+
+    /// Writes `message` at the `debug` level if `mask` is enabled.
+    ///
+    /// Debug logging requires NGINX built with `--with-debug`.
+    pub fn debug(&self, mask: DebugMask, message: impl fmt::Display) {
+        // SAFETY: the log is valid per the contract of `from_ngx_log`
+        if check_mask(mask, unsafe { self.0.as_ref() }.log_level) {
+            self.write(NGX_LOG_DEBUG, 0, message);
+        }
+    }
+
+    fn write(&self, level: u32, err: ngx_err_t, message: impl fmt::Display) {
+        let message = message.to_string();
+        unsafe {
+            ngx_log_error_core(
+                level as ngx_uint_t,
+                self.as_ptr(),
+                err,
+                c"%*s".as_ptr(),
+                message.len(),
+                message.as_ptr(),
+            )
+        };
+    }
+}
+
+/// Writes `message` at `level` to the log of the configuration being parsed, followed by the
+/// position of the current directive in the configuration file, as `ngx_conf_log_error` does.
+///
+/// Use it for non-fatal messages of directive handlers, e.g. warnings about deprecated
+/// directives; errors are returned as a [`ConfError`](crate::core::ConfError) instead.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer, for the configuration being
+/// parsed.
+pub unsafe fn conf_log(cf: *mut ngx_conf_t, level: LogLevel, message: impl fmt::Display) {
+    let message = message.to_string();
+    // the message is passed with its length
+    ngx_conf_log_error(
+        u32::from(level) as ngx_uint_t,
+        cf,
+        0,
+        c"%*s".as_ptr(),
+        message.len(),
+        message.as_ptr(),
+    );
+}
+
+/// Writes a formatted message to the log of the configuration being parsed, followed by the
+/// position of the current directive, with [`conf_log`].
+///
+/// The level defaults to [`LogLevel::Emerg`], the level of configuration errors:
+///
+/// ```rust,ignore
+/// if bucket.len() < 3 {
+///     ngx_conf_log_error!(cf, "invalid bucket name \"{}\"", bucket);
+///     return NGX_CONF_ERROR as _;
+/// }
+/// ngx_conf_log_error!(cf, LogLevel::Warn, "\"s3_endpoint\" is deprecated");
+/// ```
+///
+/// Directive handlers report to the error log rather than to the standard output, which is not
+/// seen once NGINX runs as a daemon, and which `nginx -t` does not associate with the failure.
+///
+/// `cf` is the `ngx_conf_t` pointer. The macro calls the unsafe [`conf_log`], so it is used in an
+/// `unsafe` block, with the same safety requirements.
+#[macro_export]
+macro_rules! ngx_conf_log_error {
+    ( $cf:expr, $fmt:literal $($arg:tt)* ) => {
+        $crate::ngx_conf_log_error!($cf, $crate::log::LogLevel::Emerg, $fmt $($arg)*)
+    };
+    ( $cf:expr, $level:expr, $($arg:tt)+ ) => {
+        $crate::log::conf_log($cf, $level, ::std::format_args!($($arg)+))
+    };
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -0,0 +1,75 @@
+use crate::core::ConfError;
+use crate::ffi::*;
+use crate::http::register_once;
+
+use std::os::raw::c_void;
+use std::ptr::addr_of;
+
+/// # Safety
+///
+/// The caller has provided a valid `ngx_conf_t` that points to valid memory and is non-null.
+pub unsafe fn ngx_stream_conf_get_module_main_conf(cf: *mut ngx_conf_t, module: &ngx_module_t) -> *mut c_void {
+    let stream_conf_ctx = (*cf).ctx as *mut ngx_stream_conf_ctx_t;
+    *(*stream_conf_ctx).main_conf.add(module.ctx_index)
+}
+
+/// # Safety
+///
+/// The caller has provided a valid `ngx_conf_t` that points to valid memory and is non-null.
+pub unsafe fn ngx_stream_conf_get_module_srv_conf(cf: *mut ngx_conf_t, module: &ngx_module_t) -> *mut c_void {
+    let stream_conf_ctx = (*cf).ctx as *mut ngx_stream_conf_ctx_t;
+    *(*stream_conf_ctx).srv_conf.add(module.ctx_index)
+}
+
+/// Adds `handler` to the handlers of a stream session processing `phase`, e.g.
+/// `ngx_stream_phases_NGX_STREAM_ACCESS_PHASE` or `ngx_stream_phases_NGX_STREAM_PREREAD_PHASE`.
+///
+/// This is meant to be called from the `postconfiguration` handler of a module. As for HTTP
+/// phase handlers, registering the same handler twice for a configuration cycle fails with an
+/// error. Content handlers are set for each server with [`ngx_stream_set_content_handler`].
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer within the `stream` block.
+pub unsafe fn ngx_stream_add_phase_handler(
+    cf: *mut ngx_conf_t,
+    phase: ngx_stream_phases,
+    handler: unsafe extern "C" fn(*mut ngx_stream_session_t) -> ngx_int_t,
+) -> Result<(), ConfError> {
+    if phase == ngx_stream_phases_NGX_STREAM_CONTENT_PHASE {
+        return Err(ConfError::new("content handlers are set for each server"));
+    }
+    register_once(cf, handler as *const () as usize, "phase handler")?;
+
+    let cmcf = ngx_stream_conf_get_module_main_conf(cf, &*addr_of!(ngx_stream_core_module))
+        as *mut ngx_stream_core_main_conf_t;
+    let h = ngx_array_push(&mut (*cmcf).phases[phase as usize].handlers) as *mut ngx_stream_handler_pt;
+    if h.is_null() {
+        return Err(ConfError::new("failed to add phase handler"));
+    }
+    *h = Some(handler);
+    Ok(())
+}
+
+/// Sets `handler` as the content handler of the `server` block being parsed.
+///
+/// This is meant to be called from the handler of a directive in the `server` context, the way
+/// `proxy_pass` does. The content handler takes over the session and finalizes it with
+/// [`Session::finalize`](crate::stream::Session::finalize) once done.
+///
+/// # Safety
+///
+/// The caller has provided a valid non-null `ngx_conf_t` pointer within a `server` block of the
+/// `stream` block.
+pub unsafe fn ngx_stream_set_content_handler(
+    cf: *mut ngx_conf_t,
+    handler: unsafe extern "C" fn(*mut ngx_stream_session_t),
+) -> Result<(), ConfError> {
+    let cscf =
+        ngx_stream_conf_get_module_srv_conf(cf, &*addr_of!(ngx_stream_core_module)) as *mut ngx_stream_core_srv_conf_t;
+    if (*cscf).handler.is_some() {
+        return Err(ConfError::new("the server already has a content handler"));
+    }
+    (*cscf).handler = Some(handler);
+    Ok(())
+}
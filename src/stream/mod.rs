@@ -0,0 +1,7 @@
+mod conf;
+mod module;
+mod session;
+
+pub use conf::*;
+pub use module::*;
+pub use session::*;
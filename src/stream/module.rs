@@ -0,0 +1,86 @@
+use crate::core::NGX_CONF_ERROR;
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::Merge;
+
+use core::ptr;
+use std::os::raw::{c_char, c_void};
+
+/// The `StreamModule` trait provides the NGINX configuration stage interface of a stream module.
+///
+/// These functions allocate structures, initialize them, and merge through the configuration
+/// layers, like [`HTTPModule`](crate::http::HTTPModule) does for HTTP modules.
+///
+/// ```rust,ignore
+/// static ngx_stream_echo_module_ctx: ngx_stream_module_t = ngx_stream_module_t {
+///     preconfiguration: Some(Module::preconfiguration),
+///     postconfiguration: Some(Module::postconfiguration),
+///     create_main_conf: Some(Module::create_main_conf),
+///     init_main_conf: Some(Module::init_main_conf),
+///     create_srv_conf: Some(Module::create_srv_conf),
+///     merge_srv_conf: Some(Module::merge_srv_conf),
+/// };
+/// ```
+///
+/// The module itself is declared with the `NGX_STREAM_MODULE` type.
+pub trait StreamModule {
+    /// Configuration in the `stream` block.
+    type MainConf: Merge + Default;
+    /// Configuration in a `server` block within the `stream` block.
+    type SrvConf: Merge + Default;
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn preconfiguration(_cf: *mut ngx_conf_t) -> ngx_int_t {
+        Status::NGX_OK.into()
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn postconfiguration(_cf: *mut ngx_conf_t) -> ngx_int_t {
+        Status::NGX_OK.into()
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn create_main_conf(cf: *mut ngx_conf_t) -> *mut c_void {
+        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        pool.allocate::<Self::MainConf>(Default::default()) as *mut c_void
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn init_main_conf(_cf: *mut ngx_conf_t, _conf: *mut c_void) -> *mut c_char {
+        ptr::null_mut()
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn create_srv_conf(cf: *mut ngx_conf_t) -> *mut c_void {
+        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        pool.allocate::<Self::SrvConf>(Default::default()) as *mut c_void
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn merge_srv_conf(cf: *mut ngx_conf_t, prev: *mut c_void, conf: *mut c_void) -> *mut c_char {
+        let prev = &mut *(prev as *mut Self::SrvConf);
+        let conf = &mut *(conf as *mut Self::SrvConf);
+        if conf.merge(prev).is_err() {
+            return NGX_CONF_ERROR as _;
+        }
+        ngx_conf_result(cf, ptr::null(), conf.finalize(&mut *cf))
+    }
+}
@@ -0,0 +1,137 @@
+use crate::core::*;
+use crate::ffi::*;
+
+use std::os::raw::c_void;
+
+/// Define a static stream phase handler.
+///
+/// Handlers are expected to take a single [`Session`] argument and return a [`Status`].
+#[macro_export]
+macro_rules! stream_session_handler {
+    ( $name: ident, $handler: expr ) => {
+        #[no_mangle]
+        extern "C" fn $name(s: *mut ngx_stream_session_t) -> ngx_int_t {
+            let status: Status = $handler(unsafe { $crate::stream::Session::from_ngx_stream_session(s) });
+            status.0
+        }
+    };
+}
+
+/// Define a static stream content handler.
+///
+/// Handlers are expected to take a single [`Session`] argument and to finalize the session once
+/// done, see [`Session::finalize`].
+#[macro_export]
+macro_rules! stream_content_handler {
+    ( $name: ident, $handler: expr ) => {
+        #[no_mangle]
+        extern "C" fn $name(s: *mut ngx_stream_session_t) {
+            $handler(unsafe { $crate::stream::Session::from_ngx_stream_session(s) });
+        }
+    };
+}
+
+/// Wrapper struct for an `ngx_stream_session_t` pointer, providing methods for working with
+/// stream sessions.
+#[repr(transparent)]
+pub struct Session(ngx_stream_session_t);
+
+impl<'a> From<&'a Session> for *const ngx_stream_session_t {
+    fn from(session: &'a Session) -> Self {
+        &session.0 as *const _
+    }
+}
+
+impl<'a> From<&'a mut Session> for *mut ngx_stream_session_t {
+    fn from(session: &'a mut Session) -> Self {
+        &session.0 as *const _ as *mut _
+    }
+}
+
+impl Session {
+    /// Create a [`Session`] from an [`ngx_stream_session_t`].
+    ///
+    /// [`ngx_stream_session_t`]: https://nginx.org/en/docs/dev/development_guide.html#stream
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a valid `ngx_stream_session_t`
+    /// which shares the same representation as `Session`.
+    pub unsafe fn from_ngx_stream_session<'a>(s: *mut ngx_stream_session_t) -> &'a mut Session {
+        &mut *s.cast::<Session>()
+    }
+
+    /// Pointer to a [`ngx_connection_t`] client connection object.
+    ///
+    /// [`ngx_connection_t`]: https://nginx.org/en/docs/dev/development_guide.html#connection
+    pub fn connection(&self) -> *mut ngx_connection_t {
+        self.0.connection
+    }
+
+    /// Pointer to a [`ngx_log_t`].
+    ///
+    /// [`ngx_log_t`]: https://nginx.org/en/docs/dev/development_guide.html#logging
+    pub fn log(&self) -> *mut ngx_log_t {
+        unsafe { (*self.connection()).log }
+    }
+
+    /// Session pool, the pool of the client connection.
+    pub fn pool(&self) -> Pool {
+        // SAFETY: the session is allocated from the connection pool, which outlives it.
+        unsafe { Pool::from_ngx_pool((*self.connection()).pool) }
+    }
+
+    /// Module main configuration.
+    pub fn get_module_main_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        unsafe { (*self.0.main_conf.add(module.ctx_index) as *const T).as_ref() }
+    }
+
+    /// Module server configuration.
+    pub fn get_module_srv_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        unsafe { (*self.0.srv_conf.add(module.ctx_index) as *const T).as_ref() }
+    }
+
+    /// Get Module context
+    pub fn get_module_ctx<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        unsafe { (*self.0.ctx.add(module.ctx_index) as *const T).as_ref() }
+    }
+
+    /// Sets the value as the module's context.
+    pub fn set_module_ctx(&self, value: *mut c_void, module: &ngx_module_t) {
+        unsafe {
+            *self.0.ctx.add(module.ctx_index) = value;
+        };
+    }
+
+    /// Returns the number of bytes received from the client.
+    pub fn received(&self) -> usize {
+        self.0.received as usize
+    }
+
+    /// Returns the session status, e.g. `NGX_STREAM_OK`, as logged in `$status`.
+    pub fn status(&self) -> ngx_uint_t {
+        self.0.status
+    }
+
+    /// Finalizes the session with `status`, e.g. `NGX_STREAM_OK` or `NGX_STREAM_FORBIDDEN`,
+    /// running the log phase and closing the connection.
+    pub fn finalize(&mut self, status: u32) {
+        unsafe { ngx_stream_finalize_session(self.into(), status as ngx_uint_t) }
+    }
+
+    /// Returns the inner [`ngx_stream_session_t`].
+    ///
+    /// [`ngx_stream_session_t`]: https://nginx.org/en/docs/dev/development_guide.html#stream
+    pub fn get_inner(&self) -> &ngx_stream_session_t {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("received", &self.received())
+            .field("status", &self.status())
+            .finish()
+    }
+}
@@ -0,0 +1,270 @@
+use crate::event::{ngx_delete_posted_event, ngx_post_event};
+use crate::ffi::*;
+
+use std::cell::{RefCell, UnsafeCell};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::mem::{self, ManuallyDrop};
+use std::os::raw::c_void;
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::os::unix::net::UnixStream;
+use std::ptr::{self, addr_of_mut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+pub use std::sync::mpsc::{SendError, TrySendError};
+
+/// Creates a bounded channel from helper threads to the event loop of the current worker.
+///
+/// Values sent with a [`Sender`] are queued, at most `capacity` at a time, and passed to
+/// `on_message` on the event loop, from a posted event scheduled when the queue becomes
+/// non-empty. Senders wake the event loop through a socket pair registered with NGINX, so the
+/// channel does not rely on a timer polling the queue.
+///
+/// ```rust,ignore
+/// static RESULTS: WorkerState<Receiver<Lookup>> = WorkerState::new();
+///
+/// // in init_process
+/// let (tx, rx) = ngx::sync::channel(64, |lookup: Lookup| lookup.resume_request())?;
+/// RESULTS.set(rx);
+/// std::thread::spawn(move || {
+///     for lookup in requests {
+///         // blocks while the event loop is behind
+///         if tx.send(lookup.run()).is_err() {
+///             break;
+///         }
+///     }
+/// });
+/// ```
+///
+/// The function must be called on the worker's event loop, e.g. from the `init_process` hook.
+pub fn channel<T, F>(capacity: usize, on_message: F) -> io::Result<(Sender<T>, Receiver<T>)>
+where
+    T: Send + 'static,
+    F: FnMut(T) + 'static,
+{
+    let (writer, reader) = UnixStream::pair()?;
+    writer.set_nonblocking(true)?;
+    reader.set_nonblocking(true)?;
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::with_capacity(capacity),
+            notified: false,
+            closed: false,
+        }),
+        space: Condvar::new(),
+        capacity: capacity.max(1),
+        writer,
+    });
+
+    let log = unsafe { (*ngx_cycle).log };
+    let fd = reader.into_raw_fd();
+    let c = unsafe { ngx_get_connection(fd, log) };
+    if c.is_null() {
+        // SAFETY: the descriptor was not taken over by NGINX
+        drop(unsafe { UnixStream::from_raw_fd(fd) });
+        return Err(io::Error::other("no free connections for the channel"));
+    }
+
+    let mut inner = Box::new(ReceiverInner {
+        shared: shared.clone(),
+        connection: c,
+        // SAFETY: all-zero bits are a valid inactive event
+        drain: UnsafeCell::new(unsafe { mem::zeroed() }),
+        on_message: RefCell::new(Box::new(on_message)),
+    });
+
+    let data = &*inner as *const ReceiverInner<T> as *mut c_void;
+    let drain = inner.drain.get_mut();
+    drain.handler = Some(channel_drain_handler::<T>);
+    drain.data = data;
+    drain.log = log;
+
+    unsafe {
+        (*c).data = data;
+        (*(*c).read).handler = Some(channel_read_handler::<T>);
+        (*(*c).read).log = log;
+
+        if ngx_handle_read_event((*c).read, 0) != NGX_OK as ngx_int_t {
+            // closes the descriptor
+            inner.connection = ptr::null_mut();
+            ngx_close_connection(c);
+            return Err(io::Error::other("failed to register the channel"));
+        }
+    }
+
+    Ok((Sender(shared), Receiver(inner)))
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Signaled when the receiver takes values from the queue or is dropped.
+    space: Condvar,
+    capacity: usize,
+    writer: UnixStream,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    /// `true` if the event loop has been woken and did not drain the queue yet.
+    notified: bool,
+    closed: bool,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Queues `value` in a state with free space, waking the event loop if needed.
+    fn push(&self, mut state: MutexGuard<'_, State<T>>, value: T) {
+        state.items.push_back(value);
+        let wake = !mem::replace(&mut state.notified, true);
+        drop(state);
+
+        if wake {
+            // a full socket buffer means a wakeup is already pending
+            let _ = (&self.writer).write(&[1]);
+        }
+    }
+}
+
+/// The sending half of a [`channel`], usable from any thread.
+pub struct Sender<T>(Arc<Shared<T>>);
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender(self.0.clone())
+    }
+}
+
+impl<T: Send> Sender<T> {
+    /// Sends a value, blocking while the channel is full.
+    ///
+    /// Returns the value back if the receiver was dropped. This must not be called from the
+    /// event loop, which would never drain the channel while blocked.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.0.lock();
+        while !state.closed && state.items.len() >= self.0.capacity {
+            state = self.0.space.wait(state).unwrap_or_else(|err| err.into_inner());
+        }
+
+        if state.closed {
+            return Err(SendError(value));
+        }
+        self.0.push(state, value);
+        Ok(())
+    }
+
+    /// Sends a value if the channel has free space, without blocking.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let state = self.0.lock();
+        if state.closed {
+            return Err(TrySendError::Disconnected(value));
+        }
+        if state.items.len() >= self.0.capacity {
+            return Err(TrySendError::Full(value));
+        }
+        self.0.push(state, value);
+        Ok(())
+    }
+
+    /// Returns `true` if the receiver was dropped.
+    pub fn is_closed(&self) -> bool {
+        self.0.lock().closed
+    }
+}
+
+/// The receiving half of a [`channel`], owned by the worker's event loop.
+///
+/// Dropping the receiver unregisters the channel from the event loop and disconnects the
+/// senders, discarding queued values. The receiver must not be dropped from its own
+/// `on_message` callback.
+pub struct Receiver<T>(Box<ReceiverInner<T>>);
+
+struct ReceiverInner<T> {
+    shared: Arc<Shared<T>>,
+    connection: *mut ngx_connection_t,
+    drain: UnsafeCell<ngx_event_t>,
+    on_message: RefCell<Box<dyn FnMut(T)>>,
+}
+
+impl<T> Receiver<T> {
+    /// Returns the number of values waiting to be processed.
+    pub fn len(&self) -> usize {
+        self.0.shared.lock().items.len()
+    }
+
+    /// Returns `true` if no values are waiting to be processed.
+    pub fn is_empty(&self) -> bool {
+        self.0.shared.lock().items.is_empty()
+    }
+
+    /// Returns the capacity of the channel.
+    pub fn capacity(&self) -> usize {
+        self.0.shared.capacity
+    }
+}
+
+impl<T> Drop for ReceiverInner<T> {
+    fn drop(&mut self) {
+        let items = {
+            let mut state = self.shared.lock();
+            state.closed = true;
+            mem::take(&mut state.items)
+        };
+        self.shared.space.notify_all();
+        drop(items);
+
+        let drain = self.drain.get_mut();
+        if drain.posted() != 0 {
+            unsafe { ngx_delete_posted_event(drain) };
+        }
+        if !self.connection.is_null() {
+            unsafe { ngx_close_connection(self.connection) };
+        }
+    }
+}
+
+unsafe extern "C" fn channel_read_handler<T>(rev: *mut ngx_event_t) {
+    let c = (*rev).data as *mut ngx_connection_t;
+    let inner = &*((*c).data as *const ReceiverInner<T>);
+
+    // SAFETY: the descriptor is owned by the connection
+    let mut socket = ManuallyDrop::new(UnixStream::from_raw_fd((*c).fd));
+    let mut buf = [0u8; 64];
+    loop {
+        match socket.read(&mut buf) {
+            Ok(0) => break,
+            Ok(_) => continue,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                (*rev).set_ready(0);
+                break;
+            }
+            Err(_) => break,
+        }
+    }
+
+    if ngx_handle_read_event(rev, 0) != NGX_OK as ngx_int_t {
+        crate::ngx_log_debug!((*rev).log, "channel: failed to re-arm the read event");
+    }
+
+    ngx_post_event(inner.drain.get(), addr_of_mut!(ngx_posted_events));
+}
+
+unsafe extern "C" fn channel_drain_handler<T>(ev: *mut ngx_event_t) {
+    let inner = &*((*ev).data as *const ReceiverInner<T>);
+
+    let items = {
+        let mut state = inner.shared.lock();
+        state.notified = false;
+        mem::take(&mut state.items)
+    };
+    inner.shared.space.notify_all();
+
+    let mut on_message = inner.on_message.borrow_mut();
+    for value in items {
+        on_message(value);
+    }
+}
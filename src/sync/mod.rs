@@ -0,0 +1,3 @@
+mod channel;
+
+pub use channel::*;
@@ -0,0 +1,38 @@
+// Every item of the prelude is imported by name, so removing or renaming one fails to compile.
+#[allow(unused_imports)]
+use ngx::ffi::prelude::{
+    nginx_version, ngx_array_push, ngx_array_t, ngx_buf_t, ngx_chain_t, ngx_command_t, ngx_conf_log_error, ngx_conf_t,
+    ngx_connection_t, ngx_cycle, ngx_cycle_t, ngx_event_t, ngx_flag_t, ngx_http_add_variable, ngx_http_core_module,
+    ngx_http_handler_pt, ngx_http_module_t, ngx_http_phases, ngx_http_request_t, ngx_http_upstream_srv_conf_t,
+    ngx_http_upstream_t, ngx_http_variable_t, ngx_int_t, ngx_list_t, ngx_log_t, ngx_module_t, ngx_msec_t, ngx_pool_t,
+    ngx_str_t, ngx_table_elt_t, ngx_uint_t, ngx_variable_value_t, NGX_CONF_1MORE, NGX_CONF_2MORE, NGX_CONF_ANY,
+    NGX_CONF_BLOCK, NGX_CONF_FLAG, NGX_CONF_NOARGS, NGX_CONF_TAKE1, NGX_CONF_TAKE12, NGX_CONF_TAKE123,
+    NGX_CONF_TAKE1234, NGX_CONF_TAKE13, NGX_CONF_TAKE2, NGX_CONF_TAKE23, NGX_CONF_TAKE3, NGX_CONF_TAKE4,
+    NGX_CONF_TAKE5, NGX_CONF_TAKE6, NGX_CONF_TAKE7, NGX_CONF_UNSET, NGX_HTTP_ACCESS_PHASE, NGX_HTTP_CONTENT_PHASE,
+    NGX_HTTP_FIND_CONFIG_PHASE, NGX_HTTP_LIF_CONF, NGX_HTTP_LOC_CONF, NGX_HTTP_LOG_PHASE, NGX_HTTP_MAIN_CONF,
+    NGX_HTTP_MODULE, NGX_HTTP_POST_ACCESS_PHASE, NGX_HTTP_POST_READ_PHASE, NGX_HTTP_POST_REWRITE_PHASE,
+    NGX_HTTP_PREACCESS_PHASE, NGX_HTTP_PRECONTENT_PHASE, NGX_HTTP_REWRITE_PHASE, NGX_HTTP_SERVER_REWRITE_PHASE,
+    NGX_HTTP_SIF_CONF, NGX_HTTP_SRV_CONF, NGX_HTTP_UPS_CONF, NGX_HTTP_VAR_CHANGEABLE, NGX_HTTP_VAR_NOCACHEABLE,
+    NGX_LOG_ALERT, NGX_LOG_CRIT, NGX_LOG_DEBUG, NGX_LOG_EMERG, NGX_LOG_ERR, NGX_LOG_INFO, NGX_LOG_NOTICE, NGX_LOG_WARN,
+    NGX_RS_HTTP_LOC_CONF_OFFSET, NGX_RS_HTTP_MAIN_CONF_OFFSET, NGX_RS_HTTP_SRV_CONF_OFFSET, NGX_RS_MODULE_SIGNATURE,
+};
+
+#[test]
+fn test_prelude_phases() {
+    let phases: [ngx_http_phases; 11] = [
+        NGX_HTTP_POST_READ_PHASE,
+        NGX_HTTP_SERVER_REWRITE_PHASE,
+        NGX_HTTP_FIND_CONFIG_PHASE,
+        NGX_HTTP_REWRITE_PHASE,
+        NGX_HTTP_POST_REWRITE_PHASE,
+        NGX_HTTP_PREACCESS_PHASE,
+        NGX_HTTP_ACCESS_PHASE,
+        NGX_HTTP_POST_ACCESS_PHASE,
+        NGX_HTTP_PRECONTENT_PHASE,
+        NGX_HTTP_CONTENT_PHASE,
+        NGX_HTTP_LOG_PHASE,
+    ];
+
+    // the phases are listed in the order NGINX runs them
+    assert!(phases.windows(2).all(|pair| pair[0] < pair[1]));
+}